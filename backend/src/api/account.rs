@@ -0,0 +1,158 @@
+use actix_session::Session;
+use actix_web::{delete, post, web, HttpRequest, HttpResponse, Responder};
+use log::{info, warn, error};
+use std::error::Error;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::api::sessions::{current_session_id, end_device_session};
+use crate::models::UserSession;
+use crate::models::admission::applicant_id;
+use crate::models::local_account::LOCAL_PROVIDER;
+use crate::models::passkey::PASSKEY_PROVIDER;
+use crate::models::reddit::revoke_token;
+use crate::models::revocation::PendingRevocation;
+
+async fn revoke(data: &AppState, token: &str, token_type_hint: &str) -> Result<(), Box<dyn Error>> {
+    let config = &data.env_config;
+    revoke_token(&config.reddit_revoke_uri, &config.reddit_client_id, &config.reddit_client_secret, &config.reddit_author, token, token_type_hint).await
+}
+
+// Refresh token first so the access token can't be renewed in between.
+// Whatever reddit doesn't confirm is queued for retry_revocations.
+pub async fn revoke_reddit_tokens(data: &AppState, user_id: &str, user_session: &UserSession) -> Result<(), Box<dyn Error>> {
+    let mut tokens = Vec::new();
+    if let Some(refresh_token) = &user_session.reddit_refresh_token {
+        tokens.push((refresh_token.as_str(), "refresh_token"));
+    }
+    tokens.push((user_session.reddit_access_token.as_str(), "access_token"));
+    for (token, token_type_hint) in tokens {
+        if let Err(e) = revoke(data, token, token_type_hint).await {
+            warn!("[WARN]: Unable to revoke {} for {}, will retry: {}", token_type_hint, user_id, e);
+            data.revocation_store.save(&PendingRevocation::new(user_id, token, token_type_hint, e.to_string())).await?;
+        }
+    }
+    Ok(())
+}
+
+// Run periodically from main, gives up on a token after MAX_ATTEMPTS
+pub async fn retry_revocations(data: &AppState) -> Result<(), Box<dyn Error>> {
+    for mut pending in data.revocation_store.get_due().await? {
+        match revoke(data, &pending.token, &pending.token_type_hint).await {
+            Ok(_) => {
+                info!("[INFO]: Revoked {} for {} after {} failed attempts", pending.token_type_hint, pending.user_id, pending.attempts);
+                data.revocation_store.delete(&pending.id).await?;
+            },
+            Err(e) => {
+                pending.failed(e.to_string());
+                if pending.gave_up() {
+                    error!("[ERROR]: Giving up revoking {} for {}: {}", pending.token_type_hint, pending.user_id, pending.last_error);
+                    data.revocation_store.delete(&pending.id).await?;
+                } else {
+                    data.revocation_store.save(&pending).await?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Revokes the tokens and drops the stored session, the data stays. Only once
+// no device is signed in any more, see sessions::end_device_session.
+pub async fn end_session(data: &AppState, user_id: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(user_session) = data.session_store.get_session(user_id).await? {
+        revoke_reddit_tokens(data, user_id, &user_session).await?;
+        data.session_store.delete_session(user_id).await?;
+    }
+    Ok(())
+}
+
+async fn delete_user_data(data: &AppState, user_id: &str) -> Result<(), Box<dyn Error>> {
+    data.meal_store.delete_user(user_id).await?;
+    data.body_store.delete_user(user_id).await?;
+    data.food_store.delete_user(user_id).await?;
+    data.targets_store.delete_user(user_id).await?;
+    data.profile_store.delete_user(user_id).await?;
+    data.purchase_store.delete_user(user_id).await?;
+    data.pantry_store.delete_user(user_id).await?;
+    data.plan_store.delete_user(user_id).await?;
+    data.share_store.delete_user(user_id).await?;
+    data.admission_store.delete_user(user_id).await?;
+    for linked in data.identity_store.get_identities(user_id).await? {
+        let identity = &linked.identity;
+        if identity.provider == LOCAL_PROVIDER {
+            data.local_account_store.delete_account(&identity.subject).await?;
+        }
+        // admission is kept under the reddit id, or provider:subject
+        match identity.provider.as_str() {
+            "reddit" => data.admission_store.delete_user(&identity.subject).await?,
+            provider => data.admission_store.delete_user(&applicant_id(provider, &identity.subject)).await?,
+        }
+    }
+    // passkey sign-ups are admitted before they have a credential
+    data.admission_store.delete_user(&applicant_id(PASSKEY_PROVIDER, user_id)).await?;
+    data.passkey_store.delete_user(user_id).await?;
+    data.identity_store.delete_user(user_id).await?;
+    data.user_store.delete_user(user_id).await?;
+    data.device_session_store.delete_user(user_id).await?;
+    data.api_token_store.delete_user(user_id).await?;
+    data.search_cache.invalidate(user_id);
+    Ok(())
+}
+
+#[post("/logout")]
+async fn logout(req: HttpRequest, session: Session, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::NoContent().finish();
+    };
+    let session_id = current_session_id(&session);
+    session.purge();
+    let result = match session_id {
+        Some(id) => end_device_session(&data, &user_id, &id).await.map(|_| ()),
+        // a cookie from before sessions were tracked
+        None => match data.device_session_store.get_user_sessions(&user_id).await {
+            Ok(others) if others.is_empty() => end_session(&data, &user_id).await,
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        },
+    };
+    match result {
+        Err(e) => {
+            error!("[ERROR]: Unable to end session for {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            info!("[INFO]: {} logged out", user_id);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+// Everything goes, export first if you want to keep it
+#[delete("/account")]
+async fn delete_account(req: HttpRequest, session: Session, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let result = match end_session(&data, &user_id).await {
+        Err(e) => Err(e),
+        Ok(_) => delete_user_data(&data, &user_id).await,
+    };
+    match result {
+        // still signed in, so they can try again
+        Err(e) => {
+            error!("[ERROR]: Unable to delete account {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            session.purge();
+            info!("[INFO]: Deleted account {}", user_id);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(logout)
+        .service(delete_account);
+}
@@ -0,0 +1,115 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use log::{info, error};
+use std::error::Error;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::models::fdc::last_import_report;
+
+// The flag on the user, so it doesn't depend on a reddit session
+pub async fn is_admin(data: &AppState, user_id: &str) -> bool {
+    match data.user_store.get_user(user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load user for admin check: {}", e);
+            false
+        },
+        Ok(user) => user.is_some_and(|user| user.admin),
+    }
+}
+
+// For admins from before the flag, who are only named by reddit username
+// in the .env. Run once at startup, returns how many were flagged.
+pub async fn flag_configured_admins(data: &AppState) -> Result<usize, Box<dyn Error>> {
+    let mut flagged = 0;
+    for (user_id, user_session) in data.session_store.get_sessions().await? {
+        if data.env_config.is_admin(&user_session.reddit_user.name) && data.user_store.set_admin(&user_id, true).await? {
+            flagged += 1;
+        }
+    }
+    Ok(flagged)
+}
+
+// People the admission rules turned away, oldest first
+#[get("/admin/waitlist")]
+async fn get_waitlist(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if !is_admin(&data, &user_id).await {
+        return HttpResponse::Forbidden().finish();
+    }
+    match data.admission_store.get_waitlist().await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load waitlist: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(entries) => HttpResponse::Ok().json(entries),
+    }
+}
+
+// Lets them in on their next login
+#[post("/admin/waitlist/{user_id}/admit")]
+async fn admit_from_waitlist(req: HttpRequest, path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if !is_admin(&data, &user_id).await {
+        return HttpResponse::Forbidden().finish();
+    }
+    let waiting_id = path.into_inner();
+    match data.admission_store.admit(&waiting_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to admit {}: {}", waiting_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            info!("[INFO]: {} admitted {} from the waitlist", user_id, waiting_id);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+#[delete("/admin/waitlist/{user_id}")]
+async fn dismiss_from_waitlist(req: HttpRequest, path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if !is_admin(&data, &user_id).await {
+        return HttpResponse::Forbidden().finish();
+    }
+    match data.admission_store.remove_from_waitlist(&path).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to update waitlist: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Ok(true) => HttpResponse::NoContent().finish(),
+    }
+}
+
+// Nutrients the last FoodData Central import had nowhere to put, most
+// common first
+#[get("/admin/fdc/unmapped")]
+async fn get_unmapped_nutrients(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if !is_admin(&data, &user_id).await {
+        return HttpResponse::Forbidden().finish();
+    }
+    match last_import_report(&data.food_store).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load FoodData Central import report: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(None) => HttpResponse::NotFound().body("No FoodData Central import has run yet"),
+        Ok(Some(report)) => HttpResponse::Ok().json(report.unmapped),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_waitlist)
+        .service(admit_from_waitlist)
+        .service(dismiss_from_waitlist)
+        .service(get_unmapped_nutrients);
+}
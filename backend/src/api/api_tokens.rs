@@ -0,0 +1,199 @@
+use actix_web::http::{header, Method};
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use log::{info, error};
+use serde::Deserialize;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::api::admin::is_admin;
+use crate::models::api_token::{TokenScope, MAX_TOKEN_DAYS};
+
+#[derive(Clone, Debug, Deserialize)]
+struct NewToken {
+    name: String,
+    scopes: Vec<TokenScope>,
+    expires_in_days: i64,
+}
+
+// "Authorization: Bearer abm_...", None without one
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim().to_owned())
+}
+
+// Where tokens can't be used at all, even to read: signing in, sessions,
+// the account and everything in it at once, and the tokens themselves
+const NO_TOKEN_PATHS: [&str; 12] = [
+    "/tokens", "/sessions", "/identities", "/me", "/account", "/logout",
+    "/export", "/import", "/local-accounts", "/passkeys", "/plans/feed", "/login",
+];
+
+// the path itself or anything under it
+fn is_under(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+// What a token needs for a request to /api, None where tokens can't be used.
+// Takes the path as routed, with percent-encoding undone, so /api/%61dmin
+// is /api/admin here too.
+fn required_scope(method: &Method, path: &str) -> Option<TokenScope> {
+    let path = path.strip_prefix("/api").unwrap_or(path);
+    if NO_TOKEN_PATHS.iter().any(|prefix| is_under(path, prefix)) {
+        return None;
+    }
+    if is_under(path, "/admin") {
+        return Some(TokenScope::Admin);
+    }
+    match *method {
+        Method::GET | Method::HEAD => Some(TokenScope::Read),
+        _ if is_under(path, "/meals") => Some(TokenScope::WriteMeals),
+        _ => None,
+    }
+}
+
+// The user a bearer token acts as, or the response turning it away
+pub async fn token_user(data: &AppState, req: &HttpRequest, token: &str) -> Result<String, HttpResponse> {
+    let unauthorized = || HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+        .body("Invalid or expired API token");
+    let mut api_token = match data.api_token_store.get_token(token).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to check API token: {}", e);
+            return Err(HttpResponse::InternalServerError().finish());
+        },
+        Ok(None) => return Err(unauthorized()),
+        Ok(Some(api_token)) if api_token.is_expired() => return Err(unauthorized()),
+        Ok(Some(api_token)) => api_token,
+    };
+    match required_scope(req.method(), req.match_info().as_str()) {
+        None => return Err(HttpResponse::Forbidden().body("API tokens can't be used for this")),
+        Some(scope) if !api_token.allows(scope) => return Err(HttpResponse::Forbidden().body(format!("This token needs the {} scope", scope))),
+        Some(_) => {},
+    }
+    if let Err(e) = data.api_token_store.touch(token, &mut api_token).await {
+        error!("[ERROR]: Unable to update API token {}: {}", api_token.id, e);
+    }
+    Ok(api_token.user_id)
+}
+
+#[get("/tokens")]
+async fn get_tokens(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.api_token_store.get_user_tokens(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load API tokens for {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(tokens) => HttpResponse::Ok().json(tokens),
+    }
+}
+
+// The only time the token itself is sent
+#[post("/tokens")]
+async fn create_token(req: HttpRequest, new_token: web::Json<NewToken>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let name = new_token.name.trim();
+    if name.is_empty() || name.len() > 64 {
+        return HttpResponse::BadRequest().body("Give the token a name of up to 64 characters");
+    }
+    let mut scopes = Vec::new();
+    for scope in &new_token.scopes {
+        if !scopes.contains(scope) {
+            scopes.push(*scope);
+        }
+    }
+    if scopes.is_empty() {
+        return HttpResponse::BadRequest().body("Pick at least one scope");
+    }
+    if !(1..=MAX_TOKEN_DAYS).contains(&new_token.expires_in_days) {
+        return HttpResponse::BadRequest().body(format!("Tokens last 1 to {} days", MAX_TOKEN_DAYS));
+    }
+    if scopes.contains(&TokenScope::Admin) && !is_admin(&data, &user_id).await {
+        return HttpResponse::Forbidden().body("Only admins can make admin tokens");
+    }
+    match data.api_token_store.create_token(&user_id, name, scopes, new_token.expires_in_days).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to create API token for {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(issued) => {
+            info!("[INFO]: {} created API token {}", user_id, issued.api_token.id);
+            HttpResponse::Created().json(issued)
+        }
+    }
+}
+
+#[delete("/tokens/{id}")]
+async fn delete_token(req: HttpRequest, path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let id = path.into_inner();
+    match data.api_token_store.delete_token(&user_id, &id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to delete API token {} of {}: {}", id, user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Ok(true) => {
+            info!("[INFO]: {} deleted API token {}", user_id, id);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_tokens)
+        .service(create_token)
+        .service(delete_token);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_need_the_read_scope() {
+        assert_eq!(required_scope(&Method::GET, "/api/meals"), Some(TokenScope::Read));
+        assert_eq!(required_scope(&Method::HEAD, "/api/body/trend"), Some(TokenScope::Read));
+        assert_eq!(required_scope(&Method::GET, "/api/identity-providers"), Some(TokenScope::Read));
+    }
+
+    #[test]
+    fn only_meals_can_be_written() {
+        assert_eq!(required_scope(&Method::POST, "/api/meals"), Some(TokenScope::WriteMeals));
+        assert_eq!(required_scope(&Method::DELETE, "/api/meals/abc"), Some(TokenScope::WriteMeals));
+        assert_eq!(required_scope(&Method::POST, "/api/mealsx"), None);
+        assert_eq!(required_scope(&Method::PUT, "/api/targets"), None);
+    }
+
+    #[test]
+    fn admin_routes_need_the_admin_scope() {
+        assert_eq!(required_scope(&Method::GET, "/api/admin/jobs"), Some(TokenScope::Admin));
+        assert_eq!(required_scope(&Method::POST, "/api/admin/waitlist/abc"), Some(TokenScope::Admin));
+    }
+
+    #[test]
+    fn account_routes_take_no_tokens() {
+        for path in ["/api/tokens", "/api/tokens/abc", "/api/sessions", "/api/identities", "/api/me", "/api/export", "/api/plans/feed"] {
+            assert_eq!(required_scope(&Method::GET, path), None, "{}", path);
+        }
+        assert_eq!(required_scope(&Method::DELETE, "/api/account"), None);
+        assert_eq!(required_scope(&Method::POST, "/api/sessions/revoke-others"), None);
+        // other routes that only start the same
+        assert_eq!(required_scope(&Method::GET, "/api/plans"), Some(TokenScope::Read));
+    }
+
+    #[actix_web::test]
+    async fn encoded_paths_are_checked_as_routed() {
+        let req = actix_web::test::TestRequest::get().uri("/api/%61dmin/jobs").to_http_request();
+        assert_eq!(required_scope(req.method(), req.match_info().as_str()), Some(TokenScope::Admin));
+        let req = actix_web::test::TestRequest::get().uri("/api/%74okens").to_http_request();
+        assert_eq!(required_scope(req.method(), req.match_info().as_str()), None);
+    }
+}
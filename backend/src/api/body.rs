@@ -0,0 +1,159 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Local};
+use log::{info, error};
+use serde::Deserialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::api::{current_user_id, tdee};
+use crate::models::{BodyMetric, BodyTrend};
+
+#[derive(Clone, Debug, Deserialize)]
+struct NewBodyMetric {
+    recorded_at: Option<DateTime<Local>>,
+    weight_kg: Option<f64>,
+    body_fat_pct: Option<f64>,
+    waist_cm: Option<f64>,
+    #[serde(default)]
+    measurements: HashMap<String, f64>,
+}
+
+impl NewBodyMetric {
+    fn is_empty(&self) -> bool {
+        self.weight_kg.is_none() && self.body_fat_pct.is_none() && self.waist_cm.is_none() && self.measurements.is_empty()
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let positive = |value: f64| value.is_finite() && value > 0.0;
+        if self.weight_kg.map_or(false, |weight| !positive(weight)) {
+            return Err("Weight should be more than 0".to_owned());
+        }
+        if self.body_fat_pct.map_or(false, |pct| !pct.is_finite() || !(0.0..=100.0).contains(&pct)) {
+            return Err("Body fat should be between 0 and 100%".to_owned());
+        }
+        if self.waist_cm.map_or(false, |waist| !positive(waist)) {
+            return Err("Waist should be more than 0".to_owned());
+        }
+        if let Some((name, _)) = self.measurements.iter().find(|(_, value)| !positive(**value)) {
+            return Err(format!("{} should be more than 0", name));
+        }
+        Ok(())
+    }
+}
+
+#[get("/body")]
+async fn list_body_metrics(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.body_store.get_metrics(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load body metrics: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(metrics) => HttpResponse::Ok().json(metrics),
+    }
+}
+
+#[post("/body")]
+async fn add_body_metric(req: HttpRequest, body: web::Json<NewBodyMetric>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if body.is_empty() {
+        return HttpResponse::BadRequest().body("No measurements given");
+    }
+    if let Err(message) = body.check() {
+        return HttpResponse::BadRequest().body(message);
+    }
+    let body = body.into_inner();
+    let metric = BodyMetric {
+        id: Uuid::new_v4(),
+        recorded_at: body.recorded_at.unwrap_or_else(Local::now),
+        weight_kg: body.weight_kg,
+        body_fat_pct: body.body_fat_pct,
+        waist_cm: body.waist_cm,
+        measurements: body.measurements,
+    };
+    match data.body_store.save_metric(&user_id, &metric).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save body metric: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            info!("[INFO]: Body metric {} saved for {}", metric.id, user_id);
+            if metric.weight_kg.is_some() {
+                if let Err(e) = tdee::adjust_targets_if_due(&data, &user_id).await {
+                    error!("[ERROR]: Unable to auto adjust targets: {}", e);
+                }
+            }
+            HttpResponse::Created().json(metric)
+        }
+    }
+}
+
+#[delete("/body/{metric_id}")]
+async fn delete_body_metric(req: HttpRequest, path: web::Path<Uuid>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.body_store.delete_metric(&user_id, &path).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to delete body metric: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Ok(true) => HttpResponse::NoContent().finish(),
+    }
+}
+
+#[get("/body/trend")]
+async fn body_trend(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.body_store.get_metrics(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load body metrics: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(metrics) => HttpResponse::Ok().json(BodyTrend::from_metrics(&metrics)),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(body_trend)
+        .service(list_body_metrics)
+        .service(add_body_metric)
+        .service(delete_body_metric);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measured(weight_kg: Option<f64>, body_fat_pct: Option<f64>) -> NewBodyMetric {
+        NewBodyMetric {
+            recorded_at: None,
+            weight_kg,
+            body_fat_pct,
+            waist_cm: None,
+            measurements: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn rejects_impossible_measurements() {
+        assert!(measured(Some(80.0), Some(20.0)).check().is_ok());
+        assert!(measured(None, Some(0.0)).check().is_ok());
+        assert!(measured(Some(0.0), None).check().is_err());
+        assert!(measured(Some(-80.0), None).check().is_err());
+        assert!(measured(Some(f64::NAN), None).check().is_err());
+        assert!(measured(None, Some(100.5)).check().is_err());
+        assert!(measured(None, Some(-1.0)).check().is_err());
+        let mut arm = measured(None, None);
+        arm.measurements.insert("arm".to_owned(), f64::INFINITY);
+        assert!(arm.check().is_err());
+    }
+}
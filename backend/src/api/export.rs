@@ -0,0 +1,207 @@
+use actix_web::{get, post, web, http::header, HttpRequest, HttpResponse, Responder};
+use chrono::Local;
+use log::{info, error};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::models::archive::{Archive, OnConflict, ARCHIVE_VERSION};
+use crate::models::plan::PrepTask;
+
+// a restore carries the whole diary, well past the default JSON limit
+const RESTORE_LIMIT_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Json,
+    // the per table CSVs in a zip
+    Csv,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct RestoreQuery {
+    #[serde(default)]
+    on_conflict: OnConflict,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+struct RestoreCounts {
+    added: usize,
+    replaced: usize,
+    skipped: usize,
+}
+
+impl RestoreCounts {
+    // Counts the row and says whether to write it
+    fn should_write(&mut self, exists: bool, on_conflict: OnConflict) -> bool {
+        match (exists, on_conflict) {
+            (false, _) => self.added += 1,
+            (true, OnConflict::Replace) => self.replaced += 1,
+            (true, OnConflict::Skip) => {
+                self.skipped += 1;
+                return false;
+            },
+        }
+        true
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+struct RestoreReport {
+    tables: BTreeMap<&'static str, RestoreCounts>,
+}
+
+async fn build_archive(data: &AppState, user_id: &str) -> Result<Archive, Box<dyn Error>> {
+    Ok(Archive {
+        version: ARCHIVE_VERSION,
+        exported_at: Local::now(),
+        profile: data.profile_store.get_profile(user_id).await?,
+        targets: data.targets_store.get_targets(user_id).await?,
+        custom_foods: data.food_store.get_custom_foods(user_id).await?,
+        entries: data.meal_store.get_entries(user_id).await?,
+        body_metrics: data.body_store.get_metrics(user_id).await?,
+        purchases: data.purchase_store.get_purchases(user_id).await?,
+        pantry: data.pantry_store.get_items(user_id).await?,
+        planned_meals: data.plan_store.get_meals(user_id).await?,
+        prep_tasks: data.plan_store.get_tasks(user_id).await?,
+    })
+}
+
+// Which of a table's rows to write, and the account's rows they replace.
+// Rows whose id the account already has are conflicts.
+fn plan_table<T: Clone, K: Eq + Hash>(counts: &mut RestoreCounts, existing: Vec<T>, rows: &[T], id: impl Fn(&T) -> K, on_conflict: OnConflict) -> (Vec<T>, Vec<T>) {
+    let mut existing: HashMap<K, T> = existing.into_iter().map(|row| (id(&row), row)).collect();
+    let (mut removed, mut saved) = (Vec::new(), Vec::new());
+    for row in rows {
+        let old = existing.remove(&id(row));
+        if counts.should_write(old.is_some(), on_conflict) {
+            removed.extend(old);
+            saved.push(row.clone());
+        }
+    }
+    (removed, saved)
+}
+
+// Archive::read has already checked the rows. This works out every table's
+// changes before writing any, then writes each store in one batch, so a
+// failed read leaves the account as it was. Replacing deletes the old row in the same batch since most
+// stores key on time as well as id.
+async fn restore_archive(data: &AppState, user_id: &str, archive: &Archive, on_conflict: OnConflict) -> Result<RestoreReport, Box<dyn Error>> {
+    let mut report = RestoreReport::default();
+    let tables = &mut report.tables;
+
+    let write_profile = tables.entry("profile").or_default().should_write(data.profile_store.has_profile(user_id).await?, on_conflict);
+    let write_targets = tables.entry("targets").or_default().should_write(data.targets_store.has_targets(user_id).await?, on_conflict);
+    let (_, foods) = plan_table(tables.entry("custom_foods").or_default(), data.food_store.get_custom_foods(user_id).await?,
+        &archive.custom_foods, |food| food.id.clone(), on_conflict);
+    let (old_entries, entries) = plan_table(tables.entry("entries").or_default(), data.meal_store.get_entries(user_id).await?,
+        &archive.entries, |entry| entry.id, on_conflict);
+    let (old_metrics, metrics) = plan_table(tables.entry("body_metrics").or_default(), data.body_store.get_metrics(user_id).await?,
+        &archive.body_metrics, |metric| metric.id, on_conflict);
+    let (old_purchases, purchases) = plan_table(tables.entry("purchases").or_default(), data.purchase_store.get_purchases(user_id).await?,
+        &archive.purchases, |purchase| purchase.id, on_conflict);
+    let (old_items, items) = plan_table(tables.entry("pantry").or_default(), data.pantry_store.get_items(user_id).await?,
+        &archive.pantry, |item| item.id, on_conflict);
+    let (old_meals, meals) = plan_table(tables.entry("planned_meals").or_default(), data.plan_store.get_meals(user_id).await?,
+        &archive.planned_meals, |meal| meal.id, on_conflict);
+    // a replaced planned meal takes its prep tasks with it, as delete_meal
+    // would, so those don't count as conflicts
+    let replaced_meals: HashSet<Uuid> = old_meals.iter().map(|meal| meal.id).collect();
+    let (mut old_tasks, existing_tasks): (Vec<PrepTask>, Vec<PrepTask>) = data.plan_store.get_tasks(user_id).await?
+        .into_iter()
+        .partition(|task| task.planned_meal_id.is_some_and(|id| replaced_meals.contains(&id)));
+    let (replaced_tasks, tasks) = plan_table(tables.entry("prep_tasks").or_default(), existing_tasks,
+        &archive.prep_tasks, |task| task.id, on_conflict);
+    old_tasks.extend(replaced_tasks);
+
+    if write_profile {
+        data.profile_store.save_profile(user_id, &archive.profile).await?;
+    }
+    if write_targets {
+        data.targets_store.save_targets(user_id, &archive.targets).await?;
+    }
+    data.food_store.save_custom_foods(user_id, &foods).await?;
+    data.meal_store.replace_entries(user_id, &old_entries, &entries).await?;
+    data.body_store.replace_metrics(user_id, &old_metrics, &metrics).await?;
+    data.purchase_store.replace_purchases(user_id, &old_purchases, &purchases).await?;
+    data.pantry_store.replace_items(user_id, &old_items, &items).await?;
+    data.plan_store.replace_meals(user_id, &old_meals, &meals).await?;
+    data.plan_store.replace_tasks(user_id, &old_tasks, &tasks).await?;
+    data.search_cache.invalidate(user_id);
+
+    Ok(report)
+}
+
+// Everything the user has put in, as a versioned JSON archive or a zip of CSVs
+#[get("")]
+async fn export_account(req: HttpRequest, query: web::Query<ExportQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let archive = match build_archive(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to gather export for {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(archive) => archive,
+    };
+    let stamp = archive.exported_at.format("%Y-%m-%d");
+    match query.format {
+        ExportFormat::Json => HttpResponse::Ok()
+            .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"ab-macros-export-{}.json\"", stamp)))
+            .json(archive),
+        ExportFormat::Csv => match archive.to_zip() {
+            Err(e) => {
+                error!("[ERROR]: Unable to zip export for {}: {}", user_id, e);
+                HttpResponse::InternalServerError().finish()
+            },
+            Ok(zip) => HttpResponse::Ok()
+                .content_type("application/zip")
+                .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"ab-macros-export-{}.zip\"", stamp)))
+                .body(zip),
+        },
+    }
+}
+
+// Takes the JSON archive or the zip from an export, into this account
+#[post("/restore")]
+async fn restore_account(req: HttpRequest, query: web::Query<RestoreQuery>, body: web::Bytes, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let archive = match Archive::read(&body) {
+        Err(e) => return HttpResponse::BadRequest().body(format!("Unreadable archive: {}", e)),
+        Ok(archive) => archive,
+    };
+    match restore_archive(&data, &user_id, &archive, query.on_conflict).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to restore archive for {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(report) => {
+            info!("[INFO]: Restored archive from {} for {}", archive.exported_at, user_id);
+            HttpResponse::Ok().json(report)
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/export")
+            .app_data(web::PayloadConfig::new(RESTORE_LIMIT_BYTES))
+            .service(export_account)
+            .service(restore_account)
+    );
+}
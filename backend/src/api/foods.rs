@@ -0,0 +1,182 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Duration, Local};
+use log::{info, error};
+use serde::Deserialize;
+use std::error::Error;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::models::{Food, FoodCategory};
+use crate::models::barcode::normalize_barcode;
+use crate::models::search::{food_frequency, SearchIndex, UserIndex};
+use crate::models::sourcing::{check_sourcing, Sourcing};
+
+// how far back the user's eating habits count toward search ranking
+const FREQUENCY_DAYS: i64 = 90;
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+const MAX_SEARCH_LIMIT: usize = 50;
+
+#[derive(Clone, Debug, Deserialize)]
+struct NewCustomFood {
+    name: String,
+    category: FoodCategory,
+    protein_g: f64,
+    fat_g: f64,
+    carbs_g: f64,
+    calories: f64,
+    #[serde(default)]
+    omega3_mg: f64,
+    #[serde(default)]
+    omega6_mg: f64,
+    #[serde(default)]
+    default_sourcing: Vec<Sourcing>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    limit: Option<usize>,
+}
+
+// Catalogue and custom foods to search, and how often the user logged each
+// lately. Cached, see SearchCache.
+pub async fn search_index_for(data: &AppState, user_id: &str) -> Result<UserIndex, Box<dyn Error>> {
+    if let Some(cached) = data.search_cache.get(user_id) {
+        return Ok(cached);
+    }
+    let mut foods = data.food_store.get_foods().await?;
+    foods.extend(data.food_store.get_custom_foods(user_id).await?);
+    let now = Local::now();
+    let entries = data.meal_store.get_entries_between(user_id, &(now - Duration::days(FREQUENCY_DAYS)), &now).await?;
+    let built = (Arc::new(SearchIndex::new(foods)), Arc::new(food_frequency(&entries)));
+    data.search_cache.insert(user_id, built.clone());
+    Ok(built)
+}
+
+// The shared catalogue, plus the user's own foods when logged in
+#[get("/foods")]
+async fn list_foods(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let mut foods = match data.food_store.get_foods().await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load foods: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(foods) => foods,
+    };
+    if let Some(user_id) = current_user_id(&req) {
+        match data.food_store.get_custom_foods(&user_id).await {
+            Err(e) => {
+                error!("[ERROR]: Unable to load custom foods: {}", e);
+                return HttpResponse::InternalServerError().finish();
+            },
+            Ok(custom) => foods.extend(custom),
+        }
+    }
+    HttpResponse::Ok().json(foods)
+}
+
+#[post("/foods/custom")]
+async fn add_custom_food(req: HttpRequest, body: web::Json<NewCustomFood>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let body = body.into_inner();
+    if body.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Food needs a name");
+    }
+    if let Err(reason) = check_sourcing(&body.default_sourcing, body.category) {
+        return HttpResponse::BadRequest().body(reason);
+    }
+    let food = Food {
+        id: format!("custom-{}", Uuid::new_v4()),
+        name: body.name.trim().to_owned(),
+        category: body.category,
+        protein_g: body.protein_g,
+        fat_g: body.fat_g,
+        carbs_g: body.carbs_g,
+        calories: body.calories,
+        omega3_mg: body.omega3_mg,
+        omega6_mg: body.omega6_mg,
+        default_sourcing: body.default_sourcing,
+        custom: true,
+        provenance: None,
+    };
+    match data.food_store.save_custom_food(&user_id, &food).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save custom food: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            data.search_cache.invalidate(&user_id);
+            info!("[INFO]: Custom food {} saved for {}", food.id, user_id);
+            HttpResponse::Created().json(food)
+        }
+    }
+}
+
+#[delete("/foods/custom/{food_id}")]
+async fn delete_custom_food(req: HttpRequest, path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.food_store.delete_custom_food(&user_id, &path).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to delete custom food: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Ok(true) => {
+            data.search_cache.invalidate(&user_id);
+            HttpResponse::NoContent().finish()
+        },
+    }
+}
+
+// Names, aliases and near misses, the user's usual foods first. With no
+// query it's just their usual foods.
+#[get("/foods/search")]
+async fn search_foods(req: HttpRequest, query: web::Query<SearchQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match search_index_for(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load foods to search: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok((index, uses)) => {
+            let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).min(MAX_SEARCH_LIMIT);
+            HttpResponse::Ok().json(index.search(&query.q, &uses, limit))
+        },
+    }
+}
+
+// Packaged foods from the imported Open Food Facts data
+#[get("/foods/barcode/{ean}")]
+async fn barcode_food(req: HttpRequest, path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    if current_user_id(&req).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let Some(ean) = normalize_barcode(&path) else {
+        return HttpResponse::BadRequest().body("Barcodes are 8 to 14 digits");
+    };
+    match data.food_store.get_barcode_food(&ean).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to look up barcode {}: {}", ean, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Ok(Some(food)) => HttpResponse::Ok().json(food),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(barcode_food)
+        .service(search_foods)
+        .service(list_foods)
+        .service(add_custom_food)
+        .service(delete_custom_food);
+}
@@ -0,0 +1,90 @@
+use actix_web::{delete, get, web, HttpRequest, HttpResponse, Responder};
+use log::{info, error};
+use serde::Serialize;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::models::local_account::LOCAL_PROVIDER;
+use crate::models::passkey::PASSKEY_PROVIDER;
+
+#[derive(Clone, Debug, Serialize)]
+struct ProviderInfo {
+    id: String,
+    name: String,
+}
+
+// The login buttons, no session needed
+#[get("/identity-providers")]
+async fn get_identity_providers(data: web::Data<AppState>) -> impl Responder {
+    let providers: Vec<ProviderInfo> = data.identity_providers.all().iter()
+        .map(|provider| ProviderInfo {
+            id: provider.id().to_owned(),
+            name: provider.name().to_owned(),
+        })
+        .collect();
+    HttpResponse::Ok().json(providers)
+}
+
+// The ways the signed in user can sign in
+#[get("/identities")]
+async fn get_identities(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.identity_store.get_identities(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load identities for {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(identities) => HttpResponse::Ok().json(identities),
+    }
+}
+
+// The last one stays, otherwise there'd be no way back in. Passwords and
+// passkeys have nothing left to sign in to once unlinked, so they go too.
+#[delete("/identities/{provider}/{subject}")]
+async fn unlink_identity(req: HttpRequest, path: web::Path<(String, String)>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let (provider, subject) = path.into_inner();
+    let identities = match data.identity_store.get_identities(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load identities for {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(identities) => identities,
+    };
+    if identities.len() <= 1 {
+        return HttpResponse::Conflict().body("Can't remove your only way to sign in");
+    }
+    match data.identity_store.unlink(&user_id, &provider, &subject).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to unlink {} identity for {}: {}", provider, user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Ok(true) => {
+            if provider == LOCAL_PROVIDER {
+                if let Err(e) = data.local_account_store.delete_account(&subject).await {
+                    error!("[ERROR]: Unable to delete local account {}: {}", subject, e);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
+            if provider == PASSKEY_PROVIDER {
+                if let Err(e) = data.passkey_store.delete_passkey(&user_id, &subject).await {
+                    error!("[ERROR]: Unable to delete passkey {}: {}", subject, e);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
+            info!("[INFO]: Unlinked {} identity for {}", provider, user_id);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_identity_providers)
+        .service(get_identities)
+        .service(unlink_identity);
+}
@@ -0,0 +1,184 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Duration;
+use log::{info, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::models::{Food, FoodCategory, MealEntry};
+use crate::models::import::{food_from_row, guess_category, import_entry, normalize_name, parse_export, preview, ImportSource, PreviewRow, RowStatus};
+
+// years of Cronometer servings run to several megabytes
+const IMPORT_LIMIT_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Clone, Debug, Deserialize)]
+struct ImportRequest {
+    // the export file's contents
+    csv: String,
+    // worked out from the header when missing
+    source: Option<ImportSource>,
+    // export food name to one of our food ids
+    #[serde(default)]
+    mappings: HashMap<String, String>,
+    // category for foods created from unmatched names, guessed when missing
+    #[serde(default)]
+    categories: HashMap<String, FoodCategory>,
+    // commit only: make custom foods for unmatched rows instead of skipping them
+    #[serde(default)]
+    create_custom: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct ImportPreview {
+    source: ImportSource,
+    matched: usize,
+    unmatched: usize,
+    duplicates: usize,
+    invalid: usize,
+    rows: Vec<PreviewRow>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+struct ImportResult {
+    imported: usize,
+    created_foods: Vec<Food>,
+    skipped: usize,
+}
+
+// Catalogue and custom foods, plus any mapped to that live elsewhere (barcodes)
+async fn import_foods(data: &AppState, user_id: &str, mappings: &HashMap<String, String>) -> Result<Vec<Food>, Box<dyn Error>> {
+    let mut foods = data.food_store.get_foods().await?;
+    foods.extend(data.food_store.get_custom_foods(user_id).await?);
+    for food_id in mappings.values() {
+        if foods.iter().any(|food| &food.id == food_id) {
+            continue;
+        }
+        if let Some(food) = data.food_store.get_food(user_id, food_id).await? {
+            foods.push(food);
+        }
+    }
+    Ok(foods)
+}
+
+// the export's source, its rows as they'd import, and the foods they were matched against
+type Previewed = (ImportSource, Vec<PreviewRow>, Vec<Food>);
+
+async fn preview_import(data: &AppState, user_id: &str, request: &ImportRequest) -> Result<Result<Previewed, String>, Box<dyn Error>> {
+    let (source, rows) = match parse_export(&request.csv, request.source) {
+        Err(message) => return Ok(Err(message)),
+        Ok(parsed) => parsed,
+    };
+    let foods = import_foods(data, user_id, &request.mappings).await?;
+    let mut times = rows.iter().filter_map(|row| row.eaten_at);
+    let existing = match times.next() {
+        None => Vec::new(),
+        Some(first) => {
+            let (from, to) = times.fold((first, first), |(from, to), at| (from.min(at), to.max(at)));
+            data.meal_store.get_entries_between(user_id, &from, &(to + Duration::seconds(1))).await?
+        }
+    };
+    let rows = preview(rows, &foods, &request.mappings, &existing);
+    Ok(Ok((source, rows, foods)))
+}
+
+// Dry run: what each row of the export would become, nothing is saved
+#[post("/preview")]
+async fn import_preview(req: HttpRequest, body: web::Json<ImportRequest>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match preview_import(&data, &user_id, &body).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to preview import: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(Err(message)) => HttpResponse::BadRequest().body(message),
+        Ok(Ok((source, rows, _))) => {
+            let count = |status: RowStatus| rows.iter().filter(|row| row.status == status).count();
+            HttpResponse::Ok().json(ImportPreview {
+                source,
+                matched: count(RowStatus::Matched),
+                unmatched: count(RowStatus::Unmatched),
+                duplicates: count(RowStatus::Duplicate),
+                invalid: count(RowStatus::Invalid),
+                rows,
+            })
+        }
+    }
+}
+
+// Saves the matched rows at their original times. Imported history goes
+// straight into the diary, it was eaten long ago so no pantry or costs.
+#[post("/commit")]
+async fn import_commit(req: HttpRequest, body: web::Json<ImportRequest>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let (rows, foods) = match preview_import(&data, &user_id, &body).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to read import: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(Err(message)) => return HttpResponse::BadRequest().body(message),
+        Ok(Ok((_, rows, foods))) => (rows, foods),
+    };
+    let mut result = ImportResult::default();
+    let mut created: HashMap<String, Food> = HashMap::new();
+    let mut entries: Vec<MealEntry> = Vec::new();
+    for previewed in &rows {
+        let food = match (previewed.status, &previewed.food_id) {
+            (RowStatus::Matched, Some(food_id)) => foods.iter().find(|food| &food.id == food_id).cloned(),
+            (RowStatus::Unmatched, _) if body.create_custom => {
+                let name = normalize_name(&previewed.row.food_name);
+                match created.get(&name) {
+                    Some(food) => Some(food.clone()),
+                    None => {
+                        let category = body.categories.get(&previewed.row.food_name)
+                            .copied()
+                            .or_else(|| guess_category(&previewed.row.food_name));
+                        let food = category.and_then(|category| food_from_row(format!("custom-{}", Uuid::new_v4()), &previewed.row, category));
+                        if let Some(food) = &food {
+                            if let Err(e) = data.food_store.save_custom_food(&user_id, food).await {
+                                error!("[ERROR]: Unable to save imported food: {}", e);
+                                return HttpResponse::InternalServerError().finish();
+                            }
+                            created.insert(name, food.clone());
+                            result.created_foods.push(food.clone());
+                        }
+                        food
+                    }
+                }
+            },
+            _ => None,
+        };
+        match food.and_then(|food| {
+            // later rows of a created food may be servings we couldn't weigh
+            let mut row = previewed.row.clone();
+            row.grams = row.grams_for(&food);
+            import_entry(&food, &row)
+        }) {
+            None => result.skipped += 1,
+            Some(entry) => entries.push(entry),
+        }
+    }
+    if let Err(e) = data.meal_store.save_entries(&user_id, &entries).await {
+        error!("[ERROR]: Unable to save imported meals: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    data.search_cache.invalidate(&user_id);
+    result.imported = entries.len();
+    info!("[INFO]: Imported {} meals and {} foods for {}", result.imported, result.created_foods.len(), user_id);
+    HttpResponse::Ok().json(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/import")
+            .app_data(web::JsonConfig::default().limit(IMPORT_LIMIT_BYTES))
+            .service(import_preview)
+            .service(import_commit)
+    );
+}
@@ -0,0 +1,132 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Duration, Local};
+use futures::future::LocalBoxFuture;
+use log::warn;
+use std::error::Error;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::api::admin::is_admin;
+use crate::api::account::{end_session, retry_revocations};
+use crate::api::sharing::{refresh_reddit_session, share_summary};
+use crate::models::device_session::SESSION_IDLE_DAYS;
+use crate::models::scheduler::{JobResult, Schedule};
+use crate::models::sharing::SharePeriod;
+
+// tokens expiring within this get renewed ahead of time
+const REFRESH_AHEAD_MINUTES: i64 = 15;
+// only for people who have been around lately
+const REFRESH_ACTIVE_HOURS: i64 = 24;
+
+// Reddit tokens for users no device has been signed in as for a while,
+// expired device sessions and API tokens, and passkey ceremonies nobody
+// finished
+async fn sweep_sessions(data: &AppState) -> JobResult {
+    let devices = data.device_session_store.delete_expired().await?;
+    let api_tokens = data.api_token_store.delete_expired().await?;
+    let challenges = data.passkey_store.delete_expired_challenges().await?;
+    let stale_before = Local::now() - Duration::days(SESSION_IDLE_DAYS);
+    let mut ended = 0;
+    for (user_id, user_session) in data.session_store.get_sessions().await? {
+        if user_session.expires_at > stale_before || !data.device_session_store.get_user_sessions(&user_id).await?.is_empty() {
+            continue;
+        }
+        end_session(data, &user_id).await?;
+        ended += 1;
+    }
+    Ok(format!("removed {} device sessions, {} reddit sessions, {} API tokens, {} passkey challenges", devices, ended, api_tokens, challenges))
+}
+
+// Keeps the access tokens of active users fresh, so sharing and the admin
+// check don't wait on reddit
+async fn refresh_tokens(data: &AppState) -> JobResult {
+    let expiring_before = Local::now() + Duration::minutes(REFRESH_AHEAD_MINUTES);
+    let active_since = Local::now() - Duration::hours(REFRESH_ACTIVE_HOURS);
+    let (mut refreshed, mut failed) = (0, 0);
+    for (user_id, mut user_session) in data.session_store.get_sessions().await? {
+        if user_session.expires_at > expiring_before || user_session.reddit_refresh_token.is_none() {
+            continue;
+        }
+        let sessions = data.device_session_store.get_user_sessions(&user_id).await?;
+        if !sessions.iter().any(|device| device.last_seen_at > active_since) {
+            continue;
+        }
+        match refresh_reddit_session(data, &user_id, &mut user_session).await {
+            Err(e) => {
+                warn!("[WARN]: Unable to refresh reddit token for {}: {}", user_id, e);
+                failed += 1;
+            },
+            Ok(_) => refreshed += 1,
+        }
+    }
+    Ok(format!("refreshed {} reddit tokens, {} failed", refreshed, failed))
+}
+
+// Last week's summary for everyone sharing, for GET /sharing/digest
+async fn generate_digests(data: &AppState) -> JobResult {
+    let last_week = Local::now().date_naive() - Duration::days(7);
+    let mut generated = 0;
+    for user_id in data.share_store.get_enabled_users().await? {
+        match share_summary(data, &user_id, SharePeriod::Week, last_week).await {
+            Err(e) => warn!("[WARN]: Unable to build digest for {}: {}", user_id, e),
+            Ok(summary) => {
+                data.share_store.save_digest(&user_id, &summary).await?;
+                generated += 1;
+            },
+        }
+    }
+    Ok(format!("generated {} weekly digests", generated))
+}
+
+// sled has no compaction to call, it rewrites its segments itself as they
+// empty out. This drops index entries pointing at nothing, flushes every
+// database and reports their sizes.
+async fn tidy_stores(data: &AppState) -> JobResult {
+    let dangling = data.device_session_store.delete_dangling().await?;
+    let mut sizes = Vec::new();
+    for (name, db) in data.databases() {
+        db.flush_async().await?;
+        sizes.push(format!("{} {} KiB", name, db.size_on_disk()? / 1024));
+    }
+    Ok(format!("removed {} dangling index entries; {}", dangling, sizes.join(", ")))
+}
+
+fn job(data: &web::Data<AppState>, run: for<'a> fn(&'a AppState) -> LocalBoxFuture<'a, JobResult>) -> impl Fn() -> LocalBoxFuture<'static, JobResult> {
+    let data = data.clone();
+    move || {
+        let data = data.clone();
+        Box::pin(async move { run(&data).await })
+    }
+}
+
+// Started once from main, replaces the old revocation retry loop
+pub fn schedule(data: &web::Data<AppState>) -> Result<(), Box<dyn Error>> {
+    let scheduler = &data.scheduler;
+    scheduler.add("retry-revocations", Schedule::Every(Duration::minutes(5)), Duration::seconds(30),
+        job(data, |data| Box::pin(async move { retry_revocations(data).await.map(|_| "retried pending revocations".to_owned()) })));
+    scheduler.add("sweep-sessions", Schedule::Every(Duration::hours(1)), Duration::minutes(5),
+        job(data, |data| Box::pin(sweep_sessions(data))));
+    scheduler.add("refresh-tokens", Schedule::Every(Duration::minutes(10)), Duration::minutes(1),
+        job(data, |data| Box::pin(refresh_tokens(data))));
+    // Monday morning, after the week it covers
+    scheduler.add("weekly-digests", Schedule::cron("0 6 * * 1")?, Duration::minutes(10),
+        job(data, |data| Box::pin(generate_digests(data))));
+    scheduler.add("tidy-stores", Schedule::cron("30 4 * * *")?, Duration::minutes(15),
+        job(data, |data| Box::pin(tidy_stores(data))));
+    Ok(())
+}
+
+#[get("/admin/jobs")]
+async fn get_jobs(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if !is_admin(&data, &user_id).await {
+        return HttpResponse::Forbidden().finish();
+    }
+    HttpResponse::Ok().json(data.scheduler.statuses())
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_jobs);
+}
@@ -0,0 +1,346 @@
+use actix_session::Session;
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::Local;
+use log::{info, warn, error};
+use serde::Deserialize;
+use std::error::Error;
+use std::sync::OnceLock;
+
+use crate::AppState;
+use crate::api::{current_user_id, set_current_user};
+use crate::api::admin::is_admin;
+use crate::api::login::admit_applicant;
+use crate::api::sessions::{client_network, current_session_id, end_other_sessions};
+use crate::models::admission::{applicant_id, Admission};
+use crate::models::identity::ExternalIdentity;
+use crate::models::local_account::{check_password, hash_password, normalize_username, verify_password, LocalAccount, LOCAL_PROVIDER};
+use crate::models::user::User;
+
+#[derive(Clone, Debug, Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PasswordChange {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PasswordReset {
+    token: String,
+    password: String,
+}
+
+enum LoginResult {
+    User(String),
+    Invalid,
+    // seconds until they can try again
+    Locked(i64),
+}
+
+// Unknown usernames still pay for a hash, so timing doesn't tell which exist
+fn dummy_hash() -> &'static str {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    DUMMY.get_or_init(|| hash_password("not anyone's password").unwrap_or_default())
+}
+
+// The username of the local account signed in as this user, if any
+async fn local_username(data: &AppState, user_id: &str) -> Result<Option<String>, Box<dyn Error>> {
+    Ok(data.identity_store.get_identities(user_id).await?
+        .into_iter()
+        .find(|linked| linked.identity.provider == LOCAL_PROVIDER)
+        .map(|linked| linked.identity.subject))
+}
+
+// Checks the password and counts failures towards the lockout
+async fn check_credentials(data: &AppState, username: &str, network: &str, password: &str) -> Result<LoginResult, Box<dyn Error>> {
+    let store = &data.local_account_store;
+    let Some(account) = store.get_account(username).await? else {
+        verify_password(password, dummy_hash());
+        return Ok(LoginResult::Invalid);
+    };
+    if let Some(locked_until) = store.locked_until(username, network).await? {
+        return Ok(LoginResult::Locked((locked_until - Local::now()).num_seconds().max(1)));
+    }
+    if !verify_password(password, &account.password_hash) {
+        let attempts = store.record_failure(username, network).await?;
+        warn!("[WARN]: Failed login for local account {} from {} ({} in a row)", username, network, attempts.failures);
+        return Ok(LoginResult::Invalid);
+    }
+    store.clear_attempts(username, network).await?;
+    match data.identity_store.user_for(LOCAL_PROVIDER, username).await? {
+        None => Ok(LoginResult::Invalid),
+        Some(user_id) => Ok(LoginResult::User(user_id)),
+    }
+}
+
+async fn sign_in(data: &AppState, req: &HttpRequest, session: &Session, user_id: &str) -> HttpResponse {
+    match set_current_user(data, req, session, user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to sign in {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            info!("[SUCCESS]: {} signed in with a password", user_id);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+// Whether to show the username/password forms
+#[get("/local-accounts/enabled")]
+async fn local_accounts_enabled(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(data.env_config.local_accounts)
+}
+
+// Signed out this makes a new user, signed in it adds a password login to
+// the current one
+#[post("/local-accounts/register")]
+async fn register(req: HttpRequest, session: Session, credentials: web::Json<Credentials>, data: web::Data<AppState>) -> impl Responder {
+    if !data.env_config.local_accounts {
+        return HttpResponse::NotFound().finish();
+    }
+    let Some(username) = normalize_username(&credentials.username) else {
+        return HttpResponse::BadRequest().body("Usernames are 3 to 32 letters, numbers, _ or -");
+    };
+    if let Err(e) = check_password(&credentials.password) {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    let signed_in = current_user_id(&req);
+    if let Some(user_id) = &signed_in {
+        match local_username(&data, user_id).await {
+            Err(e) => {
+                error!("[ERROR]: Unable to load identities for {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().finish();
+            },
+            Ok(Some(_)) => return HttpResponse::Conflict().body("You already have a password login"),
+            Ok(None) => {},
+        }
+    }
+    // new users go through admission like any other sign-up
+    if signed_in.is_none() {
+        match data.local_account_store.get_account(&username).await {
+            Err(e) => {
+                error!("[ERROR]: Unable to load local account {}: {}", username, e);
+                return HttpResponse::InternalServerError().finish();
+            },
+            Ok(Some(_)) => return HttpResponse::Conflict().body("That username is taken"),
+            Ok(None) => {},
+        }
+        match admit_applicant(&data, &applicant_id(LOCAL_PROVIDER, &username), &username).await {
+            Err(e) => {
+                error!("[ERROR]: Unable to check admission for {}: {}", username, e);
+                return HttpResponse::InternalServerError().finish();
+            },
+            Ok(Admission::Waitlisted(reason)) => {
+                info!("[INFO]: Waitlisted local account {}: {}", username, reason);
+                return HttpResponse::Forbidden().body(format!("You're on the waitlist: {}. Register again once an admin lets you in.", reason));
+            },
+            Ok(Admission::Admitted) => {},
+        }
+    }
+    let password_hash = match hash_password(&credentials.password) {
+        Err(e) => {
+            error!("[ERROR]: Unable to hash password: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(password_hash) => password_hash,
+    };
+    let now = Local::now();
+    let account = LocalAccount {
+        username: username.clone(),
+        password_hash,
+        created_at: now,
+        password_changed_at: now,
+    };
+    match data.local_account_store.create_account(&account).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to create local account {}: {}", username, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(false) => return HttpResponse::Conflict().body("That username is taken"),
+        Ok(true) => {},
+    }
+    let user_id = match &signed_in {
+        Some(user_id) => user_id.clone(),
+        None => {
+            let user = User::new(&username);
+            if let Err(e) = data.user_store.save_user(&user).await {
+                error!("[ERROR]: Unable to create user for {}: {}", username, e);
+                return HttpResponse::InternalServerError().finish();
+            }
+            user.id
+        },
+    };
+    let identity = ExternalIdentity {
+        provider: LOCAL_PROVIDER.to_owned(),
+        subject: username.clone(),
+        display_name: username.clone(),
+        email: None,
+    };
+    if let Err(e) = data.identity_store.link(&user_id, &identity).await {
+        error!("[ERROR]: Unable to link local account {} to {}: {}", username, user_id, e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    info!("[INFO]: Registered local account {} for {}", username, user_id);
+    match signed_in {
+        Some(_) => HttpResponse::NoContent().finish(),
+        None => sign_in(&data, &req, &session, &user_id).await,
+    }
+}
+
+#[post("/local-accounts/login")]
+async fn login(req: HttpRequest, session: Session, credentials: web::Json<Credentials>, data: web::Data<AppState>) -> impl Responder {
+    if !data.env_config.local_accounts {
+        return HttpResponse::NotFound().finish();
+    }
+    let Some(username) = normalize_username(&credentials.username) else {
+        return HttpResponse::Unauthorized().body("Wrong username or password");
+    };
+    match check_credentials(&data, &username, &client_network(&req), &credentials.password).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to check login for {}: {}", username, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(LoginResult::Invalid) => HttpResponse::Unauthorized().body("Wrong username or password"),
+        Ok(LoginResult::Locked(seconds)) => HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", seconds.to_string()))
+            .body("Too many failed logins, try again later"),
+        Ok(LoginResult::User(user_id)) => sign_in(&data, &req, &session, &user_id).await,
+    }
+}
+
+#[put("/local-accounts/password")]
+async fn change_password(req: HttpRequest, session: Session, change: web::Json<PasswordChange>, data: web::Data<AppState>) -> impl Responder {
+    if !data.env_config.local_accounts {
+        return HttpResponse::NotFound().finish();
+    }
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let username = match local_username(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load identities for {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Ok(Some(username)) => username,
+    };
+    if let Err(e) = check_password(&change.new_password) {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    // the current password goes through the same lockout as logging in
+    match check_credentials(&data, &username, &client_network(&req), &change.current_password).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to check password for {}: {}", username, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(LoginResult::Invalid) => return HttpResponse::Forbidden().body("Current password is wrong"),
+        Ok(LoginResult::Locked(seconds)) => return HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", seconds.to_string()))
+            .body("Too many failed attempts, try again later"),
+        Ok(LoginResult::User(_)) => {},
+    }
+    // whoever had the old password is signed out everywhere else
+    let current_id = current_session_id(&session);
+    match set_password(&data, &username, &change.new_password, current_id.as_deref()).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to change password for {}: {}", username, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            info!("[INFO]: Changed password for local account {}", username);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+// The new password, and every session of the user but keep signed out
+async fn set_password(data: &AppState, username: &str, password: &str, keep: Option<&str>) -> Result<bool, Box<dyn Error>> {
+    let Some(mut account) = data.local_account_store.get_account(username).await? else {
+        return Ok(false);
+    };
+    account.password_hash = hash_password(password)?;
+    account.password_changed_at = Local::now();
+    data.local_account_store.save_account(&account).await?;
+    data.local_account_store.clear_all_attempts(username).await?;
+    if let Some(user_id) = data.identity_store.user_for(LOCAL_PROVIDER, username).await? {
+        let ended = end_other_sessions(data, &user_id, keep).await?;
+        info!("[INFO]: Signed out {} sessions of {} after a password change", ended, user_id);
+    }
+    Ok(true)
+}
+
+// With a token from an admin, sign in normally afterwards
+#[post("/local-accounts/reset")]
+async fn reset_password(reset: web::Json<PasswordReset>, data: web::Data<AppState>) -> impl Responder {
+    if !data.env_config.local_accounts {
+        return HttpResponse::NotFound().finish();
+    }
+    if let Err(e) = check_password(&reset.password) {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    let username = match data.local_account_store.take_reset_token(reset.token.trim()).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to check reset token: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(None) => return HttpResponse::BadRequest().body("That reset code is wrong or has expired"),
+        Ok(Some(token)) => token.username,
+    };
+    match set_password(&data, &username, &reset.password, None).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to reset password for {}: {}", username, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::BadRequest().body("That account no longer exists"),
+        Ok(true) => {
+            info!("[INFO]: Reset password for local account {}", username);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+// There's no email to send it to, the admin passes the token on
+#[post("/admin/local-accounts/{username}/reset-token")]
+async fn issue_reset_token(req: HttpRequest, path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if !is_admin(&data, &user_id).await {
+        return HttpResponse::Forbidden().finish();
+    }
+    let Some(username) = normalize_username(&path) else {
+        return HttpResponse::NotFound().finish();
+    };
+    match data.local_account_store.get_account(&username).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load local account {}: {}", username, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Ok(Some(_)) => {},
+    }
+    match data.local_account_store.issue_reset_token(&username).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to issue reset token for {}: {}", username, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(issued) => {
+            info!("[INFO]: {} issued a reset token for {}", user_id, username);
+            HttpResponse::Ok().json(issued)
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(local_accounts_enabled)
+        .service(register)
+        .service(login)
+        .service(change_password)
+        .service(reset_password)
+        .service(issue_reset_token);
+}
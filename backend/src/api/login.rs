@@ -0,0 +1,254 @@
+use actix_session::Session;
+use actix_web::{get, web, http::header, HttpRequest, HttpResponse, Responder};
+use chrono::{Duration, Local};
+use log::{info, error};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::api::{current_user_id, set_current_user};
+use crate::models::UserSession;
+use crate::models::admission::{applicant_id, Admission, WaitlistEntry};
+use crate::models::identity::{PendingLogin, ProviderLogin};
+use crate::models::reddit::SUBMIT_SCOPE;
+
+// where the state, nonce and PKCE verifier wait for the callback
+const PENDING_LOGIN_KEY: &str = "pending_login";
+
+#[derive(Clone, Debug, Deserialize)]
+struct LoginQuery {
+    // add this provider to the signed in account instead of signing in
+    #[serde(default)]
+    link: bool,
+    // link reddit again, this time allowed to post, and turn on sharing
+    #[serde(default)]
+    sharing: bool,
+}
+
+enum SignIn {
+    User(String),
+    Waitlisted(String),
+    // linking an identity that already signs in to someone else
+    AlreadyLinked,
+}
+
+// Admins, and anyone let in before (even under older rules), skip the rules.
+// Everyone else is checked once and either admitted or waitlisted. Admission
+// is about the reddit account, so it's kept under the reddit id.
+pub async fn admit_user(data: &AppState, user_id: &str, user_session: &UserSession) -> Result<Admission, Box<dyn Error>> {
+    let user = &user_session.reddit_user;
+    let known = data.admission_store.is_admitted(&user.id).await? || data.session_store.get_session(user_id).await?.is_some();
+    let admission = if known || data.env_config.is_admin(&user.name) {
+        Admission::Admitted
+    } else {
+        data.env_config.admission_rules.check(&data.env_config.reddit_api(), &user_session.reddit_access_token, user).await?
+    };
+    record_admission(data, &user.id, &user.name, &admission).await?;
+    Ok(admission)
+}
+
+// New accounts from any other sign-in. The rules only know about reddit
+// accounts, so unless they're open these wait for an admin to let them in.
+pub async fn admit_applicant(data: &AppState, applicant_id: &str, name: &str) -> Result<Admission, Box<dyn Error>> {
+    let admission = if data.env_config.admission_rules.is_open() || data.admission_store.is_admitted(applicant_id).await? {
+        Admission::Admitted
+    } else {
+        Admission::Waitlisted("New accounts need a Reddit login that meets the rules, or an admin to let them in".to_owned())
+    };
+    record_admission(data, applicant_id, name, &admission).await?;
+    Ok(admission)
+}
+
+async fn record_admission(data: &AppState, applicant_id: &str, name: &str, admission: &Admission) -> Result<(), Box<dyn Error>> {
+    match admission {
+        Admission::Admitted => data.admission_store.admit(applicant_id).await,
+        Admission::Waitlisted(reason) => {
+            let requested_at = match data.admission_store.get_waitlist_entry(applicant_id).await? {
+                Some(entry) => entry.requested_at,
+                None => Local::now(),
+            };
+            data.admission_store.add_to_waitlist(&WaitlistEntry {
+                user_id: applicant_id.to_owned(),
+                name: name.to_owned(),
+                reason: reason.clone(),
+                requested_at,
+            }).await
+        },
+    }
+}
+
+// Finds (or makes) the user an identity signs in as. Reddit logins go
+// through admission and keep their tokens for the reddit features.
+async fn sign_in(data: &AppState, pending: &PendingLogin, login: &ProviderLogin) -> Result<SignIn, Box<dyn Error>> {
+    let identity = &login.identity;
+    let owner = data.identity_store.user_for(&identity.provider, &identity.subject).await?;
+    let first_sign_in = pending.link_to.is_none() && owner.is_none();
+    let user_id = match (&pending.link_to, owner) {
+        (Some(link_to), Some(owner)) if &owner != link_to => return Ok(SignIn::AlreadyLinked),
+        (Some(link_to), _) => link_to.clone(),
+        (None, Some(owner)) => owner,
+        (None, None) => Uuid::new_v4().to_string(),
+    };
+    // reddit ones are checked below, every time
+    if first_sign_in && login.reddit_user.is_none() {
+        let applicant = applicant_id(&identity.provider, &identity.subject);
+        if let Admission::Waitlisted(reason) = admit_applicant(data, &applicant, &identity.display_name).await? {
+            return Ok(SignIn::Waitlisted(reason));
+        }
+    }
+    if let Some(reddit_user) = &login.reddit_user {
+        let user_session = UserSession {
+            reddit_user: reddit_user.clone(),
+            reddit_access_token: login.access_token.clone(),
+            reddit_refresh_token: login.refresh_token.clone(),
+            expires_at: Local::now() + Duration::seconds(login.expires_in as i64),
+            can_submit: login.scopes.iter().any(|scope| scope == SUBMIT_SCOPE),
+        };
+        if pending.link_to.is_none() {
+            if let Admission::Waitlisted(reason) = admit_user(data, &user_id, &user_session).await? {
+                return Ok(SignIn::Waitlisted(reason));
+            }
+        }
+        data.session_store.save_session(&user_id, &user_session).await?;
+    }
+    let user = data.user_store.ensure_user(&user_id, &identity.display_name).await?;
+    if login.reddit_user.as_ref().is_some_and(|reddit_user| data.env_config.is_admin(&reddit_user.name)) && !user.admin {
+        data.user_store.set_admin(&user_id, true).await?;
+    }
+    data.identity_store.link(&user_id, identity).await?;
+    Ok(SignIn::User(user_id))
+}
+
+// The end of the sharing link, reddit can still leave out the submit scope
+// if the user unticked it
+async fn enable_sharing(data: &AppState, user_id: &str, login: &ProviderLogin) -> Result<(), Box<dyn Error>> {
+    if !login.scopes.iter().any(|scope| scope == SUBMIT_SCOPE) {
+        info!("[INFO]: {} didn't let us post to reddit", user_id);
+        return Ok(());
+    }
+    let mut settings = data.share_store.get_settings(user_id).await?;
+    settings.enabled = true;
+    data.share_store.save_settings(user_id, &settings).await
+}
+
+// Where people the admission rules turned away end up
+#[get("/login/waitlist")]
+async fn login_waitlist(data: web::Data<AppState>) -> impl Responder {
+    let rules: String = data.env_config.admission_rules.describe().iter()
+        .map(|rule| format!("<li>{}</li>", rule))
+        .collect();
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(format!(
+            "<!doctype html><html><head><title>AB macros</title></head><body>\
+             <h1>You're on the waitlist</h1>\
+             <p>To keep the tracker for the community, new sign-ups need one of:</p><ul>{}</ul>\
+             <p>Your request has been noted and an admin can let you in. Otherwise try again once you qualify.</p>\
+             <p><a href=\"/\">Back</a></p></body></html>",
+            rules,
+        ))
+}
+
+// Sends the user off to the provider
+#[get("/login/{provider}")]
+async fn login(req: HttpRequest, path: web::Path<String>, query: web::Query<LoginQuery>, session: Session, data: web::Data<AppState>) -> impl Responder {
+    let Some(provider) = data.identity_providers.get(&path) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if query.sharing && provider.id() != "reddit" {
+        return HttpResponse::BadRequest().body("Summaries are only shared to Reddit");
+    }
+    let link_to = match (query.link || query.sharing, current_user_id(&req)) {
+        (false, _) => None,
+        (true, None) => return HttpResponse::Unauthorized().finish(),
+        (true, user_id) => user_id,
+    };
+    let pending = PendingLogin::new(provider.id(), link_to, query.sharing);
+    let auth_url = match provider.authorization_url(&pending).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to start {} login: {}", provider.id(), e);
+            return HttpResponse::BadGateway().finish();
+        },
+        Ok(auth_url) => auth_url,
+    };
+    if let Err(e) = session.insert(PENDING_LOGIN_KEY, &pending) {
+        error!("[ERROR]: Unable to set session cookie: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    info!("[AUTH URL]: {}", auth_url);
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, auth_url))
+        .finish()
+}
+
+#[get("/login/{provider}/callback")]
+async fn login_callback(req: HttpRequest, path: web::Path<String>, query: web::Query<HashMap<String, String>>, session: Session, data: web::Data<AppState>) -> impl Responder {
+    let Some(provider) = data.identity_providers.get(&path) else {
+        return HttpResponse::NotFound().finish();
+    };
+    // one use only, whatever happens next
+    let Some(Ok(pending)) = session.remove_as::<PendingLogin>(PENDING_LOGIN_KEY) else {
+        return HttpResponse::BadRequest().body("Login expired, try again");
+    };
+    if pending.provider != provider.id() || query.get("state") != Some(&pending.state) {
+        return HttpResponse::BadRequest().body("Login state doesn't match, try again");
+    }
+    let Some(code) = query.get("code") else {
+        return HttpResponse::BadRequest().body(format!("{} login was cancelled", provider.name()));
+    };
+    let login = match provider.complete_login(code, &pending).await {
+        Err(e) => {
+            error!("[ERROR]: {} login failed: {}", provider.id(), e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(login) => login,
+    };
+    match sign_in(&data, &pending, &login).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to sign in {}: {}", login.identity.display_name, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(SignIn::Waitlisted(reason)) => {
+            info!("[INFO]: Waitlisted {}: {}", login.identity.display_name, reason);
+            HttpResponse::Found()
+                .insert_header((header::LOCATION, "/login/waitlist"))
+                .finish()
+        },
+        Ok(SignIn::AlreadyLinked) => HttpResponse::Conflict().body(format!("That {} login already belongs to another account", provider.name())),
+        // already signed in as them, the cookie stays as it is
+        Ok(SignIn::User(user_id)) if pending.link_to.is_some() => {
+            info!("[SUCCESS]: {} linked {}", user_id, provider.id());
+            if pending.sharing {
+                if let Err(e) = enable_sharing(&data, &user_id, &login).await {
+                    error!("[ERROR]: Unable to turn on sharing for {}: {}", user_id, e);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
+            HttpResponse::Found()
+                .insert_header((header::LOCATION, "/"))
+                .finish()
+        },
+        Ok(SignIn::User(user_id)) => match set_current_user(&data, &req, &session, &user_id).await {
+            Err(e) => {
+                error!("[ERROR]: Unable to sign in {}: {}", user_id, e);
+                HttpResponse::InternalServerError().finish()
+            },
+            Ok(_) => {
+                info!("[SUCCESS]: {} signed in with {}", user_id, provider.id());
+                HttpResponse::Found()
+                    .insert_header((header::LOCATION, "/"))
+                    .finish()
+            }
+        },
+    }
+}
+
+// Outside /api, the provider redirect URIs point here. The waitlist goes
+// first so it isn't taken for a provider.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(login_waitlist)
+        .service(login)
+        .service(login_callback);
+}
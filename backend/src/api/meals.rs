@@ -0,0 +1,155 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Local, NaiveDate};
+use log::{info, error};
+use serde::Deserialize;
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::api::{current_user_id, foods::search_index_for};
+use crate::models::{Food, MealEntry};
+use crate::models::cost::price_per_gram_at;
+use crate::models::sourcing::{check_sourcing, Sourcing};
+use crate::models::meal::day_bounds;
+use crate::models::parser::parse_meal;
+
+#[derive(Clone, Debug, Deserialize)]
+struct MealQuery {
+    date: Option<NaiveDate>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct NewMealEntry {
+    food_id: String,
+    grams: f64,
+    // falls back to the food's default sourcing
+    sourcing: Option<Vec<Sourcing>>,
+    eaten_at: Option<DateTime<Local>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct MealText {
+    text: String,
+}
+
+#[get("/meals")]
+async fn list_meals(req: HttpRequest, query: web::Query<MealQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let date = query.date.unwrap_or_else(|| Local::now().date_naive());
+    let Some((from, to)) = day_bounds(date) else {
+        return HttpResponse::BadRequest().body("Invalid date");
+    };
+    match data.meal_store.get_entries_between(&user_id, &from, &to).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load meals: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(entries) => HttpResponse::Ok().json(entries),
+    }
+}
+
+// Prices and pantry stock are best effort, a meal still gets logged without them
+pub async fn log_meal(data: &AppState, user_id: &str, food: &Food, grams: f64, sourcing: Vec<Sourcing>, eaten_at: DateTime<Local>) -> Result<MealEntry, Box<dyn Error>> {
+    let mut entry = MealEntry::from_food(food, grams, sourcing, eaten_at);
+    match data.purchase_store.get_purchases(user_id).await {
+        Err(e) => error!("[ERROR]: Unable to load purchases, meal will have no cost: {}", e),
+        Ok(purchases) => {
+            entry.cost = price_per_gram_at(&purchases, &food.id, &entry.eaten_at).map(|per_gram| per_gram * entry.grams);
+        }
+    }
+    // saved before touching the pantry, stock only comes out for a meal
+    // that's actually logged
+    data.meal_store.save_entry(user_id, &entry).await?;
+    data.search_cache.invalidate(user_id);
+    info!("[INFO]: Meal entry {} saved for {}", entry.id, user_id);
+    match data.pantry_store.consume(user_id, &food.id, entry.grams).await {
+        Err(e) => error!("[ERROR]: Unable to take meal out of the pantry: {}", e),
+        Ok(grams) if grams > 0.0 => {
+            entry.pantry_grams = grams;
+            if let Err(e) = data.meal_store.save_entry(user_id, &entry).await {
+                error!("[ERROR]: Unable to record pantry use on {}, putting it back: {}", entry.id, e);
+                entry.pantry_grams = 0.0;
+                data.pantry_store.restore(user_id, &food.id, grams).await?;
+            }
+        },
+        Ok(_) => {},
+    }
+    Ok(entry)
+}
+
+#[post("/meals")]
+async fn add_meal(req: HttpRequest, body: web::Json<NewMealEntry>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if !(body.grams > 0.0) {
+        return HttpResponse::BadRequest().body("Amount must be more than 0g");
+    }
+    let food = match data.food_store.get_food(&user_id, &body.food_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load food {}: {}", body.food_id, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(None) => return HttpResponse::BadRequest().body("Unknown food"),
+        Ok(Some(food)) => food,
+    };
+    let sourcing = body.sourcing.clone().unwrap_or_else(|| food.default_sourcing.clone());
+    if let Err(reason) = check_sourcing(&sourcing, food.category) {
+        return HttpResponse::BadRequest().body(format!("{} for {}", reason, food.name));
+    }
+    match log_meal(&data, &user_id, &food, body.grams, sourcing, body.eaten_at.unwrap_or_else(Local::now)).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save meal entry: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(entry) => HttpResponse::Created().json(entry),
+    }
+}
+
+// Reads "300g ribeye, 3 eggs and a tbsp of honey" into foods and grams for
+// the user to check, nothing is logged until they post each one to /meals
+#[post("/meals/parse")]
+async fn parse_meal_text(req: HttpRequest, body: web::Json<MealText>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match search_index_for(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load foods to parse meal: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok((index, uses)) => HttpResponse::Ok().json(parse_meal(&body.text, &index, &uses)),
+    }
+}
+
+#[delete("/meals/{entry_id}")]
+async fn delete_meal(req: HttpRequest, path: web::Path<Uuid>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.meal_store.delete_entry(&user_id, &path).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to delete meal entry: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Ok(Some(entry)) => {
+            data.search_cache.invalidate(&user_id);
+            if entry.pantry_grams > 0.0 {
+                if let Err(e) = data.pantry_store.restore(&user_id, &entry.food_id, entry.pantry_grams).await {
+                    error!("[ERROR]: Unable to put meal back in the pantry: {}", e);
+                }
+            }
+            HttpResponse::NoContent().finish()
+        },
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_meals)
+        .service(parse_meal_text)
+        .service(add_meal)
+        .service(delete_meal);
+}
@@ -0,0 +1,96 @@
+pub mod account;
+pub mod api_tokens;
+pub mod admin;
+pub mod body;
+pub mod export;
+pub mod foods;
+pub mod identities;
+pub mod import;
+pub mod jobs;
+pub mod local_accounts;
+pub mod login;
+pub mod meals;
+pub mod pantry;
+pub mod passkeys;
+pub mod plans;
+pub mod profile;
+pub mod purchases;
+pub mod reports;
+pub mod sessions;
+pub mod sharing;
+pub mod summary;
+pub mod targets;
+pub mod tdee;
+pub mod users;
+
+use actix_session::{Session, SessionExt};
+use actix_web::{web, HttpRequest};
+use log::error;
+use std::error::Error;
+
+use crate::AppState;
+use crate::api::sessions::start_device_session;
+
+// The login callback stores the user id in the (encrypted) session cookie
+pub const SESSION_USER_KEY: &str = "user_id";
+// and which device session the cookie is, checked by sessions::session_middleware.
+pub const SESSION_ID_KEY: &str = "session_id";
+
+// Requests with an API token carry the user in their extensions instead, put
+// there by the session middleware, and never touch the cookie
+#[derive(Clone, Debug)]
+pub struct TokenUser(pub String);
+
+// A fresh cookie for the user and the device session it belongs to, every
+// sign-in goes through here
+pub async fn set_current_user(data: &AppState, req: &HttpRequest, session: &Session, user_id: &str) -> Result<(), Box<dyn Error>> {
+    let device = start_device_session(data, req, user_id).await?;
+    session.renew();
+    session.insert(SESSION_USER_KEY, user_id)?;
+    session.insert(SESSION_ID_KEY, &device.id)?;
+    Ok(())
+}
+
+// Who the request is from, by API token or by cookie
+pub fn current_user_id(req: &HttpRequest) -> Option<String> {
+    if let Some(TokenUser(user_id)) = req.extensions().get::<TokenUser>() {
+        return Some(user_id.clone());
+    }
+    session_user_id(&req.get_session())
+}
+
+pub fn session_user_id(session: &Session) -> Option<String> {
+    match session.get::<String>(SESSION_USER_KEY) {
+        Err(e) => {
+            error!("[ERROR]: Unable to read session cookie: {}", e);
+            None
+        },
+        Ok(user_id) => user_id,
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.configure(account::configure)
+        .configure(api_tokens::configure)
+        .configure(admin::configure)
+        .configure(body::configure)
+        .configure(export::configure)
+        .configure(foods::configure)
+        .configure(identities::configure)
+        .configure(import::configure)
+        .configure(jobs::configure)
+        .configure(local_accounts::configure)
+        .configure(meals::configure)
+        .configure(pantry::configure)
+        .configure(passkeys::configure)
+        .configure(plans::configure)
+        .configure(profile::configure)
+        .configure(purchases::configure)
+        .configure(reports::configure)
+        .configure(sessions::configure)
+        .configure(sharing::configure)
+        .configure(summary::configure)
+        .configure(targets::configure)
+        .configure(tdee::configure)
+        .configure(users::configure);
+}
@@ -0,0 +1,194 @@
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Local};
+use log::{info, error};
+use serde::Deserialize;
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::models::PantryItem;
+use crate::models::cost::PurchaseUnit;
+use crate::models::meal::day_bounds;
+use crate::models::pantry::{shopping_list, stock_levels, ShoppingItem, StockLevel, LOW_STOCK_DAYS, USAGE_WINDOW_DAYS};
+
+#[derive(Clone, Debug, Deserialize)]
+struct NewPantryItem {
+    food_id: String,
+    quantity: f64,
+    unit: PurchaseUnit,
+    grams_per_unit: Option<f64>,
+    location: Option<String>,
+    stocked_at: Option<DateTime<Local>>,
+}
+
+// After a stocktake
+#[derive(Clone, Debug, Deserialize)]
+struct PantryUpdate {
+    grams_remaining: f64,
+    location: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct StatusQuery {
+    low_days: Option<f64>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ShoppingQuery {
+    // how many days the shop should last
+    days: Option<f64>,
+}
+
+// Stocks a lot of a food, shared with purchases that go straight into the pantry
+pub async fn stock_food(data: &AppState, user_id: &str, food_id: &str, grams: f64, location: Option<String>, stocked_at: DateTime<Local>) -> Result<Option<PantryItem>, Box<dyn Error>> {
+    let Some(food) = data.food_store.get_food(user_id, food_id).await? else {
+        return Ok(None);
+    };
+    let item = PantryItem {
+        id: Uuid::new_v4(),
+        food_id: food.id,
+        food_name: food.name,
+        grams_stocked: grams,
+        grams_remaining: grams,
+        location: location.filter(|location| !location.trim().is_empty()),
+        stocked_at,
+    };
+    data.pantry_store.stock(user_id, &item).await?;
+    info!("[INFO]: Pantry item {} stocked for {}", item.id, user_id);
+    Ok(Some(item))
+}
+
+async fn stock_levels_for(data: &AppState, user_id: &str, low_days: f64) -> Result<Vec<StockLevel>, Box<dyn Error>> {
+    let now = Local::now();
+    let today = now.date_naive();
+    let (from, _) = day_bounds(today - Duration::days(USAGE_WINDOW_DAYS - 1)).ok_or("invalid window start")?;
+    let entries = data.meal_store.get_entries_between(user_id, &from, &now).await?;
+    let items = data.pantry_store.get_items(user_id).await?;
+    Ok(stock_levels(&items, &entries, &now, low_days))
+}
+
+async fn shopping_list_for(data: &AppState, user_id: &str, days: f64) -> Result<Vec<ShoppingItem>, Box<dyn Error>> {
+    let levels = stock_levels_for(data, user_id, LOW_STOCK_DAYS).await?;
+    let purchases = data.purchase_store.get_purchases(user_id).await?;
+    Ok(shopping_list(&levels, &purchases, &Local::now(), days))
+}
+
+#[get("/pantry")]
+async fn list_pantry(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.pantry_store.get_items(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load pantry: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(items) => HttpResponse::Ok().json(items),
+    }
+}
+
+#[post("/pantry")]
+async fn add_pantry_item(req: HttpRequest, body: web::Json<NewPantryItem>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let body = body.into_inner();
+    let Some(grams) = body.unit.to_grams(body.quantity, body.grams_per_unit) else {
+        return HttpResponse::BadRequest().body("Pantry items need an amount in grams (set grams_per_unit for units)");
+    };
+    match stock_food(&data, &user_id, &body.food_id, grams, body.location, body.stocked_at.unwrap_or_else(Local::now)).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to stock pantry: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(None) => HttpResponse::BadRequest().body("Unknown food"),
+        Ok(Some(item)) => HttpResponse::Created().json(item),
+    }
+}
+
+#[put("/pantry/{item_id}")]
+async fn update_pantry_item(req: HttpRequest, path: web::Path<Uuid>, body: web::Json<PantryUpdate>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if !body.grams_remaining.is_finite() || body.grams_remaining < 0.0 {
+        return HttpResponse::BadRequest().body("Remaining amount can't be negative");
+    }
+    let mut item = match data.pantry_store.get_item(&user_id, &path).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load pantry item: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Ok(Some(item)) => item,
+    };
+    let body = body.into_inner();
+    item.grams_remaining = body.grams_remaining;
+    item.grams_stocked = item.grams_stocked.max(body.grams_remaining);
+    item.location = body.location.filter(|location| !location.trim().is_empty());
+    match data.pantry_store.save_item(&user_id, &item).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save pantry item: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => HttpResponse::Ok().json(item),
+    }
+}
+
+#[delete("/pantry/{item_id}")]
+async fn delete_pantry_item(req: HttpRequest, path: web::Path<Uuid>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.pantry_store.delete_item(&user_id, &path).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to delete pantry item: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Ok(true) => HttpResponse::NoContent().finish(),
+    }
+}
+
+// Stock on hand and days of supply per food, whatever runs out first on top
+#[get("/pantry/status")]
+async fn pantry_status(req: HttpRequest, query: web::Query<StatusQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match stock_levels_for(&data, &user_id, query.low_days.unwrap_or(LOW_STOCK_DAYS)).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to work out stock levels: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(levels) => HttpResponse::Ok().json(levels),
+    }
+}
+
+#[get("/pantry/shopping-list")]
+async fn pantry_shopping_list(req: HttpRequest, query: web::Query<ShoppingQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let days = query.days.unwrap_or(7.0);
+    if !(days > 0.0) {
+        return HttpResponse::BadRequest().body("Days must be more than 0");
+    }
+    match shopping_list_for(&data, &user_id, days).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to build shopping list: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(list) => HttpResponse::Ok().json(list),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(pantry_status)
+        .service(pantry_shopping_list)
+        .service(list_pantry)
+        .service(add_pantry_item)
+        .service(update_pantry_item)
+        .service(delete_pantry_item);
+}
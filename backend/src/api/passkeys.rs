@@ -0,0 +1,299 @@
+use actix_session::Session;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Local;
+use log::{info, warn, error};
+use serde::Deserialize;
+use std::error::Error;
+use uuid::Uuid;
+use webauthn_rs::prelude::{DiscoverableKey, PublicKeyCredential, RegisterPublicKeyCredential};
+
+use crate::AppState;
+use crate::api::{current_user_id, set_current_user};
+use crate::api::login::admit_applicant;
+use crate::api::sessions::client_network;
+use crate::models::admission::{applicant_id, Admission};
+use crate::models::identity::{ExternalIdentity, LoginError};
+use crate::models::passkey::{credential_id, PasskeyCeremony, StoredPasskey, PASSKEY_PROVIDER};
+
+// the id of the ceremony in progress, the state itself is in the passkey store
+const PASSKEY_CHALLENGE_KEY: &str = "passkey_challenge";
+// the user id a signed out browser signs up as, kept so an admin can let it
+// in from the waitlist and a retry finds it admitted
+const PASSKEY_SIGNUP_KEY: &str = "passkey_signup";
+
+#[derive(Clone, Debug, Deserialize)]
+struct RegistrationStart {
+    // what to call the passkey, and the display name when signing up
+    name: String,
+}
+
+enum PasskeyLogin {
+    User(String),
+    Invalid,
+}
+
+fn take_challenge_id(session: &Session) -> Option<String> {
+    match session.remove_as::<String>(PASSKEY_CHALLENGE_KEY) {
+        Some(Ok(id)) => Some(id),
+        _ => None,
+    }
+}
+
+// Checks the assertion against the user's passkeys and the signature
+// counter, then stores the new counter
+async fn authenticate(data: &AppState, credential: &PublicKeyCredential, ceremony: PasskeyCeremony) -> Result<PasskeyLogin, Box<dyn Error>> {
+    let Some(webauthn) = &data.webauthn else {
+        return Ok(PasskeyLogin::Invalid);
+    };
+    let PasskeyCeremony::Authentication { state } = ceremony else {
+        return Ok(PasskeyLogin::Invalid);
+    };
+    let (_, raw_id) = webauthn.identify_discoverable_authentication(credential)?;
+    let id = credential_id(raw_id);
+    let Some(user_id) = data.passkey_store.user_for(&id).await? else {
+        return Ok(PasskeyLogin::Invalid);
+    };
+    let mut passkeys = data.passkey_store.get_passkeys(&user_id).await?;
+    let keys: Vec<DiscoverableKey> = passkeys.iter().map(|stored| DiscoverableKey::from(&stored.passkey)).collect();
+    let result = webauthn.finish_discoverable_authentication(credential, state, &keys)?;
+    let Some(stored) = passkeys.iter_mut().find(|stored| stored.id == credential_id(result.cred_id())) else {
+        return Ok(PasskeyLogin::Invalid);
+    };
+    if !stored.counter_ok(result.counter()) {
+        warn!("[WARN]: Passkey {} for {} went from counter {} to {}, possibly cloned", stored.id, user_id, stored.counter, result.counter());
+        return Ok(PasskeyLogin::Invalid);
+    }
+    stored.passkey.update_credential(&result);
+    stored.counter = result.counter();
+    stored.last_used_at = Some(Local::now());
+    data.passkey_store.save_passkey(&user_id, stored).await?;
+    // unlinked passkeys are deleted, but the identity is what signs in
+    match data.identity_store.user_for(PASSKEY_PROVIDER, &stored.id).await? {
+        Some(owner) if owner == user_id => Ok(PasskeyLogin::User(user_id)),
+        _ => Ok(PasskeyLogin::Invalid),
+    }
+}
+
+enum Registered {
+    // the user, and whether they're new
+    User(String, bool),
+    Waitlisted(String),
+}
+
+async fn register(data: &AppState, credential: &RegisterPublicKeyCredential, ceremony: PasskeyCeremony) -> Result<Registered, Box<dyn Error>> {
+    let Some(webauthn) = &data.webauthn else {
+        return Err(Box::new(LoginError("Passkeys aren't enabled".to_owned())));
+    };
+    let PasskeyCeremony::Registration { user_id, name, new_user, state } = ceremony else {
+        return Err(Box::new(LoginError("Not a passkey registration".to_owned())));
+    };
+    let passkey = webauthn.finish_passkey_registration(credential, &state)?;
+    // new users go through admission like any other sign-up, once they've
+    // shown they have an authenticator
+    if new_user {
+        if let Admission::Waitlisted(reason) = admit_applicant(data, &applicant_id(PASSKEY_PROVIDER, &user_id), &name).await? {
+            return Ok(Registered::Waitlisted(reason));
+        }
+    }
+    let stored = StoredPasskey {
+        id: credential_id(passkey.cred_id()),
+        name: name.clone(),
+        passkey,
+        counter: 0,
+        created_at: Local::now(),
+        last_used_at: None,
+    };
+    data.passkey_store.save_passkey(&user_id, &stored).await?;
+    if new_user {
+        data.user_store.ensure_user(&user_id, &name).await?;
+    }
+    data.identity_store.link(&user_id, &ExternalIdentity {
+        provider: PASSKEY_PROVIDER.to_owned(),
+        subject: stored.id.clone(),
+        display_name: name,
+        email: None,
+    }).await?;
+    Ok(Registered::User(user_id, new_user))
+}
+
+async fn sign_in(data: &AppState, req: &HttpRequest, session: &Session, user_id: &str) -> HttpResponse {
+    match set_current_user(data, req, session, user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to sign in {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            info!("[SUCCESS]: {} signed in with a passkey", user_id);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+// Whether to show the passkey buttons
+#[get("/passkeys/enabled")]
+async fn passkeys_enabled(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(data.webauthn.is_some())
+}
+
+// Signed in this adds a passkey to the user, signed out it signs up a new
+// user with only a passkey
+#[post("/passkeys/register/start")]
+async fn start_registration(req: HttpRequest, session: Session, start: web::Json<RegistrationStart>, data: web::Data<AppState>) -> impl Responder {
+    let Some(webauthn) = &data.webauthn else {
+        return HttpResponse::NotFound().finish();
+    };
+    let name = start.name.trim().to_owned();
+    if name.is_empty() || name.len() > 64 {
+        return HttpResponse::BadRequest().body("Give the passkey a name of up to 64 characters");
+    }
+    let signed_in = current_user_id(&req);
+    let new_user = signed_in.is_none();
+    // nothing is written for sign-ups until the passkey checks out in
+    // finish_registration, the challenge below (capped per network) aside
+    let (user_id, handle, existing) = match signed_in {
+        Some(user_id) => match (data.passkey_store.handle_for(&user_id).await, data.passkey_store.get_passkeys(&user_id).await) {
+            (Ok(handle), Ok(existing)) => (user_id, handle, existing),
+            (Err(e), _) | (_, Err(e)) => {
+                error!("[ERROR]: Unable to load passkeys for {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().finish();
+            },
+        },
+        // kept in the cookie, so a waitlisted sign-up can try again as the
+        // same applicant once an admin lets them in
+        None => {
+            let handle = match session.get::<String>(PASSKEY_SIGNUP_KEY) {
+                Ok(Some(user_id)) => Uuid::parse_str(&user_id).unwrap_or_else(|_| Uuid::new_v4()),
+                _ => Uuid::new_v4(),
+            };
+            if let Err(e) = session.insert(PASSKEY_SIGNUP_KEY, handle.to_string()) {
+                error!("[ERROR]: Unable to set session cookie: {}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+            (handle.to_string(), handle, Vec::new())
+        },
+    };
+    // the authenticator refuses to register the same key twice
+    let exclude = existing.iter().map(|stored| stored.passkey.cred_id().clone()).collect::<Vec<_>>();
+    let (challenge, state) = match webauthn.start_passkey_registration(handle, &name, &name, Some(exclude)) {
+        Err(e) => {
+            error!("[ERROR]: Unable to start passkey registration for {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(started) => started,
+    };
+    let ceremony = PasskeyCeremony::Registration { user_id: user_id.clone(), name, new_user, state };
+    match data.passkey_store.save_challenge(ceremony, &client_network(&req)).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save passkey challenge for {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(None) => HttpResponse::TooManyRequests().body("Too many passkey attempts at once, try again in a few minutes"),
+        Ok(Some(id)) => match session.insert(PASSKEY_CHALLENGE_KEY, id) {
+            Err(e) => {
+                error!("[ERROR]: Unable to set session cookie: {}", e);
+                HttpResponse::InternalServerError().finish()
+            },
+            Ok(_) => HttpResponse::Ok().json(challenge),
+        },
+    }
+}
+
+#[post("/passkeys/register/finish")]
+async fn finish_registration(req: HttpRequest, session: Session, credential: web::Json<RegisterPublicKeyCredential>, data: web::Data<AppState>) -> impl Responder {
+    let Some(id) = take_challenge_id(&session) else {
+        return HttpResponse::BadRequest().body("Passkey registration expired, try again");
+    };
+    let ceremony = match data.passkey_store.take_challenge(&id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load passkey challenge: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(None) => return HttpResponse::BadRequest().body("Passkey registration expired, try again"),
+        Ok(Some(ceremony)) => ceremony,
+    };
+    // adding to an account needs the same account signed in at the end
+    if let PasskeyCeremony::Registration { user_id, new_user: false, .. } = &ceremony {
+        if current_user_id(&req).as_ref() != Some(user_id) {
+            return HttpResponse::Unauthorized().finish();
+        }
+    }
+    match register(&data, &credential, ceremony).await {
+        Err(e) => {
+            warn!("[WARN]: Passkey registration failed: {}", e);
+            HttpResponse::BadRequest().body("The passkey couldn't be registered")
+        },
+        Ok(Registered::Waitlisted(reason)) => {
+            info!("[INFO]: Waitlisted a passkey sign-up: {}", reason);
+            HttpResponse::Forbidden().body(format!("You're on the waitlist: {}. Try again from this browser once an admin lets you in.", reason))
+        },
+        Ok(Registered::User(user_id, true)) => {
+            session.remove(PASSKEY_SIGNUP_KEY);
+            info!("[INFO]: Signed up {} with a passkey", user_id);
+            sign_in(&data, &req, &session, &user_id).await
+        },
+        Ok(Registered::User(user_id, false)) => {
+            info!("[INFO]: Added a passkey for {}", user_id);
+            HttpResponse::NoContent().finish()
+        },
+    }
+}
+
+// Usernameless, the authenticator picks the account
+#[post("/passkeys/login/start")]
+async fn start_login(req: HttpRequest, session: Session, data: web::Data<AppState>) -> impl Responder {
+    let Some(webauthn) = &data.webauthn else {
+        return HttpResponse::NotFound().finish();
+    };
+    let (challenge, state) = match webauthn.start_discoverable_authentication() {
+        Err(e) => {
+            error!("[ERROR]: Unable to start passkey login: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(started) => started,
+    };
+    match data.passkey_store.save_challenge(PasskeyCeremony::Authentication { state }, &client_network(&req)).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save passkey challenge: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(None) => HttpResponse::TooManyRequests().body("Too many passkey attempts at once, try again in a few minutes"),
+        Ok(Some(id)) => match session.insert(PASSKEY_CHALLENGE_KEY, id) {
+            Err(e) => {
+                error!("[ERROR]: Unable to set session cookie: {}", e);
+                HttpResponse::InternalServerError().finish()
+            },
+            Ok(_) => HttpResponse::Ok().json(challenge),
+        },
+    }
+}
+
+#[post("/passkeys/login/finish")]
+async fn finish_login(req: HttpRequest, session: Session, credential: web::Json<PublicKeyCredential>, data: web::Data<AppState>) -> impl Responder {
+    let Some(id) = take_challenge_id(&session) else {
+        return HttpResponse::BadRequest().body("Passkey login expired, try again");
+    };
+    let ceremony = match data.passkey_store.take_challenge(&id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load passkey challenge: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(None) => return HttpResponse::BadRequest().body("Passkey login expired, try again"),
+        Ok(Some(ceremony)) => ceremony,
+    };
+    match authenticate(&data, &credential, ceremony).await {
+        Err(e) => {
+            warn!("[WARN]: Passkey login failed: {}", e);
+            HttpResponse::Unauthorized().body("That passkey didn't work")
+        },
+        Ok(PasskeyLogin::Invalid) => HttpResponse::Unauthorized().body("That passkey didn't work"),
+        Ok(PasskeyLogin::User(user_id)) => sign_in(&data, &req, &session, &user_id).await,
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(passkeys_enabled)
+        .service(start_registration)
+        .service(finish_registration)
+        .service(start_login)
+        .service(finish_login);
+}
@@ -0,0 +1,423 @@
+use actix_web::{delete, get, post, web, http::header, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime};
+use log::{info, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::api::{current_user_id, meals::log_meal, targets::effective_targets_for};
+use crate::models::{Food, MealEntry};
+use crate::models::plan::{thaw_task, to_ical, MealSlot, PlannedMeal, PrepTask};
+use crate::models::planner::{solve, FoodBounds, DEFAULT_TOLERANCE};
+
+// How far either side of today the calendar feed reaches
+const FEED_PAST_DAYS: i64 = 14;
+const FEED_FUTURE_DAYS: i64 = 60;
+
+#[derive(Clone, Debug, Deserialize)]
+struct PlanRequest {
+    // leave empty to plan from whatever is in the pantry
+    #[serde(default)]
+    foods: Vec<FoodBounds>,
+    tolerance: Option<f64>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PlanPortion {
+    food_id: String,
+    grams: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct CommitPlan {
+    foods: Vec<PlanPortion>,
+    eaten_at: Option<DateTime<Local>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct WeekQuery {
+    // any day in the week, defaults to this one
+    week_of: Option<NaiveDate>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct NewPlannedMeal {
+    date: NaiveDate,
+    slot: MealSlot,
+    foods: Vec<PlanPortion>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct NewPrepTask {
+    date: NaiveDate,
+    time: Option<NaiveTime>,
+    title: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct WeekPlan {
+    from: NaiveDate,
+    to: NaiveDate,
+    meals: Vec<PlannedMeal>,
+    tasks: Vec<PrepTask>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct FeedToken {
+    token: String,
+    path: String,
+}
+
+impl FeedToken {
+    fn new(token: String) -> Self {
+        FeedToken {
+            path: format!("/api/calendar/{}.ics", token),
+            token,
+        }
+    }
+}
+
+fn week_of(date: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+    (monday, monday + Duration::days(7))
+}
+
+async fn week_plan(data: &AppState, user_id: &str, date: NaiveDate) -> Result<WeekPlan, Box<dyn Error>> {
+    let (from, to) = week_of(date);
+    Ok(WeekPlan {
+        from,
+        to,
+        meals: data.plan_store.get_meals_between(user_id, from, to).await?,
+        tasks: data.plan_store.get_tasks_between(user_id, from, to).await?,
+    })
+}
+
+async fn calendar_for(data: &AppState, user_id: &str) -> Result<String, Box<dyn Error>> {
+    let now = Local::now();
+    let today = now.date_naive();
+    let from = today - Duration::days(FEED_PAST_DAYS);
+    let to = today + Duration::days(FEED_FUTURE_DAYS);
+    let meals = data.plan_store.get_meals_between(user_id, from, to).await?;
+    let tasks = data.plan_store.get_tasks_between(user_id, from, to).await?;
+    Ok(to_ical(&meals, &tasks, &now))
+}
+
+// Pantry foods that fit the diet profile, capped at what's on hand
+async fn pantry_bounds(data: &AppState, user_id: &str) -> Result<Vec<FoodBounds>, Box<dyn Error>> {
+    let profile = data.profile_store.get_profile(user_id).await?;
+    let mut on_hand: HashMap<String, f64> = HashMap::new();
+    for item in data.pantry_store.get_items(user_id).await? {
+        *on_hand.entry(item.food_id).or_default() += item.grams_remaining;
+    }
+    let mut bounds = Vec::new();
+    for (food_id, grams) in on_hand {
+        if grams <= 0.0 {
+            continue;
+        }
+        if let Some(food) = data.food_store.get_food(user_id, &food_id).await? {
+            if profile.is_allowed(food.category) {
+                bounds.push(FoodBounds { food_id, min_g: 0.0, max_g: Some(grams) });
+            }
+        }
+    }
+    bounds.sort_by(|a, b| a.food_id.cmp(&b.food_id));
+    Ok(bounds)
+}
+
+// Grams of each food that land today's targets, see planner::solve
+#[post("/plan")]
+async fn make_plan(req: HttpRequest, body: web::Json<PlanRequest>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let body = body.into_inner();
+    let tolerance = body.tolerance.unwrap_or(DEFAULT_TOLERANCE);
+    if !(tolerance > 0.0) {
+        return HttpResponse::BadRequest().body("Tolerance must be more than 0");
+    }
+    let requested = if body.foods.is_empty() {
+        match pantry_bounds(&data, &user_id).await {
+            Err(e) => {
+                error!("[ERROR]: Unable to load pantry for plan: {}", e);
+                return HttpResponse::InternalServerError().finish();
+            },
+            Ok(bounds) => bounds,
+        }
+    } else {
+        body.foods
+    };
+    if requested.is_empty() {
+        return HttpResponse::BadRequest().body("No foods to plan with");
+    }
+    let mut foods: Vec<(Food, FoodBounds)> = Vec::with_capacity(requested.len());
+    for bounds in requested {
+        if bounds.max_g.is_some_and(|max| max < bounds.min_g) {
+            return HttpResponse::BadRequest().body(format!("Max for {} is below its min", bounds.food_id));
+        }
+        match data.food_store.get_food(&user_id, &bounds.food_id).await {
+            Err(e) => {
+                error!("[ERROR]: Unable to load food {}: {}", bounds.food_id, e);
+                return HttpResponse::InternalServerError().finish();
+            },
+            Ok(None) => return HttpResponse::BadRequest().body(format!("Unknown food {}", bounds.food_id)),
+            Ok(Some(food)) => foods.push((food, bounds)),
+        }
+    }
+    match effective_targets_for(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to resolve targets: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(targets) => HttpResponse::Ok().json(solve(&foods, &targets, tolerance)),
+    }
+}
+
+// Takes back entries logged for a plan that couldn't be logged in full
+async fn unlog_entries(data: &AppState, user_id: &str, entries: &[MealEntry]) -> Result<(), Box<dyn Error>> {
+    for entry in entries {
+        data.meal_store.delete_entry(user_id, &entry.id).await?;
+        if entry.pantry_grams > 0.0 {
+            data.pantry_store.restore(user_id, &entry.food_id, entry.pantry_grams).await?;
+        }
+    }
+    Ok(())
+}
+
+// Logs every food in a plan as a meal entry, all of them or none
+#[post("/plan/commit")]
+async fn commit_plan(req: HttpRequest, body: web::Json<CommitPlan>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let body = body.into_inner();
+    let eaten_at = body.eaten_at.unwrap_or_else(Local::now);
+    let mut foods = Vec::with_capacity(body.foods.len());
+    for portion in body.foods {
+        if !portion.grams.is_finite() || portion.grams < 0.0 {
+            return HttpResponse::BadRequest().body(format!("Invalid amount for {}", portion.food_id));
+        }
+        if portion.grams == 0.0 {
+            continue;
+        }
+        match data.food_store.get_food(&user_id, &portion.food_id).await {
+            Err(e) => {
+                error!("[ERROR]: Unable to load food {}: {}", portion.food_id, e);
+                return HttpResponse::InternalServerError().finish();
+            },
+            Ok(None) => return HttpResponse::BadRequest().body(format!("Unknown food {}", portion.food_id)),
+            Ok(Some(food)) => foods.push((food, portion.grams)),
+        }
+    }
+    let mut entries = Vec::with_capacity(foods.len());
+    for (food, grams) in foods {
+        match log_meal(&data, &user_id, &food, grams, food.default_sourcing.clone(), eaten_at).await {
+            Err(e) => {
+                error!("[ERROR]: Unable to save meal entry from plan, taking back {} entries: {}", entries.len(), e);
+                if let Err(e) = unlog_entries(&data, &user_id, &entries).await {
+                    error!("[ERROR]: Unable to take back plan entries for {}: {}", user_id, e);
+                }
+                return HttpResponse::InternalServerError().finish();
+            },
+            Ok(entry) => entries.push(entry),
+        }
+    }
+    HttpResponse::Created().json(entries)
+}
+
+#[get("/plans")]
+async fn get_week_plan(req: HttpRequest, query: web::Query<WeekQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let date = query.week_of.unwrap_or_else(|| Local::now().date_naive());
+    match week_plan(&data, &user_id, date).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load meal plan: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(plan) => HttpResponse::Ok().json(plan),
+    }
+}
+
+// Plans a slot's foods, with thaw tasks for anything that's in the freezer
+#[post("/plans/meals")]
+async fn add_planned_meal(req: HttpRequest, body: web::Json<NewPlannedMeal>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let body = body.into_inner();
+    let mut foods = Vec::with_capacity(body.foods.len());
+    for portion in body.foods {
+        if !portion.grams.is_finite() || portion.grams <= 0.0 {
+            return HttpResponse::BadRequest().body(format!("Invalid amount for {}", portion.food_id));
+        }
+        match data.food_store.get_food(&user_id, &portion.food_id).await {
+            Err(e) => {
+                error!("[ERROR]: Unable to load food {}: {}", portion.food_id, e);
+                return HttpResponse::InternalServerError().finish();
+            },
+            Ok(None) => return HttpResponse::BadRequest().body(format!("Unknown food {}", portion.food_id)),
+            Ok(Some(food)) => foods.push((food, portion.grams)),
+        }
+    }
+    if foods.is_empty() {
+        return HttpResponse::BadRequest().body("Nothing to plan");
+    }
+    let pantry = match data.pantry_store.get_items(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load pantry, no thaw tasks will be added: {}", e);
+            Vec::new()
+        },
+        Ok(items) => items,
+    };
+    let mut meals = Vec::with_capacity(foods.len());
+    for (food, grams) in foods {
+        let meal = PlannedMeal::from_food(&food, grams, body.date, body.slot);
+        if let Err(e) = data.plan_store.save_meal(&user_id, &meal).await {
+            error!("[ERROR]: Unable to save planned meal: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+        if let Some(task) = thaw_task(&meal, &pantry) {
+            if let Err(e) = data.plan_store.save_task(&user_id, &task).await {
+                error!("[ERROR]: Unable to save thaw task: {}", e);
+            }
+        }
+        meals.push(meal);
+    }
+    info!("[INFO]: Planned {} foods for {:?} on {} for {}", meals.len(), body.slot, body.date, user_id);
+    HttpResponse::Created().json(meals)
+}
+
+#[delete("/plans/meals/{meal_id}")]
+async fn delete_planned_meal(req: HttpRequest, path: web::Path<Uuid>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.plan_store.delete_meal(&user_id, &path).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to delete planned meal: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Ok(true) => HttpResponse::NoContent().finish(),
+    }
+}
+
+#[post("/plans/prep")]
+async fn add_prep_task(req: HttpRequest, body: web::Json<NewPrepTask>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let body = body.into_inner();
+    if body.title.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Prep task needs a title");
+    }
+    let task = PrepTask {
+        id: Uuid::new_v4(),
+        date: body.date,
+        time: body.time,
+        title: body.title.trim().to_owned(),
+        planned_meal_id: None,
+    };
+    match data.plan_store.save_task(&user_id, &task).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save prep task: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => HttpResponse::Created().json(task),
+    }
+}
+
+#[delete("/plans/prep/{task_id}")]
+async fn delete_prep_task(req: HttpRequest, path: web::Path<Uuid>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.plan_store.delete_task(&user_id, &path).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to delete prep task: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Ok(true) => HttpResponse::NoContent().finish(),
+    }
+}
+
+// Creates the feed token the first time it's asked for. Only its hash is
+// kept, so after that there's nothing to show (204) and a lost link means
+// rotating to a new one.
+#[get("/plans/feed")]
+async fn get_feed(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.plan_store.has_feed_token(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load calendar feed token: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(true) => return HttpResponse::NoContent().finish(),
+        Ok(false) => {},
+    }
+    match data.plan_store.rotate_feed_token(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to create calendar feed token: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(token) => HttpResponse::Ok().json(FeedToken::new(token)),
+    }
+}
+
+#[post("/plans/feed/rotate")]
+async fn rotate_feed(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.plan_store.rotate_feed_token(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to rotate calendar feed token: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(token) => {
+            info!("[INFO]: Calendar feed token rotated for {}", user_id);
+            HttpResponse::Ok().json(FeedToken::new(token))
+        }
+    }
+}
+
+// Calendar apps can't log in, the secret token in the URL stands in for the session
+#[get("/calendar/{token}.ics")]
+async fn calendar_feed(path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let user_id = match data.plan_store.user_for_feed_token(&path).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to look up calendar feed token: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Ok(Some(user_id)) => user_id,
+    };
+    match calendar_for(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to build calendar feed: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(calendar) => HttpResponse::Ok()
+            .insert_header((header::CONTENT_TYPE, "text/calendar; charset=utf-8"))
+            .body(calendar),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(make_plan)
+        .service(commit_plan)
+        .service(get_week_plan)
+        .service(add_planned_meal)
+        .service(delete_planned_meal)
+        .service(add_prep_task)
+        .service(delete_prep_task)
+        .service(get_feed)
+        .service(rotate_feed)
+        .service(calendar_feed);
+}
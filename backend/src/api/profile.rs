@@ -0,0 +1,81 @@
+use actix_web::{get, put, web, HttpRequest, HttpResponse, Responder};
+use log::{info, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::models::FoodCategory;
+use crate::models::profile::{MacroRatios, UserProfile};
+
+#[derive(Clone, Debug, Deserialize)]
+struct ProfileQuery {
+    // also reset the macro targets to the profile's default ratios
+    #[serde(default)]
+    apply_defaults: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct ProfileResponse {
+    profile: UserProfile,
+    allowed_categories: Vec<FoodCategory>,
+    score_weights: HashMap<FoodCategory, f64>,
+    macro_ratios: Option<MacroRatios>,
+}
+
+impl From<UserProfile> for ProfileResponse {
+    fn from(profile: UserProfile) -> Self {
+        ProfileResponse {
+            allowed_categories: profile.allowed_categories(),
+            score_weights: profile.score_weights(),
+            macro_ratios: profile.macro_ratios(),
+            profile,
+        }
+    }
+}
+
+#[get("/profile")]
+async fn get_profile(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.profile_store.get_profile(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load profile: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(profile) => HttpResponse::Ok().json(ProfileResponse::from(profile)),
+    }
+}
+
+#[put("/profile")]
+async fn update_profile(req: HttpRequest, query: web::Query<ProfileQuery>, body: web::Json<UserProfile>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let profile = body.into_inner();
+    if let Err(e) = data.profile_store.save_profile(&user_id, &profile).await {
+        error!("[ERROR]: Unable to save profile: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    info!("[INFO]: Diet profile set to {:?} for {}", profile.diet, user_id);
+    if let (true, Some(ratios)) = (query.apply_defaults, profile.macro_ratios()) {
+        let result = match data.targets_store.get_targets(&user_id).await {
+            Err(e) => Err(e),
+            Ok(mut targets) => {
+                ratios.apply(&mut targets);
+                data.targets_store.save_targets(&user_id, &targets).await
+            }
+        };
+        if let Err(e) = result {
+            error!("[ERROR]: Unable to apply profile macro ratios: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    HttpResponse::Ok().json(ProfileResponse::from(profile))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_profile)
+        .service(update_profile);
+}
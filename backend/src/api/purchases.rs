@@ -0,0 +1,105 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Local};
+use log::{info, error};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::api::{current_user_id, pantry};
+use crate::models::Purchase;
+use crate::models::cost::PurchaseUnit;
+
+#[derive(Clone, Debug, Deserialize)]
+struct NewPurchase {
+    food_id: String,
+    price: f64,
+    quantity: f64,
+    unit: PurchaseUnit,
+    grams_per_unit: Option<f64>,
+    store: Option<String>,
+    purchased_at: Option<DateTime<Local>>,
+    // stock the pantry with it as well
+    #[serde(default)]
+    add_to_pantry: bool,
+    location: Option<String>,
+}
+
+#[get("/purchases")]
+async fn list_purchases(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.purchase_store.get_purchases(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load purchases: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(purchases) => HttpResponse::Ok().json(purchases),
+    }
+}
+
+#[post("/purchases")]
+async fn add_purchase(req: HttpRequest, body: web::Json<NewPurchase>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let body = body.into_inner();
+    match data.food_store.get_food(&user_id, &body.food_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load food {}: {}", body.food_id, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(None) => return HttpResponse::BadRequest().body("Unknown food"),
+        Ok(Some(_)) => {},
+    }
+    let purchase = Purchase {
+        id: Uuid::new_v4(),
+        food_id: body.food_id,
+        price: body.price,
+        quantity: body.quantity,
+        unit: body.unit,
+        grams_per_unit: body.grams_per_unit,
+        store: body.store.filter(|store| !store.trim().is_empty()),
+        purchased_at: body.purchased_at.unwrap_or_else(Local::now),
+    };
+    let grams = match purchase.checked_grams() {
+        Err(reason) => return HttpResponse::BadRequest().body(reason),
+        Ok(grams) => grams,
+    };
+    match data.purchase_store.save_purchase(&user_id, &purchase).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save purchase: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            info!("[INFO]: Purchase {} saved for {}", purchase.id, user_id);
+            if body.add_to_pantry {
+                if let Err(e) = pantry::stock_food(&data, &user_id, &purchase.food_id, grams, body.location, purchase.purchased_at).await {
+                    error!("[ERROR]: Unable to stock pantry with purchase: {}", e);
+                }
+            }
+            HttpResponse::Created().json(purchase)
+        }
+    }
+}
+
+#[delete("/purchases/{purchase_id}")]
+async fn delete_purchase(req: HttpRequest, path: web::Path<Uuid>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.purchase_store.delete_purchase(&user_id, &path).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to delete purchase: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Ok(true) => HttpResponse::NoContent().finish(),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_purchases)
+        .service(add_purchase)
+        .service(delete_purchase);
+}
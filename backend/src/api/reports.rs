@@ -0,0 +1,98 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Local, NaiveDate};
+use log::error;
+use serde::Deserialize;
+use std::error::Error;
+
+use crate::AppState;
+use crate::api::{current_user_id, targets::effective_targets_for};
+use crate::models::cost::{protein_costs, ProteinCost, SpendReport};
+use crate::models::meal::{day_bounds, week_bounds};
+use crate::models::sourcing::SourcingReport;
+
+#[derive(Clone, Debug, Deserialize)]
+struct WeekQuery {
+    // any day in the week, defaults to this week
+    week_of: Option<NaiveDate>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct RangeQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[get("/reports/sourcing")]
+async fn sourcing_report(req: HttpRequest, query: web::Query<WeekQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let Some((from, to)) = week_bounds(query.week_of.unwrap_or_else(|| Local::now().date_naive())) else {
+        return HttpResponse::BadRequest().body("Invalid date");
+    };
+    match data.meal_store.get_entries_between(&user_id, &from, &to).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load meals: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(entries) => HttpResponse::Ok().json(SourcingReport::from_entries(&entries)),
+    }
+}
+
+// Daily spend between two days (inclusive), this week by default
+#[get("/reports/spend")]
+async fn spend_report(req: HttpRequest, query: web::Query<RangeQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let today = Local::now().date_naive();
+    let bounds = match (query.from, query.to) {
+        (None, None) => week_bounds(today),
+        (from, to) => day_bounds(from.unwrap_or(today))
+            .zip(day_bounds(to.unwrap_or(today)))
+            .map(|((from, _), (_, to))| (from, to)),
+    };
+    let Some((from, to)) = bounds else {
+        return HttpResponse::BadRequest().body("Invalid date");
+    };
+    if from > to {
+        return HttpResponse::BadRequest().body("from should be on or before to");
+    }
+    match data.meal_store.get_entries_between(&user_id, &from, &to).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load meals: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(entries) => HttpResponse::Ok().json(SpendReport::from_entries(&entries)),
+    }
+}
+
+async fn protein_costs_for(data: &AppState, user_id: &str) -> Result<Vec<ProteinCost>, Box<dyn Error>> {
+    let mut foods = data.food_store.get_foods().await?;
+    foods.extend(data.food_store.get_custom_foods(user_id).await?);
+    let purchases = data.purchase_store.get_purchases(user_id).await?;
+    let targets = effective_targets_for(data, user_id).await?;
+    Ok(protein_costs(&foods, &purchases, targets.protein_g))
+}
+
+// Cost per 100g protein for every food with a known price, cheapest first,
+// and what hitting today's protein target with each one would cost
+#[get("/reports/protein-cost")]
+async fn protein_cost_report(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match protein_costs_for(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to build protein cost report: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(costs) => HttpResponse::Ok().json(costs),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(sourcing_report)
+        .service(spend_report)
+        .service(protein_cost_report);
+}
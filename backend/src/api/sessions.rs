@@ -0,0 +1,244 @@
+use actix_session::{Session, SessionExt};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{delete, get, http::header, post, web, Error as ActixError, HttpRequest, HttpResponse, Responder};
+use log::{info, error};
+use serde::Serialize;
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::AppState;
+use crate::api::{current_user_id, session_user_id, TokenUser, SESSION_ID_KEY};
+use crate::api::account::end_session;
+use crate::api::api_tokens::{bearer_token, token_user};
+use crate::models::device_session::{ip_prefix, DeviceSession};
+
+#[derive(Clone, Debug, Serialize)]
+struct SessionInfo {
+    #[serde(flatten)]
+    session: DeviceSession,
+    // the one making the request
+    current: bool,
+}
+
+pub fn current_session_id(session: &Session) -> Option<String> {
+    session.get::<String>(SESSION_ID_KEY).ok().flatten()
+}
+
+// The address the request came from. X-Forwarded-For is only believed when
+// the connection is from one of TRUSTED_PROXIES, and then only the hops the
+// proxies added, anything further left is whatever the client sent.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let Some(data) = req.app_data::<web::Data<AppState>>() else {
+        return Some(peer);
+    };
+    let forwarded_for: Vec<&str> = req.headers().get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    Some(forwarded_client(peer, &forwarded_for.join(","), &data.env_config.trusted_proxies))
+}
+
+fn forwarded_client(peer: IpAddr, forwarded_for: &str, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+    for hop in forwarded_for.rsplit(',').map(str::trim).filter(|hop| !hop.is_empty()) {
+        match hop.parse::<IpAddr>().ok().or_else(|| hop.parse::<SocketAddr>().ok().map(|addr| addr.ip())) {
+            Some(ip) if trusted_proxies.contains(&ip) => continue,
+            Some(ip) => return ip,
+            // a hop we can't read, nothing left of it can be trusted
+            None => return peer,
+        }
+    }
+    peer
+}
+
+// The prefix of client_ip, what failed logins and passkey ceremonies are
+// counted by. Without a peer address (only in tests) everyone shares one.
+pub fn client_network(req: &HttpRequest) -> String {
+    client_ip(req).map(ip_prefix).unwrap_or_else(|| "unknown".to_owned())
+}
+
+pub async fn start_device_session(data: &AppState, req: &HttpRequest, user_id: &str) -> Result<DeviceSession, Box<dyn Error>> {
+    let user_agent = req.headers().get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let device = DeviceSession::new(user_id, user_agent, client_ip(req));
+    data.device_session_store.save_session(&device).await?;
+    Ok(device)
+}
+
+// Signs out cookies whose device session was revoked, went idle or was never
+// there (from before sessions were tracked). set_current_user makes them.
+async fn check_device_session(data: &AppState, req: &HttpRequest, session: &Session, user_id: &str) -> Result<(), Box<dyn Error>> {
+    let store = &data.device_session_store;
+    let device = match current_session_id(session) {
+        None => None,
+        Some(id) => store.get_session(&id).await?,
+    };
+    match device {
+        Some(mut device) if device.user_id == user_id && !device.is_expired() => store.touch(&mut device, client_ip(req)).await?,
+        Some(device) if device.is_expired() => {
+            info!("[INFO]: Signed out idle session {} of {}", device.id, user_id);
+            store.delete_session(&device.user_id, &device.id).await?;
+            session.purge();
+        },
+        _ => {
+            info!("[INFO]: Signed out an untracked or revoked session of {}", user_id);
+            session.purge();
+        },
+    }
+    Ok(())
+}
+
+// Wraps /api, runs before every handler. A bearer API token stands in for
+// the cookie for the one request, and never gets a device session.
+pub async fn session_middleware(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, ActixError> {
+    let Some(data) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+    if let Some(token) = bearer_token(req.request()) {
+        let user_id = match token_user(&data, req.request(), &token).await {
+            Err(response) => return Ok(req.into_response(response).map_into_right_body()),
+            Ok(user_id) => user_id,
+        };
+        req.extensions_mut().insert(TokenUser(user_id));
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+    let session = req.get_session();
+    if let Some(user_id) = session_user_id(&session) {
+        // an unchecked cookie could be a revoked one, so it doesn't get in
+        if let Err(e) = check_device_session(&data, req.request(), &session, &user_id).await {
+            error!("[ERROR]: Unable to check session for {}: {}", user_id, e);
+            session.purge();
+            return Ok(req.into_response(HttpResponse::InternalServerError().finish()).map_into_right_body());
+        }
+    }
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+// Removes one of the user's sessions, and the reddit tokens with the last one
+pub async fn end_device_session(data: &AppState, user_id: &str, id: &str) -> Result<bool, Box<dyn Error>> {
+    let removed = data.device_session_store.delete_session(user_id, id).await?;
+    if removed && data.device_session_store.get_user_sessions(user_id).await?.is_empty() {
+        end_session(data, user_id).await?;
+    }
+    Ok(removed)
+}
+
+#[get("/sessions")]
+async fn get_sessions(req: HttpRequest, session: Session, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let current_id = current_session_id(&session);
+    match data.device_session_store.get_user_sessions(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load sessions for {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(sessions) => {
+            let sessions: Vec<SessionInfo> = sessions.into_iter()
+                .filter(|device| !device.is_expired())
+                .map(|device| SessionInfo {
+                    current: current_id.as_deref() == Some(device.id.as_str()),
+                    session: device,
+                })
+                .collect();
+            HttpResponse::Ok().json(sessions)
+        }
+    }
+}
+
+// Revoking the current one is the same as logging out
+#[delete("/sessions/{id}")]
+async fn revoke_session(req: HttpRequest, session: Session, path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let id = path.into_inner();
+    match end_device_session(&data, &user_id, &id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to revoke session {} of {}: {}", id, user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Ok(true) => {
+            if current_session_id(&session).as_deref() == Some(id.as_str()) {
+                session.purge();
+            }
+            info!("[INFO]: {} revoked session {}", user_id, id);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+// Every session of the user but the one kept, if any. With none kept the
+// reddit tokens go with the last one.
+pub async fn end_other_sessions(data: &AppState, user_id: &str, keep: Option<&str>) -> Result<usize, Box<dyn Error>> {
+    let others: Vec<DeviceSession> = data.device_session_store.get_user_sessions(user_id).await?
+        .into_iter()
+        .filter(|device| keep != Some(device.id.as_str()))
+        .collect();
+    for device in &others {
+        end_device_session(data, user_id, &device.id).await?;
+    }
+    Ok(others.len())
+}
+
+// Everywhere but here
+#[post("/sessions/revoke-others")]
+async fn revoke_other_sessions(req: HttpRequest, session: Session, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let current_id = current_session_id(&session);
+    match end_other_sessions(&data, &user_id, current_id.as_deref()).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to revoke other sessions of {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(revoked) => {
+            info!("[INFO]: {} revoked {} other sessions", user_id, revoked);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_sessions)
+        .service(revoke_session)
+        .service(revoke_other_sessions);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_for_needs_a_trusted_proxy() {
+        let proxy = ip("10.0.0.1");
+        // straight from the client, whatever it claims
+        assert_eq!(forwarded_client(ip("198.51.100.7"), "203.0.113.9", &[proxy]), ip("198.51.100.7"));
+        assert_eq!(forwarded_client(ip("198.51.100.7"), "", &[]), ip("198.51.100.7"));
+        // through the proxy
+        assert_eq!(forwarded_client(proxy, "203.0.113.9", &[proxy]), ip("203.0.113.9"));
+        assert_eq!(forwarded_client(proxy, "", &[proxy]), proxy);
+    }
+
+    #[test]
+    fn only_the_hops_proxies_added_count() {
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+        // the client made up the first one, the proxy appended the second
+        assert_eq!(forwarded_client(proxies[0], "192.0.2.1, 203.0.113.9", &proxies), ip("203.0.113.9"));
+        assert_eq!(forwarded_client(proxies[0], "192.0.2.1, 203.0.113.9, 10.0.0.2", &proxies), ip("203.0.113.9"));
+        assert_eq!(forwarded_client(proxies[0], "203.0.113.9:51234", &proxies), ip("203.0.113.9"));
+        assert_eq!(forwarded_client(proxies[0], "192.0.2.1, garbage", &proxies), proxies[0]);
+    }
+}
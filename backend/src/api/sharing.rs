@@ -0,0 +1,297 @@
+use actix_web::{get, post, put, web, http::header, HttpRequest, HttpResponse, Responder};
+use chrono::{Duration, Local, NaiveDate};
+use log::{info, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+
+use crate::AppState;
+use crate::api::{current_user_id, targets::effective_targets_for};
+use crate::models::{BodyTrend, UserSession};
+use crate::models::meal::{day_bounds, week_bounds, MacroTotals};
+use crate::models::reddit::{refresh_access_token, subreddit_name, thread_fullname};
+use crate::models::sharing::{SharePeriod, ShareSettings, ShareSummary};
+
+// refresh a little before reddit would turn the token down
+const TOKEN_MARGIN_SECONDS: i64 = 60;
+// where to get a token that can post, sent as the Location of a 403
+const SHARING_LINK: &str = "/login/reddit?sharing=true";
+
+#[derive(Clone, Debug, Deserialize)]
+struct ShareQuery {
+    #[serde(default)]
+    period: SharePeriod,
+    // any day in the period, defaults to today
+    date: Option<NaiveDate>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct SharePreview {
+    title: String,
+    markdown: String,
+    // "r/carnivore" or the thread being replied to, None until one is set
+    destination: Option<String>,
+    enabled: bool,
+    summary: ShareSummary,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct SharePosted {
+    url: String,
+}
+
+pub async fn share_summary(data: &AppState, user_id: &str, period: SharePeriod, date: NaiveDate) -> Result<ShareSummary, Box<dyn Error>> {
+    let (from, to) = match period {
+        SharePeriod::Day => (date, date),
+        SharePeriod::Week => {
+            let (start, _) = week_bounds(date).ok_or("invalid date")?;
+            (start.date_naive(), start.date_naive() + Duration::days(6))
+        },
+    };
+    let (start, _) = day_bounds(from).ok_or("invalid date")?;
+    let (end, _) = day_bounds(to + Duration::days(1)).ok_or("invalid date")?;
+    let entries = data.meal_store.get_entries_between(user_id, &start, &end).await?;
+    let days: HashSet<NaiveDate> = entries.iter().map(|entry| entry.eaten_at.date_naive()).collect();
+    let mut totals = MacroTotals::default();
+    for entry in &entries {
+        totals += entry.macros();
+    }
+    // a week reads better as an average day, over the days actually logged
+    if period == SharePeriod::Week && !days.is_empty() {
+        let logged = days.len() as f64;
+        totals = MacroTotals {
+            protein_g: totals.protein_g / logged,
+            fat_g: totals.fat_g / logged,
+            carbs_g: totals.carbs_g / logged,
+            calories: totals.calories / logged,
+        };
+    }
+    let profile = data.profile_store.get_profile(user_id).await?;
+    let trend = BodyTrend::from_metrics(&data.body_store.get_metrics(user_id).await?);
+    let point = trend.trend_on(to);
+    // the rate is only known for the latest weigh-in
+    let is_latest = point.map(|point| point.date) == trend.points.last().map(|point| point.date);
+    Ok(ShareSummary {
+        period,
+        from,
+        to,
+        days_logged: days.len(),
+        totals,
+        targets: effective_targets_for(data, user_id).await?,
+        animal_score: profile.animal_score(&entries),
+        trend_weight_kg: point.map(|point| point.trend_kg),
+        weekly_rate_kg: trend.weekly_rate_kg.filter(|_| is_latest),
+    })
+}
+
+fn destination(settings: &ShareSettings) -> Option<String> {
+    match (&settings.thread, &settings.subreddit) {
+        (Some(thread), _) => Some(thread.clone()),
+        (None, Some(subreddit)) => Some(format!("r/{}", subreddit)),
+        (None, None) => None,
+    }
+}
+
+// The stored access token, refreshed first if it's (nearly) expired, and
+// whether it may post
+async fn access_token_for(data: &AppState, user_id: &str) -> Result<Option<(String, bool)>, Box<dyn Error>> {
+    let Some(mut user_session) = data.session_store.get_session(user_id).await? else {
+        return Ok(None);
+    };
+    if user_session.expires_at > Local::now() + Duration::seconds(TOKEN_MARGIN_SECONDS) {
+        return Ok(Some((user_session.reddit_access_token, user_session.can_submit)));
+    }
+    if !refresh_reddit_session(data, user_id, &mut user_session).await? {
+        return Ok(None);
+    }
+    Ok(Some((user_session.reddit_access_token, user_session.can_submit)))
+}
+
+// A new access token for the stored session, false without a refresh token
+pub async fn refresh_reddit_session(data: &AppState, user_id: &str, user_session: &mut UserSession) -> Result<bool, Box<dyn Error>> {
+    let Some(refresh_token) = user_session.reddit_refresh_token.clone() else {
+        return Ok(false);
+    };
+    let config = &data.env_config;
+    let refreshed = refresh_access_token(&config.reddit_access_uri, &config.reddit_client_id, &config.reddit_client_secret, &config.reddit_author, &refresh_token).await?;
+    user_session.reddit_access_token = refreshed.access_token;
+    user_session.expires_at = Local::now() + Duration::seconds(refreshed.expires_in as i64);
+    data.session_store.save_session(user_id, user_session).await?;
+    Ok(true)
+}
+
+#[get("/sharing/settings")]
+async fn get_share_settings(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.share_store.get_settings(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load share settings: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(settings) => HttpResponse::Ok().json(settings),
+    }
+}
+
+#[put("/sharing/settings")]
+async fn update_share_settings(req: HttpRequest, body: web::Json<ShareSettings>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let mut settings = body.into_inner();
+    // stored as the bare name and the fullname, whatever was pasted in
+    settings.subreddit = match settings.subreddit.as_deref().filter(|text| !text.trim().is_empty()) {
+        None => None,
+        Some(text) => match subreddit_name(text) {
+            None => return HttpResponse::BadRequest().body(format!("Not a subreddit: {}", text)),
+            subreddit => subreddit,
+        },
+    };
+    settings.thread = match settings.thread.as_deref().filter(|text| !text.trim().is_empty()) {
+        None => None,
+        Some(text) => match thread_fullname(text) {
+            None => return HttpResponse::BadRequest().body(format!("Not a reddit post: {}", text)),
+            thread => thread,
+        },
+    };
+    // Turning it on needs a token that can post. The rest is saved, the link
+    // back from reddit turns sharing on.
+    let needs_link = settings.enabled && !match data.session_store.get_session(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load reddit session for {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(user_session) => user_session.map_or(false, |user_session| user_session.can_submit),
+    };
+    if needs_link {
+        settings.enabled = false;
+    }
+    match data.share_store.save_settings(&user_id, &settings).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save share settings: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) if needs_link => HttpResponse::Forbidden()
+            .insert_header((header::LOCATION, SHARING_LINK))
+            .body("Let the tracker post to Reddit first"),
+        Ok(_) => {
+            info!("[INFO]: Sharing {} for {}", if settings.enabled { "enabled" } else { "disabled" }, user_id);
+            HttpResponse::Ok().json(settings)
+        }
+    }
+}
+
+// Exactly what would be posted, and where
+#[get("/sharing/preview")]
+async fn preview_share(req: HttpRequest, query: web::Query<ShareQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let date = query.date.unwrap_or_else(|| Local::now().date_naive());
+    let result = match data.share_store.get_settings(&user_id).await {
+        Err(e) => Err(e),
+        Ok(settings) => share_summary(&data, &user_id, query.period, date).await.map(|summary| (settings, summary)),
+    };
+    match result {
+        Err(e) => {
+            error!("[ERROR]: Unable to build share preview: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok((settings, summary)) => HttpResponse::Ok().json(SharePreview {
+            title: summary.title(),
+            markdown: summary.markdown(settings.hide_weight),
+            destination: destination(&settings),
+            enabled: settings.enabled,
+            summary,
+        }),
+    }
+}
+
+// Last week's summary, made on Monday mornings for users who share
+#[get("/sharing/digest")]
+async fn get_digest(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let result = match data.share_store.get_settings(&user_id).await {
+        Err(e) => Err(e),
+        Ok(settings) => data.share_store.get_digest(&user_id).await.map(|digest| (settings, digest)),
+    };
+    match result {
+        Err(e) => {
+            error!("[ERROR]: Unable to load digest for {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok((_, None)) => HttpResponse::NotFound().finish(),
+        Ok((settings, Some(summary))) => HttpResponse::Ok().json(SharePreview {
+            title: summary.title(),
+            markdown: summary.markdown(settings.hide_weight),
+            destination: destination(&settings),
+            enabled: settings.enabled,
+            summary,
+        }),
+    }
+}
+
+// Replies in the chosen thread if there is one, otherwise a new post in the subreddit
+#[post("/sharing/post")]
+async fn post_share(req: HttpRequest, query: web::Query<ShareQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let settings = match data.share_store.get_settings(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load share settings: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(settings) => settings,
+    };
+    if !settings.enabled {
+        return HttpResponse::Forbidden().body("Sharing to Reddit is turned off");
+    }
+    let date = query.date.unwrap_or_else(|| Local::now().date_naive());
+    let summary = match share_summary(&data, &user_id, query.period, date).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to build share summary: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(summary) => summary,
+    };
+    let access_token = match access_token_for(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to refresh reddit token for {}: {}", user_id, e);
+            return HttpResponse::BadGateway().body("Reddit wouldn't renew the login, log in again");
+        },
+        Ok(None) => return HttpResponse::Unauthorized().body("Log in with Reddit again to post"),
+        Ok(Some((_, false))) => return HttpResponse::Forbidden()
+            .insert_header((header::LOCATION, SHARING_LINK))
+            .body("Let the tracker post to Reddit first"),
+        Ok(Some((access_token, true))) => access_token,
+    };
+    let api = data.env_config.reddit_api();
+    let text = summary.markdown(settings.hide_weight);
+    let posted = match (&settings.thread, &settings.subreddit) {
+        (Some(thread), _) => api.submit_comment(&access_token, thread, &text).await,
+        (None, Some(subreddit)) => api.submit_post(&access_token, subreddit, &summary.title(), &text).await,
+        (None, None) => return HttpResponse::BadRequest().body("Choose a subreddit or thread first"),
+    };
+    match posted {
+        Err(e) => {
+            error!("[ERROR]: Reddit refused the summary from {}: {}", user_id, e);
+            HttpResponse::BadGateway().body(format!("Reddit refused the post: {}", e))
+        },
+        Ok(url) => {
+            info!("[INFO]: Shared {:?} summary for {} to {}", query.period, user_id, url);
+            HttpResponse::Ok().json(SharePosted { url })
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_share_settings)
+        .service(update_share_settings)
+        .service(preview_share)
+        .service(get_digest)
+        .service(post_share);
+}
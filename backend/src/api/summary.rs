@@ -0,0 +1,73 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Duration, Local, NaiveDate};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::api::{current_user_id, targets::effective_targets_for};
+use crate::models::MealEntry;
+use crate::models::meal::{day_bounds, MacroTotals};
+use crate::models::plan::planned_totals;
+use crate::models::targets::EffectiveTargets;
+
+#[derive(Clone, Debug, Deserialize)]
+struct SummaryQuery {
+    date: Option<NaiveDate>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DailySummary {
+    pub date: NaiveDate,
+    pub totals: MacroTotals,
+    pub targets: EffectiveTargets,
+    pub animal_score: Option<f64>,
+    // what the meal plan had for the day, if anything
+    pub planned: Option<MacroTotals>,
+    // entries outside the user's diet profile
+    pub off_plan: Vec<Uuid>,
+    pub entries: Vec<MealEntry>,
+}
+
+pub async fn daily_summary(data: &AppState, user_id: &str, date: NaiveDate) -> Result<DailySummary, Box<dyn Error>> {
+    let (from, to) = day_bounds(date).ok_or("invalid date")?;
+    let entries = data.meal_store.get_entries_between(user_id, &from, &to).await?;
+    let mut totals = MacroTotals::default();
+    for entry in &entries {
+        totals += entry.macros();
+    }
+    let profile = data.profile_store.get_profile(user_id).await?;
+    let planned = data.plan_store.get_meals_between(user_id, date, date + Duration::days(1)).await?;
+    Ok(DailySummary {
+        date,
+        totals,
+        targets: effective_targets_for(data, user_id).await?,
+        animal_score: profile.animal_score(&entries),
+        planned: planned_totals(&planned, date),
+        off_plan: entries.iter()
+            .filter(|entry| !profile.is_allowed(entry.category))
+            .map(|entry| entry.id)
+            .collect(),
+        entries,
+    })
+}
+
+#[get("/summary")]
+async fn get_summary(req: HttpRequest, query: web::Query<SummaryQuery>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let date = query.date.unwrap_or_else(|| Local::now().date_naive());
+    match daily_summary(&data, &user_id, date).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to build summary: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(summary) => HttpResponse::Ok().json(summary),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_summary);
+}
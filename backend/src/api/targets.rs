@@ -0,0 +1,78 @@
+use actix_web::{get, put, web, HttpRequest, HttpResponse, Responder};
+use log::{info, error};
+use std::error::Error;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::models::{BodyTrend, Targets};
+use crate::models::targets::EffectiveTargets;
+
+// Relative targets follow the latest weigh-in, so they are resolved on every read
+pub async fn effective_targets_for(data: &AppState, user_id: &str) -> Result<EffectiveTargets, Box<dyn Error>> {
+    let targets = data.targets_store.get_targets(user_id).await?;
+    let trend = BodyTrend::from_metrics(&data.body_store.get_metrics(user_id).await?);
+    Ok(targets.effective(trend.latest_weight_kg, trend.latest_body_fat_pct))
+}
+
+#[get("/targets")]
+async fn get_targets(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match data.targets_store.get_targets(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load targets: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(targets) => HttpResponse::Ok().json(targets),
+    }
+}
+
+#[get("/targets/effective")]
+async fn get_effective_targets(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match effective_targets_for(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to resolve targets: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(targets) => HttpResponse::Ok().json(targets),
+    }
+}
+
+#[put("/targets")]
+async fn update_targets(req: HttpRequest, body: web::Json<Targets>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let mut targets = body.into_inner();
+    if let Err(message) = targets.check() {
+        return HttpResponse::BadRequest().body(message);
+    }
+    // only the auto adjuster gets to move this
+    targets.last_adjusted = match data.targets_store.get_targets(&user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load targets: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(current) => current.last_adjusted,
+    };
+    match data.targets_store.save_targets(&user_id, &targets).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save targets: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            info!("[INFO]: Targets updated for {}", user_id);
+            HttpResponse::Ok().json(targets)
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_targets)
+        .service(get_effective_targets)
+        .service(update_targets);
+}
@@ -0,0 +1,63 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Duration, Local};
+use log::{info, error};
+use std::error::Error;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::models::{BodyTrend, Confidence, TdeeEstimate};
+use crate::models::meal::day_bounds;
+use crate::models::tdee::WINDOW_DAYS;
+
+async fn estimate_for(data: &AppState, user_id: &str) -> Result<TdeeEstimate, Box<dyn Error>> {
+    let today = Local::now().date_naive();
+    let (from, _) = day_bounds(today - Duration::days(WINDOW_DAYS)).ok_or("invalid window start")?;
+    let (to, _) = day_bounds(today).ok_or("invalid window end")?;
+    let entries = data.meal_store.get_entries_between(user_id, &from, &to).await?;
+    let trend = BodyTrend::from_metrics(&data.body_store.get_metrics(user_id).await?);
+    Ok(TdeeEstimate::estimate(&entries, &trend, today))
+}
+
+// Weekly nudge of the calorie target toward the goal rate for users who opted
+// in. Runs whenever a new weigh-in comes in.
+pub async fn adjust_targets_if_due(data: &AppState, user_id: &str) -> Result<(), Box<dyn Error>> {
+    let mut targets = data.targets_store.get_targets(user_id).await?;
+    if !targets.auto_adjust {
+        return Ok(());
+    }
+    let now = Local::now();
+    if let Some(last_adjusted) = targets.last_adjusted {
+        if now - last_adjusted < Duration::days(7) {
+            return Ok(());
+        }
+    }
+    let estimate = estimate_for(data, user_id).await?;
+    if estimate.confidence < Confidence::Medium {
+        return Ok(());
+    }
+    if let Some(calories) = estimate.adjusted_calories(&targets) {
+        info!("[INFO]: Adjusting calorie target for {} from {} to {}", user_id, targets.calories, calories);
+        targets.calories = calories;
+        targets.last_adjusted = Some(now);
+        data.targets_store.save_targets(user_id, &targets).await?;
+    }
+    Ok(())
+}
+
+#[get("/tdee")]
+async fn get_tdee(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match estimate_for(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to estimate TDEE: {}", e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(estimate) => HttpResponse::Ok().json(estimate),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_tdee);
+}
@@ -0,0 +1,185 @@
+use actix_web::{get, put, web, HttpRequest, HttpResponse, Responder};
+use log::{info, error};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::error::Error;
+
+use crate::AppState;
+use crate::api::current_user_id;
+use crate::models::identity::ExternalIdentity;
+use crate::models::user::{is_legacy_id, legacy_user_id, User, UserSettings};
+
+#[derive(Clone, Debug, Deserialize)]
+struct UserUpdate {
+    display_name: String,
+    settings: UserSettings,
+}
+
+// Reddit users from before internal users have everything under their reddit
+// id. Each gets a UUID, their data moves to it and their reddit account
+// becomes an identity like any other, so signing in finds them through that.
+// Admission stays under the reddit id, it's about the reddit account.
+//
+// The new id comes from the reddit id and the user and reddit session records
+// move last, so a run that stops halfway is picked up by the next one and
+// finishes moving to the same id.
+pub async fn migrate_legacy_users(data: &AppState) -> Result<usize, Box<dyn Error>> {
+    let mut legacy_ids = BTreeSet::new();
+    for user_id in data.user_store.get_user_ids().await? {
+        if is_legacy_id(&user_id) {
+            legacy_ids.insert(user_id);
+        }
+    }
+    for (user_id, _) in data.session_store.get_sessions().await? {
+        if is_legacy_id(&user_id) {
+            legacy_ids.insert(user_id);
+        }
+    }
+    for reddit_id in &legacy_ids {
+        let user_id = legacy_user_id(reddit_id);
+        let display_name = match data.user_store.get_user(reddit_id).await? {
+            Some(user) => Some(user.display_name),
+            None => data.session_store.get_session(reddit_id).await?.map(|user_session| user_session.reddit_user.name),
+        };
+        data.body_store.move_user(reddit_id, &user_id).await?;
+        data.food_store.move_user(reddit_id, &user_id).await?;
+        data.meal_store.move_user(reddit_id, &user_id).await?;
+        data.targets_store.move_user(reddit_id, &user_id).await?;
+        data.profile_store.move_user(reddit_id, &user_id).await?;
+        data.purchase_store.move_user(reddit_id, &user_id).await?;
+        data.pantry_store.move_user(reddit_id, &user_id).await?;
+        data.plan_store.move_user(reddit_id, &user_id).await?;
+        data.share_store.move_user(reddit_id, &user_id).await?;
+        data.identity_store.move_user(reddit_id, &user_id).await?;
+        data.passkey_store.move_user(reddit_id, &user_id).await?;
+        data.api_token_store.move_user(reddit_id, &user_id).await?;
+        // signed in before, their identity was linked to the reddit id and
+        // has just moved with the rest
+        if data.identity_store.user_for("reddit", reddit_id).await?.is_none() {
+            data.identity_store.link(&user_id, &ExternalIdentity {
+                provider: "reddit".to_owned(),
+                subject: reddit_id.clone(),
+                display_name: display_name.clone().unwrap_or_else(|| reddit_id.clone()),
+                email: None,
+            }).await?;
+        }
+        // their cookies still carry the reddit id, so they sign in again
+        data.device_session_store.delete_user(reddit_id).await?;
+        data.search_cache.invalidate(reddit_id);
+        data.session_store.move_user(reddit_id, &user_id).await?;
+        data.user_store.move_user(reddit_id, &user_id).await?;
+        data.user_store.ensure_user(&user_id, display_name.as_deref().unwrap_or(reddit_id)).await?;
+        info!("[INFO]: Moved legacy user {} to {}", reddit_id, user_id);
+    }
+    Ok(legacy_ids.len())
+}
+
+// Users without a record yet get one named after their first identity
+async fn load_user(data: &AppState, user_id: &str) -> Result<Option<User>, Box<dyn Error>> {
+    if let Some(user) = data.user_store.get_user(user_id).await? {
+        return Ok(Some(user));
+    }
+    match data.identity_store.get_identities(user_id).await?.first() {
+        None => Ok(None),
+        Some(linked) => Ok(Some(data.user_store.ensure_user(user_id, &linked.identity.display_name).await?)),
+    }
+}
+
+#[get("/me")]
+async fn get_me(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match load_user(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(None) => HttpResponse::Unauthorized().finish(),
+        Ok(Some(user)) => HttpResponse::Ok().json(user),
+    }
+}
+
+// The name is ours, renaming on reddit (or anywhere else) doesn't change it
+#[put("/me")]
+async fn update_me(req: HttpRequest, update: web::Json<UserUpdate>, data: web::Data<AppState>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let display_name = update.display_name.trim();
+    if display_name.is_empty() || display_name.chars().count() > 64 {
+        return HttpResponse::BadRequest().body("Names are 1 to 64 characters");
+    }
+    let mut user = match load_user(&data, &user_id).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to load user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Ok(Some(user)) => user,
+    };
+    user.display_name = display_name.to_owned();
+    user.settings = update.settings.clone();
+    match data.user_store.save_user(&user).await {
+        Err(e) => {
+            error!("[ERROR]: Unable to save user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        },
+        Ok(_) => {
+            info!("[INFO]: Updated user {}", user_id);
+            HttpResponse::Ok().json(user)
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_me)
+        .service(update_me);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::cookie::Key;
+    use chrono::Local;
+    use uuid::Uuid;
+    use crate::EnvConfig;
+    use crate::models::body::BodyMetric;
+
+    fn weigh_in(weight_kg: f64) -> BodyMetric {
+        BodyMetric {
+            id: Uuid::new_v4(),
+            recorded_at: Local::now(),
+            weight_kg: Some(weight_kg),
+            body_fat_pct: None,
+            waist_cm: None,
+            measurements: Default::default(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn a_stopped_migration_finishes_on_the_same_id() {
+        let dir = std::env::temp_dir().join(format!("legacy-migration-{}", Uuid::new_v4().simple()));
+        let data = AppState::open(&dir, &EnvConfig::new(), &Key::generate()).unwrap();
+        data.user_store.ensure_user("t2_abc", "alice").await.unwrap();
+        data.body_store.save_metric("t2_abc", &weigh_in(80.0)).await.unwrap();
+        data.body_store.save_metric("t2_abc", &weigh_in(79.0)).await.unwrap();
+
+        // the last run stopped after moving some of the body data
+        let user_id = legacy_user_id("t2_abc");
+        let first = data.body_store.get_metrics("t2_abc").await.unwrap()[0].clone();
+        data.body_store.delete_metric("t2_abc", &first.id).await.unwrap();
+        data.body_store.save_metric(&user_id, &first).await.unwrap();
+
+        assert_eq!(migrate_legacy_users(&data).await.unwrap(), 1);
+        assert_eq!(data.body_store.get_metrics(&user_id).await.unwrap().len(), 2);
+        assert!(data.body_store.get_metrics("t2_abc").await.unwrap().is_empty());
+        assert!(data.user_store.get_user("t2_abc").await.unwrap().is_none());
+        assert_eq!(data.user_store.get_user(&user_id).await.unwrap().unwrap().display_name, "alice");
+        assert_eq!(data.identity_store.user_for("reddit", "t2_abc").await.unwrap(), Some(user_id));
+        // and there's nothing left for the next startup
+        assert_eq!(migrate_legacy_users(&data).await.unwrap(), 0);
+        drop(data);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
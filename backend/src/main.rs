@@ -1,64 +1,126 @@
 use core::error;
 use std::{boxed, env};
 use actix_session::storage::SessionStore as ActixSessionStore;
+use actix_session::{Session, SessionMiddleware, storage::CookieSessionStore};
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::HttpResponse;
 use log::{LevelFilter, info, warn, error, debug};
 use env_logger::{Builder, Env};
-use actix_web::{middleware::{from_fn, Next}, get, post, web, App, http::header, cookie::Cookie, HttpServer, Responder, Error as ActixError};
+use actix_web::{middleware::{from_fn, Next}, get, post, web, App, http::header, cookie::{Cookie, Key}, HttpServer, Responder, Error as ActixError};
 use reqwest::{Client, Response, Error as ReqwestError};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
 use sled::Db;
 use std::sync::Arc;
 use chrono::{Duration, Local};
 
 // local stuff
+mod api;
 mod models;
-use crate::models::{UserSession, RedditUser, SessionStore};
+use crate::models::{UserSession, RedditUser, SessionStore, BodyStore, FoodStore, MealStore, TargetsStore, ProfileStore, PurchaseStore, PantryStore, PlanStore, ShareStore, AdmissionStore, RevocationStore, LocalAccountStore, PasskeyStore, UserStore, DeviceSessionStore, ApiTokenStore};
+use webauthn_rs::prelude::{Url, Webauthn, WebauthnBuilder};
+use crate::models::admission::AdmissionRules;
+use crate::models::identity::{IdentityProviders, IdentityStore};
+use crate::models::oidc::OidcProvider;
+use jsonwebtoken::Algorithm;
+use crate::models::reddit::{RedditApi, RedditProvider};
+use crate::models::scheduler::Scheduler;
+use crate::models::search::SearchCache;
 
 #[derive(Clone, Debug)]
 struct AppState {
     env_config: EnvConfig,
     session_store: SessionStore,
+    body_store: BodyStore,
+    food_store: FoodStore,
+    meal_store: MealStore,
+    targets_store: TargetsStore,
+    profile_store: ProfileStore,
+    purchase_store: PurchaseStore,
+    pantry_store: PantryStore,
+    plan_store: PlanStore,
+    share_store: ShareStore,
+    admission_store: AdmissionStore,
+    revocation_store: RevocationStore,
+    identity_store: IdentityStore,
+    identity_providers: IdentityProviders,
+    local_account_store: LocalAccountStore,
+    passkey_store: PasskeyStore,
+    user_store: UserStore,
+    device_session_store: DeviceSessionStore,
+    api_token_store: ApiTokenStore,
+    // None unless WEBAUTHN_RP_ID is set
+    webauthn: Option<Arc<Webauthn>>,
+    // background jobs, started by api::jobs::schedule
+    scheduler: Scheduler,
+    search_cache: SearchCache,
 }
 
 impl AppState {
-    fn new(env_config: &EnvConfig, session_store: SessionStore) -> Self {
-        AppState {
-            env_config: env_config.clone(),
-            session_store
-        }
+    fn new(env_config: &EnvConfig, session_key: &Key) -> Result<Self, sled::Error> {
+        Self::open(Path::new(""), env_config, session_key)
     }
-}
-
-#[derive(Clone, Debug, Serialize)]
-struct TokenRequest {
-    grant_type: String,
-    code: String,
-    redirect_uri: String,
-}
 
-impl TokenRequest {
-    fn new(code: String, env_config: &EnvConfig) -> Self {
-        TokenRequest {
-            grant_type: "authorization_code".to_owned(),
-            code: code.clone(),
-            redirect_uri: env_config.reddit_redirect_uri.clone(),
-        }
+    // Every database under the directory, the working one outside of tests
+    fn open(dir: &Path, env_config: &EnvConfig, session_key: &Key) -> Result<Self, sled::Error> {
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        Ok(AppState {
+            env_config: env_config.clone(),
+            session_store: SessionStore::new(&path("user-sessions"))?,
+            body_store: BodyStore::new(&path("user-body"))?,
+            food_store: FoodStore::new(&path("foods"))?,
+            meal_store: MealStore::new(&path("user-meals"))?,
+            targets_store: TargetsStore::new(&path("user-targets"))?,
+            profile_store: ProfileStore::new(&path("user-profiles"))?,
+            purchase_store: PurchaseStore::new(&path("user-purchases"))?,
+            pantry_store: PantryStore::new(&path("user-pantry"))?,
+            plan_store: PlanStore::new(&path("user-plans"))?,
+            share_store: ShareStore::new(&path("user-sharing"))?,
+            admission_store: AdmissionStore::new(&path("user-admissions"))?,
+            revocation_store: RevocationStore::new(&path("token-revocations"), session_key)?,
+            identity_store: IdentityStore::new(&path("user-identities"))?,
+            identity_providers: env_config.identity_providers(),
+            local_account_store: LocalAccountStore::new(&path("local-accounts"))?,
+            passkey_store: PasskeyStore::new(&path("user-passkeys"))?,
+            user_store: UserStore::new(&path("users"))?,
+            device_session_store: DeviceSessionStore::new(&path("device-sessions"))?,
+            api_token_store: ApiTokenStore::new(&path("api-tokens"))?,
+            webauthn: env_config.webauthn(),
+            scheduler: Scheduler::new(&path("job-statuses"))?,
+            search_cache: SearchCache::default(),
+        })
     }
-}
 
-#[derive(Clone, Debug, Deserialize)]
-struct TokenResponse {
-    access_token: String,
-    token_type: String,
-    expires_in: u64,
-    refresh_token: Option<String>,
-    scope: String,
+    // Every database, by the path new opens it at, for the jobs that go
+    // through all of them
+    fn databases(&self) -> Vec<(&'static str, &Arc<Db>)> {
+        vec![
+            ("user-sessions", &self.session_store.db),
+            ("user-body", &self.body_store.db),
+            ("foods", &self.food_store.db),
+            ("user-meals", &self.meal_store.db),
+            ("user-targets", &self.targets_store.db),
+            ("user-profiles", &self.profile_store.db),
+            ("user-purchases", &self.purchase_store.db),
+            ("user-pantry", &self.pantry_store.db),
+            ("user-plans", &self.plan_store.db),
+            ("user-sharing", &self.share_store.db),
+            ("user-admissions", &self.admission_store.db),
+            ("token-revocations", &self.revocation_store.db),
+            ("user-identities", &self.identity_store.db),
+            ("local-accounts", &self.local_account_store.db),
+            ("user-passkeys", &self.passkey_store.db),
+            ("users", &self.user_store.db),
+            ("device-sessions", &self.device_session_store.db),
+            ("api-tokens", &self.api_token_store.db),
+            ("job-statuses", &self.scheduler.db),
+        ]
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -66,7 +128,8 @@ struct SessionCookie {
     message: String,
 }
 
-#[derive(Clone, Debug)]
+// Debug by hand, it's logged at startup and the secrets stay out of it
+#[derive(Clone)]
 struct EnvConfig {
     reddit_client_id: String,
     reddit_client_secret: String,
@@ -75,11 +138,169 @@ struct EnvConfig {
     reddit_access_uri: String,
     reddit_author: String,
     reddit_get_user_uri: String,
+    // oauth.reddit.com unless pointed at a mock for testing
+    reddit_api_base: String,
+    // www.reddit.com/api/v1/revoke_token unless pointed at a stand-in
+    reddit_revoke_uri: String,
+    admission_rules: AdmissionRules,
+    // reddit usernames that can manage the waitlist
+    admins: Vec<String>,
+    // a self-hosted OpenID Connect provider, off while the issuer is empty
+    oidc_issuer: String,
+    oidc_name: String,
+    oidc_client_id: String,
+    oidc_client_secret: Option<String>,
+    oidc_redirect_uri: String,
+    oidc_scopes: String,
+    // what ID tokens are signed with when the provider's key doesn't say
+    oidc_id_token_alg: Algorithm,
+    // username/password sign-up and login, off unless LOCAL_ACCOUNTS=true
+    local_accounts: bool,
+    // reverse proxies whose X-Forwarded-For is believed, nobody else's is
+    trusted_proxies: Vec<IpAddr>,
+    // the domain passkeys are bound to, passkeys are off while it's empty
+    webauthn_rp_id: String,
+    // the full origin the site is served from, https://macros.example.com
+    webauthn_origin: String,
+    webauthn_rp_name: String,
+    // signs and encrypts the cookies, 64 bytes as hex (openssl rand -hex 64).
+    // Without one every restart signs everyone out.
+    session_key: Option<Vec<u8>>,
     log_level: LevelFilter,
 }
 
+impl fmt::Debug for EnvConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let redacted = |set: bool| if set { "<redacted>" } else { "<unset>" };
+        f.debug_struct("EnvConfig")
+            .field("reddit_client_id", &self.reddit_client_id)
+            .field("reddit_client_secret", &redacted(!self.reddit_client_secret.is_empty()))
+            .field("reddit_redirect_uri", &self.reddit_redirect_uri)
+            .field("reddit_auth_uri", &self.reddit_auth_uri)
+            .field("reddit_access_uri", &self.reddit_access_uri)
+            .field("reddit_author", &self.reddit_author)
+            .field("reddit_get_user_uri", &self.reddit_get_user_uri)
+            .field("reddit_api_base", &self.reddit_api_base)
+            .field("reddit_revoke_uri", &self.reddit_revoke_uri)
+            .field("admission_rules", &self.admission_rules)
+            .field("admins", &self.admins)
+            .field("oidc_issuer", &self.oidc_issuer)
+            .field("oidc_name", &self.oidc_name)
+            .field("oidc_client_id", &self.oidc_client_id)
+            .field("oidc_client_secret", &redacted(self.oidc_client_secret.is_some()))
+            .field("oidc_redirect_uri", &self.oidc_redirect_uri)
+            .field("oidc_scopes", &self.oidc_scopes)
+            .field("oidc_id_token_alg", &self.oidc_id_token_alg)
+            .field("local_accounts", &self.local_accounts)
+            .field("trusted_proxies", &self.trusted_proxies)
+            .field("webauthn_rp_id", &self.webauthn_rp_id)
+            .field("webauthn_origin", &self.webauthn_origin)
+            .field("webauthn_rp_name", &self.webauthn_rp_name)
+            .field("session_key", &redacted(self.session_key.is_some()))
+            .field("log_level", &self.log_level)
+            .finish()
+    }
+}
+
+// "00ff.." into bytes, None if it isn't hex
+fn hex_bytes(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    if value.len() % 2 != 0 {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// "a, b,c" from the .env into ["a", "b", "c"]
+fn list(value: Option<&str>) -> Vec<String> {
+    value.unwrap_or_default()
+        .split(',')
+        .map(|item| item.trim().to_owned())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
 
 impl EnvConfig {
+    fn is_admin(&self, name: &str) -> bool {
+        self.admins.iter().any(|admin| admin.eq_ignore_ascii_case(name))
+    }
+
+    fn reddit_api(&self) -> RedditApi {
+        RedditApi {
+            api_base: self.reddit_api_base.clone(),
+            user_agent: self.reddit_author.clone(),
+        }
+    }
+
+    // The configured cookie key, or a throwaway one
+    fn session_key(&self) -> Key {
+        match self.session_key.as_deref().map(Key::try_from) {
+            Some(Ok(key)) => key,
+            Some(Err(e)) => panic!("[ERROR]: SESSION_KEY must be at least 64 bytes: {}", e),
+            None => {
+                warn!("[WARN]: No SESSION_KEY set, everyone is signed out on restart");
+                Key::generate()
+            },
+        }
+    }
+
+    // Reddit always, plus the OpenID Connect provider when configured
+    fn identity_providers(&self) -> IdentityProviders {
+        let mut providers = IdentityProviders::default();
+        // mysubreddits only asked for when admission looks at subscriptions
+        let scope = if self.admission_rules.subreddits.is_empty() { "identity" } else { "identity%20mysubreddits" };
+        providers.add(Arc::new(RedditProvider {
+            client_id: self.reddit_client_id.clone(),
+            client_secret: self.reddit_client_secret.clone(),
+            redirect_uri: self.reddit_redirect_uri.clone(),
+            auth_uri: self.reddit_auth_uri.clone(),
+            access_uri: self.reddit_access_uri.clone(),
+            get_user_uri: self.reddit_get_user_uri.clone(),
+            user_agent: self.reddit_author.clone(),
+            scope: scope.to_owned(),
+        }));
+        if !self.oidc_issuer.is_empty() {
+            providers.add(Arc::new(OidcProvider::new(
+                "oidc".to_owned(),
+                self.oidc_name.clone(),
+                self.oidc_issuer.clone(),
+                self.oidc_client_id.clone(),
+                self.oidc_client_secret.clone(),
+                self.oidc_redirect_uri.clone(),
+                self.oidc_scopes.clone(),
+                self.oidc_id_token_alg,
+            )));
+        }
+        providers
+    }
+
+    // A misconfigured origin only turns passkeys off, the rest still runs
+    fn webauthn(&self) -> Option<Arc<Webauthn>> {
+        if self.webauthn_rp_id.is_empty() {
+            return None;
+        }
+        let origin = match Url::parse(&self.webauthn_origin) {
+            Err(e) => {
+                error!("[ERROR]: Invalid WEBAUTHN_ORIGIN {}: {}", self.webauthn_origin, e);
+                return None;
+            },
+            Ok(origin) => origin,
+        };
+        let webauthn = WebauthnBuilder::new(&self.webauthn_rp_id, &origin)
+            .map(|builder| builder.rp_name(&self.webauthn_rp_name))
+            .and_then(|builder| builder.build());
+        match webauthn {
+            Err(e) => {
+                error!("[ERROR]: Unable to set up passkeys: {}", e);
+                None
+            },
+            Ok(webauthn) => Some(Arc::new(webauthn)),
+        }
+    }
+
     fn is_empty(&self) -> bool {
         self.reddit_redirect_uri.is_empty() || self.reddit_client_id.is_empty() || self.reddit_client_secret.is_empty() || self.reddit_access_uri.is_empty()
     }
@@ -93,6 +314,23 @@ impl EnvConfig {
             reddit_access_uri: String::new(),
             reddit_author: String::new(),
             reddit_get_user_uri: String::new(),
+            reddit_api_base: "https://oauth.reddit.com".to_owned(),
+            reddit_revoke_uri: "https://www.reddit.com/api/v1/revoke_token".to_owned(),
+            admission_rules: AdmissionRules::default(),
+            admins: Vec::new(),
+            oidc_issuer: String::new(),
+            oidc_name: "OpenID Connect".to_owned(),
+            oidc_client_id: String::new(),
+            oidc_client_secret: None,
+            oidc_redirect_uri: String::new(),
+            oidc_scopes: "openid profile email".to_owned(),
+            oidc_id_token_alg: Algorithm::RS256,
+            local_accounts: false,
+            trusted_proxies: Vec::new(),
+            webauthn_rp_id: String::new(),
+            webauthn_origin: String::new(),
+            webauthn_rp_name: "AB macros".to_owned(),
+            session_key: None,
             log_level: LevelFilter::Off,
         };
         let env_file = include_str!(".env");
@@ -110,6 +348,26 @@ impl EnvConfig {
                        "REDDIT_ACCESS_URI" => env_config.reddit_access_uri = line_parts.next().expect("[ERORR] Missing reddit access uri!").to_owned(),
                        "REDDIT_AUTHOR" => env_config.reddit_author = line_parts.next().expect("[ERORR] Missing reddit author").to_owned(),
                        "REDDIT_GET_USER_URI" => env_config.reddit_get_user_uri = line_parts.next().expect("[ERORR] Missing reddit get_user uri !").to_owned(),
+                       "REDDIT_API_BASE" => env_config.reddit_api_base = line_parts.next().expect("[ERROR]: Missing reddit api base!").to_owned(),
+                       "REDDIT_REVOKE_URI" => env_config.reddit_revoke_uri = line_parts.next().expect("[ERROR]: Missing reddit revoke uri!").to_owned(),
+                       "ADMISSION_MIN_ACCOUNT_AGE_DAYS" => env_config.admission_rules.min_account_age_days = Some(line_parts.next().and_then(|days| days.trim().parse().ok()).expect("[ERROR]: Invalid minimum account age!")),
+                       "ADMISSION_MIN_KARMA" => env_config.admission_rules.min_karma = Some(line_parts.next().and_then(|karma| karma.trim().parse().ok()).expect("[ERROR]: Invalid minimum karma!")),
+                       "ADMISSION_SUBREDDITS" => env_config.admission_rules.subreddits = list(line_parts.next()).iter().map(|name| name.trim_start_matches("r/").to_lowercase()).collect(),
+                       "ADMISSION_OVERRIDES" => env_config.admission_rules.overrides = list(line_parts.next()),
+                       "ADMINS" => env_config.admins = list(line_parts.next()),
+                       "OIDC_ISSUER" => env_config.oidc_issuer = line_parts.next().expect("[ERROR]: Missing OIDC issuer!").to_owned(),
+                       "OIDC_NAME" => env_config.oidc_name = line_parts.next().expect("[ERROR]: Missing OIDC name!").to_owned(),
+                       "OIDC_CLIENT_ID" => env_config.oidc_client_id = line_parts.next().expect("[ERROR]: Missing OIDC client id!").to_owned(),
+                       "OIDC_CLIENT_SECRET" => env_config.oidc_client_secret = line_parts.next().filter(|secret| !secret.is_empty()).map(str::to_owned),
+                       "OIDC_REDIRECT_URI" => env_config.oidc_redirect_uri = line_parts.next().expect("[ERROR]: Missing OIDC redirect uri!").to_owned(),
+                       "OIDC_SCOPES" => env_config.oidc_scopes = line_parts.next().expect("[ERROR]: Missing OIDC scopes!").to_owned(),
+                       "OIDC_ID_TOKEN_ALG" => env_config.oidc_id_token_alg = line_parts.next().and_then(|alg| alg.parse().ok()).expect("[ERROR]: OIDC_ID_TOKEN_ALG must be a JWT algorithm like RS256!"),
+                       "WEBAUTHN_RP_ID" => env_config.webauthn_rp_id = line_parts.next().expect("[ERROR]: Missing WebAuthn relying party id!").to_owned(),
+                       "WEBAUTHN_ORIGIN" => env_config.webauthn_origin = line_parts.next().expect("[ERROR]: Missing WebAuthn origin!").to_owned(),
+                       "WEBAUTHN_RP_NAME" => env_config.webauthn_rp_name = line_parts.next().expect("[ERROR]: Missing WebAuthn relying party name!").to_owned(),
+                       "SESSION_KEY" => env_config.session_key = Some(line_parts.next().and_then(hex_bytes).expect("[ERROR]: SESSION_KEY must be hex!")),
+                       "TRUSTED_PROXIES" => env_config.trusted_proxies = list(line_parts.next()).iter().map(|ip| ip.parse().expect("[ERROR]: TRUSTED_PROXIES must be IP addresses!")).collect(),
+                       "LOCAL_ACCOUNTS" => env_config.local_accounts = line_parts.next().is_some_and(|enabled| enabled.trim().eq_ignore_ascii_case("true")),
                        "RUST_LOG" => match line_parts.next().expect("[ERROR]: No RUST_LOG set at build time.").to_lowercase().as_str() {
                            "off" => env_config.log_level = LevelFilter::Off,
                            "error" => env_config.log_level = LevelFilter::Error,
@@ -134,68 +392,6 @@ impl EnvConfig {
     }
 }
 
-#[derive(Debug)]
-struct RedditLoginError(String);
-
-impl Error for RedditLoginError {}
-
-impl fmt::Display for RedditLoginError {
-  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-      write!(f, "{}", self.0)
-  }
-}
-
-async fn get_reddit_access_token(auth_code: String, env_config: &EnvConfig) -> Result<TokenResponse, Box<dyn Error>> {
-    let client = Client::new();
-    let token_request = TokenRequest::new(auth_code, env_config);
-    info!("[TOKEN REQUEST]: {:?}", token_request);
-    let token_result = client.post(env_config.reddit_access_uri.clone())
-        .basic_auth(env_config.reddit_client_id.clone(), Some(env_config.reddit_client_secret.clone()))
-        .header("User-Agent", env_config.reddit_author.clone())
-        .form(&token_request);
-    info!("[ACCESS TOKEN REQUEST]: {:?}", token_result);
-    let token_result = token_result.send().await;
-    match token_result {
-        Err(e) => Err(Box::new(RedditLoginError(e.to_string()))), 
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.json::<TokenResponse>().await {
-                    Err(e) => Err(Box::new(RedditLoginError(e.to_string()))),
-                    Ok(token_data) => {
-                        info!("[SUCCESS]: Obtained reddit access token.");
-                        Ok(token_data)
-                    }
-                }
-            } else {
-                let error_msg = format!("[ERROR]: Reddit token exchange failed with status: {}", response.status());
-                error!("{}", error_msg);
-                Err(Box::new(RedditLoginError(error_msg)))
-            }
-        }
-    }
-}
-
-async fn get_reddit_user(token_response: TokenResponse, env_config: &EnvConfig) -> Result<UserSession, Box<dyn Error>> {
-    let client = Client::new();
-    let reddit_user = client.get(env_config.reddit_get_user_uri.clone())
-        .bearer_auth(token_response.access_token.clone())
-        .header("User-Agent", env_config.reddit_author.clone())
-        .send()
-        .await?
-        .json::<RedditUser>()
-        .await?;
-    let right_now = Local::now();
-    let expiration_time = Duration::seconds(token_response.expires_in as i64);
-    let user_session = UserSession {
-        reddit_user,
-        reddit_access_token: token_response.access_token,
-        reddit_refresh_token: token_response.refresh_token,
-        expires_at: right_now + expiration_time,
-    };
-    info!("[INFO]: User session: {:?}", user_session);
-    Ok(user_session)
-}
-
 async fn auth_middleware(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, ActixError> {
     match req.cookie("session") {
         None => error!("[ERROR]: no session cookie found! "),
@@ -229,57 +425,6 @@ async fn auth_success(data: web::Data<AppState>) -> impl Responder {
     HttpResponse::Ok()
 }
 
-// // Route to initiate the OAuth flow
-#[get("/login/reddit")]
-async fn reddit_login(data: web::Data<AppState>) -> impl Responder {
-    // Create the Reddit authorization URL
-    // client_id={}&response_type=code&state=randomstate&redirect_uri={}&duration=permanent&scope=identity
-    let auth_url = format!("{}client_id={}&response_type=code&state=randomstate&redirect_uri={}&duration=permanent&scope=identity",
-        data.env_config.reddit_auth_uri,
-        data.env_config.reddit_client_id, data.env_config.reddit_redirect_uri
-    );
-    info!("[AUTH URL]: {}", auth_url);
-    // Redirect the user to Reddit's authorization page
-    HttpResponse::Found()
-        .insert_header((header::LOCATION, auth_url))
-        .finish()
-}
-
-#[get("/login/reddit/callback")]
-async fn reddit_login_callback(query: web::Query<HashMap<String,String>>, data: web::Data<AppState>) -> impl Responder {
-    info!("[INFO]: Received callback query: {:?}", query);
-    let code = query.get("code");
-    let state = query.get("state");
-    info!("Code: {:?}, State: {:?}", code, state);
-    match code {
-        None => HttpResponse::BadRequest().finish(),
-        Some(code_value) => {
-            // get access token to fetch username
-            match get_reddit_access_token(code_value.to_string(), &data.env_config).await {
-                Err(_e) => HttpResponse::InternalServerError().finish(),
-                Ok(token) => {
-                    println!("token: {:?}", token);
-                    // get reddit username
-                    match get_reddit_user(token, &data.env_config).await {
-                        Err(_e) => HttpResponse::InternalServerError().finish(),
-                        Ok(session) => {
-                            // save user session on backend
-                            match data.session_store.save_session(&session.reddit_user.id, &session).await {
-                                Err(_e) => HttpResponse::InternalServerError().finish(),
-                                Ok(_) => {
-                                    // set cookie up
-                                    info!("[SUCCESS]: User session successfully created!");
-                                    HttpResponse::Ok().finish()
-                                }
-                            }
-                        }
-                    }
-                },
-            }
-        } 
-    }
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let env_config = EnvConfig::new();
@@ -287,15 +432,82 @@ async fn main() -> std::io::Result<()> {
     Builder::new()
         .filter_level(env_config.log_level)
         .init();
-    let session_store = SessionStore::new("user-sessions")?;
-    let app_state = web::Data::new(AppState::new(&env_config, session_store));
+    // Admin imports run instead of the server:
+    //   backend import-off <export.jsonl|export.csv>    Open Food Facts into the barcode index
+    //   backend import-fdc <csv dir|file.json> [version] FoodData Central into the catalogue
+    //   backend reset-password <username>                 a reset code for a local account
+    let args: Vec<String> = env::args().collect();
+    match args.as_slice() {
+        [_, command, username] if command == "reset-password" => {
+            let local_account_store = LocalAccountStore::new("local-accounts")?;
+            let issued = match local_account_store.get_account(&username.to_lowercase()).await {
+                Err(e) => return Err(std::io::Error::other(e.to_string())),
+                Ok(None) => {
+                    println!("No local account called {}", username);
+                    return Ok(());
+                },
+                Ok(Some(account)) => local_account_store.issue_reset_token(&account.username)
+                    .await
+                    .map_err(|e| std::io::Error::other(e.to_string()))?,
+            };
+            println!("Reset code for {} (valid until {}):", username, issued.expires_at.format("%Y-%m-%d %H:%M"));
+            println!("{}", issued.token);
+            return Ok(());
+        },
+        [_, command, path] if command == "import-off" => {
+            let food_store = FoodStore::new("foods")?;
+            let stats = models::barcode::import_open_food_facts(&food_store, std::path::Path::new(path))
+                .await
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            info!("[INFO]: Open Food Facts import done: {:?}", stats);
+            println!("Imported {} of {} products ({} skipped)", stats.imported, stats.read, stats.skipped);
+            return Ok(());
+        },
+        [_, command, path, rest @ ..] if command == "import-fdc" && rest.len() <= 1 => {
+            let food_store = FoodStore::new("foods")?;
+            let report = models::fdc::import_food_data_central(&food_store, std::path::Path::new(path), rest.first().map(String::as_str))
+                .await
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            info!("[INFO]: FoodData Central import done: {} imported, {} skipped", report.imported, report.skipped);
+            println!("Imported {} of {} foods ({} skipped)", report.imported, report.read, report.skipped);
+            println!("Nutrients with nowhere to go:");
+            for nutrient in &report.unmapped {
+                println!("  {:>5}  {} ({}) in {} foods", nutrient.id, nutrient.name, nutrient.unit, nutrient.foods);
+            }
+            return Ok(());
+        },
+        _ => {},
+    }
+    let session_key = env_config.session_key();
+    let app_state = web::Data::new(AppState::new(&env_config, &session_key)?);
+    match api::users::migrate_legacy_users(&app_state).await {
+        Err(e) => error!("[ERROR]: Unable to migrate legacy users: {}", e),
+        Ok(migrated) => info!("[INFO]: {} legacy users moved to new ids", migrated),
+    }
+    match api::admin::flag_configured_admins(&app_state).await {
+        Err(e) => error!("[ERROR]: Unable to flag configured admins: {}", e),
+        Ok(flagged) => info!("[INFO]: {} admins from ADMINS", flagged),
+    }
+    // session sweeping, token refresh and the like
+    if let Err(e) = api::jobs::schedule(&app_state) {
+        error!("[ERROR]: Unable to schedule background jobs: {}", e);
+    }
     info!("[INFO] Environment config: {:?}", env_config);
     HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
+            .wrap(
+                SessionMiddleware::builder(CookieSessionStore::default(), session_key.clone())
+                    .cookie_name("session".to_owned())
+                    .build()
+            )
             .service(echo)
-            .service(reddit_login)
-            .service(reddit_login_callback)
+            .configure(api::login::configure)
+            .service(
+                web::scope("/api")
+                    .wrap(from_fn(api::sessions::session_middleware))
+                    .configure(api::configure)
+            )
             .service(
                 web::scope("/protected")
                     .wrap(from_fn(auth_middleware))
@@ -0,0 +1,344 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::sync::Arc;
+use std::error::Error;
+use chrono::{DateTime, Local, TimeZone};
+
+use crate::models::reddit::RedditApi;
+use crate::models::session::RedditUser;
+
+// most anyone is subscribed to fewer, and it bounds the paging
+const MAX_SUBSCRIPTION_PAGES: usize = 10;
+
+// Who gets in on first login. Any one rule passing is enough, and with no
+// rules set everyone does.
+#[derive(Clone, Debug, Default)]
+pub struct AdmissionRules {
+    pub min_account_age_days: Option<i64>,
+    pub min_karma: Option<i64>,
+    // lower case, without the r/
+    pub subreddits: Vec<String>,
+    // reddit usernames let in whatever the rules say
+    pub overrides: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Admission {
+    Admitted,
+    // not yet, with why
+    Waitlisted(String),
+}
+
+// What admission is kept under for sign-ins other than reddit
+pub fn applicant_id(provider: &str, subject: &str) -> String {
+    format!("{}:{}", provider, subject)
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WaitlistEntry {
+    // the reddit id, or applicant_id for other sign-ins. A waitlisted
+    // account has no internal user yet.
+    pub user_id: String,
+    pub name: String,
+    pub reason: String,
+    pub requested_at: DateTime<Local>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Listing {
+    data: ListingData,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListingData {
+    children: Vec<Child>,
+    after: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Child {
+    data: SubredditData,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubredditData {
+    display_name: String,
+}
+
+impl AdmissionRules {
+    pub fn is_open(&self) -> bool {
+        self.min_account_age_days.is_none() && self.min_karma.is_none() && self.subreddits.is_empty()
+    }
+
+    pub fn is_override(&self, name: &str) -> bool {
+        self.overrides.iter().any(|allowed| allowed.eq_ignore_ascii_case(name))
+    }
+
+    // What the waitlist page tells people they need
+    pub fn describe(&self) -> Vec<String> {
+        let mut rules = Vec::new();
+        if let Some(days) = self.min_account_age_days {
+            rules.push(format!("a Reddit account at least {} days old", days));
+        }
+        if let Some(karma) = self.min_karma {
+            rules.push(format!("at least {} karma", karma));
+        }
+        if !self.subreddits.is_empty() {
+            let names: Vec<String> = self.subreddits.iter().map(|name| format!("r/{}", name)).collect();
+            rules.push(format!("a subscription to {}", names.join(" or ")));
+        }
+        rules
+    }
+
+    // Age and karma come with the login, subscriptions are only looked up
+    // when those weren't enough
+    pub async fn check(&self, api: &RedditApi, access_token: &str, user: &RedditUser) -> Result<Admission, Box<dyn Error>> {
+        if self.is_open() || self.is_override(&user.name) {
+            return Ok(Admission::Admitted);
+        }
+        if let (Some(days), Some(created)) = (self.min_account_age_days, user.created_utc) {
+            let created = Local.timestamp_opt(created as i64, 0).single().ok_or("invalid account creation time")?;
+            if (Local::now() - created).num_days() >= days {
+                return Ok(Admission::Admitted);
+            }
+        }
+        if let Some(karma) = self.min_karma {
+            if user.link_karma + user.comment_karma >= karma {
+                return Ok(Admission::Admitted);
+            }
+        }
+        if !self.subreddits.is_empty() {
+            let subscribed = api.subscriptions(access_token).await?;
+            if subscribed.iter().any(|name| self.subreddits.contains(&name.to_lowercase())) {
+                return Ok(Admission::Admitted);
+            }
+        }
+        Ok(Admission::Waitlisted(format!("Needs {}", self.describe().join(", or "))))
+    }
+}
+
+impl RedditApi {
+    // Names of the subreddits the user is subscribed to, needs the mysubreddits scope
+    pub async fn subscriptions(&self, access_token: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let client = Client::new();
+        let mut names = Vec::new();
+        let mut after: Option<String> = None;
+        for _ in 0..MAX_SUBSCRIPTION_PAGES {
+            let mut request = client.get(format!("{}/subreddits/mine/subscriber", self.api_base.trim_end_matches('/')))
+                .bearer_auth(access_token)
+                .header("User-Agent", self.user_agent.clone())
+                .query(&[("limit", "100")]);
+            if let Some(after) = &after {
+                request = request.query(&[("after", after)]);
+            }
+            let response = request.send().await?;
+            if !response.status().is_success() {
+                return Err(format!("Reddit answered {} to the subscription list", response.status()).into());
+            }
+            let listing = response.json::<Listing>().await?;
+            names.extend(listing.data.children.into_iter().map(|child| child.data.display_name));
+            match listing.data.after {
+                None => break,
+                next => after = next,
+            }
+        }
+        Ok(names)
+    }
+}
+
+// Who has been let in and who is waiting, keyed by reddit user id
+#[derive(Clone, Debug)]
+pub struct AdmissionStore {
+    pub db: Arc<Db>,
+}
+
+impl AdmissionStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(AdmissionStore {
+            db: Arc::new(db),
+        })
+    }
+
+    // account deletion, they go through the rules again if they come back
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        self.db.remove(format!("admitted:{}", user_id).as_bytes())?;
+        self.db.remove(format!("waitlist:{}", user_id).as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn is_admitted(&self, user_id: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self.db.contains_key(format!("admitted:{}", user_id).as_bytes())?)
+    }
+
+    // Also takes them off the waitlist
+    pub async fn admit(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        self.db.insert(format!("admitted:{}", user_id).as_bytes(), Local::now().to_rfc3339().as_bytes())?;
+        self.db.remove(format!("waitlist:{}", user_id).as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn add_to_waitlist(&self, entry: &WaitlistEntry) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(entry)?;
+        self.db.insert(format!("waitlist:{}", entry.user_id).as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_waitlist_entry(&self, user_id: &str) -> Result<Option<WaitlistEntry>, Box<dyn Error>> {
+        if let Some(data) = self.db.get(format!("waitlist:{}", user_id).as_bytes())? {
+            Ok(Some(serde_json::from_slice(&data)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn get_waitlist(&self) -> Result<Vec<WaitlistEntry>, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for item in self.db.scan_prefix(b"waitlist:") {
+            let (_, value) = item?;
+            entries.push(serde_json::from_slice::<WaitlistEntry>(&value)?);
+        }
+        entries.sort_by_key(|entry| entry.requested_at);
+        Ok(entries)
+    }
+
+    pub async fn remove_from_waitlist(&self, user_id: &str) -> Result<bool, Box<dyn Error>> {
+        let removed = self.db.remove(format!("waitlist:{}", user_id).as_bytes())?;
+        self.db.flush()?;
+        Ok(removed.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+
+    // A stand-in for reddit answering each connection with the next page, see
+    // the one in reddit.rs. Gives its base url and the request lines it got.
+    fn mock_reddit_pages(pages: Vec<String>) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let (sent, received) = channel();
+        thread::spawn(move || {
+            for page in pages {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                sent.send(request_line).unwrap();
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", page.len(), page);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (base, received)
+    }
+
+    fn page(names: &[&str], after: Option<&str>) -> String {
+        let children: Vec<String> = names.iter().map(|name| format!(r#"{{"data":{{"display_name":"{}"}}}}"#, name)).collect();
+        match after {
+            Some(after) => format!(r#"{{"data":{{"children":[{}],"after":"{}"}}}}"#, children.join(","), after),
+            None => format!(r#"{{"data":{{"children":[{}],"after":null}}}}"#, children.join(",")),
+        }
+    }
+
+    fn api(api_base: String) -> RedditApi {
+        RedditApi { api_base, user_agent: "abmacros-test".to_owned() }
+    }
+
+    // nothing listens here, for rules that shouldn't need reddit
+    fn no_reddit() -> RedditApi {
+        api("http://127.0.0.1:9".to_owned())
+    }
+
+    fn user(age_days: i64, karma: i64) -> RedditUser {
+        RedditUser {
+            name: "alice".to_owned(),
+            id: "t2_abc".to_owned(),
+            created_utc: Some((Local::now() - Duration::days(age_days)).timestamp() as f64),
+            link_karma: karma / 2,
+            comment_karma: karma - karma / 2,
+        }
+    }
+
+    fn waitlisted(admission: Admission) -> bool {
+        matches!(admission, Admission::Waitlisted(_))
+    }
+
+    #[actix_web::test]
+    async fn open_rules_and_overrides_admit() {
+        assert_eq!(AdmissionRules::default().check(&no_reddit(), "token", &user(0, 0)).await.unwrap(), Admission::Admitted);
+        let rules = AdmissionRules { min_karma: Some(1000), overrides: vec!["Alice".to_owned()], ..AdmissionRules::default() };
+        assert_eq!(rules.check(&no_reddit(), "token", &user(0, 0)).await.unwrap(), Admission::Admitted);
+    }
+
+    #[actix_web::test]
+    async fn checks_account_age() {
+        let rules = AdmissionRules { min_account_age_days: Some(365), ..AdmissionRules::default() };
+        assert_eq!(rules.check(&no_reddit(), "token", &user(400, 0)).await.unwrap(), Admission::Admitted);
+        assert_eq!(
+            rules.check(&no_reddit(), "token", &user(10, 0)).await.unwrap(),
+            Admission::Waitlisted("Needs a Reddit account at least 365 days old".to_owned()),
+        );
+        // no creation time, no way to pass on age
+        let unknown = RedditUser { created_utc: None, ..user(400, 0) };
+        assert!(waitlisted(rules.check(&no_reddit(), "token", &unknown).await.unwrap()));
+    }
+
+    #[actix_web::test]
+    async fn checks_karma() {
+        let rules = AdmissionRules { min_karma: Some(100), ..AdmissionRules::default() };
+        // link and comment karma together
+        assert_eq!(rules.check(&no_reddit(), "token", &user(0, 100)).await.unwrap(), Admission::Admitted);
+        assert!(waitlisted(rules.check(&no_reddit(), "token", &user(0, 99)).await.unwrap()));
+    }
+
+    #[actix_web::test]
+    async fn checks_subscriptions() {
+        let rules = AdmissionRules { subreddits: vec!["carnivore".to_owned()], ..AdmissionRules::default() };
+        let (base, received) = mock_reddit_pages(vec![page(&["AskReddit", "Carnivore"], None)]);
+        assert_eq!(rules.check(&api(base), "token", &user(0, 0)).await.unwrap(), Admission::Admitted);
+        assert!(received.recv().unwrap().starts_with("GET /subreddits/mine/subscriber?limit=100 "));
+
+        let (base, _received) = mock_reddit_pages(vec![page(&["AskReddit"], None)]);
+        assert_eq!(
+            rules.check(&api(base), "token", &user(0, 0)).await.unwrap(),
+            Admission::Waitlisted("Needs a subscription to r/carnivore".to_owned()),
+        );
+    }
+
+    #[actix_web::test]
+    async fn age_or_karma_save_the_subscription_lookup() {
+        let rules = AdmissionRules { min_karma: Some(100), subreddits: vec!["carnivore".to_owned()], ..AdmissionRules::default() };
+        assert_eq!(rules.check(&no_reddit(), "token", &user(0, 500)).await.unwrap(), Admission::Admitted);
+    }
+
+    #[actix_web::test]
+    async fn stops_paging_subscriptions() {
+        // reddit always has another page
+        let pages = (0..=MAX_SUBSCRIPTION_PAGES).map(|n| page(&[format!("sub{}", n).as_str()], Some(format!("t5_{}", n).as_str()))).collect();
+        let (base, received) = mock_reddit_pages(pages);
+        let names = api(base).subscriptions("token").await.unwrap();
+        assert_eq!(names.len(), MAX_SUBSCRIPTION_PAGES);
+        let requests: Vec<String> = received.try_iter().collect();
+        assert_eq!(requests.len(), MAX_SUBSCRIPTION_PAGES);
+        // each page asks for the one after the last
+        assert!(!requests[0].contains("after="));
+        assert!(requests[1].contains("after=t5_0"));
+    }
+}
@@ -0,0 +1,193 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::models::user::move_prefix;
+use crate::models::secret::token_hash;
+
+// so a leaked one is easy to recognise and grep for
+pub const TOKEN_PREFIX: &str = "abm_";
+pub const MAX_TOKEN_DAYS: i64 = 365;
+// last_used_at is only written this often, scripts can be chatty
+const TOUCH_MINUTES: i64 = 5;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum TokenScope {
+    // any GET but the account itself, see api::api_tokens::required_scope
+    #[serde(rename = "read")]
+    Read,
+    // logging and deleting meals
+    #[serde(rename = "write:meals")]
+    WriteMeals,
+    // the /admin routes, for admins only
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenScope::Read => write!(f, "read"),
+            TokenScope::WriteMeals => write!(f, "write:meals"),
+            TokenScope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+// A personal token for scripts, sent as Authorization: Bearer. Only the
+// hash is stored, the token itself is shown once when it's made.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: DateTime<Local>,
+    pub expires_at: DateTime<Local>,
+    pub last_used_at: Option<DateTime<Local>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IssuedApiToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+}
+
+impl ApiToken {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Local::now()
+    }
+
+    pub fn allows(&self, scope: TokenScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    fn needs_touch(&self) -> bool {
+        self.last_used_at.map_or(true, |last_used_at| Local::now() - last_used_at > Duration::minutes(TOUCH_MINUTES))
+    }
+}
+
+// Tokens by hash, and an index of each user's token ids to their hash
+#[derive(Clone, Debug)]
+pub struct ApiTokenStore {
+    pub db: Arc<Db>,
+}
+
+impl ApiTokenStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(ApiTokenStore {
+            db: Arc::new(db),
+        })
+    }
+
+    pub async fn create_token(&self, user_id: &str, name: &str, scopes: Vec<TokenScope>, days: i64) -> Result<IssuedApiToken, Box<dyn Error>> {
+        let token = format!("{}{}{}", TOKEN_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let now = Local::now();
+        let api_token = ApiToken {
+            id: Uuid::new_v4().simple().to_string(),
+            user_id: user_id.to_owned(),
+            name: name.to_owned(),
+            scopes,
+            created_at: now,
+            expires_at: now + Duration::days(days),
+            last_used_at: None,
+        };
+        let hash = token_hash(&token);
+        self.db.insert(format!("token:{}", hash).as_bytes(), serde_json::to_vec(&api_token)?)?;
+        self.db.insert(format!("user:{}:{}", user_id, api_token.id).as_bytes(), hash.as_bytes())?;
+        self.db.flush()?;
+        Ok(IssuedApiToken { token, api_token })
+    }
+
+    // Expired ones too, the caller decides
+    pub async fn get_token(&self, token: &str) -> Result<Option<ApiToken>, Box<dyn Error>> {
+        if let Some(data) = self.db.get(format!("token:{}", token_hash(token)).as_bytes())? {
+            Ok(Some(serde_json::from_slice(&data)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Keeps last_used_at roughly up to date
+    pub async fn touch(&self, token: &str, api_token: &mut ApiToken) -> Result<(), Box<dyn Error>> {
+        let key = format!("token:{}", token_hash(token));
+        // not bringing back one deleted in the meantime
+        if api_token.needs_touch() && self.db.contains_key(key.as_bytes())? {
+            api_token.last_used_at = Some(Local::now());
+            self.db.insert(key.as_bytes(), serde_json::to_vec(api_token)?)?;
+            self.db.flush()?;
+        }
+        Ok(())
+    }
+
+    // Newest first
+    pub async fn get_user_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>, Box<dyn Error>> {
+        let mut tokens = Vec::new();
+        for item in self.db.scan_prefix(format!("user:{}:", user_id).as_bytes()) {
+            let (_, hash) = item?;
+            let hash = String::from_utf8(hash.to_vec())?;
+            if let Some(data) = self.db.get(format!("token:{}", hash).as_bytes())? {
+                tokens.push(serde_json::from_slice::<ApiToken>(&data)?);
+            }
+        }
+        tokens.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(tokens)
+    }
+
+    pub async fn delete_token(&self, user_id: &str, id: &str) -> Result<bool, Box<dyn Error>> {
+        let removed = self.db.remove(format!("user:{}:{}", user_id, id).as_bytes())?;
+        if let Some(hash) = &removed {
+            self.db.remove(format!("token:{}", String::from_utf8(hash.to_vec())?).as_bytes())?;
+        }
+        self.db.flush()?;
+        Ok(removed.is_some())
+    }
+
+    // for the session sweep
+    pub async fn delete_expired(&self) -> Result<usize, Box<dyn Error>> {
+        let mut expired = Vec::new();
+        for item in self.db.scan_prefix(b"token:") {
+            let (_, data) = item?;
+            let api_token: ApiToken = serde_json::from_slice(&data)?;
+            if api_token.is_expired() {
+                expired.push(api_token);
+            }
+        }
+        for api_token in &expired {
+            self.delete_token(&api_token.user_id, &api_token.id).await?;
+        }
+        Ok(expired.len())
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let prefix = format!("user:{}:", from);
+        let mut moved = 0;
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (_, hash) = item?;
+            let key = format!("token:{}", String::from_utf8(hash.to_vec())?);
+            if let Some(data) = self.db.get(key.as_bytes())? {
+                let mut api_token: ApiToken = serde_json::from_slice(&data)?;
+                api_token.user_id = to.to_owned();
+                self.db.insert(key.as_bytes(), serde_json::to_vec(&api_token)?)?;
+                moved += 1;
+            }
+        }
+        move_prefix(&self.db, &prefix, &format!("user:{}:", to))?;
+        Ok(moved)
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        for api_token in self.get_user_tokens(user_id).await? {
+            self.delete_token(user_id, &api_token.id).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,390 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::hash::Hash;
+use std::io::{Cursor, Read, Write};
+use chrono::{DateTime, Local};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::models::body::BodyMetric;
+use crate::models::cost::Purchase;
+use crate::models::csv;
+use crate::models::food::Food;
+use crate::models::meal::MealEntry;
+use crate::models::pantry::PantryItem;
+use crate::models::plan::{PlannedMeal, PrepTask};
+use crate::models::profile::UserProfile;
+use crate::models::sourcing::check_sourcing;
+use crate::models::targets::Targets;
+
+// Bump when a field changes meaning, restores refuse anything newer
+pub const ARCHIVE_VERSION: u32 = 1;
+
+// the JSON copy inside the zip, which is what a restore reads
+const ARCHIVE_FILE: &str = "archive.json";
+// what that may unpack to, well past a real diary but short of a zip bomb
+const MAX_ARCHIVE_FILE_BYTES: u64 = 512 * 1024 * 1024;
+
+// Everything one user has put in. Tables added after version 1 need a
+// serde default so older archives still restore. There are no recipes in
+// here because there's nowhere to keep them yet, they join as a new table
+// once there is.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Archive {
+    pub version: u32,
+    pub exported_at: DateTime<Local>,
+    pub profile: UserProfile,
+    pub targets: Targets,
+    #[serde(default)]
+    pub custom_foods: Vec<Food>,
+    #[serde(default)]
+    pub entries: Vec<MealEntry>,
+    #[serde(default)]
+    pub body_metrics: Vec<BodyMetric>,
+    #[serde(default)]
+    pub purchases: Vec<Purchase>,
+    #[serde(default)]
+    pub pantry: Vec<PantryItem>,
+    #[serde(default)]
+    pub planned_meals: Vec<PlannedMeal>,
+    #[serde(default)]
+    pub prep_tasks: Vec<PrepTask>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    // keep what the account already has
+    #[default]
+    Skip,
+    // overwrite it with the archive's copy
+    Replace,
+}
+
+// How an enum serializes, for CSV cells
+fn label<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(text)) => text,
+        _ => String::new(),
+    }
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn entries_csv(entries: &[MealEntry]) -> String {
+    let records: Vec<Vec<String>> = entries.iter()
+        .map(|entry| vec![
+            entry.id.to_string(),
+            entry.eaten_at.to_rfc3339(),
+            entry.food_id.clone(),
+            entry.food_name.clone(),
+            label(&entry.category),
+            entry.grams.to_string(),
+            entry.protein_g.to_string(),
+            entry.fat_g.to_string(),
+            entry.carbs_g.to_string(),
+            entry.calories.to_string(),
+            entry.omega3_mg.to_string(),
+            entry.omega6_mg.to_string(),
+            entry.sourcing.iter().map(label).collect::<Vec<_>>().join(";"),
+            optional(entry.cost),
+        ])
+        .collect();
+    csv::write(&[
+        "id", "eaten_at", "food_id", "food_name", "category", "grams", "protein_g", "fat_g", "carbs_g",
+        "calories", "omega3_mg", "omega6_mg", "sourcing", "cost",
+    ], &records)
+}
+
+fn body_metrics_csv(metrics: &[BodyMetric]) -> String {
+    let records: Vec<Vec<String>> = metrics.iter()
+        .map(|metric| {
+            let mut measurements: Vec<String> = metric.measurements.iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            measurements.sort();
+            vec![
+                metric.id.to_string(),
+                metric.recorded_at.to_rfc3339(),
+                optional(metric.weight_kg),
+                optional(metric.body_fat_pct),
+                optional(metric.waist_cm),
+                measurements.join(";"),
+            ]
+        })
+        .collect();
+    csv::write(&["id", "recorded_at", "weight_kg", "body_fat_pct", "waist_cm", "measurements"], &records)
+}
+
+fn custom_foods_csv(foods: &[Food]) -> String {
+    let records: Vec<Vec<String>> = foods.iter()
+        .map(|food| vec![
+            food.id.clone(),
+            food.name.clone(),
+            label(&food.category),
+            food.protein_g.to_string(),
+            food.fat_g.to_string(),
+            food.carbs_g.to_string(),
+            food.calories.to_string(),
+            food.omega3_mg.to_string(),
+            food.omega6_mg.to_string(),
+            food.default_sourcing.iter().map(label).collect::<Vec<_>>().join(";"),
+        ])
+        .collect();
+    csv::write(&[
+        "id", "name", "category", "protein_g", "fat_g", "carbs_g", "calories", "omega3_mg", "omega6_mg", "default_sourcing",
+    ], &records)
+}
+
+fn purchases_csv(purchases: &[Purchase]) -> String {
+    let records: Vec<Vec<String>> = purchases.iter()
+        .map(|purchase| vec![
+            purchase.id.to_string(),
+            purchase.purchased_at.to_rfc3339(),
+            purchase.food_id.clone(),
+            purchase.price.to_string(),
+            purchase.quantity.to_string(),
+            label(&purchase.unit),
+            optional(purchase.grams_per_unit),
+            purchase.store.clone().unwrap_or_default(),
+        ])
+        .collect();
+    csv::write(&["id", "purchased_at", "food_id", "price", "quantity", "unit", "grams_per_unit", "store"], &records)
+}
+
+fn pantry_csv(items: &[PantryItem]) -> String {
+    let records: Vec<Vec<String>> = items.iter()
+        .map(|item| vec![
+            item.id.to_string(),
+            item.stocked_at.to_rfc3339(),
+            item.food_id.clone(),
+            item.food_name.clone(),
+            item.grams_stocked.to_string(),
+            item.grams_remaining.to_string(),
+            item.location.clone().unwrap_or_default(),
+        ])
+        .collect();
+    csv::write(&["id", "stocked_at", "food_id", "food_name", "grams_stocked", "grams_remaining", "location"], &records)
+}
+
+fn planned_meals_csv(meals: &[PlannedMeal]) -> String {
+    let records: Vec<Vec<String>> = meals.iter()
+        .map(|meal| vec![
+            meal.id.to_string(),
+            meal.date.to_string(),
+            label(&meal.slot),
+            meal.food_id.clone(),
+            meal.food_name.clone(),
+            meal.grams.to_string(),
+            meal.macros.protein_g.to_string(),
+            meal.macros.fat_g.to_string(),
+            meal.macros.carbs_g.to_string(),
+            meal.macros.calories.to_string(),
+        ])
+        .collect();
+    csv::write(&[
+        "id", "date", "slot", "food_id", "food_name", "grams", "protein_g", "fat_g", "carbs_g", "calories",
+    ], &records)
+}
+
+fn prep_tasks_csv(tasks: &[PrepTask]) -> String {
+    let records: Vec<Vec<String>> = tasks.iter()
+        .map(|task| vec![
+            task.id.to_string(),
+            task.date.to_string(),
+            task.time.map(|time| time.format("%H:%M").to_string()).unwrap_or_default(),
+            task.title.clone(),
+            task.planned_meal_id.map(|id| id.to_string()).unwrap_or_default(),
+        ])
+        .collect();
+    csv::write(&["id", "date", "time", "title", "planned_meal_id"], &records)
+}
+
+impl Archive {
+    // A table per CSV for spreadsheets, plus the JSON archive so the zip
+    // itself can be restored
+    pub fn to_zip(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let files = [
+            (ARCHIVE_FILE, serde_json::to_string_pretty(self)?),
+            ("entries.csv", entries_csv(&self.entries)),
+            ("body_metrics.csv", body_metrics_csv(&self.body_metrics)),
+            ("custom_foods.csv", custom_foods_csv(&self.custom_foods)),
+            ("purchases.csv", purchases_csv(&self.purchases)),
+            ("pantry.csv", pantry_csv(&self.pantry)),
+            ("planned_meals.csv", planned_meals_csv(&self.planned_meals)),
+            ("prep_tasks.csv", prep_tasks_csv(&self.prep_tasks)),
+        ];
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, contents) in files {
+            zip.start_file(name, options)?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        Ok(zip.finish()?.into_inner())
+    }
+
+    // Takes either the JSON archive or the zip it came in
+    pub fn read(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let archive: Archive = if bytes.starts_with(b"PK\x03\x04") {
+            let mut zip = ZipArchive::new(Cursor::new(bytes))?;
+            let file = zip.by_name(ARCHIVE_FILE)?;
+            if file.size() > MAX_ARCHIVE_FILE_BYTES {
+                return Err(format!("{} unpacks to more than {} MiB", ARCHIVE_FILE, MAX_ARCHIVE_FILE_BYTES / 1024 / 1024).into());
+            }
+            // the size in the header is the zip's word for it, so read no
+            // more than the cap whatever it says
+            let mut json = Vec::new();
+            file.take(MAX_ARCHIVE_FILE_BYTES + 1).read_to_end(&mut json)?;
+            if json.len() as u64 > MAX_ARCHIVE_FILE_BYTES {
+                return Err(format!("{} unpacks to more than {} MiB", ARCHIVE_FILE, MAX_ARCHIVE_FILE_BYTES / 1024 / 1024).into());
+            }
+            serde_json::from_slice(&json)?
+        } else {
+            serde_json::from_slice(bytes)?
+        };
+        if archive.version > ARCHIVE_VERSION {
+            return Err(format!("Archive version {} is newer than this server understands ({})", archive.version, ARCHIVE_VERSION).into());
+        }
+        archive.check()?;
+        Ok(archive)
+    }
+
+    // The first thing in the archive the API wouldn't have let in, so a
+    // restore can refuse before writing anything
+    pub fn check(&self) -> Result<(), String> {
+        let amount = |value: f64| value.is_finite() && value >= 0.0;
+        fn unique<T: Eq + Hash>(table: &str, mut ids: impl Iterator<Item = T>) -> Result<(), String> {
+            let mut seen = HashSet::new();
+            if !ids.all(|id| seen.insert(id)) {
+                return Err(format!("{} has the same id twice", table));
+            }
+            Ok(())
+        }
+        for food in &self.custom_foods {
+            if !food.id.starts_with("custom-") || food.name.trim().is_empty() {
+                return Err(format!("Custom food {:?} needs a custom- id and a name", food.id));
+            }
+            let per_100g = [food.protein_g, food.fat_g, food.carbs_g, food.calories, food.omega3_mg, food.omega6_mg];
+            if !per_100g.into_iter().all(amount) {
+                return Err(format!("Custom food {} has an invalid amount", food.id));
+            }
+            check_sourcing(&food.default_sourcing, food.category).map_err(|reason| format!("Custom food {}: {}", food.id, reason))?;
+        }
+        for entry in &self.entries {
+            let amounts = [entry.grams, entry.protein_g, entry.fat_g, entry.carbs_g, entry.calories, entry.omega3_mg, entry.omega6_mg, entry.pantry_grams];
+            if !(entry.grams > 0.0) || !amounts.into_iter().all(amount) || !entry.cost.map_or(true, amount) {
+                return Err(format!("Meal entry {} has an invalid amount", entry.id));
+            }
+            check_sourcing(&entry.sourcing, entry.category).map_err(|reason| format!("Meal entry {}: {}", entry.id, reason))?;
+        }
+        for metric in &self.body_metrics {
+            let values = [metric.weight_kg, metric.body_fat_pct, metric.waist_cm];
+            if !values.into_iter().flatten().chain(metric.measurements.values().copied()).all(amount) {
+                return Err(format!("Body metric {} has an invalid value", metric.id));
+            }
+        }
+        for purchase in &self.purchases {
+            purchase.checked_grams().map_err(|reason| format!("Purchase {}: {}", purchase.id, reason))?;
+        }
+        for item in &self.pantry {
+            if !amount(item.grams_stocked) || !amount(item.grams_remaining) {
+                return Err(format!("Pantry item {} has an invalid amount", item.id));
+            }
+        }
+        for meal in &self.planned_meals {
+            if !(meal.grams > 0.0) || !meal.grams.is_finite() {
+                return Err(format!("Planned meal {} has an invalid amount", meal.id));
+            }
+        }
+        unique("custom_foods", self.custom_foods.iter().map(|food| &food.id))?;
+        unique("entries", self.entries.iter().map(|entry| entry.id))?;
+        unique("body_metrics", self.body_metrics.iter().map(|metric| metric.id))?;
+        unique("purchases", self.purchases.iter().map(|purchase| purchase.id))?;
+        unique("pantry", self.pantry.iter().map(|item| item.id))?;
+        unique("planned_meals", self.planned_meals.iter().map(|meal| meal.id))?;
+        unique("prep_tasks", self.prep_tasks.iter().map(|task| task.id))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::food::FoodCategory;
+    use crate::models::sourcing::Sourcing;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn archive() -> Archive {
+        let food = Food {
+            id: format!("custom-{}", Uuid::new_v4()),
+            name: "Grandma's Liver Pâté".to_owned(),
+            category: FoodCategory::Beef,
+            protein_g: 14.0,
+            fat_g: 28.0,
+            carbs_g: 2.0,
+            calories: 319.0,
+            omega3_mg: 0.0,
+            omega6_mg: 0.0,
+            default_sourcing: vec![Sourcing::GrassFed],
+            custom: true,
+            provenance: None,
+        };
+        let entry = MealEntry::from_food(&food, 50.0, food.default_sourcing.clone(), Local::now());
+        Archive {
+            version: ARCHIVE_VERSION,
+            exported_at: Local::now(),
+            profile: UserProfile::default(),
+            targets: Targets::default(),
+            custom_foods: vec![food],
+            entries: vec![entry],
+            body_metrics: vec![BodyMetric {
+                id: Uuid::new_v4(),
+                recorded_at: Local::now(),
+                weight_kg: Some(82.5),
+                body_fat_pct: None,
+                waist_cm: Some(86.0),
+                measurements: HashMap::from([("neck".to_owned(), 39.0)]),
+            }],
+            purchases: Vec::new(),
+            pantry: Vec::new(),
+            planned_meals: Vec::new(),
+            prep_tasks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn json_and_zip_round_trip() {
+        let original = archive();
+        let from_json = Archive::read(&serde_json::to_vec(&original).unwrap()).unwrap();
+        let from_zip = Archive::read(&original.to_zip().unwrap()).unwrap();
+        for restored in [from_json, from_zip] {
+            assert_eq!(restored.custom_foods[0].id, original.custom_foods[0].id);
+            assert_eq!(restored.entries[0].id, original.entries[0].id);
+            assert_eq!(restored.entries[0].grams, 50.0);
+            assert_eq!(restored.body_metrics[0].measurements["neck"], 39.0);
+        }
+    }
+
+    #[test]
+    fn refuses_newer_versions_and_bad_rows() {
+        let mut newer = archive();
+        newer.version = ARCHIVE_VERSION + 1;
+        assert!(Archive::read(&serde_json::to_vec(&newer).unwrap()).is_err());
+
+        let mut negative = archive();
+        negative.entries[0].grams = -50.0;
+        assert!(negative.check().is_err());
+
+        let mut twice = archive();
+        twice.entries.push(twice.entries[0].clone());
+        assert!(twice.check().is_err());
+
+        let mut shared = archive();
+        shared.custom_foods[0].id = "ribeye-steak".to_owned();
+        assert!(shared.check().is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::models::food::{Food, FoodCategory, FoodSource, FoodStore, Provenance};
+
+const KJ_PER_KCAL: f64 = 4.184;
+
+// Open Food Facts category tags we keep, most specific first. Anything that
+// only matches the generic meat tag is filed under beef like the catalogue.
+const CATEGORY_TAGS: [(&str, FoodCategory); 24] = [
+    ("en:beef", FoodCategory::Beef),
+    ("en:pemmican", FoodCategory::Beef),
+    ("en:beef-jerky", FoodCategory::Beef),
+    ("en:poultries", FoodCategory::Poultry),
+    ("en:chickens", FoodCategory::Poultry),
+    ("en:turkeys", FoodCategory::Poultry),
+    ("en:fishes", FoodCategory::Fish),
+    ("en:sardines", FoodCategory::Fish),
+    ("en:seafood", FoodCategory::Fish),
+    ("en:eggs", FoodCategory::Eggs),
+    ("en:pork", FoodCategory::Pork),
+    ("en:bacons", FoodCategory::Pork),
+    ("en:hams", FoodCategory::Pork),
+    ("en:kefirs", FoodCategory::Dairy),
+    ("en:cheeses", FoodCategory::Dairy),
+    ("en:milks", FoodCategory::Dairy),
+    ("en:butters", FoodCategory::Dairy),
+    ("en:creams", FoodCategory::Dairy),
+    ("en:yogurts", FoodCategory::Dairy),
+    ("en:dairies", FoodCategory::Dairy),
+    ("en:honeys", FoodCategory::Honey),
+    ("en:fruits", FoodCategory::Fruits),
+    ("en:dried-fruits", FoodCategory::Fruits),
+    ("en:meats", FoodCategory::Beef),
+];
+
+// plant based stand-ins get tagged with the thing they imitate
+const EXCLUDED_TAG_WORDS: [&str; 3] = ["alternative", "substitute", "analogue"];
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ImportStats {
+    pub read: usize,
+    pub imported: usize,
+    pub skipped: usize,
+}
+
+// Barcodes are stored as EAN-13, so a 12 digit UPC-A finds the same product
+pub fn normalize_barcode(code: &str) -> Option<String> {
+    let digits: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.len() < 8 || digits.len() > 14 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let digits = digits.trim_start_matches('0');
+    Some(format!("{:0>13}", digits))
+}
+
+pub fn barcode_food_id(ean: &str) -> String {
+    format!("off-{}", ean)
+}
+
+fn category_for(tags: &[String]) -> Option<FoodCategory> {
+    if tags.iter().any(|tag| EXCLUDED_TAG_WORDS.iter().any(|word| tag.contains(word))) {
+        return None;
+    }
+    CATEGORY_TAGS.iter()
+        .find(|(wanted, _)| tags.iter().any(|tag| tag == wanted))
+        .map(|(_, category)| *category)
+}
+
+// One product's fields by their export column names, whichever format it came from
+fn product_food(fields: &HashMap<String, String>, tags: &[String]) -> Option<(String, Food)> {
+    let ean = normalize_barcode(fields.get("code")?)?;
+    let category = category_for(tags)?;
+    let number = |key: &str| fields.get(key).and_then(|value| value.trim().parse::<f64>().ok()).filter(|value| value.is_finite() && *value >= 0.0);
+    let protein_g = number("proteins_100g")?;
+    let fat_g = number("fat_100g")?;
+    let carbs_g = number("carbohydrates_100g")?;
+    let calories = number("energy-kcal_100g")
+        .or_else(|| number("energy_100g").map(|kj| kj / KJ_PER_KCAL))
+        .unwrap_or(protein_g * 4.0 + fat_g * 9.0 + carbs_g * 4.0);
+    let product_name = fields.get("product_name").map(|name| name.trim()).filter(|name| !name.is_empty())?;
+    let name = match fields.get("brands").and_then(|brands| brands.split(',').next()).map(str::trim).filter(|brand| !brand.is_empty()) {
+        Some(brand) => format!("{} ({})", product_name, brand),
+        None => product_name.to_owned(),
+    };
+    let food = Food {
+        id: barcode_food_id(&ean),
+        name,
+        category,
+        protein_g,
+        fat_g,
+        carbs_g,
+        calories,
+        omega3_mg: 0.0,
+        omega6_mg: 0.0,
+        default_sourcing: Vec::new(),
+        custom: false,
+        provenance: Some(Provenance {
+            source: FoodSource::OpenFoodFacts,
+            source_id: ean.clone(),
+            version: None,
+        }),
+    };
+    Some((ean, food))
+}
+
+fn value_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+// A line of the JSONL dump, nutriments flattened in next to the top level fields
+fn parse_json_line(line: &str) -> Option<(String, Food)> {
+    let product: Value = serde_json::from_str(line).ok()?;
+    let mut fields: HashMap<String, String> = HashMap::new();
+    for key in ["code", "product_name", "brands"] {
+        if let Some(text) = product.get(key).and_then(value_text) {
+            fields.insert(key.to_owned(), text);
+        }
+    }
+    if let Some(Value::Object(nutriments)) = product.get("nutriments") {
+        for (key, value) in nutriments {
+            if let Some(text) = value_text(value) {
+                fields.insert(key.clone(), text);
+            }
+        }
+    }
+    let tags: Vec<String> = product.get("categories_tags")
+        .and_then(Value::as_array)
+        .map(|tags| tags.iter().filter_map(|tag| tag.as_str().map(str::to_owned)).collect())
+        .unwrap_or_default();
+    product_food(&fields, &tags)
+}
+
+// A row of the CSV export, which is actually tab separated
+fn parse_csv_line(header: &[String], line: &str) -> Option<(String, Food)> {
+    let fields: HashMap<String, String> = header.iter()
+        .cloned()
+        .zip(line.split('\t').map(str::to_owned))
+        .collect();
+    let tags: Vec<String> = fields.get("categories_tags")
+        .map(|tags| tags.split(',').map(|tag| tag.trim().to_owned()).collect())
+        .unwrap_or_default();
+    product_food(&fields, &tags)
+}
+
+// Loads an Open Food Facts export (.jsonl or .csv) into the barcode index,
+// keeping only products in categories we track
+pub async fn import_open_food_facts(store: &FoodStore, path: &Path) -> Result<ImportStats, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let is_json = path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ext.eq_ignore_ascii_case("jsonl") || ext.eq_ignore_ascii_case("json"));
+    let mut header: Option<Vec<String>> = None;
+    let mut stats = ImportStats::default();
+    // by bytes rather than lines(), which gives up on the first line that
+    // isn't UTF-8 and those turn up in the dumps
+    for line in reader.split(b'\n') {
+        let mut line = line?;
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        let Ok(line) = String::from_utf8(line) else {
+            stats.read += 1;
+            stats.skipped += 1;
+            continue;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !is_json && header.is_none() {
+            header = Some(line.split('\t').map(|column| column.trim().to_owned()).collect());
+            continue;
+        }
+        let parsed = if is_json {
+            parse_json_line(&line)
+        } else {
+            parse_csv_line(header.as_deref().unwrap_or_default(), &line)
+        };
+        stats.read += 1;
+        match parsed {
+            None => stats.skipped += 1,
+            Some((ean, food)) => {
+                store.save_barcode_food(&ean, &food).await?;
+                stats.imported += 1;
+            }
+        }
+    }
+    store.barcodes.flush()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn temporary_store() -> FoodStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        FoodStore {
+            custom: db.open_tree("custom").unwrap(),
+            barcodes: db.open_tree("barcodes").unwrap(),
+            meta: db.open_tree("meta").unwrap(),
+            db: Arc::new(db),
+        }
+    }
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    // runs the import on the contents written to a file with the extension
+    async fn import(store: &FoodStore, extension: &str, contents: &[u8]) -> ImportStats {
+        let path = std::env::temp_dir().join(format!("off-import-{}.{}", Uuid::new_v4().simple(), extension));
+        std::fs::write(&path, contents).unwrap();
+        let stats = import_open_food_facts(store, &path).await;
+        let _ = std::fs::remove_file(&path);
+        stats.unwrap()
+    }
+
+    #[test]
+    fn pads_barcodes_to_ean_13() {
+        assert_eq!(normalize_barcode("4006381333931").as_deref(), Some("4006381333931"));
+        // UPC-A, with the spacing printed under the bars
+        assert_eq!(normalize_barcode("0 12345 67890 5").as_deref(), Some("0012345678905"));
+        assert_eq!(normalize_barcode("012345678905"), normalize_barcode("0012345678905"));
+        assert_eq!(normalize_barcode("96385074").as_deref(), Some("0000096385074"));
+        assert_eq!(normalize_barcode("1234567"), None);
+        assert_eq!(normalize_barcode("123456789012345"), None);
+        assert_eq!(normalize_barcode("40063813339x1"), None);
+        assert_eq!(normalize_barcode(""), None);
+    }
+
+    #[test]
+    fn keeps_tracked_categories_only() {
+        assert_eq!(category_for(&tags(&["en:meats", "en:beef"])), Some(FoodCategory::Beef));
+        assert_eq!(category_for(&tags(&["en:meats", "en:bacons"])), Some(FoodCategory::Pork));
+        assert_eq!(category_for(&tags(&["en:dairies", "en:cheeses"])), Some(FoodCategory::Dairy));
+        assert_eq!(category_for(&tags(&["en:meats"])), Some(FoodCategory::Beef));
+        assert_eq!(category_for(&tags(&["en:breads"])), None);
+        assert_eq!(category_for(&tags(&["en:meats", "en:meat-alternatives"])), None);
+        assert_eq!(category_for(&[]), None);
+    }
+
+    #[actix_web::test]
+    async fn imports_jsonl() {
+        let store = temporary_store();
+        let contents = [
+            r#"{"code":"012345678905","product_name":"Ground Beef","brands":"Farm Co, Other","categories_tags":["en:meats","en:beef"],"nutriments":{"proteins_100g":17,"fat_100g":20,"carbohydrates_100g":0,"energy-kcal_100g":254}}"#,
+            // energy in kJ only
+            r#"{"code":"4006381333931","product_name":"Butter","categories_tags":["en:butters"],"nutriments":{"proteins_100g":"0.9","fat_100g":81,"carbohydrates_100g":0.1,"energy_100g":3000}}"#,
+            r#"{"code":"4006381333948","product_name":"Bread","categories_tags":["en:breads"],"nutriments":{"proteins_100g":9,"fat_100g":3,"carbohydrates_100g":49}}"#,
+            "",
+            "not json",
+        ].join("\n");
+        let stats = import(&store, "jsonl", contents.as_bytes()).await;
+        assert_eq!((stats.read, stats.imported, stats.skipped), (4, 2, 2));
+
+        let beef = store.get_barcode_food("0012345678905").await.unwrap().unwrap();
+        assert_eq!(beef.id, "off-0012345678905");
+        assert_eq!(beef.name, "Ground Beef (Farm Co)");
+        assert_eq!(beef.category, FoodCategory::Beef);
+        assert_eq!(beef.calories, 254.0);
+        let butter = store.get_barcode_food("4006381333931").await.unwrap().unwrap();
+        assert_eq!(butter.protein_g, 0.9);
+        assert!((butter.calories - 3000.0 / KJ_PER_KCAL).abs() < 1e-9);
+        assert!(store.get_barcode_food("4006381333948").await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn imports_tab_separated_csv() {
+        let store = temporary_store();
+        let mut contents = b"code\tproduct_name\tbrands\tcategories_tags\tproteins_100g\tfat_100g\tcarbohydrates_100g\r\n".to_vec();
+        contents.extend_from_slice(b"96385074\tSardines\t\ten:seafood,en:sardines\t25\t11\t0\r\n");
+        // a broken product name mustn't stop the rest
+        contents.extend_from_slice(b"4006381333955\tQu\xe9so\t\ten:cheeses\t25\t33\t1\r\n");
+        contents.extend_from_slice(b"4006381333962\tKefir\t\ten:dairies,en:kefirs\t3\t2\t4\n");
+        let stats = import(&store, "csv", &contents).await;
+        assert_eq!((stats.read, stats.imported, stats.skipped), (3, 2, 1));
+
+        let sardines = store.get_barcode_food("0000096385074").await.unwrap().unwrap();
+        assert_eq!(sardines.name, "Sardines");
+        assert_eq!(sardines.category, FoodCategory::Fish);
+        // no energy column, so it comes from the macros
+        assert_eq!(sardines.calories, 25.0 * 4.0 + 11.0 * 9.0);
+        let kefir = store.get_barcode_food("4006381333962").await.unwrap().unwrap();
+        assert_eq!(kefir.category, FoodCategory::Dairy);
+    }
+}
@@ -0,0 +1,258 @@
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::error::Error;
+use chrono::{DateTime, Local, NaiveDate};
+use uuid::Uuid;
+
+use crate::models::user::move_prefix;
+
+// Smoothing factor per day for the trend weight (same as the Hacker's Diet 10% rule)
+const TREND_ALPHA: f64 = 0.1;
+// How far back the weekly rate regression looks
+const RATE_WINDOW_DAYS: i64 = 14;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BodyMetric {
+    pub id: Uuid,
+    pub recorded_at: DateTime<Local>,
+    pub weight_kg: Option<f64>,
+    pub body_fat_pct: Option<f64>,
+    pub waist_cm: Option<f64>,
+    // anything else people like to track: chest, hips, neck, arm...
+    #[serde(default)]
+    pub measurements: HashMap<String, f64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TrendPoint {
+    pub date: NaiveDate,
+    pub weight_kg: f64,
+    pub trend_kg: f64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BodyTrend {
+    pub latest_weight_kg: Option<f64>,
+    pub trend_weight_kg: Option<f64>,
+    pub weekly_rate_kg: Option<f64>,
+    pub latest_body_fat_pct: Option<f64>,
+    pub points: Vec<TrendPoint>,
+}
+
+impl BodyTrend {
+    // Exponentially smoothed trend over daily average weights. Gaps between
+    // weigh-ins are bridged by compounding the daily smoothing factor.
+    pub fn from_metrics(metrics: &[BodyMetric]) -> Self {
+        let mut daily: BTreeMap<NaiveDate, (f64, u32)> = BTreeMap::new();
+        for metric in metrics {
+            if let Some(weight) = metric.weight_kg {
+                let day = daily.entry(metric.recorded_at.date_naive()).or_insert((0.0, 0));
+                day.0 += weight;
+                day.1 += 1;
+            }
+        }
+
+        let mut points: Vec<TrendPoint> = Vec::with_capacity(daily.len());
+        for (date, (total, count)) in daily {
+            let weight_kg = total / count as f64;
+            let trend_kg = match points.last() {
+                None => weight_kg,
+                Some(previous) => {
+                    let days = (date - previous.date).num_days() as f64;
+                    let alpha = 1.0 - (1.0 - TREND_ALPHA).powf(days);
+                    previous.trend_kg + alpha * (weight_kg - previous.trend_kg)
+                }
+            };
+            points.push(TrendPoint { date, weight_kg, trend_kg });
+        }
+
+        let latest_body_fat_pct = metrics.iter()
+            .filter(|metric| metric.body_fat_pct.is_some())
+            .max_by_key(|metric| metric.recorded_at)
+            .and_then(|metric| metric.body_fat_pct);
+
+        BodyTrend {
+            latest_weight_kg: points.last().map(|point| point.weight_kg),
+            trend_weight_kg: points.last().map(|point| point.trend_kg),
+            weekly_rate_kg: weekly_rate(&points),
+            latest_body_fat_pct,
+            points,
+        }
+    }
+
+    // Trend weight on (or most recently before) a given day
+    pub fn trend_on(&self, date: NaiveDate) -> Option<&TrendPoint> {
+        self.points.iter()
+            .take_while(|point| point.date <= date)
+            .last()
+    }
+}
+
+// Least squares slope of the trend line over the last couple of weeks, in kg/week
+fn weekly_rate(points: &[TrendPoint]) -> Option<f64> {
+    let last = points.last()?;
+    let window: Vec<(f64, f64)> = points.iter()
+        .filter(|point| (last.date - point.date).num_days() <= RATE_WINDOW_DAYS)
+        .map(|point| ((point.date - last.date).num_days() as f64, point.trend_kg))
+        .collect();
+    if window.len() < 2 {
+        return None;
+    }
+    let n = window.len() as f64;
+    let mean_x = window.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = window.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance: f64 = window.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = window.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    if variance == 0.0 {
+        return None;
+    }
+    Some(covariance / variance * 7.0)
+}
+
+#[derive(Clone, Debug)]
+pub struct BodyStore {
+    pub db: Arc<Db>,
+}
+
+impl BodyStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(BodyStore {
+            db: Arc::new(db),
+        })
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        move_prefix(&self.db, &format!("{}:", from), &format!("{}:", to))
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        for item in self.db.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (key, _) = item?;
+            self.db.remove(key)?;
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // keys sort by user, then time, so a prefix scan comes back in order
+    fn key(user_id: &str, metric: &BodyMetric) -> String {
+        format!("{}:{:020}:{}", user_id, metric.recorded_at.timestamp_millis(), metric.id)
+    }
+
+    pub async fn save_metric(&self, user_id: &str, metric: &BodyMetric) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(metric)?;
+        self.db.insert(Self::key(user_id, metric).as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // see MealStore::replace_entries
+    pub async fn replace_metrics(&self, user_id: &str, removed: &[BodyMetric], saved: &[BodyMetric]) -> Result<(), Box<dyn Error>> {
+        let mut batch = Batch::default();
+        for metric in removed {
+            batch.remove(Self::key(user_id, metric).as_bytes());
+        }
+        for metric in saved {
+            batch.insert(Self::key(user_id, metric).as_bytes(), serde_json::to_vec(metric)?);
+        }
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_metrics(&self, user_id: &str) -> Result<Vec<BodyMetric>, Box<dyn Error>> {
+        let mut metrics = Vec::new();
+        for item in self.db.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (_, data) = item?;
+            metrics.push(serde_json::from_slice::<BodyMetric>(&data)?);
+        }
+        Ok(metrics)
+    }
+
+    pub async fn delete_metric(&self, user_id: &str, metric_id: &Uuid) -> Result<bool, Box<dyn Error>> {
+        for metric in self.get_metrics(user_id).await? {
+            if &metric.id == metric_id {
+                self.db.remove(Self::key(user_id, &metric).as_bytes())?;
+                self.db.flush()?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn weigh_in(day: u32, hour: u32, weight_kg: f64) -> BodyMetric {
+        BodyMetric {
+            id: Uuid::new_v4(),
+            recorded_at: Local.with_ymd_and_hms(2024, 6, day, hour, 0, 0).earliest().unwrap(),
+            weight_kg: Some(weight_kg),
+            body_fat_pct: None,
+            waist_cm: None,
+            measurements: HashMap::new(),
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, day).unwrap()
+    }
+
+    #[test]
+    fn averages_weigh_ins_on_the_same_day() {
+        let trend = BodyTrend::from_metrics(&[weigh_in(1, 7, 80.0), weigh_in(1, 21, 81.0)]);
+        assert_eq!(trend.points.len(), 1);
+        assert_eq!(trend.latest_weight_kg, Some(80.5));
+        assert_eq!(trend.trend_weight_kg, Some(80.5));
+        assert_eq!(trend.weekly_rate_kg, None);
+    }
+
+    #[test]
+    fn smooths_toward_new_weights() {
+        let trend = BodyTrend::from_metrics(&[weigh_in(1, 7, 80.0), weigh_in(2, 7, 79.0)]);
+        assert!((trend.trend_weight_kg.unwrap() - 79.9).abs() < 1e-9);
+        assert_eq!(trend.latest_weight_kg, Some(79.0));
+    }
+
+    #[test]
+    fn gaps_count_as_the_days_they_cover() {
+        let skipped = BodyTrend::from_metrics(&[weigh_in(1, 7, 80.0), weigh_in(4, 7, 77.0)]);
+        let daily = BodyTrend::from_metrics(&[weigh_in(1, 7, 80.0), weigh_in(2, 7, 77.0), weigh_in(3, 7, 77.0), weigh_in(4, 7, 77.0)]);
+        assert!((skipped.trend_weight_kg.unwrap() - daily.trend_weight_kg.unwrap()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn weekly_rate_follows_the_trend() {
+        let steady: Vec<BodyMetric> = (1..=14).map(|day| weigh_in(day, 7, 80.0)).collect();
+        assert_eq!(BodyTrend::from_metrics(&steady).weekly_rate_kg, Some(0.0));
+        let losing: Vec<BodyMetric> = (1..=14).map(|day| weigh_in(day, 7, 80.0 - 0.1 * day as f64)).collect();
+        assert!(BodyTrend::from_metrics(&losing).weekly_rate_kg.unwrap() < 0.0);
+    }
+
+    #[test]
+    fn trend_on_takes_the_last_point_up_to_the_day() {
+        let trend = BodyTrend::from_metrics(&[weigh_in(3, 7, 80.0), weigh_in(6, 7, 79.0)]);
+        assert!(trend.trend_on(date(2)).is_none());
+        assert_eq!(trend.trend_on(date(3)).unwrap().date, date(3));
+        assert_eq!(trend.trend_on(date(5)).unwrap().date, date(3));
+        assert_eq!(trend.trend_on(date(30)).unwrap().date, date(6));
+    }
+
+    #[test]
+    fn ignores_metrics_without_a_weight() {
+        let mut body_fat = weigh_in(2, 7, 0.0);
+        body_fat.weight_kg = None;
+        body_fat.body_fat_pct = Some(18.0);
+        let trend = BodyTrend::from_metrics(&[weigh_in(1, 7, 80.0), body_fat]);
+        assert_eq!(trend.points.len(), 1);
+        assert_eq!(trend.latest_body_fat_pct, Some(18.0));
+    }
+}
@@ -0,0 +1,256 @@
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::error::Error;
+use chrono::{DateTime, Local, NaiveDate};
+use uuid::Uuid;
+
+use crate::models::user::move_prefix;
+use crate::models::food::Food;
+use crate::models::meal::MealEntry;
+
+const GRAMS_PER_LB: f64 = 453.592;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PurchaseUnit {
+    Kg,
+    Lb,
+    // eggs, cans, jars... needs grams_per_unit
+    Unit,
+}
+
+impl PurchaseUnit {
+    // None unless it comes to a finite, positive amount
+    pub fn to_grams(&self, quantity: f64, grams_per_unit: Option<f64>) -> Option<f64> {
+        let grams = match self {
+            PurchaseUnit::Kg => quantity * 1000.0,
+            PurchaseUnit::Lb => quantity * GRAMS_PER_LB,
+            PurchaseUnit::Unit => grams_per_unit? * quantity,
+        };
+        (grams.is_finite() && grams > 0.0 && quantity > 0.0).then_some(grams)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Purchase {
+    pub id: Uuid,
+    pub food_id: String,
+    pub price: f64,
+    pub quantity: f64,
+    pub unit: PurchaseUnit,
+    pub grams_per_unit: Option<f64>,
+    pub store: Option<String>,
+    pub purchased_at: DateTime<Local>,
+}
+
+impl Purchase {
+    pub fn grams(&self) -> Option<f64> {
+        self.unit.to_grams(self.quantity, self.grams_per_unit)
+    }
+
+    // The grams bought, or why the purchase can't be used for prices
+    pub fn checked_grams(&self) -> Result<f64, String> {
+        if !self.price.is_finite() || self.price < 0.0 {
+            return Err("Price should be zero or more".to_owned());
+        }
+        self.grams().ok_or_else(|| "Purchase needs an amount in grams (set grams_per_unit for units)".to_owned())
+    }
+
+    pub fn price_per_gram(&self) -> Option<f64> {
+        self.grams().filter(|grams| *grams > 0.0).map(|grams| self.price / grams)
+    }
+}
+
+// Price paid for a food on or before a given time, falling back to the
+// oldest purchase when everything we know about came later
+pub fn price_per_gram_at(purchases: &[Purchase], food_id: &str, at: &DateTime<Local>) -> Option<f64> {
+    let mut for_food: Vec<&Purchase> = purchases.iter()
+        .filter(|purchase| purchase.food_id == food_id && purchase.price_per_gram().is_some())
+        .collect();
+    for_food.sort_by_key(|purchase| purchase.purchased_at);
+    for_food.iter()
+        .rev()
+        .find(|purchase| purchase.purchased_at <= *at)
+        .or_else(|| for_food.first())
+        .and_then(|purchase| purchase.price_per_gram())
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SpendReport {
+    pub daily: BTreeMap<NaiveDate, f64>,
+    pub total: f64,
+    pub average_per_day: Option<f64>,
+    // grams logged without any known price
+    pub unpriced_grams: f64,
+}
+
+impl SpendReport {
+    pub fn from_entries(entries: &[MealEntry]) -> Self {
+        let mut report = SpendReport::default();
+        for entry in entries {
+            match entry.cost {
+                Some(cost) => {
+                    *report.daily.entry(entry.eaten_at.date_naive()).or_default() += cost;
+                    report.total += cost;
+                },
+                None => report.unpriced_grams += entry.grams,
+            }
+        }
+        if !report.daily.is_empty() {
+            report.average_per_day = Some(report.total / report.daily.len() as f64);
+        }
+        report
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProteinCost {
+    pub food_id: String,
+    pub food_name: String,
+    pub price_per_kg: f64,
+    pub cost_per_100g_protein: f64,
+    // grams of this food alone to hit the protein target, and what that costs
+    pub grams_for_target: f64,
+    pub cost_for_target: f64,
+}
+
+// Cheapest first
+pub fn protein_costs(foods: &[Food], purchases: &[Purchase], protein_target_g: f64) -> Vec<ProteinCost> {
+    let now = Local::now();
+    let mut costs: Vec<ProteinCost> = foods.iter()
+        .filter(|food| food.protein_g > 0.0)
+        .filter_map(|food| {
+            let per_gram = price_per_gram_at(purchases, &food.id, &now)?;
+            let cost_per_gram_protein = per_gram * 100.0 / food.protein_g;
+            let grams_for_target = protein_target_g / food.protein_g * 100.0;
+            Some(ProteinCost {
+                food_id: food.id.clone(),
+                food_name: food.name.clone(),
+                price_per_kg: per_gram * 1000.0,
+                cost_per_100g_protein: cost_per_gram_protein * 100.0,
+                grams_for_target,
+                cost_for_target: grams_for_target * per_gram,
+            })
+        })
+        .collect();
+    costs.sort_by(|a, b| a.cost_per_100g_protein.total_cmp(&b.cost_per_100g_protein));
+    costs
+}
+
+#[derive(Clone, Debug)]
+pub struct PurchaseStore {
+    pub db: Arc<Db>,
+}
+
+impl PurchaseStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(PurchaseStore {
+            db: Arc::new(db),
+        })
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        move_prefix(&self.db, &format!("{}:", from), &format!("{}:", to))
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        for item in self.db.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (key, _) = item?;
+            self.db.remove(key)?;
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn key(user_id: &str, purchase: &Purchase) -> String {
+        format!("{}:{:020}:{}", user_id, purchase.purchased_at.timestamp_millis(), purchase.id)
+    }
+
+    pub async fn save_purchase(&self, user_id: &str, purchase: &Purchase) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(purchase)?;
+        self.db.insert(Self::key(user_id, purchase).as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // see MealStore::replace_entries
+    pub async fn replace_purchases(&self, user_id: &str, removed: &[Purchase], saved: &[Purchase]) -> Result<(), Box<dyn Error>> {
+        let mut batch = Batch::default();
+        for purchase in removed {
+            batch.remove(Self::key(user_id, purchase).as_bytes());
+        }
+        for purchase in saved {
+            batch.insert(Self::key(user_id, purchase).as_bytes(), serde_json::to_vec(purchase)?);
+        }
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_purchases(&self, user_id: &str) -> Result<Vec<Purchase>, Box<dyn Error>> {
+        let mut purchases = Vec::new();
+        for item in self.db.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (_, data) = item?;
+            purchases.push(serde_json::from_slice::<Purchase>(&data)?);
+        }
+        Ok(purchases)
+    }
+
+    pub async fn delete_purchase(&self, user_id: &str, purchase_id: &Uuid) -> Result<bool, Box<dyn Error>> {
+        for purchase in self.get_purchases(user_id).await? {
+            if &purchase.id == purchase_id {
+                self.db.remove(Self::key(user_id, &purchase).as_bytes())?;
+                self.db.flush()?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn purchase(price: f64, quantity: f64, unit: PurchaseUnit, grams_per_unit: Option<f64>) -> Purchase {
+        Purchase {
+            id: Uuid::new_v4(),
+            food_id: "ribeye-steak".to_owned(),
+            price,
+            quantity,
+            unit,
+            grams_per_unit,
+            store: None,
+            purchased_at: Local::now(),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_amounts_and_prices() {
+        assert_eq!(purchase(10.0, 1.0, PurchaseUnit::Kg, None).checked_grams(), Ok(1000.0));
+        assert!(purchase(10.0, 12.0, PurchaseUnit::Unit, None).checked_grams().is_err());
+        assert!(purchase(10.0, 12.0, PurchaseUnit::Unit, Some(-50.0)).checked_grams().is_err());
+        assert!(purchase(10.0, -1.0, PurchaseUnit::Kg, None).checked_grams().is_err());
+        assert!(purchase(10.0, f64::NAN, PurchaseUnit::Lb, None).checked_grams().is_err());
+        assert!(purchase(f64::INFINITY, 1.0, PurchaseUnit::Kg, None).checked_grams().is_err());
+        assert!(purchase(-5.0, 1.0, PurchaseUnit::Kg, None).checked_grams().is_err());
+    }
+
+    #[test]
+    fn prices_at_falls_back_to_the_oldest() {
+        let mut old = purchase(20.0, 1.0, PurchaseUnit::Kg, None);
+        old.purchased_at = Local::now() - chrono::Duration::days(10);
+        let new = purchase(30.0, 1.0, PurchaseUnit::Kg, None);
+        let purchases = vec![new, old];
+        let now = Local::now();
+        assert_eq!(price_per_gram_at(&purchases, "ribeye-steak", &now), Some(0.03));
+        let before = now - chrono::Duration::days(20);
+        assert_eq!(price_per_gram_at(&purchases, "ribeye-steak", &before), Some(0.02));
+        assert_eq!(price_per_gram_at(&purchases, "salmon", &now), None);
+    }
+}
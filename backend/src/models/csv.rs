@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+fn is_blank(record: &[String]) -> bool {
+    record.iter().all(|field| field.trim().is_empty())
+}
+
+// Just enough CSV for the exports we import: comma separated, fields
+// optionally quoted, quotes doubled inside quoted fields, and newlines
+// allowed inside quotes (diary notes). Reads a line at a time, so files
+// bigger than memory (FoodData Central's food_nutrient.csv) are fine.
+pub struct Records<R> {
+    reader: R,
+    line: String,
+    started: bool,
+}
+
+impl<R: BufRead> Records<R> {
+    pub fn new(reader: R) -> Self {
+        Records { reader, line: String::new(), started: false }
+    }
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = io::Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Err(e) => return Some(Err(e)),
+                Ok(0) => {
+                    record.push(field);
+                    return (!is_blank(&record)).then_some(Ok(record));
+                },
+                Ok(_) => {},
+            }
+            let mut line = self.line.as_str();
+            if !self.started {
+                self.started = true;
+                line = line.trim_start_matches('\u{feff}');
+            }
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if quoted && chars.peek() == Some(&'"') => {
+                        field.push('"');
+                        chars.next();
+                    },
+                    '"' => quoted = !quoted,
+                    ',' if !quoted => record.push(std::mem::take(&mut field)),
+                    '\r' if !quoted => {},
+                    '\n' if !quoted => {
+                        record.push(std::mem::take(&mut field));
+                        if !is_blank(&record) {
+                            return Some(Ok(record));
+                        }
+                        record.clear();
+                    },
+                    _ => field.push(c),
+                }
+            }
+        }
+    }
+}
+
+pub fn records(text: &str) -> Vec<Vec<String>> {
+    // reading a str can't fail
+    Records::new(text.as_bytes()).filter_map(Result::ok).collect()
+}
+
+// A header and the records streamed after it
+pub struct Table<R> {
+    pub header: Vec<String>,
+    pub records: Records<R>,
+}
+
+impl<R: BufRead> Table<R> {
+    pub fn new(reader: R) -> io::Result<Self> {
+        let mut records = Records::new(reader);
+        let header = records.next().transpose()?.unwrap_or_default()
+            .into_iter()
+            .map(|column| column.trim().to_owned())
+            .collect();
+        Ok(Table { header, records })
+    }
+
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.header.iter().position(|column| column == name)
+    }
+}
+
+// Records after the header as column name to value
+pub fn rows(text: &str) -> Vec<HashMap<String, String>> {
+    let mut records = records(text).into_iter();
+    let Some(header) = records.next() else {
+        return Vec::new();
+    };
+    let header: Vec<String> = header.into_iter().map(|column| column.trim().to_owned()).collect();
+    records
+        .map(|record| header.iter().cloned().zip(record).collect())
+        .collect()
+}
+
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+// The other direction, for exports. Quotes only the fields that need it.
+pub fn write(header: &[&str], records: &[Vec<String>]) -> String {
+    let mut text = String::new();
+    let header: Vec<String> = header.iter().map(|column| column.to_string()).collect();
+    for record in std::iter::once(&header).chain(records) {
+        let fields: Vec<String> = record.iter().map(|field| quote(field)).collect();
+        text.push_str(&fields.join(","));
+        text.push_str("\r\n");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_quoted_fields_across_lines() {
+        let text = "\u{feff}Day,Food Name,Note\r\n2024-01-02,\"Eggs, fried\",\"said \"\"yum\"\"\nafter\"\r\n\r\n2024-01-03,Ribeye,\n";
+        assert_eq!(records(text), vec![
+            vec!["Day".to_owned(), "Food Name".to_owned(), "Note".to_owned()],
+            vec!["2024-01-02".to_owned(), "Eggs, fried".to_owned(), "said \"yum\"\nafter".to_owned()],
+            vec!["2024-01-03".to_owned(), "Ribeye".to_owned(), String::new()],
+        ]);
+    }
+
+    #[test]
+    fn last_record_needs_no_newline() {
+        assert_eq!(records("a,b\n1,2"), vec![vec!["a".to_owned(), "b".to_owned()], vec!["1".to_owned(), "2".to_owned()]]);
+        assert!(records("").is_empty());
+    }
+
+    #[test]
+    fn write_then_read_round_trips() {
+        let written = vec![
+            vec!["Raw Milk".to_owned(), "note, with comma".to_owned()],
+            vec!["Salmon".to_owned(), "two\nlines and \"quotes\"".to_owned()],
+        ];
+        let text = write(&["food", "note"], &written);
+        let rows = rows(&text);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["note"], "note, with comma");
+        assert_eq!(rows[1]["note"], "two\nlines and \"quotes\"");
+    }
+
+    #[test]
+    fn table_finds_columns() {
+        let table = Table::new("fdc_id, amount\n1,2.5\n".as_bytes()).unwrap();
+        assert_eq!(table.column("amount"), Some(1));
+        assert_eq!(table.column("unit"), None);
+        let records: Vec<Vec<String>> = table.records.map(Result::unwrap).collect();
+        assert_eq!(records, vec![vec!["1".to_owned(), "2.5".to_owned()]]);
+    }
+}
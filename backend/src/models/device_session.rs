@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::error::Error;
+use chrono::{DateTime, Duration, Local};
+use uuid::Uuid;
+
+// signed out after this long without a request
+pub const SESSION_IDLE_DAYS: i64 = 30;
+// last_seen_at is only written this often, not on every request
+const TOUCH_MINUTES: i64 = 5;
+
+// One signed in browser or device. The cookie holds the id, revoking it here
+// signs that device out on its next request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeviceSession {
+    pub id: String,
+    pub user_id: String,
+    pub user_agent: Option<String>,
+    // 203.0.113.0/24 or 2001:db8:1::/48, enough to recognise, not to locate
+    pub ip_prefix: Option<String>,
+    pub created_at: DateTime<Local>,
+    pub last_seen_at: DateTime<Local>,
+}
+
+impl DeviceSession {
+    pub fn new(user_id: &str, user_agent: Option<String>, ip: Option<IpAddr>) -> Self {
+        let now = Local::now();
+        DeviceSession {
+            id: Uuid::new_v4().simple().to_string(),
+            user_id: user_id.to_owned(),
+            user_agent,
+            ip_prefix: ip.map(ip_prefix),
+            created_at: now,
+            last_seen_at: now,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Local::now() - self.last_seen_at > Duration::days(SESSION_IDLE_DAYS)
+    }
+
+    fn needs_touch(&self) -> bool {
+        Local::now() - self.last_seen_at > Duration::minutes(TOUCH_MINUTES)
+    }
+}
+
+pub fn ip_prefix(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        },
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            format!("{:x}:{:x}:{:x}::/48", segments[0], segments[1], segments[2])
+        },
+    }
+}
+
+// Sessions by id, and an index of each user's session ids
+#[derive(Clone, Debug)]
+pub struct DeviceSessionStore {
+    pub db: Arc<Db>,
+}
+
+impl DeviceSessionStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(DeviceSessionStore {
+            db: Arc::new(db),
+        })
+    }
+
+    pub async fn save_session(&self, session: &DeviceSession) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(session)?;
+        self.db.insert(format!("session:{}", session.id).as_bytes(), serialized)?;
+        self.db.insert(format!("user:{}:{}", session.user_id, session.id).as_bytes(), "".as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_session(&self, id: &str) -> Result<Option<DeviceSession>, Box<dyn Error>> {
+        if let Some(data) = self.db.get(format!("session:{}", id).as_bytes())? {
+            Ok(Some(serde_json::from_slice(&data)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Keeps last_seen_at (and where from) roughly up to date
+    pub async fn touch(&self, session: &mut DeviceSession, ip: Option<IpAddr>) -> Result<(), Box<dyn Error>> {
+        if session.needs_touch() {
+            session.last_seen_at = Local::now();
+            if let Some(ip) = ip {
+                session.ip_prefix = Some(ip_prefix(ip));
+            }
+            self.save_session(session).await?;
+        }
+        Ok(())
+    }
+
+    // Most recently used first
+    pub async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<DeviceSession>, Box<dyn Error>> {
+        let prefix = format!("user:{}:", user_id);
+        let mut sessions = Vec::new();
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, _) = item?;
+            let id = String::from_utf8(key[prefix.len()..].to_vec())?;
+            if let Some(session) = self.get_session(&id).await? {
+                sessions.push(session);
+            }
+        }
+        sessions.sort_by(|a, b| b.last_seen_at.cmp(&a.last_seen_at));
+        Ok(sessions)
+    }
+
+    pub async fn delete_session(&self, user_id: &str, id: &str) -> Result<bool, Box<dyn Error>> {
+        let removed = self.db.remove(format!("user:{}:{}", user_id, id).as_bytes())?;
+        if removed.is_some() {
+            self.db.remove(format!("session:{}", id).as_bytes())?;
+        }
+        self.db.flush()?;
+        Ok(removed.is_some())
+    }
+
+    // Idle sessions, the next request would sign them out anyway
+    pub async fn delete_expired(&self) -> Result<usize, Box<dyn Error>> {
+        let mut expired = Vec::new();
+        for item in self.db.scan_prefix(b"session:") {
+            let (_, data) = item?;
+            let session: DeviceSession = serde_json::from_slice(&data)?;
+            if session.is_expired() {
+                expired.push(session);
+            }
+        }
+        for session in &expired {
+            self.delete_session(&session.user_id, &session.id).await?;
+        }
+        Ok(expired.len())
+    }
+
+    // Index entries left behind without a session
+    pub async fn delete_dangling(&self) -> Result<usize, Box<dyn Error>> {
+        let mut dangling = Vec::new();
+        for item in self.db.scan_prefix(b"user:") {
+            let (key, _) = item?;
+            let key_text = String::from_utf8(key.to_vec())?;
+            let Some((_, id)) = key_text.rsplit_once(':') else {
+                continue;
+            };
+            if !self.db.contains_key(format!("session:{}", id).as_bytes())? {
+                dangling.push(key);
+            }
+        }
+        for key in &dangling {
+            self.db.remove(key)?;
+        }
+        self.db.flush()?;
+        Ok(dangling.len())
+    }
+
+    // account deletion, and signing out everywhere
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        for session in self.get_user_sessions(user_id).await? {
+            self.delete_session(user_id, &session.id).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_network() {
+        assert_eq!(ip_prefix("203.0.113.77".parse().unwrap()), "203.0.113.0/24");
+        assert_eq!(ip_prefix("2001:db8:1:2:3::4".parse().unwrap()), "2001:db8:1::/48");
+        assert_eq!(ip_prefix("203.0.113.1".parse().unwrap()), ip_prefix("203.0.113.254".parse().unwrap()));
+    }
+}
@@ -0,0 +1,357 @@
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::models::csv;
+use crate::models::food::{Food, FoodCategory, FoodSource, FoodStore, Provenance};
+
+// FoodData Central nutrient ids (not the older SR nutrient numbers)
+const PROTEIN: u32 = 1003;
+const FAT: u32 = 1004;
+const CARBS: u32 = 1005;
+const CARBS_BY_SUMMATION: u32 = 1050;
+const ENERGY_KCAL: u32 = 1008;
+const ENERGY_ATWATER_GENERAL: u32 = 2047;
+const ENERGY_ATWATER_SPECIFIC: u32 = 2048;
+const LINOLEIC_UNDIFFERENTIATED: u32 = 1269;
+const ALA_UNDIFFERENTIATED: u32 = 1270;
+const ARACHIDONIC_UNDIFFERENTIATED: u32 = 1271;
+const DHA: u32 = 1272;
+const EPA: u32 = 1278;
+const DPA: u32 = 1280;
+const LINOLEIC: u32 = 1316;
+const GLA: u32 = 1321;
+const ALA: u32 = 1404;
+const ARACHIDONIC: u32 = 1408;
+
+const MAPPED_NUTRIENTS: [u32; 17] = [
+    PROTEIN, FAT, CARBS, CARBS_BY_SUMMATION, ENERGY_KCAL, ENERGY_ATWATER_GENERAL, ENERGY_ATWATER_SPECIFIC,
+    LINOLEIC_UNDIFFERENTIATED, ALA_UNDIFFERENTIATED, ARACHIDONIC_UNDIFFERENTIATED, DHA, EPA, DPA,
+    LINOLEIC, GLA, ALA, ARACHIDONIC,
+];
+
+// only the hand curated datasets, branded foods are Open Food Facts' job
+const DATA_TYPES: [&str; 4] = ["foundation_food", "sr_legacy_food", "Foundation", "SR Legacy"];
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UnmappedNutrient {
+    pub id: u32,
+    pub name: String,
+    pub unit: String,
+    // how many imported foods had it
+    pub foods: usize,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FdcImportReport {
+    pub read: usize,
+    pub imported: usize,
+    pub skipped: usize,
+    pub unmapped: Vec<UnmappedNutrient>,
+}
+
+// A food as read from either format, amounts per 100g keyed by nutrient id
+struct FdcFood {
+    fdc_id: String,
+    description: String,
+    category: String,
+    data_type: String,
+    publication_date: Option<String>,
+    nutrients: HashMap<u32, f64>,
+}
+
+// FDC categories that hold foods we track. Mixed ones (dairy and egg, fats
+// and oils, sweets) are split on the description.
+fn category_for(category: &str, description: &str) -> Option<FoodCategory> {
+    let description = description.to_lowercase();
+    match category {
+        "Beef Products" | "Lamb, Veal, and Game Products" => Some(FoodCategory::Beef),
+        "Poultry Products" => Some(FoodCategory::Poultry),
+        "Finfish and Shellfish Products" => Some(FoodCategory::Fish),
+        "Pork Products" => Some(FoodCategory::Pork),
+        "Sausages and Luncheon Meats" if description.contains("pork") => Some(FoodCategory::Pork),
+        "Sausages and Luncheon Meats" if description.contains("beef") => Some(FoodCategory::Beef),
+        "Dairy and Egg Products" if description.starts_with("egg") => Some(FoodCategory::Eggs),
+        "Dairy and Egg Products" => Some(FoodCategory::Dairy),
+        "Fruits and Fruit Juices" => Some(FoodCategory::Fruits),
+        "Sweets" if description.starts_with("honey") => Some(FoodCategory::Honey),
+        "Fats and Oils" if description.contains("tallow") => Some(FoodCategory::Beef),
+        "Fats and Oils" if description.contains("lard") => Some(FoodCategory::Pork),
+        _ => None,
+    }
+}
+
+impl FdcFood {
+    fn amount(&self, ids: &[u32]) -> Option<f64> {
+        ids.iter().find_map(|id| self.nutrients.get(id).copied())
+    }
+
+    fn to_food(&self, version: Option<&str>) -> Option<Food> {
+        if !DATA_TYPES.contains(&self.data_type.as_str()) {
+            return None;
+        }
+        let category = category_for(&self.category, &self.description)?;
+        let protein_g = self.amount(&[PROTEIN])?;
+        let fat_g = self.amount(&[FAT])?;
+        let carbs_g = self.amount(&[CARBS, CARBS_BY_SUMMATION]).unwrap_or(0.0);
+        let calories = self.amount(&[ENERGY_KCAL, ENERGY_ATWATER_SPECIFIC, ENERGY_ATWATER_GENERAL])
+            .unwrap_or(protein_g * 4.0 + fat_g * 9.0 + carbs_g * 4.0);
+        // fatty acids are in grams, we keep milligrams
+        let omega3_g = self.amount(&[ALA, ALA_UNDIFFERENTIATED]).unwrap_or(0.0)
+            + self.amount(&[EPA]).unwrap_or(0.0)
+            + self.amount(&[DHA]).unwrap_or(0.0)
+            + self.amount(&[DPA]).unwrap_or(0.0);
+        let omega6_g = self.amount(&[LINOLEIC, LINOLEIC_UNDIFFERENTIATED]).unwrap_or(0.0)
+            + self.amount(&[GLA]).unwrap_or(0.0)
+            + self.amount(&[ARACHIDONIC, ARACHIDONIC_UNDIFFERENTIATED]).unwrap_or(0.0);
+        Some(Food {
+            id: format!("usda-{}", self.fdc_id),
+            name: self.description.clone(),
+            category,
+            protein_g,
+            fat_g,
+            carbs_g,
+            calories,
+            omega3_mg: omega3_g * 1000.0,
+            omega6_mg: omega6_g * 1000.0,
+            default_sourcing: Vec::new(),
+            custom: false,
+            provenance: Some(Provenance {
+                source: FoodSource::Usda,
+                source_id: self.fdc_id.clone(),
+                version: version.map(str::to_owned).or_else(|| self.publication_date.clone()),
+            }),
+        })
+    }
+}
+
+type NutrientNames = HashMap<u32, (String, String)>;
+
+// What one of the readers got out of a download: the foods we might take,
+// what the nutrient ids are called, and how many foods were passed over
+// while reading (other data types)
+struct FdcDownload {
+    foods: Vec<FdcFood>,
+    names: NutrientNames,
+    passed_over: usize,
+}
+
+// Streams one of the download's CSV tables (food_nutrient.csv runs to
+// gigabytes), handing visit each record's values for the given columns
+fn each_row(path: &Path, columns: &[&str], mut visit: impl FnMut(&[Option<&str>])) -> Result<(), Box<dyn Error>> {
+    let table = csv::Table::new(BufReader::new(File::open(path)?))?;
+    let indices: Vec<Option<usize>> = columns.iter().map(|column| table.column(column)).collect();
+    for record in table.records {
+        let record = record?;
+        let values: Vec<Option<&str>> = indices.iter()
+            .map(|index| index.and_then(|index| record.get(index)).map(String::as_str))
+            .collect();
+        visit(&values);
+    }
+    Ok(())
+}
+
+// The CSV download is a directory of tables joined on fdc_id. Only foods of
+// the data types we import are kept, so the nutrients of the (far more
+// numerous) branded foods go by without being held on to.
+fn read_csv_dir(dir: &Path) -> Result<FdcDownload, Box<dyn Error>> {
+    let mut categories: HashMap<String, String> = HashMap::new();
+    each_row(&dir.join("food_category.csv"), &["id", "description"], |values| {
+        if let [Some(id), Some(description)] = values {
+            categories.insert(id.to_string(), description.to_string());
+        }
+    })?;
+    let mut names: NutrientNames = HashMap::new();
+    each_row(&dir.join("nutrient.csv"), &["id", "name", "unit_name"], |values| {
+        if let [Some(id), Some(name), unit] = values {
+            if let Ok(id) = id.parse::<u32>() {
+                names.insert(id, (name.to_string(), unit.unwrap_or_default().to_owned()));
+            }
+        }
+    })?;
+    let mut foods: BTreeMap<String, FdcFood> = BTreeMap::new();
+    let mut passed_over = 0;
+    let columns = ["fdc_id", "description", "food_category_id", "data_type", "publication_date"];
+    each_row(&dir.join("food.csv"), &columns, |values| {
+        let [Some(fdc_id), description, category_id, data_type, publication_date] = values else {
+            return;
+        };
+        let data_type = data_type.unwrap_or_default();
+        if !DATA_TYPES.contains(&data_type) {
+            passed_over += 1;
+            return;
+        }
+        foods.insert(fdc_id.to_string(), FdcFood {
+            fdc_id: fdc_id.to_string(),
+            description: description.unwrap_or_default().to_owned(),
+            category: category_id.and_then(|id| categories.get(id)).cloned().unwrap_or_default(),
+            data_type: data_type.to_owned(),
+            publication_date: publication_date.filter(|date| !date.is_empty()).map(str::to_owned),
+            nutrients: HashMap::new(),
+        });
+    })?;
+    each_row(&dir.join("food_nutrient.csv"), &["fdc_id", "nutrient_id", "amount"], |values| {
+        if let [Some(fdc_id), Some(nutrient_id), Some(amount)] = values {
+            if let (Some(food), Ok(nutrient_id), Ok(amount)) = (foods.get_mut(*fdc_id), nutrient_id.parse::<u32>(), amount.parse::<f64>()) {
+                food.nutrients.insert(nutrient_id, amount);
+            }
+        }
+    })?;
+    Ok(FdcDownload { foods: foods.into_values().collect(), names, passed_over })
+}
+
+fn json_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+fn food_from_json(product: &Value, names: &mut NutrientNames) -> Option<FdcFood> {
+    let fdc_id = product.get("fdcId").and_then(json_text)?;
+    let mut nutrients = HashMap::new();
+    for entry in product.get("foodNutrients").and_then(Value::as_array).into_iter().flatten() {
+        let nutrient = entry.get("nutrient");
+        let id = nutrient.and_then(|nutrient| nutrient.get("id")).and_then(Value::as_u64);
+        let amount = entry.get("amount").and_then(Value::as_f64);
+        if let (Some(id), Some(amount)) = (id, amount) {
+            let id = id as u32;
+            nutrients.insert(id, amount);
+            names.entry(id).or_insert_with(|| {
+                let field = |key: &str| nutrient.and_then(|nutrient| nutrient.get(key)).and_then(json_text).unwrap_or_default();
+                (field("name"), field("unitName"))
+            });
+        }
+    }
+    Some(FdcFood {
+        fdc_id,
+        description: product.get("description").and_then(json_text).unwrap_or_default(),
+        category: product.get("foodCategory")
+            .and_then(|category| category.get("description"))
+            .and_then(json_text)
+            .unwrap_or_default(),
+        data_type: product.get("dataType").and_then(json_text).unwrap_or_default(),
+        publication_date: product.get("publicationDate").and_then(json_text),
+        nutrients,
+    })
+}
+
+// Deserializes the JSON download a food at a time, only ever holding one
+// food's document rather than the whole file's
+struct JsonFoods<'a>(&'a mut FdcDownload);
+
+impl<'de, 'a> DeserializeSeed<'de> for JsonFoods<'a> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for JsonFoods<'a> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of FoodData Central foods")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(product) = seq.next_element::<Value>()? {
+            match food_from_json(&product, &mut self.0.names) {
+                Some(food) if DATA_TYPES.contains(&food.data_type.as_str()) => self.0.foods.push(food),
+                _ => self.0.passed_over += 1,
+            }
+        }
+        Ok(())
+    }
+}
+
+// The whole document, an object with the foods under a dataset key
+// (FoundationFoods, SRLegacyFoods)
+struct JsonDocument<'a>(&'a mut FdcDownload);
+
+impl<'de, 'a> Visitor<'de> for JsonDocument<'a> {
+    type Value = bool;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a FoodData Central document")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<bool, A::Error> {
+        let mut found = false;
+        while let Some(key) = map.next_key::<String>()? {
+            if key.ends_with("Foods") {
+                map.next_value_seed(JsonFoods(&mut *self.0))?;
+                found = true;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(found)
+    }
+}
+
+fn read_json(path: &Path) -> Result<FdcDownload, Box<dyn Error>> {
+    let mut download = FdcDownload { foods: Vec::new(), names: HashMap::new(), passed_over: 0 };
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(File::open(path)?));
+    if !deserializer.deserialize_map(JsonDocument(&mut download))? {
+        return Err("no list of foods in the FoodData Central file".into());
+    }
+    Ok(download)
+}
+
+// Upserts FoodData Central foods (a CSV download directory or a JSON file)
+// into the shared catalogue as usda-<fdc_id>, and reports which nutrients
+// in the imported foods we had nowhere to put
+pub async fn import_food_data_central(store: &FoodStore, path: &Path, version: Option<&str>) -> Result<FdcImportReport, Box<dyn Error>> {
+    let FdcDownload { foods: fdc_foods, names, passed_over } = if path.is_dir() {
+        read_csv_dir(path)?
+    } else {
+        read_json(path)?
+    };
+    let mut report = FdcImportReport {
+        read: passed_over,
+        skipped: passed_over,
+        ..FdcImportReport::default()
+    };
+    let mut unmapped: BTreeMap<u32, usize> = BTreeMap::new();
+    for fdc_food in &fdc_foods {
+        report.read += 1;
+        let Some(food) = fdc_food.to_food(version) else {
+            report.skipped += 1;
+            continue;
+        };
+        store.upsert_food(&food).await?;
+        report.imported += 1;
+        for id in fdc_food.nutrients.keys().filter(|id| !MAPPED_NUTRIENTS.contains(id)) {
+            *unmapped.entry(*id).or_default() += 1;
+        }
+    }
+    store.db.flush()?;
+    report.unmapped = unmapped.into_iter()
+        .map(|(id, foods)| {
+            let (name, unit) = names.get(&id).cloned().unwrap_or_default();
+            UnmappedNutrient { id, name, unit, foods }
+        })
+        .collect();
+    report.unmapped.sort_by(|a, b| b.foods.cmp(&a.foods));
+    store.meta.insert(b"fdc_report", serde_json::to_vec(&report)?)?;
+    store.meta.flush()?;
+    Ok(report)
+}
+
+// What the last import had nowhere to put, for the admin page. Imports run
+// from the command line, so this is all the server sees of them.
+pub async fn last_import_report(store: &FoodStore) -> Result<Option<FdcImportReport>, Box<dyn Error>> {
+    match store.meta.get(b"fdc_report")? {
+        None => Ok(None),
+        Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+    }
+}
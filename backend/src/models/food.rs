@@ -0,0 +1,267 @@
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db, Tree};
+use std::sync::Arc;
+use std::error::Error;
+
+use crate::models::user::move_prefix;
+use crate::models::sourcing::Sourcing;
+
+// Bump whenever Food::catalogue() changes so existing dbs get reseeded
+const CATALOGUE_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum FoodCategory {
+    Beef,
+    Poultry,
+    Fish,
+    Eggs,
+    Pork,
+    Dairy,
+    Fruits,
+    Honey,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FoodSource {
+    Usda,
+    OpenFoodFacts,
+}
+
+// Where an imported food came from, so a newer release can replace it
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Provenance {
+    pub source: FoodSource,
+    // fdc_id for USDA, the barcode for Open Food Facts
+    pub source_id: String,
+    pub version: Option<String>,
+}
+
+// Nutrition is per 100g
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Food {
+    pub id: String,
+    pub name: String,
+    pub category: FoodCategory,
+    pub protein_g: f64,
+    pub fat_g: f64,
+    pub carbs_g: f64,
+    pub calories: f64,
+    // for conventionally raised animals, see Sourcing for the adjustments
+    #[serde(default)]
+    pub omega3_mg: f64,
+    #[serde(default)]
+    pub omega6_mg: f64,
+    // what a meal entry gets tagged with when the user doesn't say
+    #[serde(default)]
+    pub default_sourcing: Vec<Sourcing>,
+    #[serde(default)]
+    pub custom: bool,
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+}
+
+impl Food {
+    fn seed(id: &str, name: &str, category: FoodCategory, protein_g: f64, fat_g: f64, carbs_g: f64, calories: f64) -> Self {
+        Food {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            category,
+            protein_g,
+            fat_g,
+            carbs_g,
+            calories,
+            omega3_mg: 0.0,
+            omega6_mg: 0.0,
+            default_sourcing: Vec::new(),
+            custom: false,
+            provenance: None,
+        }
+    }
+
+    fn omegas(mut self, omega3_mg: f64, omega6_mg: f64) -> Self {
+        self.omega3_mg = omega3_mg;
+        self.omega6_mg = omega6_mg;
+        self
+    }
+
+    fn sourced(mut self, sourcing: Sourcing) -> Self {
+        self.default_sourcing.push(sourcing);
+        self
+    }
+
+    // Built in catalogue, written to the food db when it's new or the
+    // catalogue version changed
+    pub fn catalogue() -> Vec<Food> {
+        use FoodCategory::*;
+        vec![
+            Food::seed("ribeye-steak", "Ribeye Steak", Beef, 23.0, 22.0, 0.0, 290.0).omegas(50.0, 400.0),
+            Food::seed("ground-beef", "Ground Beef (80/20)", Beef, 17.2, 20.0, 0.0, 254.0).omegas(50.0, 450.0),
+            Food::seed("beef-liver", "Beef Liver", Beef, 20.4, 3.6, 3.9, 135.0).omegas(20.0, 300.0),
+            Food::seed("brisket", "Brisket", Beef, 21.0, 20.0, 0.0, 264.0).omegas(40.0, 400.0),
+            Food::seed("beef-heart", "Beef Heart", Beef, 17.7, 3.9, 0.1, 112.0).omegas(10.0, 200.0),
+            Food::seed("beef-tallow", "Beef Tallow", Beef, 0.0, 100.0, 0.0, 902.0).omegas(600.0, 3000.0),
+            Food::seed("chicken-thigh", "Chicken Thigh", Poultry, 16.5, 16.6, 0.0, 221.0).omegas(120.0, 2800.0),
+            Food::seed("chicken-breast", "Chicken Breast", Poultry, 22.5, 2.6, 0.0, 120.0).omegas(30.0, 500.0),
+            Food::seed("chicken-liver", "Chicken Liver", Poultry, 16.9, 4.8, 0.7, 119.0).omegas(100.0, 1100.0),
+            Food::seed("salmon", "Salmon", Fish, 20.4, 13.4, 0.0, 208.0).omegas(2500.0, 900.0).sourced(Sourcing::Farmed),
+            Food::seed("sardines", "Sardines (canned)", Fish, 24.6, 11.5, 0.0, 208.0).omegas(1500.0, 300.0),
+            Food::seed("cod", "Cod", Fish, 17.8, 0.7, 0.0, 82.0).omegas(200.0, 10.0),
+            Food::seed("whole-egg", "Whole Egg", Eggs, 12.6, 9.5, 0.7, 143.0).omegas(75.0, 1500.0),
+            Food::seed("egg-yolk", "Egg Yolk", Eggs, 15.9, 26.5, 3.6, 322.0).omegas(300.0, 4000.0),
+            Food::seed("pork-belly", "Pork Belly", Pork, 9.3, 53.0, 0.0, 518.0).omegas(300.0, 4500.0),
+            Food::seed("bacon", "Bacon", Pork, 12.6, 39.7, 1.4, 417.0).omegas(200.0, 3700.0),
+            Food::seed("pork-chop", "Pork Chop", Pork, 21.0, 9.0, 0.0, 172.0).omegas(30.0, 800.0),
+            Food::seed("butter", "Butter", Dairy, 0.9, 81.1, 0.1, 717.0).omegas(300.0, 2700.0),
+            Food::seed("raw-milk", "Raw Milk", Dairy, 3.3, 3.9, 4.8, 64.0).omegas(75.0, 120.0).sourced(Sourcing::Raw),
+            Food::seed("cheddar", "Cheddar", Dairy, 24.9, 33.1, 1.3, 403.0).omegas(370.0, 570.0),
+            Food::seed("kefir", "Kefir", Dairy, 3.6, 3.5, 4.5, 63.0).omegas(40.0, 80.0),
+            Food::seed("heavy-cream", "Heavy Cream", Dairy, 2.8, 36.0, 2.9, 340.0).omegas(400.0, 900.0),
+            Food::seed("banana", "Banana", Fruits, 1.1, 0.3, 22.8, 89.0).omegas(27.0, 46.0),
+            Food::seed("blueberries", "Blueberries", Fruits, 0.7, 0.3, 14.5, 57.0).omegas(58.0, 88.0),
+            Food::seed("orange", "Orange", Fruits, 0.9, 0.1, 11.8, 47.0).omegas(10.0, 20.0),
+            Food::seed("mango", "Mango", Fruits, 0.8, 0.4, 15.0, 60.0).omegas(50.0, 20.0),
+            Food::seed("raw-honey", "Raw Honey", Honey, 0.3, 0.0, 82.4, 304.0),
+        ]
+    }
+}
+
+// Shared catalogue lives in the default tree, each user's own foods in the
+// custom tree keyed by user, and imported packaged foods in the barcodes
+// tree keyed by EAN-13. The meta tree records which catalogue version was
+// seeded and the last FoodData Central import report.
+#[derive(Clone, Debug)]
+pub struct FoodStore {
+    pub db: Arc<Db>,
+    pub custom: Tree,
+    pub barcodes: Tree,
+    pub meta: Tree,
+}
+
+impl FoodStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        let meta = db.open_tree("meta")?;
+        let seeded = meta.get(b"catalogue_version")?;
+        if seeded.as_deref() != Some(&CATALOGUE_VERSION.to_be_bytes()[..]) {
+            for food in Food::catalogue() {
+                let serialized = serde_json::to_vec(&food).map_err(|e| sled::Error::Unsupported(e.to_string()))?;
+                db.insert(food.id.as_bytes(), serialized)?;
+            }
+            meta.insert(b"catalogue_version", &CATALOGUE_VERSION.to_be_bytes())?;
+            db.flush()?;
+        }
+        let custom = db.open_tree("custom")?;
+        let barcodes = db.open_tree("barcodes")?;
+        Ok(FoodStore {
+            db: Arc::new(db),
+            custom,
+            barcodes,
+            meta,
+        })
+    }
+
+    // account deletion, the shared catalogue and barcodes aren't theirs
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        move_prefix(&self.custom, &format!("{}:", from), &format!("{}:", to))
+    }
+
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        for item in self.custom.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (key, _) = item?;
+            self.custom.remove(key)?;
+        }
+        self.custom.flush()?;
+        Ok(())
+    }
+
+    fn custom_key(user_id: &str, food_id: &str) -> String {
+        format!("{}:{}", user_id, food_id)
+    }
+
+    // The id says where to look: custom-<uuid> for the user's own foods,
+    // off-<ean> for barcodes, anything else is the shared catalogue
+    pub async fn get_food(&self, user_id: &str, food_id: &str) -> Result<Option<Food>, Box<dyn Error>> {
+        if food_id.starts_with("custom-") {
+            return match self.custom.get(Self::custom_key(user_id, food_id).as_bytes())? {
+                None => Ok(None),
+                Some(data) => Ok(Some(serde_json::from_slice::<Food>(&data)?)),
+            };
+        }
+        if let Some(ean) = food_id.strip_prefix("off-") {
+            return self.get_barcode_food(ean).await;
+        }
+        if let Some(data) = self.db.get(food_id.as_bytes())? {
+            let food: Food = serde_json::from_slice(&data)?;
+            Ok(Some(food))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn get_barcode_food(&self, ean: &str) -> Result<Option<Food>, Box<dyn Error>> {
+        match self.barcodes.get(ean.as_bytes())? {
+            None => Ok(None),
+            Some(data) => Ok(Some(serde_json::from_slice::<Food>(&data)?)),
+        }
+    }
+
+    // Adds or replaces a food in the shared catalogue. Not flushed, imports
+    // write a lot of these and flush once at the end.
+    pub async fn upsert_food(&self, food: &Food) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(food)?;
+        self.db.insert(food.id.as_bytes(), serialized)?;
+        Ok(())
+    }
+
+    // not flushed either, see upsert_food
+    pub async fn save_barcode_food(&self, ean: &str, food: &Food) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(food)?;
+        self.barcodes.insert(ean.as_bytes(), serialized)?;
+        Ok(())
+    }
+
+    pub async fn get_foods(&self) -> Result<Vec<Food>, Box<dyn Error>> {
+        let mut foods = Vec::new();
+        for item in self.db.iter() {
+            let (_, data) = item?;
+            foods.push(serde_json::from_slice::<Food>(&data)?);
+        }
+        Ok(foods)
+    }
+
+    pub async fn get_custom_foods(&self, user_id: &str) -> Result<Vec<Food>, Box<dyn Error>> {
+        let mut foods = Vec::new();
+        for item in self.custom.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (_, data) = item?;
+            foods.push(serde_json::from_slice::<Food>(&data)?);
+        }
+        Ok(foods)
+    }
+
+    pub async fn save_custom_food(&self, user_id: &str, food: &Food) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(food)?;
+        self.custom.insert(Self::custom_key(user_id, &food.id).as_bytes(), serialized)?;
+        self.custom.flush()?;
+        Ok(())
+    }
+
+    // for restores, one batch for the lot
+    pub async fn save_custom_foods(&self, user_id: &str, foods: &[Food]) -> Result<(), Box<dyn Error>> {
+        let mut batch = Batch::default();
+        for food in foods {
+            batch.insert(Self::custom_key(user_id, &food.id).as_bytes(), serde_json::to_vec(food)?);
+        }
+        self.custom.apply_batch(batch)?;
+        self.custom.flush()?;
+        Ok(())
+    }
+
+    pub async fn delete_custom_food(&self, user_id: &str, food_id: &str) -> Result<bool, Box<dyn Error>> {
+        let removed = self.custom.remove(Self::custom_key(user_id, food_id).as_bytes())?;
+        self.custom.flush()?;
+        Ok(removed.is_some())
+    }
+}
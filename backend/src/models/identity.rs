@@ -0,0 +1,187 @@
+use futures::future::LocalBoxFuture;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use chrono::{DateTime, Local};
+use uuid::Uuid;
+
+use crate::models::session::RedditUser;
+
+// What's kept in the (encrypted) session cookie between sending the user to
+// the provider and them coming back
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PendingLogin {
+    pub provider: String,
+    pub state: String,
+    pub nonce: String,
+    pub pkce_verifier: String,
+    // set when a signed in user is adding another way to sign in
+    pub link_to: Option<String>,
+    // asking reddit for permission to post, when turning on sharing
+    #[serde(default)]
+    pub sharing: bool,
+}
+
+impl PendingLogin {
+    pub fn new(provider: &str, link_to: Option<String>, sharing: bool) -> Self {
+        PendingLogin {
+            provider: provider.to_owned(),
+            state: Uuid::new_v4().simple().to_string(),
+            nonce: Uuid::new_v4().simple().to_string(),
+            // 64 characters, inside the 43-128 PKCE allows
+            pkce_verifier: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+            link_to,
+            sharing,
+        }
+    }
+}
+
+// Who the provider says they are. The subject never changes, the rest can.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExternalIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub display_name: String,
+    pub email: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProviderLogin {
+    pub identity: ExternalIdentity,
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_in: u64,
+    // what was granted, which can be less than was asked for
+    pub scopes: Vec<String>,
+    // only from reddit, for admission and the reddit features
+    pub reddit_user: Option<RedditUser>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct LoginError(pub String);
+
+// One way of signing in. Implementations live next to the API they talk to,
+// the reddit one in models::reddit and the OpenID Connect one in models::oidc.
+pub trait IdentityProvider: fmt::Debug + Send + Sync {
+    // the {provider} in /login/{provider}, and what identities are stored under
+    fn id(&self) -> &str;
+
+    // for the login button
+    fn name(&self) -> &str;
+
+    // Where to send the user, with the pending login's state (and PKCE
+    // challenge and nonce where the provider supports them)
+    fn authorization_url<'a>(&'a self, pending: &'a PendingLogin) -> LocalBoxFuture<'a, Result<String, Box<dyn Error>>>;
+
+    // Trades the callback's code for tokens and who signed in
+    fn complete_login<'a>(&'a self, code: &'a str, pending: &'a PendingLogin) -> LocalBoxFuture<'a, Result<ProviderLogin, Box<dyn Error>>>;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IdentityProviders {
+    providers: Vec<Arc<dyn IdentityProvider>>,
+}
+
+impl IdentityProviders {
+    pub fn add(&mut self, provider: Arc<dyn IdentityProvider>) {
+        self.providers.push(provider);
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<dyn IdentityProvider>> {
+        self.providers.iter().find(|provider| provider.id() == id).cloned()
+    }
+
+    pub fn all(&self) -> &[Arc<dyn IdentityProvider>] {
+        &self.providers
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LinkedIdentity {
+    pub identity: ExternalIdentity,
+    pub linked_at: DateTime<Local>,
+}
+
+// External identities both ways round: provider and subject to the user
+// they sign in as, and each user's list of identities
+#[derive(Clone, Debug)]
+pub struct IdentityStore {
+    pub db: Arc<Db>,
+}
+
+impl IdentityStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(IdentityStore {
+            db: Arc::new(db),
+        })
+    }
+
+    fn identity_key(provider: &str, subject: &str) -> String {
+        format!("identity:{}:{}", provider, subject)
+    }
+
+    fn user_key(user_id: &str, provider: &str, subject: &str) -> String {
+        format!("user:{}:{}:{}", user_id, provider, subject)
+    }
+
+    pub async fn user_for(&self, provider: &str, subject: &str) -> Result<Option<String>, Box<dyn Error>> {
+        match self.db.get(Self::identity_key(provider, subject).as_bytes())? {
+            None => Ok(None),
+            Some(data) => Ok(Some(String::from_utf8(data.to_vec())?)),
+        }
+    }
+
+    // Also refreshes the stored name and email on every login
+    pub async fn link(&self, user_id: &str, identity: &ExternalIdentity) -> Result<(), Box<dyn Error>> {
+        let key = Self::user_key(user_id, &identity.provider, &identity.subject);
+        let linked_at = match self.db.get(key.as_bytes())? {
+            Some(data) => serde_json::from_slice::<LinkedIdentity>(&data)?.linked_at,
+            None => Local::now(),
+        };
+        let linked = LinkedIdentity { identity: identity.clone(), linked_at };
+        self.db.insert(Self::identity_key(&identity.provider, &identity.subject).as_bytes(), user_id.as_bytes())?;
+        self.db.insert(key.as_bytes(), serde_json::to_vec(&linked)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_identities(&self, user_id: &str) -> Result<Vec<LinkedIdentity>, Box<dyn Error>> {
+        let mut identities = Vec::new();
+        for item in self.db.scan_prefix(format!("user:{}:", user_id).as_bytes()) {
+            let (_, data) = item?;
+            identities.push(serde_json::from_slice::<LinkedIdentity>(&data)?);
+        }
+        Ok(identities)
+    }
+
+    pub async fn unlink(&self, user_id: &str, provider: &str, subject: &str) -> Result<bool, Box<dyn Error>> {
+        let removed = self.db.remove(Self::user_key(user_id, provider, subject).as_bytes())?;
+        if removed.is_some() {
+            self.db.remove(Self::identity_key(provider, subject).as_bytes())?;
+        }
+        self.db.flush()?;
+        Ok(removed.is_some())
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let identities = self.get_identities(from).await?;
+        for linked in &identities {
+            self.unlink(from, &linked.identity.provider, &linked.identity.subject).await?;
+            self.link(to, &linked.identity).await?;
+        }
+        Ok(identities.len())
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        for linked in self.get_identities(user_id).await? {
+            self.unlink(user_id, &linked.identity.provider, &linked.identity.subject).await?;
+        }
+        Ok(())
+    }
+}
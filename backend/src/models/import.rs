@@ -0,0 +1,435 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+
+use crate::models::csv;
+use crate::models::food::{Food, FoodCategory};
+use crate::models::meal::{MacroTotals, MealEntry};
+use crate::models::plan::MealSlot;
+
+// below this share of overlapping words a name is left for the user to map
+const MATCH_THRESHOLD: f64 = 0.5;
+
+// words that say how a food was prepared rather than what it is
+const IGNORED_WORDS: [&str; 10] = ["raw", "cooked", "fresh", "whole", "and", "with", "the", "meat", "only", "lean"];
+
+// first keyword hit decides the category of a food created from an import
+const CATEGORY_WORDS: [(&str, FoodCategory); 24] = [
+    ("egg", FoodCategory::Eggs),
+    ("honey", FoodCategory::Honey),
+    ("bacon", FoodCategory::Pork),
+    ("pork", FoodCategory::Pork),
+    ("ham", FoodCategory::Pork),
+    ("chicken", FoodCategory::Poultry),
+    ("turkey", FoodCategory::Poultry),
+    ("duck", FoodCategory::Poultry),
+    ("salmon", FoodCategory::Fish),
+    ("sardine", FoodCategory::Fish),
+    ("tuna", FoodCategory::Fish),
+    ("cod", FoodCategory::Fish),
+    ("fish", FoodCategory::Fish),
+    ("beef", FoodCategory::Beef),
+    ("steak", FoodCategory::Beef),
+    ("lamb", FoodCategory::Beef),
+    ("milk", FoodCategory::Dairy),
+    ("cheese", FoodCategory::Dairy),
+    ("butter", FoodCategory::Dairy),
+    ("cream", FoodCategory::Dairy),
+    ("yogurt", FoodCategory::Dairy),
+    ("kefir", FoodCategory::Dairy),
+    ("berries", FoodCategory::Fruits),
+    ("fruit", FoodCategory::Fruits),
+];
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    Cronometer,
+    MyFitnessPal,
+}
+
+// Column names in each export, first one present wins
+struct Columns {
+    date: &'static [&'static str],
+    time: &'static [&'static str],
+    meal: &'static [&'static str],
+    food: &'static [&'static str],
+    amount: &'static [&'static str],
+    calories: &'static [&'static str],
+    protein: &'static [&'static str],
+    fat: &'static [&'static str],
+    carbs: &'static [&'static str],
+}
+
+// Cronometer's "Servings" export
+const CRONOMETER: Columns = Columns {
+    date: &["Day"],
+    time: &["Time"],
+    meal: &["Group"],
+    food: &["Food Name"],
+    amount: &["Amount"],
+    calories: &["Energy (kcal)"],
+    protein: &["Protein (g)"],
+    fat: &["Fat (g)"],
+    carbs: &["Carbs (g)", "Net Carbs (g)"],
+};
+
+// MyFitnessPal's food diary export
+const MY_FITNESS_PAL: Columns = Columns {
+    date: &["Date"],
+    time: &["Time"],
+    meal: &["Meal"],
+    food: &["Food Name", "Food"],
+    amount: &["Serving Size", "Quantity", "Amount"],
+    calories: &["Calories"],
+    protein: &["Protein (g)"],
+    fat: &["Fat (g)"],
+    carbs: &["Carbohydrates (g)", "Carbs (g)"],
+};
+
+impl ImportSource {
+    fn columns(&self) -> &'static Columns {
+        match self {
+            ImportSource::Cronometer => &CRONOMETER,
+            ImportSource::MyFitnessPal => &MY_FITNESS_PAL,
+        }
+    }
+
+    fn detect(header: &[String]) -> Option<Self> {
+        let has = |name: &str| header.iter().any(|column| column.trim() == name);
+        if has("Day") && has("Food Name") {
+            Some(ImportSource::Cronometer)
+        } else if has("Date") && has("Meal") && (has("Food") || has("Food Name")) {
+            Some(ImportSource::MyFitnessPal)
+        } else {
+            None
+        }
+    }
+}
+
+// One diary line as the other app recorded it
+#[derive(Clone, Debug, Serialize)]
+pub struct ImportRow {
+    // 1 based, counting data rows only
+    pub row: usize,
+    pub food_name: String,
+    pub eaten_at: Option<DateTime<Local>>,
+    pub grams: Option<f64>,
+    pub macros: Option<MacroTotals>,
+    pub problem: Option<String>,
+}
+
+impl ImportRow {
+    // The weight, or for servings we can't weigh ("2 large") the weight the
+    // calories they logged come to in this food. Preview and commit both
+    // size rows this way so they agree on what gets saved.
+    pub fn grams_for(&self, food: &Food) -> Option<f64> {
+        self.grams.or_else(|| {
+            let macros = self.macros?;
+            (food.calories > 0.0).then(|| macros.calories / food.calories * 100.0)
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RowStatus {
+    Matched,
+    Unmatched,
+    Duplicate,
+    Invalid,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PreviewRow {
+    #[serde(flatten)]
+    pub row: ImportRow,
+    pub status: RowStatus,
+    pub food_id: Option<String>,
+    pub matched_name: Option<String>,
+}
+
+fn field<'a>(fields: &'a HashMap<String, String>, names: &[&str]) -> Option<&'a str> {
+    names.iter()
+        .find_map(|name| fields.get(*name))
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+}
+
+fn number(fields: &HashMap<String, String>, names: &[&str]) -> Option<f64> {
+    field(fields, names)
+        .and_then(|value| value.replace(',', "").parse::<f64>().ok())
+        .filter(|value| value.is_finite() && *value >= 0.0)
+}
+
+fn parse_date(text: &str) -> Option<NaiveDate> {
+    ["%Y-%m-%d", "%m/%d/%Y", "%m/%d/%y"].iter()
+        .find_map(|format| NaiveDate::parse_from_str(text, format).ok())
+}
+
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    ["%H:%M", "%H:%M:%S", "%I:%M %p", "%I:%M:%S %p", "%I:%M%p"].iter()
+        .find_map(|format| NaiveTime::parse_from_str(&text.to_uppercase(), format).ok())
+}
+
+fn slot_for(meal: &str) -> Option<MealSlot> {
+    let meal = meal.to_lowercase();
+    if meal.starts_with("breakfast") {
+        Some(MealSlot::Breakfast)
+    } else if meal.starts_with("lunch") {
+        Some(MealSlot::Lunch)
+    } else if meal.starts_with("dinner") {
+        Some(MealSlot::Dinner)
+    } else if meal.starts_with("snack") {
+        Some(MealSlot::Snack)
+    } else {
+        None
+    }
+}
+
+// "150.00 g", "5.3 oz", "1lb"; None for servings we can't weigh ("2 large")
+fn parse_grams(amount: &str) -> Option<f64> {
+    let split = amount.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ',')).unwrap_or(amount.len());
+    let (quantity, unit) = amount.split_at(split);
+    let quantity = quantity.replace(',', "").parse::<f64>().ok()?;
+    let per_unit = match unit.trim().to_lowercase().as_str() {
+        "g" | "gram" | "grams" | "ml" => 1.0,
+        "kg" => 1000.0,
+        "oz" => 28.3495,
+        "lb" | "lbs" => 453.592,
+        _ => return None,
+    };
+    Some(quantity * per_unit)
+}
+
+fn parse_row(row: usize, fields: &HashMap<String, String>, columns: &Columns) -> ImportRow {
+    let food_name = field(fields, columns.food).unwrap_or_default().to_owned();
+    let date = field(fields, columns.date).and_then(parse_date);
+    // exports without a time column get the time of the meal they were logged under
+    let time = field(fields, columns.time).and_then(parse_time)
+        .or_else(|| field(fields, columns.meal).and_then(slot_for).map(|slot| slot.time()))
+        .unwrap_or_else(|| MealSlot::Lunch.time());
+    let eaten_at = date.and_then(|date| date.and_time(time).and_local_timezone(Local).earliest());
+    let calories = number(fields, columns.calories);
+    let macros = calories.map(|calories| MacroTotals {
+        protein_g: number(fields, columns.protein).unwrap_or(0.0),
+        fat_g: number(fields, columns.fat).unwrap_or(0.0),
+        carbs_g: number(fields, columns.carbs).unwrap_or(0.0),
+        calories,
+    });
+    let problem = if food_name.is_empty() {
+        Some("No food name".to_owned())
+    } else if eaten_at.is_none() {
+        Some("Unreadable date".to_owned())
+    } else {
+        None
+    };
+    ImportRow {
+        row,
+        food_name,
+        eaten_at,
+        grams: field(fields, columns.amount).and_then(parse_grams),
+        macros,
+        problem,
+    }
+}
+
+// Reads an export, working out which app it came from unless told
+pub fn parse_export(text: &str, source: Option<ImportSource>) -> Result<(ImportSource, Vec<ImportRow>), String> {
+    let header: Vec<String> = csv::records(text).into_iter().next().unwrap_or_default();
+    let source = source.or_else(|| ImportSource::detect(&header))
+        .ok_or("Not a Cronometer servings or MyFitnessPal food diary export")?;
+    let columns = source.columns();
+    let rows = csv::rows(text).iter()
+        .enumerate()
+        .map(|(index, fields)| parse_row(index + 1, fields, columns))
+        .collect();
+    Ok((source, rows))
+}
+
+pub fn normalize_name(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn words(name: &str) -> HashSet<String> {
+    normalize_name(name)
+        .split(' ')
+        .filter(|word| word.len() > 1 && !IGNORED_WORDS.contains(word))
+        // crude singular so "eggs" meets "egg"
+        .map(|word| word.strip_suffix('s').filter(|stem| stem.len() > 2).unwrap_or(word).to_owned())
+        .collect()
+}
+
+// Same name first, otherwise the food sharing the largest share of words.
+// Exports name foods like "Beef, Ground, 80% Lean Meat / 20% Fat, Raw" so
+// the share is taken of our (shorter) name.
+pub fn match_food<'a>(name: &str, foods: &'a [Food]) -> Option<&'a Food> {
+    let normalized = normalize_name(name);
+    if let Some(food) = foods.iter().find(|food| normalize_name(&food.name) == normalized) {
+        return Some(food);
+    }
+    let wanted = words(name);
+    foods.iter()
+        .filter_map(|food| {
+            let theirs = words(&food.name);
+            if theirs.is_empty() {
+                return None;
+            }
+            let score = theirs.intersection(&wanted).count() as f64 / theirs.len() as f64;
+            (score >= MATCH_THRESHOLD).then_some((score, food))
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, food)| food)
+}
+
+pub fn guess_category(name: &str) -> Option<FoodCategory> {
+    let name = name.to_lowercase();
+    CATEGORY_WORDS.iter()
+        .find(|(word, _)| name.contains(word))
+        .map(|(_, category)| *category)
+}
+
+fn entry_key(eaten_at: &DateTime<Local>, food_id: &str, grams: f64) -> (i64, String, i64) {
+    (eaten_at.timestamp(), food_id.to_owned(), grams.round() as i64)
+}
+
+// Works out what committing would do without writing anything. Mappings
+// (export name to food id) override the matcher, and rows already in the
+// diary with the same time, food and weight are duplicates from an earlier import.
+pub fn preview(rows: Vec<ImportRow>, foods: &[Food], mappings: &HashMap<String, String>, existing: &[MealEntry]) -> Vec<PreviewRow> {
+    let existing: HashSet<(i64, String, i64)> = existing.iter()
+        .map(|entry| entry_key(&entry.eaten_at, &entry.food_id, entry.grams))
+        .collect();
+    rows.into_iter()
+        .map(|mut row| {
+            if row.problem.is_some() {
+                return PreviewRow { row, status: RowStatus::Invalid, food_id: None, matched_name: None };
+            }
+            let food = match mappings.get(&row.food_name) {
+                Some(food_id) => foods.iter().find(|food| &food.id == food_id),
+                None => match_food(&row.food_name, foods),
+            };
+            let Some(food) = food else {
+                return PreviewRow { row, status: RowStatus::Unmatched, food_id: None, matched_name: None };
+            };
+            row.grams = row.grams_for(food);
+            let status = match (row.eaten_at, row.grams) {
+                (_, None) => {
+                    row.problem = Some("No weight or calories to size the serving".to_owned());
+                    RowStatus::Invalid
+                },
+                (Some(eaten_at), Some(grams)) if existing.contains(&entry_key(&eaten_at, &food.id, grams)) => RowStatus::Duplicate,
+                _ => RowStatus::Matched,
+            };
+            PreviewRow { row, status, food_id: Some(food.id.clone()), matched_name: Some(food.name.clone()) }
+        })
+        .collect()
+}
+
+// A diary entry for the food, keeping the macros the other app recorded
+pub fn import_entry(food: &Food, row: &ImportRow) -> Option<MealEntry> {
+    let mut entry = MealEntry::from_food(food, row.grams?, food.default_sourcing.clone(), row.eaten_at?);
+    if let Some(macros) = row.macros {
+        entry.protein_g = macros.protein_g;
+        entry.fat_g = macros.fat_g;
+        entry.carbs_g = macros.carbs_g;
+        entry.calories = macros.calories;
+    }
+    Some(entry)
+}
+
+// Per 100g nutrition for a food only the export knows about
+pub fn food_from_row(id: String, row: &ImportRow, category: FoodCategory) -> Option<Food> {
+    let grams = row.grams.filter(|grams| *grams > 0.0)?;
+    let macros = row.macros?;
+    let scale = 100.0 / grams;
+    Some(Food {
+        id,
+        name: row.food_name.clone(),
+        category,
+        protein_g: macros.protein_g * scale,
+        fat_g: macros.fat_g * scale,
+        carbs_g: macros.carbs_g * scale,
+        calories: macros.calories * scale,
+        omega3_mg: 0.0,
+        omega6_mg: 0.0,
+        default_sourcing: Vec::new(),
+        custom: true,
+        provenance: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn food(id: &str, name: &str, category: FoodCategory, calories: f64) -> Food {
+        Food {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            category,
+            protein_g: 20.0,
+            fat_g: 10.0,
+            carbs_g: 0.0,
+            calories,
+            omega3_mg: 0.0,
+            omega6_mg: 0.0,
+            default_sourcing: Vec::new(),
+            custom: false,
+            provenance: None,
+        }
+    }
+
+    #[test]
+    fn parses_weights() {
+        assert_eq!(parse_grams("150.00 g"), Some(150.0));
+        assert_eq!(parse_grams("1,000 g"), Some(1000.0));
+        assert_eq!(parse_grams("2 lbs"), Some(907.184));
+        assert_eq!(parse_grams("2 large"), None);
+    }
+
+    #[test]
+    fn reads_a_cronometer_export() {
+        let text = "Day,Time,Group,Food Name,Amount,Energy (kcal),Protein (g),Fat (g),Carbs (g)\n\
+            2024-03-01,,Breakfast,\"Eggs, Fried\",2 large,180,12,14,1\n\
+            not a date,,,Ribeye,200 g,580,46,44,0\n";
+        let (source, rows) = parse_export(text, None).unwrap();
+        assert_eq!(source, ImportSource::Cronometer);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].food_name, "Eggs, Fried");
+        assert_eq!(rows[0].grams, None);
+        assert_eq!(rows[0].macros.map(|macros| macros.calories), Some(180.0));
+        assert_eq!(rows[0].eaten_at.map(|at| at.time()), Some(MealSlot::Breakfast.time()));
+        assert!(rows[1].problem.is_some());
+    }
+
+    #[test]
+    fn preview_sizes_matches_and_spots_duplicates() {
+        let foods = vec![food("eggs", "Eggs", FoodCategory::Eggs, 150.0), food("ribeye-steak", "Ribeye Steak", FoodCategory::Beef, 290.0)];
+        let eaten_at = Local.with_ymd_and_hms(2024, 3, 1, 8, 0, 0).unwrap();
+        let row = |row: usize, food_name: &str, grams: Option<f64>, calories: f64| ImportRow {
+            row,
+            food_name: food_name.to_owned(),
+            eaten_at: Some(eaten_at),
+            grams,
+            macros: Some(MacroTotals { protein_g: 0.0, fat_g: 0.0, carbs_g: 0.0, calories }),
+            problem: None,
+        };
+        let existing = vec![MealEntry::from_food(&foods[1], 200.0, Vec::new(), eaten_at)];
+        let previewed = preview(
+            vec![row(1, "Eggs, Fried", None, 300.0), row(2, "Ribeye Steak", Some(200.0), 580.0), row(3, "Tofu", Some(100.0), 80.0)],
+            &foods,
+            &HashMap::new(),
+            &existing,
+        );
+        assert_eq!(previewed[0].status, RowStatus::Matched);
+        assert_eq!(previewed[0].row.grams, Some(200.0));
+        assert_eq!(previewed[1].status, RowStatus::Duplicate);
+        assert_eq!(previewed[2].status, RowStatus::Unmatched);
+    }
+}
@@ -0,0 +1,310 @@
+use argon2::Argon2;
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db};
+use std::error::Error;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::models::identity::LoginError;
+use crate::models::secret::token_hash;
+
+// what local accounts are stored as in the identity store
+pub const LOCAL_PROVIDER: &str = "local";
+
+pub const MIN_PASSWORD_LENGTH: usize = 10;
+// argon2 takes anything, this just keeps requests small
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+
+// failures allowed from one network before it's locked out for a while
+const FREE_ATTEMPTS: u32 = 5;
+// and from everywhere together before the account is, so guessing from many
+// networks at once stays slow too
+const ACCOUNT_FREE_ATTEMPTS: u32 = 50;
+// where the failures from everywhere are counted
+const ALL_NETWORKS: &str = "*";
+const MAX_LOCKOUT_MINUTES: i64 = 60;
+const RESET_TOKEN_HOURS: i64 = 24;
+
+// Username and password sign-in for people without reddit. Which user it
+// signs in as is kept in the identity store like every other provider.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LocalAccount {
+    // lowercase, also the identity subject
+    pub username: String,
+    // PHC string, argon2id with its own salt and parameters
+    pub password_hash: String,
+    pub created_at: DateTime<Local>,
+    pub password_changed_at: DateTime<Local>,
+}
+
+// Failed logins in a row, reset on success
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LoginAttempts {
+    pub failures: u32,
+    pub locked_until: Option<DateTime<Local>>,
+}
+
+impl LoginAttempts {
+    pub fn locked(&self) -> Option<DateTime<Local>> {
+        self.locked_until.filter(|locked_until| *locked_until > Local::now())
+    }
+
+    // Free attempts first, then 1, 2, 4... minutes up to an hour
+    pub fn failed(&mut self, free_attempts: u32) {
+        self.failures += 1;
+        if self.failures >= free_attempts {
+            let minutes = 2i64.saturating_pow(self.failures - free_attempts).min(MAX_LOCKOUT_MINUTES);
+            self.locked_until = Some(Local::now() + Duration::minutes(minutes));
+        }
+    }
+}
+
+// Only the hash is stored, the token itself is handed out once
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ResetToken {
+    pub username: String,
+    pub expires_at: DateTime<Local>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IssuedResetToken {
+    pub token: String,
+    pub expires_at: DateTime<Local>,
+}
+
+// 3 to 32 of a-z, 0-9, _ and -
+pub fn normalize_username(username: &str) -> Option<String> {
+    let username = username.trim().to_lowercase();
+    let valid = (3..=32).contains(&username.len())
+        && username.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    valid.then_some(username)
+}
+
+pub fn check_password(password: &str) -> Result<(), LoginError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(LoginError(format!("Passwords need at least {} characters", MIN_PASSWORD_LENGTH)));
+    }
+    if password.len() > MAX_PASSWORD_LENGTH {
+        return Err(LoginError(format!("Passwords can't be longer than {} bytes", MAX_PASSWORD_LENGTH)));
+    }
+    Ok(())
+}
+
+// Argon2id with the crate's default (OWASP recommended) parameters
+pub fn hash_password(password: &str) -> Result<String, Box<dyn Error>> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| LoginError(e.to_string()))?;
+    Ok(hash.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    match PasswordHash::new(password_hash) {
+        Err(_) => false,
+        Ok(parsed) => Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok(),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LocalAccountStore {
+    pub db: Arc<Db>,
+}
+
+impl LocalAccountStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(LocalAccountStore {
+            db: Arc::new(db),
+        })
+    }
+
+    pub async fn get_account(&self, username: &str) -> Result<Option<LocalAccount>, Box<dyn Error>> {
+        if let Some(data) = self.db.get(format!("account:{}", username).as_bytes())? {
+            Ok(Some(serde_json::from_slice(&data)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // false if the username is taken
+    pub async fn create_account(&self, account: &LocalAccount) -> Result<bool, Box<dyn Error>> {
+        let serialized = serde_json::to_vec(account)?;
+        let created = self.db
+            .compare_and_swap(format!("account:{}", account.username).as_bytes(), None as Option<&[u8]>, Some(serialized))?
+            .is_ok();
+        self.db.flush()?;
+        Ok(created)
+    }
+
+    pub async fn save_account(&self, account: &LocalAccount) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(account)?;
+        self.db.insert(format!("account:{}", account.username).as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // unlinking the identity or deleting the user
+    pub async fn delete_account(&self, username: &str) -> Result<(), Box<dyn Error>> {
+        self.db.remove(format!("account:{}", username).as_bytes())?;
+        self.clear_all_attempts(username).await
+    }
+
+    // Attempts are counted per username and network (see ip_prefix), so
+    // someone guessing from elsewhere can't lock the owner out for long, and
+    // per username under ALL_NETWORKS with a bigger allowance
+    fn attempts_key(username: &str, network: &str) -> String {
+        format!("attempts:{}:{}", username, network)
+    }
+
+    pub async fn get_attempts(&self, username: &str, network: &str) -> Result<LoginAttempts, Box<dyn Error>> {
+        if let Some(data) = self.db.get(Self::attempts_key(username, network).as_bytes())? {
+            Ok(serde_json::from_slice(&data)?)
+        } else {
+            Ok(LoginAttempts::default())
+        }
+    }
+
+    // When the username can be tried again from the network, if it's locked
+    pub async fn locked_until(&self, username: &str, network: &str) -> Result<Option<DateTime<Local>>, Box<dyn Error>> {
+        let here = self.get_attempts(username, network).await?.locked();
+        let everywhere = self.get_attempts(username, ALL_NETWORKS).await?.locked();
+        Ok(here.max(everywhere))
+    }
+
+    // Counts a failure against the network and the whole account, each in
+    // one step so parallel guesses all count
+    pub async fn record_failure(&self, username: &str, network: &str) -> Result<LoginAttempts, Box<dyn Error>> {
+        self.count_failure(username, ALL_NETWORKS, ACCOUNT_FREE_ATTEMPTS)?;
+        self.count_failure(username, network, FREE_ATTEMPTS)
+    }
+
+    fn count_failure(&self, username: &str, network: &str, free_attempts: u32) -> Result<LoginAttempts, Box<dyn Error>> {
+        let updated = self.db.update_and_fetch(Self::attempts_key(username, network).as_bytes(), |old| {
+            let mut attempts = old.and_then(|data| serde_json::from_slice::<LoginAttempts>(data).ok()).unwrap_or_default();
+            attempts.failed(free_attempts);
+            serde_json::to_vec(&attempts).ok()
+        })?;
+        self.db.flush()?;
+        match updated {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Err("Unable to record failed login".into()),
+        }
+    }
+
+    // a successful login, which also wipes the account-wide count
+    pub async fn clear_attempts(&self, username: &str, network: &str) -> Result<(), Box<dyn Error>> {
+        let mut batch = Batch::default();
+        batch.remove(Self::attempts_key(username, network).as_bytes());
+        batch.remove(Self::attempts_key(username, ALL_NETWORKS).as_bytes());
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // from every network, when the password changes or the account goes
+    pub async fn clear_all_attempts(&self, username: &str) -> Result<(), Box<dyn Error>> {
+        let mut batch = Batch::default();
+        // from before attempts were per network
+        batch.remove(format!("attempts:{}", username).as_bytes());
+        for key in self.db.scan_prefix(format!("attempts:{}:", username).as_bytes()).keys() {
+            batch.remove(key?);
+        }
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn issue_reset_token(&self, username: &str) -> Result<IssuedResetToken, Box<dyn Error>> {
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let reset = ResetToken {
+            username: username.to_owned(),
+            expires_at: Local::now() + Duration::hours(RESET_TOKEN_HOURS),
+        };
+        self.db.insert(format!("reset:{}", token_hash(&token)).as_bytes(), serde_json::to_vec(&reset)?)?;
+        self.db.flush()?;
+        Ok(IssuedResetToken { token, expires_at: reset.expires_at })
+    }
+
+    // One use, whether or not it had expired
+    pub async fn take_reset_token(&self, token: &str) -> Result<Option<ResetToken>, Box<dyn Error>> {
+        let removed = self.db.remove(format!("reset:{}", token_hash(token)).as_bytes())?;
+        self.db.flush()?;
+        match removed {
+            None => Ok(None),
+            Some(data) => {
+                let reset: ResetToken = serde_json::from_slice(&data)?;
+                Ok((reset.expires_at > Local::now()).then_some(reset))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> LocalAccountStore {
+        LocalAccountStore {
+            db: Arc::new(sled::Config::new().temporary(true).open().unwrap()),
+        }
+    }
+
+    #[test]
+    fn locks_after_the_free_attempts() {
+        let mut attempts = LoginAttempts::default();
+        for _ in 1..FREE_ATTEMPTS {
+            attempts.failed(FREE_ATTEMPTS);
+            assert!(attempts.locked().is_none());
+        }
+        attempts.failed(FREE_ATTEMPTS);
+        let first = attempts.locked().unwrap() - Local::now();
+        assert!(first <= Duration::minutes(1));
+        for _ in 0..20 {
+            attempts.failed(FREE_ATTEMPTS);
+        }
+        assert!(attempts.locked().unwrap() - Local::now() <= Duration::minutes(MAX_LOCKOUT_MINUTES));
+    }
+
+    #[actix_web::test]
+    async fn counts_attempts_per_network() {
+        let store = store();
+        for _ in 0..FREE_ATTEMPTS {
+            store.record_failure("alice", "198.51.100.0/24").await.unwrap();
+        }
+        assert!(store.get_attempts("alice", "198.51.100.0/24").await.unwrap().locked().is_some());
+        // the owner somewhere else isn't locked out
+        assert!(store.get_attempts("alice", "203.0.113.0/24").await.unwrap().locked().is_none());
+        store.record_failure("alice", "203.0.113.0/24").await.unwrap();
+        store.clear_attempts("alice", "203.0.113.0/24").await.unwrap();
+        assert_eq!(store.get_attempts("alice", "203.0.113.0/24").await.unwrap().failures, 0);
+        assert_eq!(store.get_attempts("alice", "198.51.100.0/24").await.unwrap().failures, FREE_ATTEMPTS);
+        store.clear_all_attempts("alice").await.unwrap();
+        assert_eq!(store.get_attempts("alice", "198.51.100.0/24").await.unwrap().failures, 0);
+    }
+
+    #[actix_web::test]
+    async fn caps_attempts_across_networks() {
+        let store = store();
+        // a new network for every guess never trips the per-network lockout
+        for guess in 0..ACCOUNT_FREE_ATTEMPTS {
+            assert!(store.locked_until("alice", &format!("network-{}", guess)).await.unwrap().is_none());
+            store.record_failure("alice", &format!("network-{}", guess)).await.unwrap();
+        }
+        assert!(store.locked_until("alice", "somewhere-new").await.unwrap().is_some());
+        assert!(store.locked_until("bob", "somewhere-new").await.unwrap().is_none());
+        store.clear_all_attempts("alice").await.unwrap();
+        assert!(store.locked_until("alice", "somewhere-new").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn checks_usernames_and_passwords() {
+        assert_eq!(normalize_username(" Alice_1 "), Some("alice_1".to_owned()));
+        assert_eq!(normalize_username("al"), None);
+        assert_eq!(normalize_username("al ice"), None);
+        assert!(check_password("short").is_err());
+        assert!(check_password("long enough password").is_ok());
+    }
+}
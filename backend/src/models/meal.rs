@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db};
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+use std::sync::Arc;
+use std::error::Error;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
+use uuid::Uuid;
+
+use crate::models::user::move_prefix;
+use crate::models::food::{Food, FoodCategory};
+use crate::models::sourcing::{adjusted_omegas, Sourcing};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct MacroTotals {
+    pub protein_g: f64,
+    pub fat_g: f64,
+    pub carbs_g: f64,
+    pub calories: f64,
+}
+
+impl AddAssign for MacroTotals {
+    fn add_assign(&mut self, other: Self) {
+        self.protein_g += other.protein_g;
+        self.fat_g += other.fat_g;
+        self.carbs_g += other.carbs_g;
+        self.calories += other.calories;
+    }
+}
+
+// Macros are worked out when the entry is logged so later catalogue edits
+// don't rewrite history
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MealEntry {
+    pub id: Uuid,
+    pub food_id: String,
+    pub food_name: String,
+    pub category: FoodCategory,
+    pub grams: f64,
+    pub protein_g: f64,
+    pub fat_g: f64,
+    pub carbs_g: f64,
+    pub calories: f64,
+    #[serde(default)]
+    pub sourcing: Vec<Sourcing>,
+    #[serde(default)]
+    pub omega3_mg: f64,
+    #[serde(default)]
+    pub omega6_mg: f64,
+    // from the user's purchase prices, if they track them
+    #[serde(default)]
+    pub cost: Option<f64>,
+    // how much came out of the pantry, put back if the entry is deleted
+    #[serde(default)]
+    pub pantry_grams: f64,
+    pub eaten_at: DateTime<Local>,
+}
+
+impl MealEntry {
+    pub fn from_food(food: &Food, grams: f64, sourcing: Vec<Sourcing>, eaten_at: DateTime<Local>) -> Self {
+        let scale = grams / 100.0;
+        let (omega3_mg, omega6_mg) = adjusted_omegas(food.omega3_mg, food.omega6_mg, &sourcing);
+        MealEntry {
+            id: Uuid::new_v4(),
+            food_id: food.id.clone(),
+            food_name: food.name.clone(),
+            category: food.category,
+            grams,
+            protein_g: food.protein_g * scale,
+            fat_g: food.fat_g * scale,
+            carbs_g: food.carbs_g * scale,
+            calories: food.calories * scale,
+            sourcing,
+            omega3_mg: omega3_mg * scale,
+            omega6_mg: omega6_mg * scale,
+            cost: None,
+            pantry_grams: 0.0,
+            eaten_at,
+        }
+    }
+
+    pub fn macros(&self) -> MacroTotals {
+        MacroTotals {
+            protein_g: self.protein_g,
+            fat_g: self.fat_g,
+            carbs_g: self.carbs_g,
+            calories: self.calories,
+        }
+    }
+}
+
+pub fn daily_totals(entries: &[MealEntry]) -> BTreeMap<NaiveDate, MacroTotals> {
+    let mut totals: BTreeMap<NaiveDate, MacroTotals> = BTreeMap::new();
+    for entry in entries {
+        *totals.entry(entry.eaten_at.date_naive()).or_default() += entry.macros();
+    }
+    totals
+}
+
+// Local midnight to the following midnight
+pub fn day_bounds(date: NaiveDate) -> Option<(DateTime<Local>, DateTime<Local>)> {
+    let start = date.and_hms_opt(0, 0, 0)?.and_local_timezone(Local).earliest()?;
+    let end = date.succ_opt()?.and_hms_opt(0, 0, 0)?.and_local_timezone(Local).earliest()?;
+    Some((start, end))
+}
+
+// Monday to Monday around the given day
+pub fn week_bounds(date: NaiveDate) -> Option<(DateTime<Local>, DateTime<Local>)> {
+    let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+    let (start, _) = day_bounds(monday)?;
+    let (end, _) = day_bounds(monday + Duration::days(7))?;
+    Some((start, end))
+}
+
+#[derive(Clone, Debug)]
+pub struct MealStore {
+    pub db: Arc<Db>,
+}
+
+impl MealStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(MealStore {
+            db: Arc::new(db),
+        })
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        move_prefix(&self.db, &format!("{}:", from), &format!("{}:", to))
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        for item in self.db.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (key, _) = item?;
+            self.db.remove(key)?;
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn time_key(user_id: &str, at: &DateTime<Local>) -> String {
+        format!("{}:{:020}", user_id, at.timestamp_millis())
+    }
+
+    fn key(user_id: &str, entry: &MealEntry) -> String {
+        format!("{}:{}", Self::time_key(user_id, &entry.eaten_at), entry.id)
+    }
+
+    pub async fn save_entry(&self, user_id: &str, entry: &MealEntry) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(entry)?;
+        self.db.insert(Self::key(user_id, entry).as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // for imports, one flush for the lot
+    pub async fn save_entries(&self, user_id: &str, entries: &[MealEntry]) -> Result<(), Box<dyn Error>> {
+        for entry in entries {
+            let serialized = serde_json::to_vec(entry)?;
+            self.db.insert(Self::key(user_id, entry).as_bytes(), serialized)?;
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // For restores: the whole table in one batch, taking out the rows being
+    // replaced in the same go (their keys may carry a different time)
+    pub async fn replace_entries(&self, user_id: &str, removed: &[MealEntry], saved: &[MealEntry]) -> Result<(), Box<dyn Error>> {
+        let mut batch = Batch::default();
+        for entry in removed {
+            batch.remove(Self::key(user_id, entry).as_bytes());
+        }
+        for entry in saved {
+            batch.insert(Self::key(user_id, entry).as_bytes(), serde_json::to_vec(entry)?);
+        }
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_entries(&self, user_id: &str) -> Result<Vec<MealEntry>, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for item in self.db.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (_, data) = item?;
+            entries.push(serde_json::from_slice::<MealEntry>(&data)?);
+        }
+        Ok(entries)
+    }
+
+    // entries eaten in [from, to)
+    pub async fn get_entries_between(&self, user_id: &str, from: &DateTime<Local>, to: &DateTime<Local>) -> Result<Vec<MealEntry>, Box<dyn Error>> {
+        let start = Self::time_key(user_id, from);
+        let end = Self::time_key(user_id, to);
+        let mut entries = Vec::new();
+        for item in self.db.range(start.as_bytes()..end.as_bytes()) {
+            let (_, data) = item?;
+            entries.push(serde_json::from_slice::<MealEntry>(&data)?);
+        }
+        Ok(entries)
+    }
+
+    // hands back the deleted entry so its side effects can be undone
+    pub async fn delete_entry(&self, user_id: &str, entry_id: &Uuid) -> Result<Option<MealEntry>, Box<dyn Error>> {
+        for entry in self.get_entries(user_id).await? {
+            if &entry.id == entry_id {
+                self.db.remove(Self::key(user_id, &entry).as_bytes())?;
+                self.db.flush()?;
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+}
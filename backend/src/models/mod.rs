@@ -1,3 +1,52 @@
 pub mod session;
+pub mod body;
+pub mod food;
+pub mod meal;
+pub mod targets;
+pub mod tdee;
+pub mod profile;
+pub mod sourcing;
+pub mod cost;
+pub mod pantry;
+pub mod planner;
+pub mod plan;
+pub mod barcode;
+pub mod fdc;
+pub mod csv;
+pub mod import;
+pub mod archive;
+pub mod parser;
+pub mod search;
+pub mod reddit;
+pub mod sharing;
+pub mod admission;
+pub mod revocation;
+pub mod identity;
+pub mod oidc;
+pub mod local_account;
+pub mod passkey;
+pub mod user;
+pub mod device_session;
+pub mod scheduler;
+pub mod api_token;
+pub mod secret;
 
 pub use session::{UserSession, SessionStore, RedditUser};
+pub use body::{BodyMetric, BodyStore, BodyTrend};
+pub use food::{Food, FoodCategory, FoodStore};
+pub use meal::{MealEntry, MealStore};
+pub use targets::{Targets, TargetsStore};
+pub use tdee::{Confidence, TdeeEstimate};
+pub use profile::ProfileStore;
+pub use cost::{Purchase, PurchaseStore};
+pub use pantry::{PantryItem, PantryStore};
+pub use plan::PlanStore;
+pub use sharing::ShareStore;
+pub use admission::AdmissionStore;
+pub use revocation::RevocationStore;
+pub use identity::IdentityStore;
+pub use local_account::LocalAccountStore;
+pub use passkey::PasskeyStore;
+pub use user::{User, UserStore};
+pub use device_session::DeviceSessionStore;
+pub use api_token::ApiTokenStore;
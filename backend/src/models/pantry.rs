@@ -0,0 +1,412 @@
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::error::Error;
+use chrono::{DateTime, Local};
+use uuid::Uuid;
+
+use crate::models::user::move_prefix;
+use crate::models::cost::{price_per_gram_at, Purchase};
+use crate::models::meal::MealEntry;
+
+// How far back consumption is averaged for days-of-supply
+pub const USAGE_WINDOW_DAYS: i64 = 14;
+// Anything with less than this many days left counts as running low
+pub const LOW_STOCK_DAYS: f64 = 3.0;
+
+// One lot of a food: a pack of ground beef, a tray of eggs, a quarter cow.
+// Meals take from the oldest lot first.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PantryItem {
+    pub id: Uuid,
+    pub food_id: String,
+    pub food_name: String,
+    pub grams_stocked: f64,
+    pub grams_remaining: f64,
+    // fridge, freezer, chest freezer in the garage...
+    pub location: Option<String>,
+    pub stocked_at: DateTime<Local>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StockLevel {
+    pub food_id: String,
+    pub food_name: String,
+    pub grams_on_hand: f64,
+    pub daily_use_g: f64,
+    // None when the food hasn't been eaten lately
+    pub days_of_supply: Option<f64>,
+    pub running_low: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShoppingItem {
+    pub food_id: String,
+    pub food_name: String,
+    pub grams_needed: f64,
+    // from the last price paid, if purchases are tracked
+    pub estimated_cost: Option<f64>,
+    pub store: Option<String>,
+}
+
+// Stock per food against the average daily use over the entries given, which
+// should cover the usage window. The average only counts days since logging
+// started so new users aren't told they have months of supply.
+pub fn stock_levels(items: &[PantryItem], entries: &[MealEntry], now: &DateTime<Local>, low_days: f64) -> Vec<StockLevel> {
+    let logged_days = entries.iter()
+        .map(|entry| entry.eaten_at)
+        .min()
+        .map(|first| (now.date_naive() - first.date_naive()).num_days() + 1)
+        .unwrap_or(1)
+        .clamp(1, USAGE_WINDOW_DAYS) as f64;
+
+    let mut levels: BTreeMap<&str, StockLevel> = BTreeMap::new();
+    for item in items {
+        let level = levels.entry(&item.food_id).or_insert_with(|| StockLevel {
+            food_id: item.food_id.clone(),
+            food_name: item.food_name.clone(),
+            grams_on_hand: 0.0,
+            daily_use_g: 0.0,
+            days_of_supply: None,
+            running_low: false,
+        });
+        level.grams_on_hand += item.grams_remaining;
+    }
+    // only foods that are kept in the pantry get a stock level
+    for entry in entries {
+        if let Some(level) = levels.get_mut(entry.food_id.as_str()) {
+            level.daily_use_g += entry.grams / logged_days;
+        }
+    }
+
+    let mut levels: Vec<StockLevel> = levels.into_values()
+        .map(|mut level| {
+            if level.daily_use_g > 0.0 {
+                level.days_of_supply = Some(level.grams_on_hand / level.daily_use_g);
+            }
+            level.running_low = level.grams_on_hand <= 0.0 || level.days_of_supply.is_some_and(|days| days < low_days);
+            level
+        })
+        .collect();
+    levels.sort_by(|a, b| {
+        a.days_of_supply.unwrap_or(f64::INFINITY).total_cmp(&b.days_of_supply.unwrap_or(f64::INFINITY))
+    });
+    levels
+}
+
+// What to buy to cover the next `days` at the current rate of use, most
+// urgent first
+pub fn shopping_list(levels: &[StockLevel], purchases: &[Purchase], now: &DateTime<Local>, days: f64) -> Vec<ShoppingItem> {
+    levels.iter()
+        .filter_map(|level| {
+            let grams_needed = level.daily_use_g * days - level.grams_on_hand;
+            if grams_needed <= 0.0 {
+                return None;
+            }
+            let store = purchases.iter()
+                .filter(|purchase| purchase.food_id == level.food_id)
+                .max_by_key(|purchase| purchase.purchased_at)
+                .and_then(|purchase| purchase.store.clone());
+            Some(ShoppingItem {
+                food_id: level.food_id.clone(),
+                food_name: level.food_name.clone(),
+                grams_needed,
+                estimated_cost: price_per_gram_at(purchases, &level.food_id, now).map(|per_gram| per_gram * grams_needed),
+                store,
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct PantryStore {
+    pub db: Arc<Db>,
+}
+
+impl PantryStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(PantryStore {
+            db: Arc::new(db),
+        })
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        move_prefix(&self.db, &format!("{}:", from), &format!("{}:", to))
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        for item in self.db.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (key, _) = item?;
+            self.db.remove(key)?;
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // oldest lots sort first
+    fn key(user_id: &str, item: &PantryItem) -> String {
+        format!("{}:{:020}:{}", user_id, item.stocked_at.timestamp_millis(), item.id)
+    }
+
+    pub async fn save_item(&self, user_id: &str, item: &PantryItem) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(item)?;
+        self.db.insert(Self::key(user_id, item).as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // see MealStore::replace_entries
+    pub async fn replace_items(&self, user_id: &str, removed: &[PantryItem], saved: &[PantryItem]) -> Result<(), Box<dyn Error>> {
+        let mut batch = Batch::default();
+        for item in removed {
+            batch.remove(Self::key(user_id, item).as_bytes());
+        }
+        for item in saved {
+            batch.insert(Self::key(user_id, item).as_bytes(), serde_json::to_vec(item)?);
+        }
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_items(&self, user_id: &str) -> Result<Vec<PantryItem>, Box<dyn Error>> {
+        let mut items = Vec::new();
+        for item in self.db.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (_, data) = item?;
+            items.push(serde_json::from_slice::<PantryItem>(&data)?);
+        }
+        Ok(items)
+    }
+
+    pub async fn get_item(&self, user_id: &str, item_id: &Uuid) -> Result<Option<PantryItem>, Box<dyn Error>> {
+        Ok(self.get_items(user_id).await?.into_iter().find(|item| &item.id == item_id))
+    }
+
+    // Empty lots are kept so the food stays on the shopping list, until the
+    // food is restocked
+    pub async fn stock(&self, user_id: &str, item: &PantryItem) -> Result<(), Box<dyn Error>> {
+        for old in self.get_items(user_id).await? {
+            if old.food_id == item.food_id && old.grams_remaining <= 0.0 {
+                self.db.remove(Self::key(user_id, &old).as_bytes())?;
+            }
+        }
+        self.save_item(user_id, item).await
+    }
+
+    // Changes one lot in place with update_and_fetch, so two meals logged at
+    // once can't both take the same stock. change returns how many grams it
+    // moved, which is what this returns too (0 if the lot is gone).
+    fn update_lot(&self, user_id: &str, item: &PantryItem, change: impl Fn(&mut PantryItem) -> f64) -> Result<f64, Box<dyn Error>> {
+        let mut moved = 0.0;
+        self.db.update_and_fetch(Self::key(user_id, item).as_bytes(), |old| {
+            moved = 0.0;
+            let data = old?;
+            match serde_json::from_slice::<PantryItem>(data) {
+                Err(_) => Some(data.to_vec()),
+                Ok(mut current) => {
+                    moved = change(&mut current);
+                    Some(serde_json::to_vec(&current).unwrap_or_else(|_| data.to_vec()))
+                }
+            }
+        })?;
+        Ok(moved)
+    }
+
+    // Takes grams out of the oldest lots first and returns how much actually
+    // came out of the pantry
+    pub async fn consume(&self, user_id: &str, food_id: &str, grams: f64) -> Result<f64, Box<dyn Error>> {
+        let mut left = grams;
+        for item in self.get_items(user_id).await? {
+            if left <= 0.0 {
+                break;
+            }
+            if item.food_id != food_id || item.grams_remaining <= 0.0 {
+                continue;
+            }
+            let taken = self.update_lot(user_id, &item, |lot| {
+                let taken = lot.grams_remaining.max(0.0).min(left);
+                lot.grams_remaining -= taken;
+                taken
+            })?;
+            left -= taken;
+        }
+        self.db.flush()?;
+        Ok(grams - left)
+    }
+
+    // Undoes consume: refills the newest lots first, which are the ones the
+    // most recent meals came out of
+    pub async fn restore(&self, user_id: &str, food_id: &str, grams: f64) -> Result<(), Box<dyn Error>> {
+        let mut left = grams;
+        for item in self.get_items(user_id).await?.into_iter().rev() {
+            if left <= 0.0 {
+                break;
+            }
+            if item.food_id != food_id {
+                continue;
+            }
+            let refilled = self.update_lot(user_id, &item, |lot| {
+                let refilled = (lot.grams_stocked - lot.grams_remaining).max(0.0).min(left);
+                lot.grams_remaining += refilled;
+                refilled
+            })?;
+            left -= refilled;
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn delete_item(&self, user_id: &str, item_id: &Uuid) -> Result<bool, Box<dyn Error>> {
+        match self.get_item(user_id, item_id).await? {
+            None => Ok(false),
+            Some(item) => {
+                self.db.remove(Self::key(user_id, &item).as_bytes())?;
+                self.db.flush()?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use crate::models::cost::PurchaseUnit;
+    use crate::models::food::{Food, FoodCategory};
+
+    fn lot(food_id: &str, grams_stocked: f64, grams_remaining: f64, stocked_at: DateTime<Local>) -> PantryItem {
+        PantryItem {
+            id: Uuid::new_v4(),
+            food_id: food_id.to_owned(),
+            food_name: food_id.to_owned(),
+            grams_stocked,
+            grams_remaining,
+            location: None,
+            stocked_at,
+        }
+    }
+
+    fn eaten(food_id: &str, grams: f64, eaten_at: DateTime<Local>) -> MealEntry {
+        let food = Food {
+            id: food_id.to_owned(),
+            name: food_id.to_owned(),
+            category: FoodCategory::Beef,
+            protein_g: 20.0,
+            fat_g: 10.0,
+            carbs_g: 0.0,
+            calories: 170.0,
+            omega3_mg: 0.0,
+            omega6_mg: 0.0,
+            default_sourcing: Vec::new(),
+            custom: false,
+            provenance: None,
+        };
+        MealEntry::from_food(&food, grams, Vec::new(), eaten_at)
+    }
+
+    fn level<'a>(levels: &'a [StockLevel], food_id: &str) -> &'a StockLevel {
+        levels.iter().find(|level| level.food_id == food_id).unwrap()
+    }
+
+    #[test]
+    fn averages_over_the_days_logged() {
+        let now = Local::now();
+        let items = vec![lot("beef", 1000.0, 1000.0, now)];
+        // three days of logging, not the whole window
+        let entries = vec![eaten("beef", 200.0, now), eaten("beef", 200.0, now - Duration::days(2))];
+        let levels = stock_levels(&items, &entries, &now, LOW_STOCK_DAYS);
+        assert!((level(&levels, "beef").daily_use_g - 400.0 / 3.0).abs() < 1e-9);
+        assert!((level(&levels, "beef").days_of_supply.unwrap() - 7.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn usage_window_is_capped() {
+        let now = Local::now();
+        let items = vec![lot("beef", 1000.0, 1000.0, now)];
+        let entries = vec![eaten("beef", 280.0, now - Duration::days(30))];
+        let levels = stock_levels(&items, &entries, &now, LOW_STOCK_DAYS);
+        assert!((level(&levels, "beef").daily_use_g - 280.0 / USAGE_WINDOW_DAYS as f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flags_foods_running_low() {
+        let now = Local::now();
+        let items = vec![
+            lot("eggs", 600.0, 100.0, now),
+            lot("liver", 500.0, 0.0, now),
+            lot("tallow", 500.0, 500.0, now),
+        ];
+        let entries = vec![eaten("eggs", 100.0, now), eaten("butter", 50.0, now)];
+        let levels = stock_levels(&items, &entries, &now, LOW_STOCK_DAYS);
+        assert!(level(&levels, "eggs").running_low);
+        // empty, even with no recent use
+        assert!(level(&levels, "liver").running_low);
+        let tallow = level(&levels, "tallow");
+        assert!(!tallow.running_low);
+        assert_eq!(tallow.days_of_supply, None);
+        // not kept in the pantry
+        assert!(levels.iter().all(|level| level.food_id != "butter"));
+        // most urgent first
+        assert_eq!(levels[0].food_id, "eggs");
+    }
+
+    #[test]
+    fn shopping_list_covers_the_days_asked() {
+        let now = Local::now();
+        let items = vec![lot("eggs", 600.0, 100.0, now), lot("beef", 2000.0, 2000.0, now)];
+        let entries = vec![eaten("eggs", 100.0, now), eaten("beef", 200.0, now)];
+        let levels = stock_levels(&items, &entries, &now, LOW_STOCK_DAYS);
+        let purchases = vec![Purchase {
+            id: Uuid::new_v4(),
+            food_id: "eggs".to_owned(),
+            price: 6.0,
+            quantity: 12.0,
+            unit: PurchaseUnit::Unit,
+            grams_per_unit: Some(50.0),
+            store: Some("Farm stand".to_owned()),
+            purchased_at: now - Duration::days(3),
+        }];
+        let list = shopping_list(&levels, &purchases, &now, 7.0);
+        // a week of beef is on hand already
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].food_id, "eggs");
+        assert!((list[0].grams_needed - 600.0).abs() < 1e-9);
+        assert!((list[0].estimated_cost.unwrap() - 6.0).abs() < 1e-9);
+        assert_eq!(list[0].store.as_deref(), Some("Farm stand"));
+    }
+
+    #[actix_web::test]
+    async fn takes_from_the_oldest_and_refills_the_newest() {
+        let store = PantryStore {
+            db: Arc::new(sled::Config::new().temporary(true).open().unwrap()),
+        };
+        let now = Local::now();
+        let old = lot("beef", 500.0, 500.0, now - Duration::days(2));
+        let new = lot("beef", 500.0, 500.0, now);
+        store.save_item("user", &old).await.unwrap();
+        store.save_item("user", &new).await.unwrap();
+        let remaining = |items: Vec<PantryItem>, id: Uuid| items.into_iter().find(|item| item.id == id).unwrap().grams_remaining;
+
+        assert_eq!(store.consume("user", "beef", 600.0).await.unwrap(), 600.0);
+        let items = store.get_items("user").await.unwrap();
+        assert_eq!(remaining(items.clone(), old.id), 0.0);
+        assert_eq!(remaining(items, new.id), 400.0);
+        // only what's left comes out
+        assert_eq!(store.consume("user", "beef", 1000.0).await.unwrap(), 400.0);
+
+        store.restore("user", "beef", 300.0).await.unwrap();
+        let items = store.get_items("user").await.unwrap();
+        assert_eq!(remaining(items.clone(), new.id), 300.0);
+        assert_eq!(remaining(items, old.id), 0.0);
+        // the newest lot fills up before the older one gets any back
+        store.restore("user", "beef", 400.0).await.unwrap();
+        let items = store.get_items("user").await.unwrap();
+        assert_eq!(remaining(items.clone(), new.id), 500.0);
+        assert_eq!(remaining(items, old.id), 200.0);
+    }
+}
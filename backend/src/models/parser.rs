@@ -0,0 +1,323 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::models::search::SearchIndex;
+
+const MAX_ALTERNATIVES: usize = 3;
+
+// how much we trust the amount, multiplied into the match score
+const WEIGHED: f64 = 1.0;
+const KNOWN_PORTION: f64 = 0.85;
+const GUESSED_PORTION: f64 = 0.6;
+
+// filler between the amount and the food
+const FILLER_WORDS: [&str; 4] = ["of", "the", "some", "x"];
+
+// words that join two foods, unless they're part of one ("mac and cheese")
+const JOINING_WORDS: [&str; 4] = ["and", "&", "+", "plus"];
+
+const FRACTIONS: [(char, f64); 3] = [('¼', 0.25), ('½', 0.5), ('¾', 0.75)];
+
+// (food id, grams in one piece/slice/can, grams per ml)
+const PORTIONS: [(&str, Option<f64>, Option<f64>); 20] = [
+    ("whole-egg", Some(50.0), None),
+    ("egg-yolk", Some(17.0), None),
+    ("bacon", Some(12.0), None),
+    ("banana", Some(118.0), None),
+    ("orange", Some(131.0), None),
+    ("mango", Some(200.0), None),
+    ("chicken-thigh", Some(115.0), None),
+    ("chicken-breast", Some(170.0), None),
+    ("pork-chop", Some(150.0), None),
+    ("ribeye-steak", Some(280.0), None),
+    ("sardines", Some(92.0), None),
+    ("cheddar", Some(28.0), None),
+    ("butter", Some(113.0), Some(0.91)),
+    ("beef-tallow", None, Some(0.9)),
+    ("raw-honey", None, Some(1.42)),
+    ("heavy-cream", None, Some(1.0)),
+    ("raw-milk", None, Some(1.03)),
+    ("kefir", None, Some(1.03)),
+    ("blueberries", None, Some(0.63)),
+    ("salmon", Some(170.0), None),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Unit {
+    Grams(f64),
+    Millilitres(f64),
+    Pieces,
+}
+
+// (spellings, unit)
+const UNITS: [(&[&str], Unit); 11] = [
+    (&["g", "gram", "grams", "gr"], Unit::Grams(1.0)),
+    (&["kg", "kilo", "kilos", "kilogram", "kilograms"], Unit::Grams(1000.0)),
+    (&["oz", "ounce", "ounces"], Unit::Grams(28.3495)),
+    (&["lb", "lbs", "pound", "pounds"], Unit::Grams(453.592)),
+    (&["ml", "millilitre", "millilitres", "milliliter", "milliliters"], Unit::Millilitres(1.0)),
+    (&["l", "litre", "litres", "liter", "liters"], Unit::Millilitres(1000.0)),
+    (&["tsp", "teaspoon", "teaspoons"], Unit::Millilitres(4.93)),
+    (&["tbsp", "tbs", "tablespoon", "tablespoons"], Unit::Millilitres(14.79)),
+    (&["cup", "cups"], Unit::Millilitres(236.6)),
+    (&["piece", "pieces", "slice", "slices", "can", "cans", "tin", "tins", "stick", "sticks"], Unit::Pieces),
+    (&["whole", "large", "medium"], Unit::Pieces),
+];
+
+const NUMBER_WORDS: [(&str, f64); 17] = [
+    ("a", 1.0), ("an", 1.0), ("one", 1.0), ("two", 2.0), ("three", 3.0), ("four", 4.0),
+    ("five", 5.0), ("six", 6.0), ("seven", 7.0), ("eight", 8.0), ("nine", 9.0), ("ten", 10.0),
+    ("eleven", 11.0), ("twelve", 12.0), ("half", 0.5), ("couple", 2.0), ("dozen", 12.0),
+];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FoodMatch {
+    pub food_id: String,
+    pub food_name: String,
+    pub score: f64,
+}
+
+// One "300g ribeye" out of the sentence, for the user to check before logging
+#[derive(Clone, Debug, Serialize)]
+pub struct ParsedItem {
+    pub text: String,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+    pub food_text: String,
+    pub grams: Option<f64>,
+    pub food: Option<FoodMatch>,
+    pub alternatives: Vec<FoodMatch>,
+    // 0-1, how sure we are of both the food and the amount
+    pub confidence: f64,
+}
+
+fn unit_for(word: &str) -> Option<Unit> {
+    let word = word.trim_end_matches('.');
+    UNITS.iter()
+        .find(|(spellings, _)| spellings.contains(&word))
+        .map(|(_, unit)| *unit)
+}
+
+fn number(word: &str) -> Option<f64> {
+    // "½", and "1½"
+    if let Some((fraction, value)) = FRACTIONS.iter().find(|(fraction, _)| word.ends_with(*fraction)) {
+        let whole = &word[..word.len() - fraction.len_utf8()];
+        return if whole.is_empty() { Some(*value) } else { whole.parse::<f64>().ok().map(|whole| whole + value) };
+    }
+    if let Some((top, bottom)) = word.split_once('/') {
+        let (top, bottom) = (top.parse::<f64>().ok()?, bottom.parse::<f64>().ok()?);
+        return (bottom != 0.0).then_some(top / bottom);
+    }
+    if let Ok(value) = word.parse::<f64>() {
+        return Some(value);
+    }
+    NUMBER_WORDS.iter().find(|(spelling, _)| *spelling == word).map(|(_, value)| *value)
+}
+
+fn is_numeric(c: char) -> bool {
+    c.is_ascii_digit() || c == '.' || FRACTIONS.iter().any(|(fraction, _)| *fraction == c)
+}
+
+// Splits "300g", "1.5lb" and "½lb" into the number and the unit
+fn tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for word in text.split_whitespace() {
+        let starts_numeric = word.starts_with(is_numeric);
+        match word.find(|c: char| c.is_alphabetic()) {
+            Some(split) if starts_numeric && split > 0 => {
+                tokens.push(word[..split].to_owned());
+                tokens.push(word[split..].to_owned());
+            },
+            _ => tokens.push(word.to_owned()),
+        }
+    }
+    tokens
+}
+
+// Whether some text reads as an amount of a food, "3 eggs" but not "cheese"
+fn is_item(text: &str) -> bool {
+    let (quantity, _, food_words) = read_amount(text);
+    quantity.is_some() && !food_words.is_empty()
+}
+
+// The pieces of a sentence that each name one food. Commas always split,
+// joining words only when both sides have their own amount, so "2 eggs and
+// 200g bacon" is two foods and "mac and cheese" one.
+fn segments(text: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    for piece in text.to_lowercase().split([',', ';', '\n']) {
+        let words: Vec<&str> = piece.split_whitespace().collect();
+        let joining = words.iter().filter(|word| JOINING_WORDS.contains(*word));
+        let mut parts = words.split(|word| JOINING_WORDS.contains(word)).map(|part| part.join(" "));
+        let mut current = parts.next().unwrap_or_default();
+        for (word, part) in joining.zip(parts) {
+            if is_item(&current) && is_item(&part) {
+                segments.push(std::mem::replace(&mut current, part));
+            } else {
+                current = [current.as_str(), *word, part.as_str()].iter()
+                    .filter(|text| !text.is_empty())
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+            }
+        }
+        if !current.is_empty() {
+            segments.push(current);
+        }
+    }
+    segments
+}
+
+fn portion(food_id: &str) -> (Option<f64>, Option<f64>) {
+    PORTIONS.iter()
+        .find(|(id, _, _)| *id == food_id)
+        .map(|(_, piece, density)| (*piece, *density))
+        .unwrap_or((None, None))
+}
+
+// Grams for the amount, and how much to trust them
+fn grams_for(food_id: Option<&str>, quantity: Option<f64>, unit: Option<Unit>) -> (Option<f64>, f64) {
+    let (piece_g, g_per_ml) = food_id.map(portion).unwrap_or((None, None));
+    match (quantity, unit) {
+        (Some(quantity), Some(Unit::Grams(per))) => (Some(quantity * per), WEIGHED),
+        // liquids and fats without a known density are taken as water
+        (Some(quantity), Some(Unit::Millilitres(per))) => match g_per_ml {
+            Some(density) => (Some(quantity * per * density), KNOWN_PORTION),
+            None => (Some(quantity * per), GUESSED_PORTION),
+        },
+        (Some(quantity), Some(Unit::Pieces) | None) => match piece_g {
+            Some(piece) => (Some(quantity * piece), KNOWN_PORTION),
+            None => (None, GUESSED_PORTION),
+        },
+        // just a food name: one piece if it comes in them
+        (None, _) => (piece_g, GUESSED_PORTION),
+    }
+}
+
+// The amount, its unit and the words left over for the food
+fn read_amount(segment: &str) -> (Option<f64>, Option<(String, Unit)>, Vec<String>) {
+    let mut quantity: Option<f64> = None;
+    let mut unit: Option<(String, Unit)> = None;
+    let mut food_words: Vec<String> = Vec::new();
+    for token in tokens(segment) {
+        let word = token.trim_matches(|c: char| !c.is_alphanumeric() && !"./¼½¾".contains(c));
+        // "a dozen", "1 1/2": numbers next to each other multiply or add
+        if unit.is_none() && food_words.is_empty() {
+            if let Some(value) = number(word) {
+                quantity = Some(match quantity {
+                    None => value,
+                    Some(previous) if value >= 1.0 => previous * value,
+                    Some(previous) => previous + value,
+                });
+                continue;
+            }
+        }
+        if unit.is_none() && food_words.is_empty() && quantity.is_some() {
+            if let Some(found) = unit_for(word) {
+                unit = Some((word.to_owned(), found));
+                continue;
+            }
+        }
+        if food_words.is_empty() && FILLER_WORDS.contains(&word) {
+            continue;
+        }
+        // "ribeye 300g"
+        if quantity.is_none() {
+            if let Some(value) = number(word).filter(|_| word.starts_with(|c: char| c.is_ascii_digit())) {
+                quantity = Some(value);
+                continue;
+            }
+        }
+        if quantity.is_some() && unit.is_none() && !food_words.is_empty() {
+            if let Some(found) = unit_for(word) {
+                unit = Some((word.to_owned(), found));
+                continue;
+            }
+        }
+        if !word.is_empty() {
+            food_words.push(word.to_owned());
+        }
+    }
+    (quantity, unit, food_words)
+}
+
+fn parse_segment(segment: &str, index: &SearchIndex, uses: &HashMap<String, usize>) -> ParsedItem {
+    let (quantity, unit, food_words) = read_amount(segment);
+    let food_text = food_words.join(" ");
+    let mut ranked = index.search(&food_text, uses, MAX_ALTERNATIVES + 1)
+        .into_iter()
+        .map(|hit| FoodMatch { food_id: hit.food.id, food_name: hit.food.name, score: hit.score });
+    let food = ranked.next();
+    let alternatives: Vec<FoodMatch> = ranked.take(MAX_ALTERNATIVES).collect();
+    let (grams, amount_confidence) = grams_for(food.as_ref().map(|food| food.food_id.as_str()), quantity, unit.as_ref().map(|(_, unit)| *unit));
+    let confidence = match (&food, grams) {
+        (Some(food), Some(_)) => food.score * amount_confidence,
+        _ => 0.0,
+    };
+    ParsedItem {
+        text: segment.to_owned(),
+        quantity,
+        unit: unit.map(|(word, _)| word),
+        food_text,
+        grams,
+        food,
+        alternatives,
+        confidence,
+    }
+}
+
+// "300g ribeye, 3 eggs and a tbsp of honey" into one item per food, foods
+// looked up like the search box does
+pub fn parse_meal(text: &str, index: &SearchIndex, uses: &HashMap<String, usize>) -> Vec<ParsedItem> {
+    segments(text).iter()
+        .map(|segment| parse_segment(segment, index, uses))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_foods_with_their_own_amounts() {
+        assert_eq!(segments("300g ribeye, 3 eggs and a tbsp of honey"), vec!["300g ribeye", "3 eggs", "a tbsp of honey"]);
+        assert_eq!(segments("2 Eggs & 200g bacon + 1 cup milk"), vec!["2 eggs", "200g bacon", "1 cup milk"]);
+        assert_eq!(segments("mac and cheese"), vec!["mac and cheese"]);
+        assert_eq!(segments("300g mac and cheese; salt and pepper"), vec!["300g mac and cheese", "salt and pepper"]);
+    }
+
+    #[test]
+    fn reads_amounts() {
+        assert_eq!(tokens("½lb ground beef"), vec!["½", "lb", "ground", "beef"]);
+        assert_eq!(tokens("1.5kg brisket"), vec!["1.5", "kg", "brisket"]);
+        assert_eq!(number("1½"), Some(1.5));
+        assert_eq!(number("3/4"), Some(0.75));
+        assert_eq!(number("dozen"), Some(12.0));
+        let (quantity, unit, food) = read_amount("½lb of ground beef");
+        assert_eq!(quantity, Some(0.5));
+        assert_eq!(unit.map(|(_, unit)| unit), Some(Unit::Grams(453.592)));
+        assert_eq!(food, vec!["ground", "beef"]);
+        let (quantity, _, food) = read_amount("ribeye 300g");
+        assert_eq!((quantity, food), (Some(300.0), vec!["ribeye".to_owned()]));
+    }
+
+    #[test]
+    fn sizes_portions() {
+        assert_eq!(grams_for(Some("whole-egg"), Some(3.0), None), (Some(150.0), KNOWN_PORTION));
+        assert_eq!(grams_for(Some("raw-milk"), Some(100.0), Some(Unit::Millilitres(1.0))), (Some(103.0), KNOWN_PORTION));
+        assert_eq!(grams_for(Some("brisket"), Some(2.0), Some(Unit::Pieces)), (None, GUESSED_PORTION));
+        assert_eq!(grams_for(Some("brisket"), Some(0.5), Some(Unit::Grams(453.592))), (Some(226.796), WEIGHED));
+    }
+
+    #[test]
+    fn parses_a_meal() {
+        let foods = crate::models::food::Food::catalogue();
+        let index = SearchIndex::new(foods);
+        let items = parse_meal("3 eggs and 200g bacon", &index, &HashMap::new());
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].food.as_ref().map(|food| food.food_id.as_str()), Some("whole-egg"));
+        assert_eq!(items[0].grams, Some(150.0));
+        assert_eq!(items[1].food.as_ref().map(|food| food.food_id.as_str()), Some("bacon"));
+        assert_eq!(items[1].grams, Some(200.0));
+    }
+}
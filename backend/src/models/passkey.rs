@@ -0,0 +1,312 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db};
+use std::error::Error;
+use std::sync::Arc;
+use uuid::Uuid;
+use webauthn_rs::prelude::{DiscoverableAuthentication, Passkey, PasskeyRegistration};
+
+use crate::models::user::{move_key, move_prefix};
+
+// what passkeys are stored as in the identity store
+pub const PASSKEY_PROVIDER: &str = "passkey";
+
+// how long the browser has to finish a ceremony
+const CHALLENGE_MINUTES: i64 = 5;
+// ceremonies one network (see device_session::ip_prefix) can have going at once
+const MAX_NETWORK_CHALLENGES: usize = 20;
+
+// Credential ids as they appear in the identity store and urls
+pub fn credential_id(raw: impl AsRef<[u8]>) -> String {
+    URL_SAFE_NO_PAD.encode(raw.as_ref())
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StoredPasskey {
+    pub id: String,
+    // what the user called it, "phone" or "yubikey"
+    pub name: String,
+    pub passkey: Passkey,
+    // the highest signature counter seen, authenticators that don't count stay at 0
+    pub counter: u32,
+    pub created_at: DateTime<Local>,
+    pub last_used_at: Option<DateTime<Local>>,
+}
+
+// A counter that doesn't go up means two authenticators share the key
+fn counter_advances(seen: u32, counter: u32) -> bool {
+    (counter == 0 && seen == 0) || counter > seen
+}
+
+impl StoredPasskey {
+    pub fn counter_ok(&self, counter: u32) -> bool {
+        counter_advances(self.counter, counter)
+    }
+}
+
+// Server side half of a ceremony between start and finish
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum PasskeyCeremony {
+    Registration {
+        user_id: String,
+        name: String,
+        // signing up rather than adding a passkey to the signed in user
+        new_user: bool,
+        state: PasskeyRegistration,
+    },
+    Authentication {
+        state: DiscoverableAuthentication,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PendingChallenge {
+    pub ceremony: PasskeyCeremony,
+    pub expires_at: DateTime<Local>,
+    // who started it, for the per-network limit
+    #[serde(default)]
+    pub network: String,
+}
+
+// Credentials by user and by credential id, the per-user handle the
+// authenticator stores, and ceremonies in progress
+#[derive(Clone, Debug)]
+pub struct PasskeyStore {
+    pub db: Arc<Db>,
+}
+
+impl PasskeyStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(PasskeyStore {
+            db: Arc::new(db),
+        })
+    }
+
+    // WebAuthn wants a UUID per user, reddit ids aren't, so one is kept here
+    pub async fn handle_for(&self, user_id: &str) -> Result<Uuid, Box<dyn Error>> {
+        let key = format!("handle:{}", user_id);
+        if let Some(data) = self.db.get(key.as_bytes())? {
+            return Ok(Uuid::parse_str(&String::from_utf8(data.to_vec())?)?);
+        }
+        let handle = Uuid::parse_str(user_id).unwrap_or_else(|_| Uuid::new_v4());
+        self.db.insert(key.as_bytes(), handle.to_string().as_bytes())?;
+        self.db.flush()?;
+        Ok(handle)
+    }
+
+    pub async fn user_for(&self, id: &str) -> Result<Option<String>, Box<dyn Error>> {
+        match self.db.get(format!("credential:{}", id).as_bytes())? {
+            None => Ok(None),
+            Some(data) => Ok(Some(String::from_utf8(data.to_vec())?)),
+        }
+    }
+
+    pub async fn get_passkeys(&self, user_id: &str) -> Result<Vec<StoredPasskey>, Box<dyn Error>> {
+        let mut passkeys = Vec::new();
+        for item in self.db.scan_prefix(format!("user:{}:", user_id).as_bytes()) {
+            let (_, data) = item?;
+            passkeys.push(serde_json::from_slice::<StoredPasskey>(&data)?);
+        }
+        Ok(passkeys)
+    }
+
+    pub async fn save_passkey(&self, user_id: &str, passkey: &StoredPasskey) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(passkey)?;
+        self.db.insert(format!("credential:{}", passkey.id).as_bytes(), user_id.as_bytes())?;
+        self.db.insert(format!("user:{}:{}", user_id, passkey.id).as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn delete_passkey(&self, user_id: &str, id: &str) -> Result<bool, Box<dyn Error>> {
+        let removed = self.db.remove(format!("user:{}:{}", user_id, id).as_bytes())?;
+        if removed.is_some() {
+            self.db.remove(format!("credential:{}", id).as_bytes())?;
+        }
+        self.db.flush()?;
+        Ok(removed.is_some())
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users.
+    // The handle stays, it's what the authenticators have.
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let passkeys = self.get_passkeys(from).await?;
+        for passkey in &passkeys {
+            self.db.insert(format!("credential:{}", passkey.id).as_bytes(), to.as_bytes())?;
+        }
+        move_prefix(&self.db, &format!("user:{}:", from), &format!("user:{}:", to))?;
+        move_key(&self.db, &format!("handle:{}", from), &format!("handle:{}", to))?;
+        Ok(passkeys.len())
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        for passkey in self.get_passkeys(user_id).await? {
+            self.delete_passkey(user_id, &passkey.id).await?;
+        }
+        self.db.remove(format!("handle:{}", user_id).as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // Returns the id the session cookie keeps, None if the network already
+    // has too many going. Only the network's own ceremonies are looked at,
+    // the session sweep clears out the rest.
+    pub async fn save_challenge(&self, ceremony: PasskeyCeremony, network: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let now = Local::now();
+        let prefix = format!("network:{}:", network);
+        let mut pending = 0;
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, expires_at) = item?;
+            match DateTime::parse_from_rfc3339(std::str::from_utf8(&expires_at)?) {
+                Ok(expires_at) if expires_at.with_timezone(&Local) > now => pending += 1,
+                _ => {
+                    self.db.remove(key)?;
+                },
+            }
+        }
+        if pending >= MAX_NETWORK_CHALLENGES {
+            return Ok(None);
+        }
+        let id = Uuid::new_v4().simple().to_string();
+        let challenge = PendingChallenge {
+            ceremony,
+            expires_at: now + Duration::minutes(CHALLENGE_MINUTES),
+            network: network.to_owned(),
+        };
+        let mut batch = Batch::default();
+        batch.insert(format!("challenge:{}", id).as_bytes(), serde_json::to_vec(&challenge)?);
+        batch.insert(format!("{}{}", prefix, id).as_bytes(), challenge.expires_at.to_rfc3339().as_bytes());
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(Some(id))
+    }
+
+    // One use, whether or not it had expired
+    pub async fn take_challenge(&self, id: &str) -> Result<Option<PasskeyCeremony>, Box<dyn Error>> {
+        let removed = self.db.remove(format!("challenge:{}", id).as_bytes())?;
+        let result = match removed {
+            None => None,
+            Some(data) => {
+                let pending: PendingChallenge = serde_json::from_slice(&data)?;
+                self.db.remove(format!("network:{}:{}", pending.network, id).as_bytes())?;
+                (pending.expires_at > Local::now()).then_some(pending.ceremony)
+            }
+        };
+        self.db.flush()?;
+        Ok(result)
+    }
+
+    // ceremonies the browser never finished
+    pub async fn delete_expired_challenges(&self) -> Result<usize, Box<dyn Error>> {
+        let now = Local::now();
+        let mut batch = Batch::default();
+        let mut expired = 0;
+        for item in self.db.scan_prefix(b"challenge:") {
+            let (key, data) = item?;
+            let pending: PendingChallenge = serde_json::from_slice(&data)?;
+            if pending.expires_at <= now {
+                let id = String::from_utf8_lossy(&key["challenge:".len()..]).into_owned();
+                batch.remove(format!("network:{}:{}", pending.network, id).as_bytes());
+                batch.remove(key);
+                expired += 1;
+            }
+        }
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+    use webauthn_authenticator_rs::WebauthnAuthenticator;
+    use webauthn_rs::prelude::{Url, Webauthn, WebauthnBuilder};
+
+    fn store() -> PasskeyStore {
+        PasskeyStore {
+            db: Arc::new(sled::Config::new().temporary(true).open().unwrap()),
+        }
+    }
+
+    fn origin() -> Url {
+        Url::parse("https://macros.example.com").unwrap()
+    }
+
+    fn webauthn() -> Webauthn {
+        WebauthnBuilder::new("macros.example.com", &origin()).unwrap().rp_name("AB macros").build().unwrap()
+    }
+
+    // A software authenticator through a whole registration and login, the
+    // ceremony state going through the store in between like it does
+    // between requests
+    #[actix_web::test]
+    async fn registers_and_signs_in_with_a_soft_passkey() {
+        let webauthn = webauthn();
+        let store = store();
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+        let user_id = Uuid::new_v4().to_string();
+        let handle = store.handle_for(&user_id).await.unwrap();
+        assert_eq!(handle.to_string(), user_id);
+
+        let (challenge, state) = webauthn.start_passkey_registration(handle, "phone", "phone", None).unwrap();
+        let ceremony = PasskeyCeremony::Registration { user_id: user_id.clone(), name: "phone".to_owned(), new_user: true, state };
+        let id = store.save_challenge(ceremony, "203.0.113.0/24").await.unwrap().unwrap();
+        let credential = authenticator.do_registration(origin(), challenge).unwrap();
+        let Some(PasskeyCeremony::Registration { state, .. }) = store.take_challenge(&id).await.unwrap() else {
+            panic!("not a registration");
+        };
+        // one use
+        assert!(store.take_challenge(&id).await.unwrap().is_none());
+        let passkey = webauthn.finish_passkey_registration(&credential, &state).unwrap();
+        let stored = StoredPasskey {
+            id: credential_id(passkey.cred_id()),
+            name: "phone".to_owned(),
+            passkey,
+            counter: 0,
+            created_at: Local::now(),
+            last_used_at: None,
+        };
+        store.save_passkey(&user_id, &stored).await.unwrap();
+        assert_eq!(store.user_for(&stored.id).await.unwrap(), Some(user_id.clone()));
+
+        let passkeys = store.get_passkeys(&user_id).await.unwrap();
+        let keys: Vec<Passkey> = passkeys.iter().map(|stored| stored.passkey.clone()).collect();
+        let (challenge, state) = webauthn.start_passkey_authentication(&keys).unwrap();
+        let assertion = authenticator.do_authentication(origin(), challenge).unwrap();
+        let result = webauthn.finish_passkey_authentication(&assertion, &state).unwrap();
+        assert_eq!(credential_id(result.cred_id()), stored.id);
+        assert!(passkeys[0].counter_ok(result.counter()));
+    }
+
+    #[actix_web::test]
+    async fn limits_ceremonies_per_network() {
+        let webauthn = webauthn();
+        let store = store();
+        for _ in 0..MAX_NETWORK_CHALLENGES {
+            let (_, state) = webauthn.start_discoverable_authentication().unwrap();
+            assert!(store.save_challenge(PasskeyCeremony::Authentication { state }, "198.51.100.0/24").await.unwrap().is_some());
+        }
+        let (_, state) = webauthn.start_discoverable_authentication().unwrap();
+        assert!(store.save_challenge(PasskeyCeremony::Authentication { state }, "198.51.100.0/24").await.unwrap().is_none());
+        let (_, state) = webauthn.start_discoverable_authentication().unwrap();
+        assert!(store.save_challenge(PasskeyCeremony::Authentication { state }, "203.0.113.0/24").await.unwrap().is_some());
+    }
+
+    #[test]
+    fn counter_has_to_go_up() {
+        // authenticators that don't count
+        assert!(counter_advances(0, 0));
+        assert!(counter_advances(0, 1));
+        assert!(counter_advances(5, 6));
+        assert!(!counter_advances(5, 5));
+        assert!(!counter_advances(5, 3));
+        // counting once means always counting
+        assert!(!counter_advances(5, 0));
+    }
+}
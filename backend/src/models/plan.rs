@@ -0,0 +1,542 @@
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db, Tree};
+use std::sync::Arc;
+use std::error::Error;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use uuid::Uuid;
+
+use crate::models::user::{move_key, move_prefix};
+use crate::models::food::Food;
+use crate::models::meal::MacroTotals;
+use crate::models::pantry::PantryItem;
+use crate::models::secret::token_hash;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum MealSlot {
+    Breakfast,
+    Lunch,
+    Snack,
+    Dinner,
+}
+
+impl MealSlot {
+    // when the slot shows up in the calendar
+    pub fn time(&self) -> NaiveTime {
+        let (hour, minute) = match self {
+            MealSlot::Breakfast => (8, 0),
+            MealSlot::Lunch => (12, 30),
+            MealSlot::Snack => (15, 30),
+            MealSlot::Dinner => (18, 30),
+        };
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap_or_default()
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            MealSlot::Breakfast => "Breakfast",
+            MealSlot::Lunch => "Lunch",
+            MealSlot::Snack => "Snack",
+            MealSlot::Dinner => "Dinner",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlannedMeal {
+    pub id: Uuid,
+    pub date: NaiveDate,
+    pub slot: MealSlot,
+    pub food_id: String,
+    pub food_name: String,
+    pub grams: f64,
+    pub macros: MacroTotals,
+}
+
+impl PlannedMeal {
+    pub fn from_food(food: &Food, grams: f64, date: NaiveDate, slot: MealSlot) -> Self {
+        let scale = grams / 100.0;
+        PlannedMeal {
+            id: Uuid::new_v4(),
+            date,
+            slot,
+            food_id: food.id.clone(),
+            food_name: food.name.clone(),
+            grams,
+            macros: MacroTotals {
+                protein_g: food.protein_g * scale,
+                fat_g: food.fat_g * scale,
+                carbs_g: food.carbs_g * scale,
+                calories: food.calories * scale,
+            },
+        }
+    }
+}
+
+// Thaw the beef, start the broth...
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PrepTask {
+    pub id: Uuid,
+    pub date: NaiveDate,
+    pub time: Option<NaiveTime>,
+    pub title: String,
+    // set on tasks added for a planned meal, they go when it does
+    #[serde(default)]
+    pub planned_meal_id: Option<Uuid>,
+}
+
+// Frozen food needs to come out the evening before
+pub fn thaw_task(meal: &PlannedMeal, pantry: &[PantryItem]) -> Option<PrepTask> {
+    // meals come out of the oldest lot, so that's the one that has to thaw
+    let lot = pantry.iter().find(|item| item.food_id == meal.food_id && item.grams_remaining > 0.0)?;
+    let frozen = lot.location.as_ref().is_some_and(|location| location.to_lowercase().contains("freez"));
+    if !frozen {
+        return None;
+    }
+    Some(PrepTask {
+        id: Uuid::new_v4(),
+        date: meal.date - Duration::days(1),
+        time: NaiveTime::from_hms_opt(20, 0, 0),
+        title: format!("Thaw {}", meal.food_name),
+        planned_meal_id: Some(meal.id),
+    })
+}
+
+pub fn planned_totals(meals: &[PlannedMeal], date: NaiveDate) -> Option<MacroTotals> {
+    let mut totals: Option<MacroTotals> = None;
+    for meal in meals.iter().filter(|meal| meal.date == date) {
+        *totals.get_or_insert_with(MacroTotals::default) += meal.macros;
+    }
+    totals
+}
+
+// RFC 5545 text escaping
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Lines longer than 75 octets continue on the next line after a space
+fn fold_line(line: &str, out: &mut String) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn format_local(at: &NaiveDateTime) -> String {
+    at.format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn push_event(out: &mut String, uid: String, stamp: &str, start: NaiveDateTime, minutes: i64, summary: &str, description: Option<String>) {
+    fold_line("BEGIN:VEVENT", out);
+    fold_line(&format!("UID:{}@ab-macros", uid), out);
+    fold_line(&format!("DTSTAMP:{}", stamp), out);
+    // floating times, the calendar shows them in whatever zone it's in
+    fold_line(&format!("DTSTART:{}", format_local(&start)), out);
+    fold_line(&format!("DTEND:{}", format_local(&(start + Duration::minutes(minutes)))), out);
+    fold_line(&format!("SUMMARY:{}", escape_text(summary)), out);
+    if let Some(description) = description {
+        fold_line(&format!("DESCRIPTION:{}", escape_text(&description)), out);
+    }
+    fold_line("END:VEVENT", out);
+}
+
+// One event per slot listing its foods, one per prep task
+pub fn to_ical(meals: &[PlannedMeal], tasks: &[PrepTask], now: &DateTime<Local>) -> String {
+    let stamp = now.naive_utc().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut out = String::new();
+    fold_line("BEGIN:VCALENDAR", &mut out);
+    fold_line("VERSION:2.0", &mut out);
+    fold_line("PRODID:-//ab-macros//meal plan//EN", &mut out);
+    fold_line("X-WR-CALNAME:Meal Plan", &mut out);
+
+    let mut slots: Vec<(NaiveDate, MealSlot)> = meals.iter().map(|meal| (meal.date, meal.slot)).collect();
+    slots.sort();
+    slots.dedup();
+    for (date, slot) in slots {
+        let mut totals = MacroTotals::default();
+        let mut lines = Vec::new();
+        for meal in meals.iter().filter(|meal| meal.date == date && meal.slot == slot) {
+            totals += meal.macros;
+            lines.push(format!("{:.0}g {}", meal.grams, meal.food_name));
+        }
+        lines.push(format!("{:.0}P {:.0}F {:.0}C {:.0} kcal", totals.protein_g, totals.fat_g, totals.carbs_g, totals.calories));
+        push_event(
+            &mut out,
+            format!("meal-{}-{}", date, slot.label().to_lowercase()),
+            &stamp,
+            date.and_time(slot.time()),
+            30,
+            slot.label(),
+            Some(lines.join("\n")),
+        );
+    }
+    for task in tasks {
+        let start = date_time(task.date, task.time);
+        push_event(&mut out, format!("prep-{}", task.id), &stamp, start, 15, &task.title, None);
+    }
+    fold_line("END:VCALENDAR", &mut out);
+    out
+}
+
+// untimed tasks go first thing in the morning
+fn date_time(date: NaiveDate, time: Option<NaiveTime>) -> NaiveDateTime {
+    date.and_time(time.or_else(|| NaiveTime::from_hms_opt(7, 0, 0)).unwrap_or_default())
+}
+
+// Planned meals in the default tree, prep tasks in their own, and the
+// calendar feed token hashes both ways round so the feed can find its user
+#[derive(Clone, Debug)]
+pub struct PlanStore {
+    pub db: Arc<Db>,
+    pub prep: Tree,
+    pub feeds: Tree,
+}
+
+impl PlanStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        let prep = db.open_tree("prep")?;
+        let feeds = db.open_tree("feeds")?;
+        Ok(PlanStore {
+            db: Arc::new(db),
+            prep,
+            feeds,
+        })
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let (from_prefix, to_prefix) = (format!("{}:", from), format!("{}:", to));
+        let mut moved = move_prefix(&self.db, &from_prefix, &to_prefix)? + move_prefix(&self.prep, &from_prefix, &to_prefix)?;
+        if let Some(hash) = self.feed_token_hash(from)? {
+            self.feeds.insert(Self::token_key(&hash).as_bytes(), to.as_bytes())?;
+            move_key(&self.feeds, &Self::user_key(from), &Self::user_key(to))?;
+            moved += 1;
+        }
+        Ok(moved)
+    }
+
+    // account deletion, feed token included
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        let prefix = format!("{}:", user_id);
+        for tree in [&**self.db, &self.prep] {
+            for item in tree.scan_prefix(prefix.as_bytes()) {
+                let (key, _) = item?;
+                tree.remove(key)?;
+            }
+        }
+        if let Some(hash) = self.feed_token_hash(user_id)? {
+            self.feeds.remove(Self::token_key(&hash).as_bytes())?;
+        }
+        self.feeds.remove(Self::user_key(user_id).as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn date_key(user_id: &str, date: NaiveDate) -> String {
+        format!("{}:{}", user_id, date.format("%Y-%m-%d"))
+    }
+
+    fn meal_key(user_id: &str, meal: &PlannedMeal) -> String {
+        format!("{}:{}:{}", Self::date_key(user_id, meal.date), meal.slot as u8, meal.id)
+    }
+
+    fn task_key(user_id: &str, task: &PrepTask) -> String {
+        format!("{}:{}", Self::date_key(user_id, task.date), task.id)
+    }
+
+    pub async fn save_meal(&self, user_id: &str, meal: &PlannedMeal) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(meal)?;
+        self.db.insert(Self::meal_key(user_id, meal).as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // see MealStore::replace_entries. Unlike delete_meal this leaves prep
+    // tasks alone, the caller replaces those with replace_tasks.
+    pub async fn replace_meals(&self, user_id: &str, removed: &[PlannedMeal], saved: &[PlannedMeal]) -> Result<(), Box<dyn Error>> {
+        let mut batch = Batch::default();
+        for meal in removed {
+            batch.remove(Self::meal_key(user_id, meal).as_bytes());
+        }
+        for meal in saved {
+            batch.insert(Self::meal_key(user_id, meal).as_bytes(), serde_json::to_vec(meal)?);
+        }
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // planned for days in [from, to)
+    pub async fn get_meals_between(&self, user_id: &str, from: NaiveDate, to: NaiveDate) -> Result<Vec<PlannedMeal>, Box<dyn Error>> {
+        let start = Self::date_key(user_id, from);
+        let end = Self::date_key(user_id, to);
+        let mut meals = Vec::new();
+        for item in self.db.range(start.as_bytes()..end.as_bytes()) {
+            let (_, data) = item?;
+            meals.push(serde_json::from_slice::<PlannedMeal>(&data)?);
+        }
+        Ok(meals)
+    }
+
+    pub async fn get_meals(&self, user_id: &str) -> Result<Vec<PlannedMeal>, Box<dyn Error>> {
+        let mut meals = Vec::new();
+        for item in self.db.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (_, data) = item?;
+            meals.push(serde_json::from_slice::<PlannedMeal>(&data)?);
+        }
+        Ok(meals)
+    }
+
+    // takes the meal's prep tasks with it
+    pub async fn delete_meal(&self, user_id: &str, meal_id: &Uuid) -> Result<bool, Box<dyn Error>> {
+        let Some(meal) = self.get_meals(user_id).await?.into_iter().find(|meal| &meal.id == meal_id) else {
+            return Ok(false);
+        };
+        self.db.remove(Self::meal_key(user_id, &meal).as_bytes())?;
+        for task in self.get_tasks(user_id).await? {
+            if task.planned_meal_id == Some(meal.id) {
+                self.prep.remove(Self::task_key(user_id, &task).as_bytes())?;
+            }
+        }
+        self.db.flush()?;
+        Ok(true)
+    }
+
+    pub async fn save_task(&self, user_id: &str, task: &PrepTask) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(task)?;
+        self.prep.insert(Self::task_key(user_id, task).as_bytes(), serialized)?;
+        self.prep.flush()?;
+        Ok(())
+    }
+
+    // see MealStore::replace_entries
+    pub async fn replace_tasks(&self, user_id: &str, removed: &[PrepTask], saved: &[PrepTask]) -> Result<(), Box<dyn Error>> {
+        let mut batch = Batch::default();
+        for task in removed {
+            batch.remove(Self::task_key(user_id, task).as_bytes());
+        }
+        for task in saved {
+            batch.insert(Self::task_key(user_id, task).as_bytes(), serde_json::to_vec(task)?);
+        }
+        self.prep.apply_batch(batch)?;
+        self.prep.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_tasks_between(&self, user_id: &str, from: NaiveDate, to: NaiveDate) -> Result<Vec<PrepTask>, Box<dyn Error>> {
+        let start = Self::date_key(user_id, from);
+        let end = Self::date_key(user_id, to);
+        let mut tasks = Vec::new();
+        for item in self.prep.range(start.as_bytes()..end.as_bytes()) {
+            let (_, data) = item?;
+            tasks.push(serde_json::from_slice::<PrepTask>(&data)?);
+        }
+        Ok(tasks)
+    }
+
+    pub async fn get_tasks(&self, user_id: &str) -> Result<Vec<PrepTask>, Box<dyn Error>> {
+        let mut tasks = Vec::new();
+        for item in self.prep.scan_prefix(format!("{}:", user_id).as_bytes()) {
+            let (_, data) = item?;
+            tasks.push(serde_json::from_slice::<PrepTask>(&data)?);
+        }
+        Ok(tasks)
+    }
+
+    pub async fn delete_task(&self, user_id: &str, task_id: &Uuid) -> Result<bool, Box<dyn Error>> {
+        for task in self.get_tasks(user_id).await? {
+            if &task.id == task_id {
+                self.prep.remove(Self::task_key(user_id, &task).as_bytes())?;
+                self.prep.flush()?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn token_key(hash: &str) -> String {
+        format!("token:{}", hash)
+    }
+
+    fn user_key(user_id: &str) -> String {
+        format!("user:{}", user_id)
+    }
+
+    // only the hash is kept, the token itself is shown once when it's made
+    fn feed_token_hash(&self, user_id: &str) -> Result<Option<String>, Box<dyn Error>> {
+        match self.feeds.get(Self::user_key(user_id).as_bytes())? {
+            None => Ok(None),
+            Some(data) => Ok(Some(String::from_utf8(data.to_vec())?)),
+        }
+    }
+
+    pub async fn has_feed_token(&self, user_id: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self.feeds.contains_key(Self::user_key(user_id).as_bytes())?)
+    }
+
+    // Replaces any old token, so a leaked feed URL can be shut off
+    pub async fn rotate_feed_token(&self, user_id: &str) -> Result<String, Box<dyn Error>> {
+        let token = Uuid::new_v4().simple().to_string();
+        let hash = token_hash(&token);
+        let mut batch = Batch::default();
+        if let Some(old) = self.feed_token_hash(user_id)? {
+            batch.remove(Self::token_key(&old).as_bytes());
+        }
+        batch.insert(Self::token_key(&hash).as_bytes(), user_id.as_bytes());
+        batch.insert(Self::user_key(user_id).as_bytes(), hash.as_bytes());
+        self.feeds.apply_batch(batch)?;
+        self.feeds.flush()?;
+        Ok(token)
+    }
+
+    pub async fn user_for_feed_token(&self, token: &str) -> Result<Option<String>, Box<dyn Error>> {
+        match self.feeds.get(Self::token_key(&token_hash(token)).as_bytes())? {
+            None => Ok(None),
+            Some(data) => Ok(Some(String::from_utf8(data.to_vec())?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::food::FoodCategory;
+
+    fn food(name: &str) -> Food {
+        Food {
+            id: name.to_lowercase(),
+            name: name.to_owned(),
+            category: FoodCategory::Beef,
+            protein_g: 20.0,
+            fat_g: 10.0,
+            carbs_g: 0.0,
+            calories: 170.0,
+            omega3_mg: 0.0,
+            omega6_mg: 0.0,
+            default_sourcing: Vec::new(),
+            custom: false,
+            provenance: None,
+        }
+    }
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    fn task(date: NaiveDate, time: Option<NaiveTime>, title: &str) -> PrepTask {
+        PrepTask { id: Uuid::new_v4(), date, time, title: title.to_owned(), planned_meal_id: None }
+    }
+
+    fn temporary_store() -> PlanStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        PlanStore {
+            prep: db.open_tree("prep").unwrap(),
+            feeds: db.open_tree("feeds").unwrap(),
+            db: Arc::new(db),
+        }
+    }
+
+    // folded lines joined back up
+    fn unfold(calendar: &str) -> String {
+        calendar.replace("\r\n ", "")
+    }
+
+    #[test]
+    fn escapes_calendar_text() {
+        let tasks = vec![task(day(4), None, "Thaw beef, ground; 80\\20")];
+        let calendar = unfold(&to_ical(&[], &tasks, &Local::now()));
+        assert!(calendar.contains("SUMMARY:Thaw beef\\, ground\\; 80\\\\20\r\n"));
+        // a slot's foods go one per line in the description
+        let meals = vec![
+            PlannedMeal::from_food(&food("Ribeye"), 300.0, day(4), MealSlot::Dinner),
+            PlannedMeal::from_food(&food("Eggs"), 100.0, day(4), MealSlot::Dinner),
+        ];
+        let calendar = unfold(&to_ical(&meals, &[], &Local::now()));
+        assert!(calendar.contains("DESCRIPTION:300g Ribeye\\n100g Eggs\\n80P 40F 0C 680 kcal\r\n"));
+    }
+
+    #[test]
+    fn folds_long_lines() {
+        let title = format!("Render {} tallow", "grass-fed bœuf ".repeat(10));
+        let calendar = to_ical(&[], &[task(day(4), None, &title)], &Local::now());
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        for line in calendar.split("\r\n") {
+            assert!(line.len() <= 75, "{}", line);
+        }
+        // multi-byte characters aren't split across lines
+        assert!(unfold(&calendar).contains(&format!("SUMMARY:{}\r\n", title)));
+    }
+
+    #[test]
+    fn places_timed_and_untimed_events() {
+        let meals = vec![
+            PlannedMeal::from_food(&food("Ribeye"), 300.0, day(4), MealSlot::Dinner),
+            PlannedMeal::from_food(&food("Eggs"), 100.0, day(4), MealSlot::Breakfast),
+        ];
+        let tasks = vec![
+            task(day(3), NaiveTime::from_hms_opt(20, 0, 0), "Thaw ribeye"),
+            task(day(4), None, "Start broth"),
+        ];
+        let calendar = to_ical(&meals, &tasks, &Local::now());
+        assert_eq!(calendar.matches("BEGIN:VEVENT").count(), 4);
+        // meals take 30 minutes from their slot's time
+        assert!(calendar.contains("DTSTART:20240304T183000\r\nDTEND:20240304T190000\r\n"));
+        assert!(calendar.contains("DTSTART:20240304T080000\r\nDTEND:20240304T083000\r\n"));
+        assert!(calendar.contains("DTSTART:20240303T200000\r\nDTEND:20240303T201500\r\n"));
+        // untimed tasks go first thing in the morning
+        assert!(calendar.contains("DTSTART:20240304T070000\r\nDTEND:20240304T071500\r\n"));
+        // breakfast sorts before dinner
+        assert!(calendar.find("UID:meal-2024-03-04-breakfast").unwrap() < calendar.find("UID:meal-2024-03-04-dinner").unwrap());
+    }
+
+    #[actix_web::test]
+    async fn reads_back_a_range_of_days() {
+        let store = temporary_store();
+        for date in [day(3), day(4), day(10)] {
+            store.save_meal("user", &PlannedMeal::from_food(&food("Ribeye"), 300.0, date, MealSlot::Dinner)).await.unwrap();
+            store.save_task("user", &task(date, None, "Start broth")).await.unwrap();
+        }
+        store.save_meal("other", &PlannedMeal::from_food(&food("Eggs"), 100.0, day(4), MealSlot::Breakfast)).await.unwrap();
+        // [from, to) and only the user's own
+        assert_eq!(store.get_meals_between("user", day(3), day(10)).await.unwrap().len(), 2);
+        assert_eq!(store.get_tasks_between("user", day(4), day(11)).await.unwrap().len(), 2);
+        assert_eq!(store.get_meals("user").await.unwrap().len(), 3);
+
+        let meal = store.get_meals("user").await.unwrap().remove(0);
+        assert!(store.delete_meal("user", &meal.id).await.unwrap());
+        assert!(!store.delete_meal("user", &meal.id).await.unwrap());
+        assert_eq!(store.get_meals("user").await.unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn feed_tokens_find_their_user() {
+        let store = temporary_store();
+        assert!(!store.has_feed_token("user").await.unwrap());
+        let old = store.rotate_feed_token("user").await.unwrap();
+        assert_eq!(store.user_for_feed_token(&old).await.unwrap().as_deref(), Some("user"));
+        // only the hash is stored
+        assert!(store.feeds.iter().values().all(|value| value.unwrap() != old.as_bytes()));
+
+        let token = store.rotate_feed_token("user").await.unwrap();
+        assert_eq!(store.user_for_feed_token(&old).await.unwrap(), None);
+        assert_eq!(store.user_for_feed_token(&token).await.unwrap().as_deref(), Some("user"));
+
+        store.move_user("user", "moved").await.unwrap();
+        assert_eq!(store.user_for_feed_token(&token).await.unwrap().as_deref(), Some("moved"));
+        assert!(store.has_feed_token("moved").await.unwrap());
+        store.delete_user("moved").await.unwrap();
+        assert_eq!(store.user_for_feed_token(&token).await.unwrap(), None);
+        assert!(!store.has_feed_token("moved").await.unwrap());
+    }
+}
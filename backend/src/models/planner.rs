@@ -0,0 +1,254 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::food::Food;
+use crate::models::meal::MacroTotals;
+use crate::models::targets::EffectiveTargets;
+
+// How close each macro has to land, as a share of its target
+pub const DEFAULT_TOLERANCE: f64 = 0.1;
+// Targets of zero (carbs on carnivore) are scaled against these instead so
+// going over still costs something
+const MIN_GRAMS_SCALE: f64 = 10.0;
+const MIN_CALORIES_SCALE: f64 = 100.0;
+const MAX_ITERATIONS: usize = 500;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FoodBounds {
+    pub food_id: String,
+    #[serde(default)]
+    pub min_g: f64,
+    // e.g. liver at 50g a day
+    pub max_g: Option<f64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlannedFood {
+    pub food_id: String,
+    pub food_name: String,
+    pub grams: f64,
+    pub macros: MacroTotals,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct MacroFit {
+    pub protein: bool,
+    pub fat: bool,
+    pub carbs: bool,
+    pub calories: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MealPlan {
+    pub foods: Vec<PlannedFood>,
+    pub totals: MacroTotals,
+    pub targets: MacroTotals,
+    pub fit: MacroFit,
+    // every macro within tolerance
+    pub feasible: bool,
+}
+
+fn per_gram(food: &Food) -> [f64; 4] {
+    [food.protein_g / 100.0, food.fat_g / 100.0, food.carbs_g / 100.0, food.calories / 100.0]
+}
+
+fn within(total: f64, target: f64, tolerance: f64, scale: f64) -> bool {
+    (total - target).abs() <= (target * tolerance).max(scale * tolerance)
+}
+
+// Bounded least squares over grams of each food: minimises the squared
+// relative miss on protein, fat, carbs and calories with every food kept
+// inside its min/max. The objective is a convex quadratic, so cyclic
+// coordinate descent with clamping converges to the optimum.
+pub fn solve(foods: &[(Food, FoodBounds)], targets: &EffectiveTargets, tolerance: f64) -> MealPlan {
+    let target = [targets.protein_g, targets.fat_g, targets.carbs_g, targets.calories];
+    let scale = [
+        target[0].max(MIN_GRAMS_SCALE),
+        target[1].max(MIN_GRAMS_SCALE),
+        target[2].max(MIN_GRAMS_SCALE),
+        target[3].max(MIN_CALORIES_SCALE),
+    ];
+    let nutrients: Vec<[f64; 4]> = foods.iter().map(|(food, _)| per_gram(food)).collect();
+    let bounds: Vec<(f64, f64)> = foods.iter()
+        .map(|(_, bounds)| {
+            let min = bounds.min_g.max(0.0);
+            (min, bounds.max_g.unwrap_or(f64::INFINITY).max(min))
+        })
+        .collect();
+
+    let mut grams: Vec<f64> = bounds.iter().map(|(min, _)| *min).collect();
+    let mut totals = [0.0; 4];
+    for (amount, per) in grams.iter().zip(&nutrients) {
+        for (total, nutrient) in totals.iter_mut().zip(per) {
+            *total += amount * nutrient;
+        }
+    }
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut moved = 0.0_f64;
+        for (j, per) in nutrients.iter().enumerate() {
+            let gradient: f64 = (0..4).map(|k| per[k] * (totals[k] - target[k]) / scale[k].powi(2)).sum();
+            let curvature: f64 = (0..4).map(|k| per[k].powi(2) / scale[k].powi(2)).sum();
+            if curvature <= 0.0 {
+                continue;
+            }
+            let (min, max) = bounds[j];
+            let next = (grams[j] - gradient / curvature).clamp(min, max);
+            let step = next - grams[j];
+            if step != 0.0 {
+                for (total, nutrient) in totals.iter_mut().zip(per) {
+                    *total += step * nutrient;
+                }
+                grams[j] = next;
+                moved = moved.max(step.abs());
+            }
+        }
+        if moved < 0.01 {
+            break;
+        }
+    }
+
+    let mut plan_totals = MacroTotals::default();
+    let planned: Vec<PlannedFood> = foods.iter()
+        .zip(&grams)
+        .map(|((food, _), amount)| {
+            let amount = amount.round();
+            let per = per_gram(food);
+            let macros = MacroTotals {
+                protein_g: per[0] * amount,
+                fat_g: per[1] * amount,
+                carbs_g: per[2] * amount,
+                calories: per[3] * amount,
+            };
+            plan_totals += macros;
+            PlannedFood {
+                food_id: food.id.clone(),
+                food_name: food.name.clone(),
+                grams: amount,
+                macros,
+            }
+        })
+        .collect();
+
+    let fit = MacroFit {
+        protein: within(plan_totals.protein_g, target[0], tolerance, MIN_GRAMS_SCALE),
+        fat: within(plan_totals.fat_g, target[1], tolerance, MIN_GRAMS_SCALE),
+        carbs: within(plan_totals.carbs_g, target[2], tolerance, MIN_GRAMS_SCALE),
+        calories: within(plan_totals.calories, target[3], tolerance, MIN_CALORIES_SCALE),
+    };
+    MealPlan {
+        foods: planned,
+        totals: plan_totals,
+        targets: MacroTotals {
+            protein_g: target[0],
+            fat_g: target[1],
+            carbs_g: target[2],
+            calories: target[3],
+        },
+        feasible: fit.protein && fit.fat && fit.carbs && fit.calories,
+        fit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::food::FoodCategory;
+
+    fn food(id: &str, protein_g: f64, fat_g: f64, carbs_g: f64) -> Food {
+        Food {
+            id: id.to_owned(),
+            name: id.to_owned(),
+            category: FoodCategory::Beef,
+            protein_g,
+            fat_g,
+            carbs_g,
+            calories: protein_g * 4.0 + fat_g * 9.0 + carbs_g * 4.0,
+            omega3_mg: 0.0,
+            omega6_mg: 0.0,
+            default_sourcing: Vec::new(),
+            custom: false,
+            provenance: None,
+        }
+    }
+
+    fn bounded(food: Food, min_g: f64, max_g: Option<f64>) -> (Food, FoodBounds) {
+        let food_id = food.id.clone();
+        (food, FoodBounds { food_id, min_g, max_g })
+    }
+
+    fn targets(protein_g: f64, fat_g: f64, carbs_g: f64) -> EffectiveTargets {
+        EffectiveTargets {
+            protein_g,
+            fat_g,
+            carbs_g,
+            calories: protein_g * 4.0 + fat_g * 9.0 + carbs_g * 4.0,
+            weight_kg: None,
+            lean_mass_kg: None,
+            protein_relative: None,
+            fat_relative: None,
+        }
+    }
+
+    fn grams(plan: &MealPlan, food_id: &str) -> f64 {
+        plan.foods.iter().find(|food| food.food_id == food_id).unwrap().grams
+    }
+
+    #[test]
+    fn hits_reachable_targets() {
+        let foods = vec![
+            bounded(food("chicken", 30.0, 2.0, 0.0), 0.0, None),
+            bounded(food("butter", 1.0, 81.0, 0.0), 0.0, None),
+        ];
+        // 500g of chicken and 100g of butter
+        let plan = solve(&foods, &targets(151.0, 91.0, 0.0), DEFAULT_TOLERANCE);
+        assert!(plan.feasible);
+        assert!((plan.totals.protein_g - 151.0).abs() < 151.0 * DEFAULT_TOLERANCE);
+        assert!((plan.totals.fat_g - 91.0).abs() < 91.0 * DEFAULT_TOLERANCE);
+        assert!((grams(&plan, "chicken") - 500.0).abs() < 25.0);
+        assert!((grams(&plan, "butter") - 100.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn leaves_carbs_out_for_a_zero_target() {
+        let foods = vec![
+            bounded(food("beef", 26.0, 15.0, 0.0), 0.0, None),
+            bounded(food("butter", 1.0, 81.0, 0.0), 0.0, None),
+            bounded(food("honey", 0.0, 0.0, 82.0), 0.0, None),
+        ];
+        let plan = solve(&foods, &targets(150.0, 120.0, 0.0), DEFAULT_TOLERANCE);
+        assert_eq!(grams(&plan, "honey"), 0.0);
+        assert_eq!(plan.totals.carbs_g, 0.0);
+        assert!(plan.fit.carbs);
+        assert!(plan.feasible);
+    }
+
+    #[test]
+    fn keeps_every_food_within_its_bounds() {
+        let foods = vec![
+            bounded(food("beef", 26.0, 15.0, 0.0), 300.0, None),
+            bounded(food("liver", 20.0, 4.0, 4.0), 0.0, Some(50.0)),
+            bounded(food("chicken", 30.0, 2.0, 0.0), 0.0, Some(100.0)),
+        ];
+        // wants far more protein than the capped foods can give
+        let plan = solve(&foods, &targets(400.0, 40.0, 0.0), DEFAULT_TOLERANCE);
+        assert!(grams(&plan, "beef") >= 300.0);
+        assert!(grams(&plan, "liver") <= 50.0);
+        assert!(grams(&plan, "chicken") <= 100.0);
+        assert!(plan.foods.iter().all(|food| food.grams >= 0.0));
+    }
+
+    #[test]
+    fn reports_a_shortfall_it_cannot_close() {
+        // nothing but butter can't reach the protein
+        let foods = vec![bounded(food("butter", 1.0, 81.0, 0.0), 0.0, None)];
+        let plan = solve(&foods, &targets(150.0, 100.0, 0.0), DEFAULT_TOLERANCE);
+        assert!(!plan.fit.protein);
+        assert!(!plan.feasible);
+        assert!(plan.totals.protein_g < 150.0);
+        // a cap below the minimum leaves the food at its minimum
+        let foods = vec![bounded(food("chicken", 30.0, 2.0, 0.0), 200.0, Some(100.0))];
+        let plan = solve(&foods, &targets(30.0, 2.0, 0.0), DEFAULT_TOLERANCE);
+        assert_eq!(grams(&plan, "chicken"), 200.0);
+        assert!(!plan.feasible);
+    }
+}
@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::error::Error;
+
+use crate::models::user::move_key;
+use crate::models::food::FoodCategory;
+use crate::models::meal::MealEntry;
+use crate::models::sourcing;
+use crate::models::targets::Targets;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DietProfile {
+    StrictCarnivore,
+    #[default]
+    AnimalBased,
+    Ketovore,
+    Lion,
+    Custom,
+}
+
+// Share of calories from each macro
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct MacroRatios {
+    pub protein: f64,
+    pub fat: f64,
+    pub carbs: f64,
+}
+
+impl MacroRatios {
+    pub fn apply(&self, targets: &mut Targets) {
+        targets.protein_g = (targets.calories * self.protein / 4.0).round();
+        targets.fat_g = (targets.calories * self.fat / 9.0).round();
+        targets.carbs_g = (targets.calories * self.carbs / 4.0).round();
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UserProfile {
+    pub diet: DietProfile,
+    // only used by the custom profile
+    #[serde(default)]
+    pub custom_allowed: Vec<FoodCategory>,
+}
+
+impl UserProfile {
+    pub fn allowed_categories(&self) -> Vec<FoodCategory> {
+        use FoodCategory::*;
+        match self.diet {
+            DietProfile::StrictCarnivore => vec![Beef, Poultry, Fish, Eggs, Pork, Dairy],
+            DietProfile::AnimalBased => vec![Beef, Poultry, Fish, Eggs, Pork, Dairy, Fruits, Honey],
+            DietProfile::Ketovore => vec![Beef, Poultry, Fish, Eggs, Pork, Dairy, Fruits],
+            DietProfile::Lion => vec![Beef],
+            DietProfile::Custom => self.custom_allowed.clone(),
+        }
+    }
+
+    pub fn is_allowed(&self, category: FoodCategory) -> bool {
+        self.allowed_categories().contains(&category)
+    }
+
+    // How much each category counts toward the Animal Score. Off-plan
+    // categories always count for nothing.
+    pub fn score_weights(&self) -> HashMap<FoodCategory, f64> {
+        use FoodCategory::*;
+        let weights = match self.diet {
+            DietProfile::StrictCarnivore | DietProfile::Lion => [
+                (Beef, 1.0), (Poultry, 0.7), (Fish, 0.9), (Eggs, 0.9), (Pork, 0.7), (Dairy, 0.7), (Fruits, 0.0), (Honey, 0.0),
+            ],
+            DietProfile::AnimalBased | DietProfile::Custom => [
+                (Beef, 1.0), (Poultry, 0.8), (Fish, 0.9), (Eggs, 0.9), (Pork, 0.7), (Dairy, 0.8), (Fruits, 0.7), (Honey, 0.6),
+            ],
+            DietProfile::Ketovore => [
+                (Beef, 1.0), (Poultry, 0.8), (Fish, 0.9), (Eggs, 0.9), (Pork, 0.7), (Dairy, 0.8), (Fruits, 0.4), (Honey, 0.0),
+            ],
+        };
+        weights.into_iter()
+            .map(|(category, weight)| (category, if self.is_allowed(category) { weight } else { 0.0 }))
+            .collect()
+    }
+
+    pub fn macro_ratios(&self) -> Option<MacroRatios> {
+        match self.diet {
+            DietProfile::StrictCarnivore | DietProfile::Lion => Some(MacroRatios { protein: 0.3, fat: 0.7, carbs: 0.0 }),
+            DietProfile::AnimalBased => Some(MacroRatios { protein: 0.3, fat: 0.45, carbs: 0.25 }),
+            DietProfile::Ketovore => Some(MacroRatios { protein: 0.3, fat: 0.65, carbs: 0.05 }),
+            DietProfile::Custom => None,
+        }
+    }
+
+    // Calorie weighted average of the category weights, 0-100. Sourcing
+    // quality is worth up to 15% of each entry's weight.
+    pub fn animal_score(&self, entries: &[MealEntry]) -> Option<f64> {
+        let weights = self.score_weights();
+        let total: f64 = entries.iter().map(|entry| entry.calories).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let weighted: f64 = entries.iter()
+            .map(|entry| {
+                let weight = weights.get(&entry.category).copied().unwrap_or(0.0);
+                entry.calories * weight * (0.85 + 0.15 * sourcing::quality(&entry.sourcing))
+            })
+            .sum();
+        Some((weighted / total * 100.0).round())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProfileStore {
+    pub db: Arc<Db>,
+}
+
+impl ProfileStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(ProfileStore {
+            db: Arc::new(db),
+        })
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        Ok(move_key(&self.db, from, to)? as usize)
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        self.db.remove(user_id.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn save_profile(&self, user_id: &str, profile: &UserProfile) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(profile)?;
+        self.db.insert(user_id.as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_profile(&self, user_id: &str) -> Result<UserProfile, Box<dyn Error>> {
+        if let Some(data) = self.db.get(user_id.as_bytes())? {
+            let profile: UserProfile = serde_json::from_slice(&data)?;
+            Ok(profile)
+        } else {
+            Ok(UserProfile::default())
+        }
+    }
+
+    pub async fn has_profile(&self, user_id: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self.db.contains_key(user_id.as_bytes())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+    use crate::models::food::Food;
+    use crate::models::sourcing::Sourcing;
+
+    const PRESETS: [DietProfile; 4] = [DietProfile::StrictCarnivore, DietProfile::AnimalBased, DietProfile::Ketovore, DietProfile::Lion];
+
+    fn profile(diet: DietProfile) -> UserProfile {
+        UserProfile { diet, custom_allowed: Vec::new() }
+    }
+
+    fn entry(category: FoodCategory, grams: f64, sourcing: Vec<Sourcing>) -> MealEntry {
+        let food = Food {
+            id: "test".to_owned(),
+            name: "Test".to_owned(),
+            category,
+            protein_g: 20.0,
+            fat_g: 10.0,
+            carbs_g: 0.0,
+            calories: 170.0,
+            omega3_mg: 0.0,
+            omega6_mg: 0.0,
+            default_sourcing: Vec::new(),
+            custom: false,
+            provenance: None,
+        };
+        MealEntry::from_food(&food, grams, sourcing, Local::now())
+    }
+
+    #[test]
+    fn presets_allow_their_categories() {
+        assert_eq!(profile(DietProfile::Lion).allowed_categories(), vec![FoodCategory::Beef]);
+        assert!(!profile(DietProfile::StrictCarnivore).is_allowed(FoodCategory::Fruits));
+        assert!(profile(DietProfile::Ketovore).is_allowed(FoodCategory::Fruits));
+        assert!(!profile(DietProfile::Ketovore).is_allowed(FoodCategory::Honey));
+        assert!(profile(DietProfile::AnimalBased).is_allowed(FoodCategory::Honey));
+        let custom = UserProfile { diet: DietProfile::Custom, custom_allowed: vec![FoodCategory::Fish, FoodCategory::Eggs] };
+        assert!(custom.is_allowed(FoodCategory::Fish));
+        assert!(!custom.is_allowed(FoodCategory::Beef));
+    }
+
+    #[test]
+    fn off_plan_categories_weigh_nothing() {
+        let lion = profile(DietProfile::Lion).score_weights();
+        assert_eq!(lion[&FoodCategory::Beef], 1.0);
+        assert_eq!(lion[&FoodCategory::Fish], 0.0);
+        let custom = UserProfile { diet: DietProfile::Custom, custom_allowed: vec![FoodCategory::Fruits] };
+        let weights = custom.score_weights();
+        assert_eq!(weights[&FoodCategory::Fruits], 0.7);
+        assert_eq!(weights[&FoodCategory::Beef], 0.0);
+        assert_eq!(profile(DietProfile::Ketovore).score_weights()[&FoodCategory::Fruits], 0.4);
+    }
+
+    #[test]
+    fn preset_ratios_cover_every_calorie() {
+        for diet in PRESETS {
+            let ratios = profile(diet).macro_ratios().unwrap();
+            assert!((ratios.protein + ratios.fat + ratios.carbs - 1.0).abs() < 1e-9, "{:?}", diet);
+        }
+        assert!(profile(DietProfile::Custom).macro_ratios().is_none());
+    }
+
+    #[test]
+    fn ratios_set_gram_targets() {
+        let mut targets = Targets { calories: 2000.0, ..Targets::default() };
+        profile(DietProfile::AnimalBased).macro_ratios().unwrap().apply(&mut targets);
+        assert_eq!((targets.protein_g, targets.fat_g, targets.carbs_g), (150.0, 100.0, 125.0));
+        profile(DietProfile::StrictCarnivore).macro_ratios().unwrap().apply(&mut targets);
+        assert_eq!(targets.carbs_g, 0.0);
+    }
+
+    #[test]
+    fn animal_score_weighs_calories_and_sourcing() {
+        let entries = vec![
+            entry(FoodCategory::Beef, 100.0, vec![Sourcing::GrassFed]),
+            entry(FoodCategory::Fruits, 100.0, Vec::new()),
+        ];
+        // beef counts fully, fruit 0.7 at the unknown sourcing quality of 0.5
+        assert_eq!(profile(DietProfile::AnimalBased).animal_score(&entries), Some(82.0));
+        // fruit is off plan for strict carnivores
+        assert_eq!(profile(DietProfile::StrictCarnivore).animal_score(&entries), Some(50.0));
+        // conventional beef loses the sourcing share
+        let conventional = vec![entry(FoodCategory::Beef, 100.0, vec![Sourcing::GrainFed])];
+        assert_eq!(profile(DietProfile::Lion).animal_score(&conventional), Some(85.0));
+        assert_eq!(profile(DietProfile::Lion).animal_score(&[]), None);
+    }
+}
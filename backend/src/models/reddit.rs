@@ -0,0 +1,379 @@
+use futures::future::LocalBoxFuture;
+use log::{info, error};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+use crate::models::identity::{ExternalIdentity, IdentityProvider, LoginError, PendingLogin, ProviderLogin};
+use crate::models::session::RedditUser;
+
+// Where the calls go and who they say they're from. The base is
+// https://oauth.reddit.com in production and a local mock in tests.
+#[derive(Clone, Debug)]
+pub struct RedditApi {
+    pub api_base: String,
+    pub user_agent: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RefreshedToken {
+    pub access_token: String,
+    pub expires_in: u64,
+}
+
+// What reddit's api_type=json endpoints wrap everything in
+#[derive(Debug, Deserialize)]
+struct JsonEnvelope<T> {
+    json: JsonBody<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonBody<T> {
+    // [code, message, field]
+    #[serde(default)]
+    errors: Vec<Vec<serde_json::Value>>,
+    data: Option<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmitData {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommentData {
+    things: Vec<Thing>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Thing {
+    data: ThingData,
+}
+
+#[derive(Debug, Deserialize)]
+struct ThingData {
+    permalink: String,
+}
+
+fn reddit_errors(errors: &[Vec<serde_json::Value>]) -> String {
+    errors.iter()
+        .map(|error| error.iter().filter_map(|part| part.as_str()).collect::<Vec<_>>().join(": "))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// "r/carnivore", "/r/carnivore/" and "carnivore" are all the same subreddit
+pub fn subreddit_name(text: &str) -> Option<String> {
+    let name = text.trim().trim_matches('/');
+    let name = name.strip_prefix("r/").unwrap_or(name);
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then(|| name.to_owned())
+}
+
+// The fullname (t3_...) of a post from its link, its id or its fullname
+pub fn thread_fullname(text: &str) -> Option<String> {
+    let text = text.trim();
+    let id = match text.split_once("/comments/") {
+        Some((_, rest)) => rest.split('/').next().unwrap_or_default(),
+        None => text.strip_prefix("t3_").unwrap_or(text),
+    };
+    let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric());
+    valid.then(|| format!("t3_{}", id.to_lowercase()))
+}
+
+impl RedditApi {
+    async fn post_form<T: for<'de> Deserialize<'de>>(&self, path: &str, access_token: &str, form: &[(&str, &str)]) -> Result<T, Box<dyn Error>> {
+        let response = Client::new()
+            .post(format!("{}{}", self.api_base.trim_end_matches('/'), path))
+            .bearer_auth(access_token)
+            .header("User-Agent", self.user_agent.clone())
+            .form(form)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(format!("Reddit answered {} to {}", response.status(), path).into());
+        }
+        let envelope = response.json::<JsonEnvelope<T>>().await?;
+        if !envelope.json.errors.is_empty() {
+            return Err(reddit_errors(&envelope.json.errors).into());
+        }
+        envelope.json.data.ok_or_else(|| format!("Reddit sent nothing back from {}", path).into())
+    }
+
+    // A self post, returns its link
+    pub async fn submit_post(&self, access_token: &str, subreddit: &str, title: &str, text: &str) -> Result<String, Box<dyn Error>> {
+        let form = [("api_type", "json"), ("kind", "self"), ("sr", subreddit), ("title", title), ("text", text)];
+        let data: SubmitData = self.post_form("/api/submit", access_token, &form).await?;
+        Ok(data.url)
+    }
+
+    // A reply to a post or comment by fullname, returns its permalink
+    pub async fn submit_comment(&self, access_token: &str, parent: &str, text: &str) -> Result<String, Box<dyn Error>> {
+        let form = [("api_type", "json"), ("thing_id", parent), ("text", text)];
+        let data: CommentData = self.post_form("/api/comment", access_token, &form).await?;
+        let permalink = data.things.into_iter().next().map(|thing| thing.data.permalink).unwrap_or_default();
+        Ok(format!("https://www.reddit.com{}", permalink))
+    }
+}
+
+// Sign in with Reddit. Reddit has no PKCE or ID tokens, just the state
+// check and /api/v1/me with the access token.
+#[derive(Clone, Debug)]
+pub struct RedditProvider {
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    // up to and including the ?
+    pub auth_uri: String,
+    pub access_uri: String,
+    pub get_user_uri: String,
+    pub user_agent: String,
+    // for signing in, space separated and already url encoded. Posting
+    // (submit) is only asked for through the sharing link.
+    pub scope: String,
+}
+
+// added to the scope when the user turns on sharing
+pub const SUBMIT_SCOPE: &str = "submit";
+
+#[derive(Clone, Debug, Serialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: String,
+    redirect_uri: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+    refresh_token: Option<String>,
+    // space separated
+    #[serde(default)]
+    scope: String,
+}
+
+impl RedditProvider {
+    async fn access_token(&self, code: &str) -> Result<TokenResponse, Box<dyn Error>> {
+        let token_request = TokenRequest {
+            grant_type: "authorization_code".to_owned(),
+            code: code.to_owned(),
+            redirect_uri: self.redirect_uri.clone(),
+        };
+        let response = Client::new()
+            .post(self.access_uri.clone())
+            .basic_auth(self.client_id.clone(), Some(self.client_secret.clone()))
+            .header("User-Agent", self.user_agent.clone())
+            .form(&token_request)
+            .send()
+            .await
+            .map_err(|e| LoginError(e.to_string()))?;
+        if !response.status().is_success() {
+            let error_msg = format!("[ERROR]: Reddit token exchange failed with status: {}", response.status());
+            error!("{}", error_msg);
+            return Err(Box::new(LoginError(error_msg)));
+        }
+        let token_data = response.json::<TokenResponse>().await.map_err(|e| LoginError(e.to_string()))?;
+        info!("[SUCCESS]: Obtained reddit access token.");
+        Ok(token_data)
+    }
+
+    async fn user(&self, access_token: &str) -> Result<RedditUser, Box<dyn Error>> {
+        let reddit_user = Client::new()
+            .get(self.get_user_uri.clone())
+            .bearer_auth(access_token)
+            .header("User-Agent", self.user_agent.clone())
+            .send()
+            .await?
+            .json::<RedditUser>()
+            .await?;
+        Ok(reddit_user)
+    }
+}
+
+impl IdentityProvider for RedditProvider {
+    fn id(&self) -> &str {
+        "reddit"
+    }
+
+    fn name(&self) -> &str {
+        "Reddit"
+    }
+
+    fn authorization_url<'a>(&'a self, pending: &'a PendingLogin) -> LocalBoxFuture<'a, Result<String, Box<dyn Error>>> {
+        Box::pin(async move {
+            let scope = match pending.sharing {
+                true => format!("{}%20{}", self.scope, SUBMIT_SCOPE),
+                false => self.scope.clone(),
+            };
+            Ok(format!("{}client_id={}&response_type=code&state={}&redirect_uri={}&duration=permanent&scope={}",
+                self.auth_uri, self.client_id, pending.state, self.redirect_uri, scope,
+            ))
+        })
+    }
+
+    fn complete_login<'a>(&'a self, code: &'a str, _pending: &'a PendingLogin) -> LocalBoxFuture<'a, Result<ProviderLogin, Box<dyn Error>>> {
+        Box::pin(async move {
+            let token = self.access_token(code).await?;
+            let reddit_user = self.user(&token.access_token).await?;
+            Ok(ProviderLogin {
+                identity: ExternalIdentity {
+                    provider: self.id().to_owned(),
+                    subject: reddit_user.id.clone(),
+                    display_name: reddit_user.name.clone(),
+                    email: None,
+                },
+                access_token: token.access_token,
+                refresh_token: token.refresh_token,
+                expires_in: token.expires_in,
+                scopes: token.scope.split_whitespace().map(str::to_owned).collect(),
+                reddit_user: Some(reddit_user),
+            })
+        })
+    }
+}
+
+// Trades the refresh token from a permanent login for a new access token
+pub async fn refresh_access_token(access_uri: &str, client_id: &str, client_secret: &str, user_agent: &str, refresh_token: &str) -> Result<RefreshedToken, Box<dyn Error>> {
+    let response = Client::new()
+        .post(access_uri)
+        .basic_auth(client_id, Some(client_secret))
+        .header("User-Agent", user_agent)
+        .form(&[("grant_type", "refresh_token"), ("refresh_token", refresh_token)])
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(format!("Reddit token refresh failed with status: {}", response.status()).into());
+    }
+    Ok(response.json::<RefreshedToken>().await?)
+}
+
+// Tells reddit to forget a token. token_type_hint is access_token or
+// refresh_token, reddit answers 204 either way for tokens it doesn't know.
+pub async fn revoke_token(revoke_uri: &str, client_id: &str, client_secret: &str, user_agent: &str, token: &str, token_type_hint: &str) -> Result<(), Box<dyn Error>> {
+    let response = Client::new()
+        .post(revoke_uri)
+        .basic_auth(client_id, Some(client_secret))
+        .header("User-Agent", user_agent)
+        .form(&[("token", token), ("token_type_hint", token_type_hint)])
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(format!("Reddit token revocation failed with status: {}", response.status()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+
+    // A stand-in for reddit answering one request. Gives its base url and
+    // the request line, headers and body it was sent.
+    fn mock_reddit(status: &str, body: &str) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let response = format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+        let (sent, received) = channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            sent.send(request).unwrap();
+        });
+        (base, received)
+    }
+
+    fn api(api_base: String) -> RedditApi {
+        RedditApi { api_base, user_agent: "abmacros-test".to_owned() }
+    }
+
+    #[test]
+    fn reads_subreddits_and_threads() {
+        assert_eq!(subreddit_name("/r/carnivore/"), Some("carnivore".to_owned()));
+        assert_eq!(subreddit_name("r/not a sub"), None);
+        assert_eq!(thread_fullname("https://www.reddit.com/r/carnivore/comments/Abc123/daily/"), Some("t3_abc123".to_owned()));
+        assert_eq!(thread_fullname("t3_abc123"), Some("t3_abc123".to_owned()));
+    }
+
+    #[actix_web::test]
+    async fn submits_a_post() {
+        let (base, received) = mock_reddit("200 OK", r#"{"json":{"errors":[],"data":{"url":"https://www.reddit.com/r/carnivore/comments/abc/week/"}}}"#);
+        let url = api(base).submit_post("token", "carnivore", "Week", "Ate meat").await.unwrap();
+        assert_eq!(url, "https://www.reddit.com/r/carnivore/comments/abc/week/");
+        let request = received.recv().unwrap();
+        assert!(request.starts_with("POST /api/submit "));
+        assert!(request.to_lowercase().contains("authorization: bearer token"));
+        assert!(request.contains("sr=carnivore"));
+    }
+
+    #[actix_web::test]
+    async fn comments_in_a_thread() {
+        let (base, received) = mock_reddit("200 OK", r#"{"json":{"errors":[],"data":{"things":[{"data":{"permalink":"/r/carnivore/comments/abc/daily/def/"}}]}}}"#);
+        let url = api(base).submit_comment("token", "t3_abc", "Ate meat").await.unwrap();
+        assert_eq!(url, "https://www.reddit.com/r/carnivore/comments/abc/daily/def/");
+        assert!(received.recv().unwrap().contains("thing_id=t3_abc"));
+    }
+
+    #[actix_web::test]
+    async fn passes_on_what_reddit_refused() {
+        let (base, _received) = mock_reddit("200 OK", r#"{"json":{"errors":[["SUBREDDIT_NOEXIST","that subreddit doesn't exist","sr"]]}}"#);
+        let error = api(base).submit_post("token", "nowhere", "Week", "Ate meat").await.unwrap_err();
+        assert_eq!(error.to_string(), "SUBREDDIT_NOEXIST: that subreddit doesn't exist: sr");
+        let (base, _received) = mock_reddit("403 Forbidden", "{}");
+        assert!(api(base).submit_post("token", "carnivore", "Week", "Ate meat").await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn revokes_tokens() {
+        let (base, received) = mock_reddit("204 No Content", "");
+        revoke_token(&format!("{}/api/v1/revoke_token", base), "client", "secret", "abmacros-test", "refresh", "refresh_token").await.unwrap();
+        let request = received.recv().unwrap();
+        assert!(request.starts_with("POST /api/v1/revoke_token "));
+        // client:secret
+        assert!(request.contains("Basic Y2xpZW50OnNlY3JldA=="));
+        assert!(request.ends_with("token=refresh&token_type_hint=refresh_token"));
+        let (base, _received) = mock_reddit("503 Service Unavailable", "");
+        assert!(revoke_token(&base, "client", "secret", "abmacros-test", "refresh", "refresh_token").await.is_err());
+    }
+
+    #[test]
+    fn only_asks_to_post_when_sharing() {
+        let provider = RedditProvider {
+            client_id: "client".to_owned(),
+            client_secret: "secret".to_owned(),
+            redirect_uri: "http://localhost/login/reddit/callback".to_owned(),
+            auth_uri: "https://www.reddit.com/api/v1/authorize?".to_owned(),
+            access_uri: String::new(),
+            get_user_uri: String::new(),
+            user_agent: String::new(),
+            scope: "identity".to_owned(),
+        };
+        let login = futures::executor::block_on(provider.authorization_url(&PendingLogin::new("reddit", None, false))).unwrap();
+        assert!(login.ends_with("&scope=identity"));
+        let sharing = futures::executor::block_on(provider.authorization_url(&PendingLogin::new("reddit", Some("user".to_owned()), true))).unwrap();
+        assert!(sharing.ends_with("&scope=identity%20submit"));
+    }
+}
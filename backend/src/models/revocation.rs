@@ -0,0 +1,248 @@
+use actix_web::cookie::Key;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sled::Db;
+use std::fmt;
+use std::sync::Arc;
+use std::error::Error;
+use chrono::{DateTime, Duration, Local};
+use uuid::Uuid;
+
+// first retry after this, doubling each time
+const FIRST_RETRY_MINUTES: i64 = 5;
+const MAX_RETRY_HOURS: i64 = 24;
+// after this many the token has long expired (access) or reddit is never
+// going to take it (refresh)
+pub const MAX_ATTEMPTS: u32 = 10;
+
+// A token reddit didn't confirm revoking, kept until a retry gets through.
+// The token is only ever stored sealed, see RevocationStore.
+#[derive(Clone, Debug)]
+pub struct PendingRevocation {
+    pub id: Uuid,
+    pub user_id: String,
+    pub token: String,
+    // access_token or refresh_token
+    pub token_type_hint: String,
+    pub attempts: u32,
+    pub last_error: String,
+    pub next_attempt_at: DateTime<Local>,
+}
+
+impl PendingRevocation {
+    pub fn new(user_id: &str, token: &str, token_type_hint: &str, error: String) -> Self {
+        let mut pending = PendingRevocation {
+            id: Uuid::new_v4(),
+            user_id: user_id.to_owned(),
+            token: token.to_owned(),
+            token_type_hint: token_type_hint.to_owned(),
+            attempts: 0,
+            last_error: String::new(),
+            next_attempt_at: Local::now(),
+        };
+        pending.failed(error);
+        pending
+    }
+
+    // Counts the attempt and backs off exponentially
+    pub fn failed(&mut self, error: String) {
+        self.attempts += 1;
+        self.last_error = error;
+        let minutes = (FIRST_RETRY_MINUTES << (self.attempts - 1).min(16)).min(MAX_RETRY_HOURS * 60);
+        self.next_attempt_at = Local::now() + Duration::minutes(minutes);
+    }
+
+    pub fn gave_up(&self) -> bool {
+        self.attempts >= MAX_ATTEMPTS
+    }
+}
+
+// What's on disk, the token encrypted with the store's key
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct StoredRevocation {
+    id: Uuid,
+    user_id: String,
+    // base64 of the nonce and the ciphertext
+    #[serde(default)]
+    sealed_token: String,
+    // in the clear, from before tokens were sealed. Sealed on the next save.
+    #[serde(default, skip_serializing)]
+    token: Option<String>,
+    token_type_hint: String,
+    attempts: u32,
+    last_error: String,
+    next_attempt_at: DateTime<Local>,
+}
+
+// AES-256-GCM, keyed from the session key so there's nothing more to
+// configure. Without a SESSION_KEY nothing queued survives a restart.
+#[derive(Clone)]
+struct TokenCipher(Aes256Gcm);
+
+impl fmt::Debug for TokenCipher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TokenCipher(..)")
+    }
+}
+
+impl TokenCipher {
+    fn new(key: &Key) -> Self {
+        let derived = Sha256::new()
+            .chain_update(key.master())
+            .chain_update(b"token-revocations")
+            .finalize();
+        TokenCipher(Aes256Gcm::new(&derived))
+    }
+
+    fn seal(&self, token: &str) -> Result<String, Box<dyn Error>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(self.0.encrypt(&nonce, token.as_bytes()).map_err(|_| "Unable to seal token")?);
+        Ok(STANDARD.encode(sealed))
+    }
+
+    fn open(&self, sealed: &str) -> Result<String, Box<dyn Error>> {
+        let sealed = STANDARD.decode(sealed)?;
+        if sealed.len() < 12 {
+            return Err("Sealed token is too short".into());
+        }
+        let (nonce, ciphertext) = sealed.split_at(12);
+        let token = self.0.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| "Unable to open sealed token")?;
+        Ok(String::from_utf8(token)?)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RevocationStore {
+    pub db: Arc<Db>,
+    cipher: TokenCipher,
+}
+
+impl RevocationStore {
+    pub fn new(path: &str, key: &Key) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(RevocationStore {
+            db: Arc::new(db),
+            cipher: TokenCipher::new(key),
+        })
+    }
+
+    pub async fn save(&self, pending: &PendingRevocation) -> Result<(), Box<dyn Error>> {
+        let stored = StoredRevocation {
+            id: pending.id,
+            user_id: pending.user_id.clone(),
+            sealed_token: self.cipher.seal(&pending.token)?,
+            token: None,
+            token_type_hint: pending.token_type_hint.clone(),
+            attempts: pending.attempts,
+            last_error: pending.last_error.clone(),
+            next_attempt_at: pending.next_attempt_at,
+        };
+        let serialized = serde_json::to_vec(&stored)?;
+        self.db.insert(pending.id.as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // The ones whose next attempt has come round. Ones sealed with another
+    // key can never be sent, they're dropped.
+    pub async fn get_due(&self) -> Result<Vec<PendingRevocation>, Box<dyn Error>> {
+        let now = Local::now();
+        let mut due = Vec::new();
+        let mut unreadable = Vec::new();
+        for item in self.db.iter() {
+            let (key, data) = item?;
+            let stored: StoredRevocation = serde_json::from_slice(&data)?;
+            if stored.next_attempt_at > now {
+                continue;
+            }
+            let token = match stored.token {
+                Some(token) => token,
+                None => match self.cipher.open(&stored.sealed_token) {
+                    Err(_) => {
+                        unreadable.push(key);
+                        continue;
+                    },
+                    Ok(token) => token,
+                },
+            };
+            due.push(PendingRevocation {
+                id: stored.id,
+                user_id: stored.user_id,
+                token,
+                token_type_hint: stored.token_type_hint,
+                attempts: stored.attempts,
+                last_error: stored.last_error,
+                next_attempt_at: stored.next_attempt_at,
+            });
+        }
+        for key in &unreadable {
+            self.db.remove(key)?;
+        }
+        if !unreadable.is_empty() {
+            self.db.flush()?;
+        }
+        Ok(due)
+    }
+
+    pub async fn delete(&self, id: &Uuid) -> Result<(), Box<dyn Error>> {
+        self.db.remove(id.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> RevocationStore {
+        RevocationStore {
+            db: Arc::new(sled::Config::new().temporary(true).open().unwrap()),
+            cipher: TokenCipher::new(&Key::generate()),
+        }
+    }
+
+    #[test]
+    fn backs_off_until_giving_up() {
+        let mut pending = PendingRevocation::new("user", "token", "access_token", "timeout".to_owned());
+        let first = pending.next_attempt_at - Local::now();
+        assert!(first <= Duration::minutes(FIRST_RETRY_MINUTES) && first > Duration::minutes(FIRST_RETRY_MINUTES - 1));
+        pending.failed("timeout".to_owned());
+        let second = pending.next_attempt_at - Local::now();
+        assert!(second > Duration::minutes(2 * FIRST_RETRY_MINUTES - 1));
+        while !pending.gave_up() {
+            pending.failed("timeout".to_owned());
+        }
+        assert_eq!(pending.attempts, MAX_ATTEMPTS);
+        assert!(pending.next_attempt_at - Local::now() <= Duration::hours(MAX_RETRY_HOURS));
+    }
+
+    #[actix_web::test]
+    async fn stores_tokens_sealed() {
+        let store = store();
+        let mut pending = PendingRevocation::new("user", "secret-refresh-token", "refresh_token", "timeout".to_owned());
+        pending.next_attempt_at = Local::now() - Duration::minutes(1);
+        store.save(&pending).await.unwrap();
+        let (_, data) = store.db.iter().next().unwrap().unwrap();
+        assert!(!String::from_utf8_lossy(&data).contains("secret-refresh-token"));
+        let due = store.get_due().await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].token, "secret-refresh-token");
+    }
+
+    #[actix_web::test]
+    async fn drops_tokens_sealed_with_another_key() {
+        let store = store();
+        let mut pending = PendingRevocation::new("user", "token", "access_token", "timeout".to_owned());
+        pending.next_attempt_at = Local::now() - Duration::minutes(1);
+        store.save(&pending).await.unwrap();
+        let other = RevocationStore { db: store.db.clone(), cipher: TokenCipher::new(&Key::generate()) };
+        assert!(other.get_due().await.unwrap().is_empty());
+        assert!(store.db.is_empty());
+    }
+}
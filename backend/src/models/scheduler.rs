@@ -0,0 +1,407 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone, Timelike};
+use log::{info, warn, error};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+// a cron expression with no match in this long is a typo, 30 February
+const CRON_SEARCH_DAYS: i64 = 366 * 5;
+
+// What a job reports back, a line for /admin/jobs
+pub type JobResult = Result<String, Box<dyn Error>>;
+
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ScheduleError(pub String);
+
+// "minute hour day-of-month month day-of-week" in local time, each field *,
+// a number, a range, a list of those, with an optional /step. Days of the
+// week count from 0 = Sunday, 7 is Sunday too.
+#[derive(Clone, Debug)]
+pub struct Cron {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // like cron, restricting both means either can match
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, ScheduleError> {
+    let invalid = || ScheduleError(format!("invalid cron field {}", field));
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            None => (part, 1),
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0).ok_or_else(invalid)?),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                None => {
+                    let value = range.parse::<u32>().map_err(|_| invalid())?;
+                    // 5/15 means from 5 to the end in steps of 15
+                    (value, if step > 1 { max } else { value })
+                },
+                Some((from, to)) => (from.parse::<u32>().map_err(|_| invalid())?, to.parse::<u32>().map_err(|_| invalid())?),
+            },
+        };
+        if from < min || to > max || from > to {
+            return Err(invalid());
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl Cron {
+    pub fn parse(expression: &str) -> Result<Self, ScheduleError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            return Err(ScheduleError(format!("cron needs 5 fields, got {}", expression)));
+        };
+        let mut weekday_bits = parse_field(weekdays, 0, 7)?;
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits |= 1;
+        }
+        Ok(Cron {
+            expression: expression.to_owned(),
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            any_day: *days == "*",
+            any_weekday: *weekdays == "*",
+        })
+    }
+
+    fn matches_day(&self, time: &NaiveDateTime) -> bool {
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+        let day_ok = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        day_ok && self.months & (1 << time.month()) != 0
+    }
+
+    // The first matching minute after the given time, skipping minutes a
+    // clock change leaves out
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let end = start + Duration::days(CRON_SEARCH_DAYS);
+        let mut time = start;
+        while time < end {
+            if !self.matches_day(&time) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += Duration::minutes(1);
+            } else {
+                match Local.from_local_datetime(&time).earliest() {
+                    Some(next) if next > after => return Some(next),
+                    _ => time += Duration::minutes(1),
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Schedule {
+    // from when the last run started, so runs don't drift later
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    pub fn cron(expression: &str) -> Result<Self, ScheduleError> {
+        Ok(Schedule::Cron(Cron::parse(expression)?))
+    }
+
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Schedule::Every(interval) => Some(after + *interval),
+            Schedule::Cron(cron) => cron.next_after(after),
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Every(interval) => write!(f, "every {}s", interval.num_seconds()),
+            Schedule::Cron(cron) => write!(f, "cron {}", cron.expression),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub schedule: String,
+    pub running: bool,
+    pub next_run_at: Option<DateTime<Local>>,
+    pub last_started_at: Option<DateTime<Local>>,
+    pub last_finished_at: Option<DateTime<Local>>,
+    // what the last successful run did
+    pub last_result: Option<String>,
+    pub last_error: Option<String>,
+    pub runs: u64,
+    pub failures: u64,
+    // runs due while the previous one was still going
+    pub skipped: u64,
+}
+
+// Up to the given amount, so jobs on the same schedule don't all start at once
+fn jitter_delay(jitter: Duration) -> Duration {
+    let max = jitter.num_milliseconds();
+    if max <= 0 {
+        return Duration::zero();
+    }
+    Duration::milliseconds(rand::thread_rng().gen_range(0..max))
+}
+
+// Runs jobs on the actix runtime of the process that added them. A job is
+// never run twice at once, a run that comes due while the last one is still
+// going is skipped and counted. Statuses are kept on disk so /admin/jobs
+// still has the last runs after a restart.
+#[derive(Clone, Debug)]
+pub struct Scheduler {
+    pub db: Arc<Db>,
+    statuses: Arc<Mutex<BTreeMap<String, JobStatus>>>,
+}
+
+impl Scheduler {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        Self::with_db(sled::open(path)?)
+    }
+
+    fn with_db(db: Db) -> Result<Self, sled::Error> {
+        let mut statuses = BTreeMap::new();
+        for item in db.iter() {
+            let (_, data) = item?;
+            match serde_json::from_slice::<JobStatus>(&data) {
+                Err(e) => warn!("[WARN]: Dropping unreadable job status: {}", e),
+                // whatever was running went down with the last process
+                Ok(status) => {
+                    statuses.insert(status.name.clone(), JobStatus { running: false, next_run_at: None, ..status });
+                },
+            }
+        }
+        Ok(Scheduler {
+            db: Arc::new(db),
+            statuses: Arc::new(Mutex::new(statuses)),
+        })
+    }
+
+    // Has to be called from inside the runtime, from main
+    pub fn add<F, Fut>(&self, name: &str, schedule: Schedule, jitter: Duration, job: F)
+    where
+        F: Fn() -> Fut + 'static,
+        Fut: Future<Output = JobResult> + 'static,
+    {
+        self.update(name, |status| {
+            status.name = name.to_owned();
+            status.schedule = schedule.to_string();
+        });
+        let scheduler = self.clone();
+        let name = name.to_owned();
+        actix_web::rt::spawn(async move {
+            loop {
+                let now = Local::now();
+                let Some(next) = schedule.next_after(now).map(|next| next + jitter_delay(jitter)) else {
+                    error!("[ERROR]: Job {} never comes due on {}, not scheduling it", name, schedule);
+                    scheduler.update(&name, |status| status.next_run_at = None);
+                    return;
+                };
+                scheduler.update(&name, |status| status.next_run_at = Some(next));
+                actix_web::rt::time::sleep((next - now).to_std().unwrap_or_default()).await;
+                if !scheduler.start(&name) {
+                    warn!("[WARN]: Skipped job {}, the last run is still going", name);
+                    continue;
+                }
+                let run = job();
+                let scheduler = scheduler.clone();
+                let name = name.clone();
+                actix_web::rt::spawn(async move {
+                    let result = run.await;
+                    scheduler.finish(&name, result);
+                });
+            }
+        });
+    }
+
+    // By name, for /admin/jobs
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        match self.statuses.lock() {
+            Err(_) => Vec::new(),
+            Ok(statuses) => statuses.values().cloned().collect(),
+        }
+    }
+
+    fn update(&self, name: &str, change: impl FnOnce(&mut JobStatus)) {
+        let Ok(mut statuses) = self.statuses.lock() else {
+            return;
+        };
+        let status = statuses.entry(name.to_owned()).or_default();
+        change(status);
+        if let Err(e) = self.save(name, status) {
+            error!("[ERROR]: Unable to save status of job {}: {}", name, e);
+        }
+    }
+
+    fn save(&self, name: &str, status: &JobStatus) -> Result<(), Box<dyn Error>> {
+        self.db.insert(name.as_bytes(), serde_json::to_vec(status)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // false if it's already running
+    fn start(&self, name: &str) -> bool {
+        let mut started = false;
+        self.update(name, |status| {
+            if status.running {
+                status.skipped += 1;
+            } else {
+                status.running = true;
+                status.last_started_at = Some(Local::now());
+                started = true;
+            }
+        });
+        started
+    }
+
+    fn finish(&self, name: &str, result: JobResult) {
+        match &result {
+            Err(e) => error!("[ERROR]: Job {} failed: {}", name, e),
+            Ok(done) => info!("[INFO]: Job {}: {}", name, done),
+        }
+        self.update(name, |status| {
+            status.running = false;
+            status.last_finished_at = Some(Local::now());
+            status.runs += 1;
+            match result {
+                Err(e) => {
+                    status.failures += 1;
+                    status.last_error = Some(e.to_string());
+                },
+                Ok(done) => status.last_result = Some(done),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(year, month, day, hour, minute, 0).earliest().unwrap()
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(parse_field("*", 0, 3).unwrap(), 0b1111);
+        assert_eq!(parse_field("1,3", 0, 5).unwrap(), 0b1010);
+        assert_eq!(parse_field("2-4", 0, 5).unwrap(), 0b11100);
+        assert_eq!(parse_field("*/15", 0, 59).unwrap(), 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(parse_field("5/20", 0, 59).unwrap(), 1 << 5 | 1 << 25 | 1 << 45);
+        assert_eq!(parse_field("1-10/3", 1, 31).unwrap(), 1 << 1 | 1 << 4 | 1 << 7 | 1 << 10);
+    }
+
+    #[test]
+    fn rejects_bad_expressions() {
+        assert!(parse_field("60", 0, 59).is_err());
+        assert!(parse_field("0", 1, 31).is_err());
+        assert!(parse_field("5-2", 0, 59).is_err());
+        assert!(parse_field("*/0", 0, 59).is_err());
+        assert!(parse_field("a", 0, 59).is_err());
+        assert!(Cron::parse("0 6 * *").is_err());
+        assert!(Cron::parse("0 6 * * * *").is_err());
+    }
+
+    #[test]
+    fn sunday_is_0_or_7() {
+        let sunday = Cron::parse("0 0 * * 7").unwrap();
+        // 2024-06-01 is a Saturday
+        assert_eq!(sunday.next_after(local(2024, 6, 1, 12, 0)), Some(local(2024, 6, 2, 0, 0)));
+        assert_eq!(Cron::parse("0 0 * * 0").unwrap().weekdays & 1, 1);
+    }
+
+    #[test]
+    fn finds_the_next_run() {
+        let daily = Cron::parse("30 4 * * *").unwrap();
+        assert_eq!(daily.next_after(local(2024, 6, 1, 4, 29)), Some(local(2024, 6, 1, 4, 30)));
+        // never the minute it's asked in
+        assert_eq!(daily.next_after(local(2024, 6, 1, 4, 30)), Some(local(2024, 6, 2, 4, 30)));
+
+        let monday = Cron::parse("0 6 * * 1").unwrap();
+        assert_eq!(monday.next_after(local(2024, 6, 1, 12, 0)), Some(local(2024, 6, 3, 6, 0)));
+
+        let quarterly = Cron::parse("0 0 1 */3 *").unwrap();
+        assert_eq!(quarterly.next_after(local(2024, 2, 15, 0, 0)), Some(local(2024, 4, 1, 0, 0)));
+
+        let leap_day = Cron::parse("0 12 29 2 *").unwrap();
+        assert_eq!(leap_day.next_after(local(2024, 3, 1, 0, 0)), Some(local(2028, 2, 29, 12, 0)));
+    }
+
+    #[test]
+    fn day_or_weekday_when_both_are_given() {
+        // the 13th, or any Friday
+        let cron = Cron::parse("0 0 13 * 5").unwrap();
+        // 2024-06-07 is a Friday, before the 13th
+        assert_eq!(cron.next_after(local(2024, 6, 1, 0, 0)), Some(local(2024, 6, 7, 0, 0)));
+        assert_eq!(cron.next_after(local(2024, 6, 12, 0, 0)), Some(local(2024, 6, 13, 0, 0)));
+    }
+
+    #[test]
+    fn never_due() {
+        assert_eq!(Cron::parse("0 0 30 2 *").unwrap().next_after(local(2024, 1, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn intervals_count_from_the_last_start() {
+        let now = local(2024, 6, 1, 12, 0);
+        assert_eq!(Schedule::Every(Duration::minutes(10)).next_after(now), Some(now + Duration::minutes(10)));
+    }
+
+    #[test]
+    fn jitter_stays_under_the_limit() {
+        assert_eq!(jitter_delay(Duration::zero()), Duration::zero());
+        for _ in 0..100 {
+            let delay = jitter_delay(Duration::seconds(1));
+            assert!(delay >= Duration::zero() && delay < Duration::seconds(1));
+        }
+    }
+
+    #[test]
+    fn statuses_outlive_a_restart() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let scheduler = Scheduler::with_db(db.clone()).unwrap();
+        scheduler.update("sweep", |status| status.name = "sweep".to_owned());
+        assert!(scheduler.start("sweep"));
+        scheduler.finish("sweep", Ok("swept".to_owned()));
+        assert!(scheduler.start("sweep"));
+
+        let restarted = Scheduler::with_db(db).unwrap();
+        let statuses = restarted.statuses();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].runs, 1);
+        assert_eq!(statuses[0].last_result.as_deref(), Some("swept"));
+        assert!(!statuses[0].running);
+    }
+}
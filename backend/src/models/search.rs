@@ -0,0 +1,263 @@
+use chrono::{DateTime, Duration, Local};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::models::food::Food;
+use crate::models::import::normalize_name;
+use crate::models::meal::MealEntry;
+
+// candidates scoring below this aren't worth offering
+const MIN_SCORE: f64 = 0.4;
+
+// the most eaten food gets this much added to its score, the rest in
+// proportion, so habits break ties without beating a better name match
+const FREQUENCY_WEIGHT: f64 = 0.15;
+
+// a cached index is rebuilt after this even if nothing said it changed, so
+// the frequencies catch up with meals logged some other way
+const CACHE_MINUTES: i64 = 10;
+// past this many users the least recently built index goes
+const CACHE_USERS: usize = 256;
+
+// say how a food comes rather than what it is, so "eggs" is a whole egg
+// before it's a yolk
+const DESCRIPTIVE_WORDS: [&str; 4] = ["whole", "raw", "fresh", "canned"];
+
+// Other names people use for the catalogue foods
+const ALIASES: [(&str, &[&str]); 22] = [
+    ("ribeye-steak", &["rib eye", "scotch fillet", "entrecote", "steak"]),
+    ("ground-beef", &["mince", "minced beef", "hamburger", "burger", "ground chuck"]),
+    ("beef-liver", &["liver", "ox liver", "calf liver"]),
+    ("brisket", &["corned beef", "pot roast"]),
+    ("beef-heart", &["heart", "ox heart"]),
+    ("beef-tallow", &["tallow", "dripping", "suet"]),
+    ("chicken-thigh", &["thigh", "drumstick"]),
+    ("chicken-breast", &["breast", "chicken fillet"]),
+    ("chicken-liver", &["chicken livers", "pate"]),
+    ("salmon", &["lox", "smoked salmon"]),
+    ("sardines", &["pilchards", "tinned fish"]),
+    ("cod", &["white fish", "salt cod", "bacalao"]),
+    ("whole-egg", &["egg", "fried egg", "scrambled eggs", "omelette"]),
+    ("egg-yolk", &["yolk", "yolks"]),
+    ("pork-belly", &["belly pork", "samgyeopsal", "siu yuk"]),
+    ("bacon", &["rashers", "streaky bacon", "back bacon"]),
+    ("pork-chop", &["chop", "pork loin"]),
+    ("raw-milk", &["milk", "whole milk"]),
+    ("cheddar", &["cheese"]),
+    ("heavy-cream", &["cream", "double cream", "whipping cream"]),
+    ("blueberries", &["berries"]),
+    ("raw-honey", &["honey"]),
+];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SearchHit {
+    pub food: Food,
+    // 0-1, how well the text matched
+    pub score: f64,
+    // the alias that matched, when it wasn't the name
+    pub matched_alias: Option<String>,
+    // times the user logged it recently
+    pub uses: usize,
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn singular(word: &str) -> &str {
+    word.strip_suffix("es").filter(|stem| stem.ends_with(['s', 'x', 'h']) && stem.len() > 2)
+        .or_else(|| word.strip_suffix('s').filter(|stem| stem.len() > 2 && !stem.ends_with('s')))
+        .unwrap_or(word)
+}
+
+// 1 for the same word, a bit less for a prefix (typing "rib" for ribeye),
+// and something for a typo
+fn word_similarity(typed: &str, other: &str) -> f64 {
+    if typed == other {
+        return 1.0;
+    }
+    if typed.len() >= 3 && other.starts_with(typed) {
+        return 0.9;
+    }
+    let longest = typed.chars().count().max(other.chars().count());
+    let similarity = 1.0 - edit_distance(typed, other) as f64 / longest as f64;
+    if similarity >= 0.7 { similarity } else { 0.0 }
+}
+
+fn words(text: &str) -> Vec<String> {
+    normalize_name(text)
+        .split(' ')
+        .filter(|word| !word.is_empty() && !DESCRIPTIVE_WORDS.contains(word))
+        .map(|word| singular(word).to_owned())
+        .collect()
+}
+
+// How well the typed words describe a name: mostly whether each typed word
+// is in it, partly how much of the name was typed
+fn words_score(typed: &[String], named: &[String]) -> f64 {
+    if typed.is_empty() || named.is_empty() {
+        return 0.0;
+    }
+    let best = |word: &String, against: &[String]| against.iter().map(|other| word_similarity(word, other)).fold(0.0, f64::max);
+    let quality = typed.iter().map(|word| best(word, named)).sum::<f64>() / typed.len() as f64;
+    let coverage = named.iter().map(|word| best(word, typed)).sum::<f64>() / named.len() as f64;
+    0.7 * quality + 0.3 * coverage
+}
+
+// How often each food was logged in the entries
+pub fn food_frequency(entries: &[MealEntry]) -> HashMap<String, usize> {
+    let mut uses: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        *uses.entry(entry.food_id.clone()).or_default() += 1;
+    }
+    uses
+}
+
+#[derive(Debug)]
+struct Indexed {
+    food: Food,
+    // the name first, then its aliases
+    terms: Vec<(Option<String>, Vec<String>)>,
+}
+
+// Foods with their names and aliases split into words up front, built per
+// request from the catalogue plus the user's own foods
+#[derive(Debug)]
+pub struct SearchIndex {
+    foods: Vec<Indexed>,
+}
+
+impl SearchIndex {
+    pub fn new(foods: Vec<Food>) -> Self {
+        let foods = foods.into_iter()
+            .map(|food| {
+                let aliases = ALIASES.iter()
+                    .filter(|(id, _)| *id == food.id)
+                    .flat_map(|(_, aliases)| aliases.iter())
+                    .map(|alias| (Some(alias.to_string()), words(alias)));
+                let terms = std::iter::once((None, words(&food.name))).chain(aliases).collect();
+                Indexed { food, terms }
+            })
+            .collect();
+        SearchIndex { foods }
+    }
+
+    // Best first: text score plus a nudge for the foods the user eats most.
+    // An empty query lists their most eaten foods.
+    pub fn search(&self, query: &str, uses: &HashMap<String, usize>, limit: usize) -> Vec<SearchHit> {
+        let typed = words(query);
+        let most_used = uses.values().copied().max().unwrap_or(0).max(1) as f64;
+        let mut hits: Vec<(f64, SearchHit)> = self.foods.iter()
+            .filter_map(|indexed| {
+                let count = uses.get(&indexed.food.id).copied().unwrap_or(0);
+                let (score, matched_alias) = if typed.is_empty() {
+                    (0.0, None)
+                } else {
+                    // backwards so the name wins a tie with an alias
+                    let (score, alias) = indexed.terms.iter()
+                        .rev()
+                        .map(|(alias, named)| (words_score(&typed, named), alias))
+                        .max_by(|a, b| a.0.total_cmp(&b.0))?;
+                    if score < MIN_SCORE {
+                        return None;
+                    }
+                    (score, alias.clone())
+                };
+                if typed.is_empty() && count == 0 {
+                    return None;
+                }
+                let rank = score + FREQUENCY_WEIGHT * count as f64 / most_used;
+                Some((rank, SearchHit { food: indexed.food.clone(), score, matched_alias, uses: count }))
+            })
+            .collect();
+        hits.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.food.name.cmp(&b.1.food.name)));
+        hits.into_iter().take(limit).map(|(_, hit)| hit).collect()
+    }
+}
+
+// A user's index and how often they ate each food lately
+pub type UserIndex = (Arc<SearchIndex>, Arc<HashMap<String, usize>>);
+
+// Built indexes by user, so every keystroke in the search box doesn't load
+// and split every food again. Whatever changes a user's foods or meals
+// calls invalidate.
+#[derive(Clone, Debug, Default)]
+pub struct SearchCache {
+    indexes: Arc<Mutex<HashMap<String, (DateTime<Local>, UserIndex)>>>,
+}
+
+impl SearchCache {
+    pub fn get(&self, user_id: &str) -> Option<UserIndex> {
+        let indexes = self.indexes.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        indexes.get(user_id)
+            .filter(|(built_at, _)| Local::now() - *built_at < Duration::minutes(CACHE_MINUTES))
+            .map(|(_, index)| index.clone())
+    }
+
+    pub fn insert(&self, user_id: &str, index: UserIndex) {
+        let mut indexes = self.indexes.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if indexes.len() >= CACHE_USERS && !indexes.contains_key(user_id) {
+            let oldest = indexes.iter().min_by_key(|(_, (built_at, _))| *built_at).map(|(user_id, _)| user_id.clone());
+            if let Some(oldest) = oldest {
+                indexes.remove(&oldest);
+            }
+        }
+        indexes.insert(user_id.to_owned(), (Local::now(), index));
+    }
+
+    pub fn invalidate(&self, user_id: &str) {
+        self.indexes.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> SearchIndex {
+        SearchIndex::new(Food::catalogue())
+    }
+
+    fn ids(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.food.id.as_str()).collect()
+    }
+
+    #[test]
+    fn matches_names_aliases_and_typos() {
+        let uses = HashMap::new();
+        assert_eq!(ids(&index().search("ribeye", &uses, 1)), vec!["ribeye-steak"]);
+        let hits = index().search("mince", &uses, 1);
+        assert_eq!(ids(&hits), vec!["ground-beef"]);
+        assert_eq!(hits[0].matched_alias.as_deref(), Some("mince"));
+        assert_eq!(ids(&index().search("sardnes", &uses, 1)), vec!["sardines"]);
+        assert!(index().search("tofu", &uses, 5).is_empty());
+    }
+
+    #[test]
+    fn habits_break_ties_and_fill_empty_queries() {
+        let uses = HashMap::from([("chicken-breast".to_owned(), 9), ("chicken-thigh".to_owned(), 1)]);
+        assert_eq!(ids(&index().search("chicken", &uses, 2)), vec!["chicken-breast", "chicken-thigh"]);
+        assert_eq!(ids(&index().search("", &uses, 10)), vec!["chicken-breast", "chicken-thigh"]);
+    }
+
+    #[test]
+    fn cache_forgets_invalidated_users() {
+        let cache = SearchCache::default();
+        cache.insert("someone", (Arc::new(index()), Arc::new(HashMap::new())));
+        assert!(cache.get("someone").is_some());
+        assert!(cache.get("someone else").is_none());
+        cache.invalidate("someone");
+        assert!(cache.get("someone").is_none());
+    }
+}
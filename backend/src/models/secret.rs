@@ -0,0 +1,18 @@
+use sha2::{Digest, Sha256};
+
+// Reset links and API tokens are only kept as this, so a copy of the
+// database can't be used to sign in
+pub fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_are_hex_sha256() {
+        assert_eq!(token_hash("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_ne!(token_hash("abc"), token_hash("abd"));
+    }
+}
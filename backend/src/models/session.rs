@@ -4,10 +4,19 @@ use std::sync::Arc;
 use std::error::Error;
 use chrono::{DateTime, Local};
 
+use crate::models::user::move_key;
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct RedditUser {
     pub name: String,
     pub id: String,
+    // account details from /api/v1/me, for the admission rules
+    #[serde(default)]
+    pub created_utc: Option<f64>,
+    #[serde(default)]
+    pub link_karma: i64,
+    #[serde(default)]
+    pub comment_karma: i64,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -16,6 +25,10 @@ pub struct UserSession {
     pub reddit_access_token: String,
     pub reddit_refresh_token: Option<String>,
     pub expires_at: DateTime<Local>,
+    // whether the token may post, only asked for when the user turns on
+    // sharing. Refreshed tokens keep the scopes of the one they replace.
+    #[serde(default)]
+    pub can_submit: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -47,6 +60,21 @@ impl SessionStore {
         }
     }
 
+    // Everyone's, keyed by user id, for the background jobs
+    pub async fn get_sessions(&self) -> Result<Vec<(String, UserSession)>, Box<dyn Error>> {
+        let mut sessions = Vec::new();
+        for item in self.db.iter() {
+            let (key, data) = item?;
+            sessions.push((String::from_utf8(key.to_vec())?, serde_json::from_slice(&data)?));
+        }
+        Ok(sessions)
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        Ok(move_key(&self.db, from, to)? as usize)
+    }
+
     pub async fn delete_session(&self, session_id: &str) -> Result<(), Box<dyn Error>> {
         self.db.remove(session_id.as_bytes())?;
         self.db.flush()?;
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::sync::Arc;
+use std::error::Error;
+use chrono::NaiveDate;
+
+use crate::models::user::move_key;
+use crate::models::meal::MacroTotals;
+use crate::models::targets::EffectiveTargets;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SharePeriod {
+    #[default]
+    Day,
+    // Monday to Sunday, as daily averages
+    Week,
+}
+
+// Nothing gets posted unless the user turned this on
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ShareSettings {
+    pub enabled: bool,
+    // where a new post goes
+    pub subreddit: Option<String>,
+    // a daily/weekly thread to comment in instead, link or id
+    pub thread: Option<String>,
+    #[serde(default)]
+    pub hide_weight: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShareSummary {
+    pub period: SharePeriod,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    // days with anything logged, the week's totals are averaged over these
+    pub days_logged: usize,
+    pub totals: MacroTotals,
+    pub targets: EffectiveTargets,
+    pub animal_score: Option<f64>,
+    pub trend_weight_kg: Option<f64>,
+    pub weekly_rate_kg: Option<f64>,
+}
+
+fn row(name: &str, eaten: f64, target: f64, unit: &str) -> String {
+    let percent = if target > 0.0 { format!("{:.0}%", eaten / target * 100.0) } else { "-".to_owned() };
+    format!("| {} | {:.0} {} | {:.0} {} | {} |\n", name, eaten, unit, target, unit, percent)
+}
+
+impl ShareSummary {
+    pub fn title(&self) -> String {
+        match self.period {
+            SharePeriod::Day => format!("Animal-based day: {}", self.from.format("%a %-d %b %Y")),
+            SharePeriod::Week => format!("Animal-based week: {} to {}", self.from.format("%-d %b"), self.to.format("%-d %b %Y")),
+        }
+    }
+
+    // Reddit markdown, the same text the preview shows
+    pub fn markdown(&self, hide_weight: bool) -> String {
+        let eaten_header = match self.period {
+            SharePeriod::Day => "Eaten",
+            SharePeriod::Week => "Daily average",
+        };
+        let mut text = format!("**{}**\n\n", self.title());
+        text.push_str(&format!("| | {} | Target | |\n|:--|--:|--:|--:|\n", eaten_header));
+        text.push_str(&row("Protein", self.totals.protein_g, self.targets.protein_g, "g"));
+        text.push_str(&row("Fat", self.totals.fat_g, self.targets.fat_g, "g"));
+        text.push_str(&row("Carbs", self.totals.carbs_g, self.targets.carbs_g, "g"));
+        text.push_str(&row("Calories", self.totals.calories, self.targets.calories, "kcal"));
+        text.push('\n');
+        if self.period == SharePeriod::Week {
+            text.push_str(&format!("Days logged: {}/7\n\n", self.days_logged));
+        }
+        if let Some(score) = self.animal_score {
+            text.push_str(&format!("Animal Score: **{:.0}**/100\n\n", score));
+        }
+        if let (false, Some(trend)) = (hide_weight, self.trend_weight_kg) {
+            match self.weekly_rate_kg {
+                Some(rate) => text.push_str(&format!("Trend weight: {:.1} kg ({:+.2} kg/week)\n\n", trend, rate)),
+                None => text.push_str(&format!("Trend weight: {:.1} kg\n\n", trend)),
+            }
+        }
+        text.push_str("^(Posted from ab-macros)");
+        text
+    }
+}
+
+// Settings keyed by user id, and the last weekly digest under digest:{user id}
+#[derive(Clone, Debug)]
+pub struct ShareStore {
+    pub db: Arc<Db>,
+}
+
+impl ShareStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(ShareStore {
+            db: Arc::new(db),
+        })
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let settings = move_key(&self.db, from, to)?;
+        let digest = move_key(&self.db, &format!("digest:{}", from), &format!("digest:{}", to))?;
+        Ok(settings as usize + digest as usize)
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        self.db.remove(user_id.as_bytes())?;
+        self.db.remove(format!("digest:{}", user_id).as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn save_settings(&self, user_id: &str, settings: &ShareSettings) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(settings)?;
+        self.db.insert(user_id.as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_settings(&self, user_id: &str) -> Result<ShareSettings, Box<dyn Error>> {
+        if let Some(data) = self.db.get(user_id.as_bytes())? {
+            let settings: ShareSettings = serde_json::from_slice(&data)?;
+            Ok(settings)
+        } else {
+            Ok(ShareSettings::default())
+        }
+    }
+
+    // Users who turned sharing on
+    pub async fn get_enabled_users(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut users = Vec::new();
+        for item in self.db.iter() {
+            let (key, data) = item?;
+            if key.starts_with(b"digest:") {
+                continue;
+            }
+            let settings: ShareSettings = serde_json::from_slice(&data)?;
+            if settings.enabled {
+                users.push(String::from_utf8(key.to_vec())?);
+            }
+        }
+        Ok(users)
+    }
+
+    // Replaces last week's
+    pub async fn save_digest(&self, user_id: &str, summary: &ShareSummary) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(summary)?;
+        self.db.insert(format!("digest:{}", user_id).as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn get_digest(&self, user_id: &str) -> Result<Option<ShareSummary>, Box<dyn Error>> {
+        if let Some(data) = self.db.get(format!("digest:{}", user_id).as_bytes())? {
+            Ok(Some(serde_json::from_slice(&data)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::models::food::FoodCategory;
+use crate::models::meal::MealEntry;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Sourcing {
+    GrassFed,
+    GrainFed,
+    PastureRaised,
+    Conventional,
+    WildCaught,
+    Farmed,
+    Raw,
+    Pasteurized,
+}
+
+impl Sourcing {
+    pub fn applies_to(&self, category: FoodCategory) -> bool {
+        use FoodCategory::*;
+        match self {
+            Sourcing::GrassFed | Sourcing::GrainFed => matches!(category, Beef | Dairy),
+            Sourcing::PastureRaised | Sourcing::Conventional => matches!(category, Poultry | Eggs | Pork),
+            Sourcing::WildCaught | Sourcing::Farmed => matches!(category, Fish),
+            Sourcing::Raw | Sourcing::Pasteurized => matches!(category, Dairy),
+        }
+    }
+
+    // The other half of the either/or pair this tag belongs to
+    pub fn opposite(&self) -> Sourcing {
+        match self {
+            Sourcing::GrassFed => Sourcing::GrainFed,
+            Sourcing::GrainFed => Sourcing::GrassFed,
+            Sourcing::PastureRaised => Sourcing::Conventional,
+            Sourcing::Conventional => Sourcing::PastureRaised,
+            Sourcing::WildCaught => Sourcing::Farmed,
+            Sourcing::Farmed => Sourcing::WildCaught,
+            Sourcing::Raw => Sourcing::Pasteurized,
+            Sourcing::Pasteurized => Sourcing::Raw,
+        }
+    }
+
+    pub fn is_premium(&self) -> bool {
+        matches!(self, Sourcing::GrassFed | Sourcing::PastureRaised | Sourcing::WildCaught | Sourcing::Raw)
+    }
+
+    // (omega-3, omega-6) multipliers against the conventional values in the catalogue
+    fn omega_factors(&self) -> (f64, f64) {
+        match self {
+            Sourcing::GrassFed => (3.0, 0.8),
+            Sourcing::PastureRaised => (2.5, 0.6),
+            // farmed salmon carries a lot more omega-6 from the feed
+            Sourcing::WildCaught => (1.0, 0.3),
+            _ => (1.0, 1.0),
+        }
+    }
+}
+
+// Why a set of tags can't go on a food of this category, if it can't
+pub fn check_sourcing(sourcing: &[Sourcing], category: FoodCategory) -> Result<(), String> {
+    if let Some(tag) = sourcing.iter().find(|tag| !tag.applies_to(category)) {
+        return Err(format!("{:?} doesn't apply to {:?}", tag, category));
+    }
+    if let Some(tag) = sourcing.iter().find(|tag| sourcing.contains(&tag.opposite())) {
+        return Err(format!("{:?} and {:?} contradict each other", tag, tag.opposite()));
+    }
+    Ok(())
+}
+
+// 1.0 when every tag is premium, 0.0 when all are conventional and 0.5 when
+// we simply don't know
+pub fn quality(sourcing: &[Sourcing]) -> f64 {
+    if sourcing.is_empty() {
+        return 0.5;
+    }
+    sourcing.iter().filter(|tag| tag.is_premium()).count() as f64 / sourcing.len() as f64
+}
+
+pub fn adjusted_omegas(omega3_mg: f64, omega6_mg: f64, sourcing: &[Sourcing]) -> (f64, f64) {
+    sourcing.iter().fold((omega3_mg, omega6_mg), |(omega3, omega6), tag| {
+        let (factor3, factor6) = tag.omega_factors();
+        (omega3 * factor3, omega6 * factor6)
+    })
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CategorySourcing {
+    pub grams: f64,
+    // tagged grams weighted by quality(), so 0.5 for grass fed but pasteurized
+    pub premium_grams: f64,
+    pub unknown_grams: f64,
+    pub grams_by_sourcing: HashMap<Sourcing, f64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SourcingReport {
+    pub categories: HashMap<FoodCategory, CategorySourcing>,
+    // quality() averaged over the animal food grams with known sourcing, 0-1
+    pub premium_share: Option<f64>,
+    pub omega3_mg: f64,
+    pub omega6_mg: f64,
+    pub omega6_to_omega3: Option<f64>,
+}
+
+impl SourcingReport {
+    pub fn from_entries(entries: &[MealEntry]) -> Self {
+        let mut report = SourcingReport::default();
+        for entry in entries {
+            report.omega3_mg += entry.omega3_mg;
+            report.omega6_mg += entry.omega6_mg;
+            let category = report.categories.entry(entry.category).or_default();
+            category.grams += entry.grams;
+            if entry.sourcing.is_empty() {
+                category.unknown_grams += entry.grams;
+            } else {
+                category.premium_grams += entry.grams * quality(&entry.sourcing);
+            }
+            for tag in &entry.sourcing {
+                *category.grams_by_sourcing.entry(*tag).or_default() += entry.grams;
+            }
+        }
+
+        let (premium, total) = report.categories.iter()
+            .filter(|(category, _)| !matches!(category, FoodCategory::Fruits | FoodCategory::Honey))
+            .fold((0.0, 0.0), |(premium, total), (_, sourcing)| (premium + sourcing.premium_grams, total + sourcing.grams - sourcing.unknown_grams));
+        report.premium_share = if total > 0.0 { Some(premium / total) } else { None };
+        report.omega6_to_omega3 = if report.omega3_mg > 0.0 { Some(report.omega6_mg / report.omega3_mg) } else { None };
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+    use crate::models::food::Food;
+
+    fn entry(category: FoodCategory, grams: f64, sourcing: Vec<Sourcing>) -> MealEntry {
+        let food = Food {
+            id: "test".to_owned(),
+            name: "Test".to_owned(),
+            category,
+            protein_g: 20.0,
+            fat_g: 10.0,
+            carbs_g: 0.0,
+            calories: 170.0,
+            omega3_mg: 0.0,
+            omega6_mg: 0.0,
+            default_sourcing: Vec::new(),
+            custom: false,
+            provenance: None,
+        };
+        MealEntry::from_food(&food, grams, sourcing, Local::now())
+    }
+
+    #[test]
+    fn rejects_contradictory_tags() {
+        assert!(check_sourcing(&[Sourcing::GrassFed, Sourcing::Raw], FoodCategory::Dairy).is_ok());
+        assert!(check_sourcing(&[Sourcing::GrassFed, Sourcing::GrainFed], FoodCategory::Beef).is_err());
+        assert!(check_sourcing(&[Sourcing::Raw, Sourcing::Pasteurized], FoodCategory::Dairy).is_err());
+        assert!(check_sourcing(&[Sourcing::WildCaught], FoodCategory::Beef).is_err());
+    }
+
+    #[test]
+    fn premium_share_follows_quality() {
+        let entries = vec![
+            entry(FoodCategory::Dairy, 100.0, vec![Sourcing::GrassFed, Sourcing::Pasteurized]),
+            entry(FoodCategory::Beef, 100.0, vec![Sourcing::GrainFed]),
+            // unknown sourcing stays out of the share
+            entry(FoodCategory::Beef, 200.0, Vec::new()),
+        ];
+        let report = SourcingReport::from_entries(&entries);
+        assert_eq!(report.premium_share, Some(0.25));
+        assert_eq!(report.categories[&FoodCategory::Beef].unknown_grams, 200.0);
+    }
+}
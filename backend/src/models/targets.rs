@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::sync::Arc;
+use std::error::Error;
+use chrono::{DateTime, Local};
+
+use crate::models::user::move_key;
+
+const LB_PER_KG: f64 = 2.20462;
+// past these a target is a typo, not a diet
+const MAX_GRAMS: f64 = 2000.0;
+const MAX_CALORIES: f64 = 20000.0;
+const MAX_GRAMS_PER_MASS: f64 = 10.0;
+const MAX_RATE_KG_PER_WEEK: f64 = 2.0;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MassUnit {
+    Kg,
+    Lb,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MassBasis {
+    BodyWeight,
+    LeanMass,
+}
+
+// e.g. 2.2 g per lb of lean mass
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RelativeTarget {
+    pub grams: f64,
+    pub per: MassUnit,
+    pub basis: MassBasis,
+}
+
+impl RelativeTarget {
+    // None when we don't have the weight (or body fat for lean mass) to work from
+    pub fn grams_for(&self, weight_kg: Option<f64>, body_fat_pct: Option<f64>) -> Option<f64> {
+        let mass_kg = match self.basis {
+            MassBasis::BodyWeight => weight_kg?,
+            MassBasis::LeanMass => weight_kg? * (1.0 - body_fat_pct? / 100.0),
+        };
+        let mass = match self.per {
+            MassUnit::Kg => mass_kg,
+            MassUnit::Lb => mass_kg * LB_PER_KG,
+        };
+        // body fat past 100% would leave negative lean mass
+        Some((self.grams * mass).max(0.0))
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if !self.grams.is_finite() || !(0.0..=MAX_GRAMS_PER_MASS).contains(&self.grams) {
+            return Err(format!("Relative targets should be between 0 and {} g", MAX_GRAMS_PER_MASS));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Targets {
+    // fixed gram targets, also the fallback for relative ones
+    pub protein_g: f64,
+    pub fat_g: f64,
+    pub carbs_g: f64,
+    pub calories: f64,
+    // negative to lose, positive to gain
+    #[serde(default)]
+    pub goal_rate_kg_per_week: f64,
+    #[serde(default)]
+    pub auto_adjust: bool,
+    #[serde(default)]
+    pub last_adjusted: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub protein_relative: Option<RelativeTarget>,
+    #[serde(default)]
+    pub fat_relative: Option<RelativeTarget>,
+}
+
+// What the user should actually aim for today, with relative targets
+// resolved against their latest weigh-in
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EffectiveTargets {
+    pub protein_g: f64,
+    pub fat_g: f64,
+    pub carbs_g: f64,
+    pub calories: f64,
+    pub weight_kg: Option<f64>,
+    pub lean_mass_kg: Option<f64>,
+    pub protein_relative: Option<RelativeTarget>,
+    pub fat_relative: Option<RelativeTarget>,
+}
+
+impl Targets {
+    pub fn check(&self) -> Result<(), String> {
+        for (name, grams) in [("Protein", self.protein_g), ("Fat", self.fat_g), ("Carbs", self.carbs_g)] {
+            if !grams.is_finite() || !(0.0..=MAX_GRAMS).contains(&grams) {
+                return Err(format!("{} should be between 0 and {} g", name, MAX_GRAMS));
+            }
+        }
+        if !self.calories.is_finite() || !(0.0..=MAX_CALORIES).contains(&self.calories) {
+            return Err(format!("Calories should be between 0 and {}", MAX_CALORIES));
+        }
+        if !self.goal_rate_kg_per_week.is_finite() || self.goal_rate_kg_per_week.abs() > MAX_RATE_KG_PER_WEEK {
+            return Err(format!("Goal rate should be within {} kg a week", MAX_RATE_KG_PER_WEEK));
+        }
+        for relative in self.protein_relative.iter().chain(&self.fat_relative) {
+            relative.check()?;
+        }
+        Ok(())
+    }
+
+    pub fn effective(&self, weight_kg: Option<f64>, body_fat_pct: Option<f64>) -> EffectiveTargets {
+        let resolve = |relative: &Option<RelativeTarget>, fixed: f64| {
+            relative.as_ref()
+                .and_then(|relative| relative.grams_for(weight_kg, body_fat_pct))
+                .unwrap_or(fixed)
+        };
+        EffectiveTargets {
+            protein_g: resolve(&self.protein_relative, self.protein_g),
+            fat_g: resolve(&self.fat_relative, self.fat_g),
+            carbs_g: self.carbs_g,
+            calories: self.calories,
+            weight_kg,
+            lean_mass_kg: weight_kg.zip(body_fat_pct).map(|(weight, fat)| weight * (1.0 - fat / 100.0)),
+            protein_relative: self.protein_relative.clone(),
+            fat_relative: self.fat_relative.clone(),
+        }
+    }
+}
+
+impl Default for Targets {
+    fn default() -> Self {
+        Targets {
+            protein_g: 180.0,
+            fat_g: 100.0,
+            carbs_g: 50.0,
+            calories: 2200.0,
+            goal_rate_kg_per_week: 0.0,
+            auto_adjust: false,
+            last_adjusted: None,
+            protein_relative: None,
+            fat_relative: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TargetsStore {
+    pub db: Arc<Db>,
+}
+
+impl TargetsStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(TargetsStore {
+            db: Arc::new(db),
+        })
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        Ok(move_key(&self.db, from, to)? as usize)
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        self.db.remove(user_id.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub async fn save_targets(&self, user_id: &str, targets: &Targets) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(targets)?;
+        self.db.insert(user_id.as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // users who never set anything get the defaults
+    pub async fn get_targets(&self, user_id: &str) -> Result<Targets, Box<dyn Error>> {
+        if let Some(data) = self.db.get(user_id.as_bytes())? {
+            let targets: Targets = serde_json::from_slice(&data)?;
+            Ok(targets)
+        } else {
+            Ok(Targets::default())
+        }
+    }
+
+    pub async fn has_targets(&self, user_id: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self.db.contains_key(user_id.as_bytes())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relative(grams: f64, per: MassUnit, basis: MassBasis) -> RelativeTarget {
+        RelativeTarget { grams, per, basis }
+    }
+
+    #[test]
+    fn relative_targets_scale_with_mass() {
+        let per_kg = relative(2.0, MassUnit::Kg, MassBasis::BodyWeight);
+        assert_eq!(per_kg.grams_for(Some(80.0), None), Some(160.0));
+        let per_lb_lean = relative(1.0, MassUnit::Lb, MassBasis::LeanMass);
+        let grams = per_lb_lean.grams_for(Some(100.0), Some(20.0)).unwrap();
+        assert!((grams - 80.0 * LB_PER_KG).abs() < 1e-9);
+        // nothing to work from
+        assert_eq!(per_kg.grams_for(None, Some(20.0)), None);
+        assert_eq!(per_lb_lean.grams_for(Some(100.0), None), None);
+    }
+
+    #[test]
+    fn relative_targets_never_go_negative() {
+        let lean = relative(2.0, MassUnit::Kg, MassBasis::LeanMass);
+        assert_eq!(lean.grams_for(Some(80.0), Some(120.0)), Some(0.0));
+    }
+
+    #[test]
+    fn effective_targets_fall_back_to_fixed() {
+        let targets = Targets {
+            protein_relative: Some(relative(2.0, MassUnit::Kg, MassBasis::LeanMass)),
+            ..Targets::default()
+        };
+        let resolved = targets.effective(Some(100.0), Some(25.0));
+        assert_eq!(resolved.protein_g, 150.0);
+        assert_eq!(resolved.fat_g, targets.fat_g);
+        assert_eq!(resolved.lean_mass_kg, Some(75.0));
+        // no body fat, so no lean mass and the fixed protein target
+        let resolved = targets.effective(Some(100.0), None);
+        assert_eq!(resolved.protein_g, targets.protein_g);
+        assert_eq!(resolved.lean_mass_kg, None);
+    }
+
+    #[test]
+    fn rejects_impossible_targets() {
+        assert!(Targets::default().check().is_ok());
+        assert!(Targets { protein_g: -1.0, ..Targets::default() }.check().is_err());
+        assert!(Targets { carbs_g: f64::NAN, ..Targets::default() }.check().is_err());
+        assert!(Targets { calories: 1e9, ..Targets::default() }.check().is_err());
+        assert!(Targets { goal_rate_kg_per_week: -5.0, ..Targets::default() }.check().is_err());
+        let fat_relative = Some(relative(-0.5, MassUnit::Kg, MassBasis::BodyWeight));
+        assert!(Targets { fat_relative, ..Targets::default() }.check().is_err());
+    }
+}
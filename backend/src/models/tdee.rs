@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use chrono::{Duration, NaiveDate};
+
+use crate::models::body::BodyTrend;
+use crate::models::meal::{daily_totals, MealEntry};
+use crate::models::targets::Targets;
+
+// Rough energy content of a kg of body mass change
+pub const KCAL_PER_KG: f64 = 7700.0;
+pub const WINDOW_DAYS: i64 = 28;
+// Need at least this much intake and weight history before guessing
+const MIN_DAYS: i64 = 7;
+// Don't move the calorie target more than this in a single week
+const MAX_WEEKLY_STEP_KCAL: f64 = 200.0;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    Insufficient,
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TdeeEstimate {
+    pub window_start: NaiveDate,
+    pub window_end: NaiveDate,
+    pub intake_days: i64,
+    pub weigh_in_days: i64,
+    pub average_intake_kcal: Option<f64>,
+    pub trend_change_kg: Option<f64>,
+    pub tdee_kcal: Option<f64>,
+    pub confidence: Confidence,
+    pub confidence_score: f64,
+}
+
+impl TdeeEstimate {
+    // Energy balance over the window ending yesterday (today is still being
+    // logged): expenditure = average intake - stored energy per day.
+    // Days without any logged food are treated as untracked, not as fasts.
+    pub fn estimate(entries: &[MealEntry], trend: &BodyTrend, today: NaiveDate) -> Self {
+        let window_end = today - Duration::days(1);
+        let window_start = window_end - Duration::days(WINDOW_DAYS - 1);
+
+        let intake: Vec<f64> = daily_totals(entries).into_iter()
+            .filter(|(date, _)| *date >= window_start && *date <= window_end)
+            .map(|(_, totals)| totals.calories)
+            .collect();
+        let intake_days = intake.len() as i64;
+        let average_intake_kcal = if intake.is_empty() {
+            None
+        } else {
+            Some(intake.iter().sum::<f64>() / intake.len() as f64)
+        };
+
+        let weigh_in_days = trend.points.iter()
+            .filter(|point| point.date >= window_start && point.date <= window_end)
+            .count() as i64;
+        // start from the trend going into the window, or the first weigh-in inside it
+        let first = trend.trend_on(window_start)
+            .or_else(|| trend.points.iter().find(|point| point.date >= window_start));
+        let last = trend.trend_on(window_end);
+        let trend_span = match (first, last) {
+            (Some(first), Some(last)) if (last.date - first.date).num_days() >= MIN_DAYS => {
+                Some((last.trend_kg - first.trend_kg, (last.date - first.date).num_days()))
+            },
+            _ => None,
+        };
+
+        let tdee_kcal = match (average_intake_kcal, trend_span) {
+            (Some(average), Some((change_kg, days))) if intake_days >= MIN_DAYS => {
+                Some(average - change_kg * KCAL_PER_KG / days as f64)
+            },
+            _ => None,
+        };
+
+        // how well the window is covered by food logs and weigh-ins; weighing
+        // every other day is plenty for the trend
+        let intake_coverage = intake_days as f64 / WINDOW_DAYS as f64;
+        let weight_coverage = (weigh_in_days as f64 / (WINDOW_DAYS / 2) as f64).min(1.0);
+        let confidence_score = if tdee_kcal.is_some() { intake_coverage * weight_coverage } else { 0.0 };
+        let confidence = match confidence_score {
+            _ if tdee_kcal.is_none() => Confidence::Insufficient,
+            score if score >= 0.75 => Confidence::High,
+            score if score >= 0.4 => Confidence::Medium,
+            _ => Confidence::Low,
+        };
+
+        TdeeEstimate {
+            window_start,
+            window_end,
+            intake_days,
+            weigh_in_days,
+            average_intake_kcal,
+            trend_change_kg: trend_span.map(|(change_kg, _)| change_kg),
+            tdee_kcal,
+            confidence,
+            confidence_score,
+        }
+    }
+
+    // Calorie target that would hit the goal rate, stepped toward from the
+    // current target so one noisy week can't swing it wildly
+    pub fn adjusted_calories(&self, targets: &Targets) -> Option<f64> {
+        let tdee = self.tdee_kcal?;
+        let goal = tdee + targets.goal_rate_kg_per_week * KCAL_PER_KG / 7.0;
+        let step = (goal - targets.calories).clamp(-MAX_WEEKLY_STEP_KCAL, MAX_WEEKLY_STEP_KCAL);
+        Some(((targets.calories + step) / 10.0).round() * 10.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+    use uuid::Uuid;
+    use crate::models::body::BodyMetric;
+    use crate::models::food::FoodCategory;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 7, 1).unwrap()
+    }
+
+    fn at_noon(days_ago: i64) -> chrono::DateTime<Local> {
+        let date = today() - Duration::days(days_ago);
+        Local.from_local_datetime(&date.and_hms_opt(12, 0, 0).unwrap()).earliest().unwrap()
+    }
+
+    fn meal(days_ago: i64, calories: f64) -> MealEntry {
+        MealEntry {
+            id: Uuid::new_v4(),
+            food_id: "beef".to_owned(),
+            food_name: "Beef".to_owned(),
+            category: FoodCategory::Beef,
+            grams: 500.0,
+            protein_g: 0.0,
+            fat_g: 0.0,
+            carbs_g: 0.0,
+            calories,
+            sourcing: Vec::new(),
+            omega3_mg: 0.0,
+            omega6_mg: 0.0,
+            cost: None,
+            pantry_grams: 0.0,
+            eaten_at: at_noon(days_ago),
+        }
+    }
+
+    fn trend(weights: impl Iterator<Item = (i64, f64)>) -> BodyTrend {
+        let metrics: Vec<BodyMetric> = weights.map(|(days_ago, weight_kg)| BodyMetric {
+            id: Uuid::new_v4(),
+            recorded_at: at_noon(days_ago),
+            weight_kg: Some(weight_kg),
+            body_fat_pct: None,
+            waist_cm: None,
+            measurements: Default::default(),
+        }).collect();
+        BodyTrend::from_metrics(&metrics)
+    }
+
+    #[test]
+    fn steady_weight_means_intake_is_expenditure() {
+        let entries: Vec<MealEntry> = (1..=WINDOW_DAYS).map(|days_ago| meal(days_ago, 2500.0)).collect();
+        let estimate = TdeeEstimate::estimate(&entries, &trend((1..=WINDOW_DAYS).map(|days_ago| (days_ago, 80.0))), today());
+        assert_eq!(estimate.intake_days, WINDOW_DAYS);
+        assert_eq!(estimate.weigh_in_days, WINDOW_DAYS);
+        assert_eq!(estimate.average_intake_kcal, Some(2500.0));
+        assert_eq!(estimate.trend_change_kg, Some(0.0));
+        assert_eq!(estimate.tdee_kcal, Some(2500.0));
+        assert_eq!(estimate.confidence, Confidence::High);
+    }
+
+    #[test]
+    fn losing_weight_means_burning_more_than_eaten() {
+        let entries: Vec<MealEntry> = (1..=WINDOW_DAYS).map(|days_ago| meal(days_ago, 2000.0)).collect();
+        let losing = trend((1..=WINDOW_DAYS).map(|days_ago| (days_ago, 80.0 + 0.05 * days_ago as f64)));
+        let estimate = TdeeEstimate::estimate(&entries, &losing, today());
+        assert!(estimate.trend_change_kg.unwrap() < 0.0);
+        assert!(estimate.tdee_kcal.unwrap() > 2000.0);
+    }
+
+    #[test]
+    fn today_and_unlogged_days_dont_count() {
+        let mut entries: Vec<MealEntry> = (1..=WINDOW_DAYS).step_by(2).map(|days_ago| meal(days_ago, 2400.0)).collect();
+        entries.push(meal(0, 5000.0));
+        entries.push(meal(WINDOW_DAYS + 1, 5000.0));
+        let estimate = TdeeEstimate::estimate(&entries, &trend((1..=WINDOW_DAYS).map(|days_ago| (days_ago, 80.0))), today());
+        assert_eq!(estimate.intake_days, WINDOW_DAYS / 2);
+        assert_eq!(estimate.average_intake_kcal, Some(2400.0));
+        assert_eq!(estimate.confidence, Confidence::Medium);
+    }
+
+    #[test]
+    fn too_little_history_is_insufficient() {
+        let entries: Vec<MealEntry> = (1..=3).map(|days_ago| meal(days_ago, 2500.0)).collect();
+        let estimate = TdeeEstimate::estimate(&entries, &trend((1..=3).map(|days_ago| (days_ago, 80.0))), today());
+        assert_eq!(estimate.tdee_kcal, None);
+        assert_eq!(estimate.confidence, Confidence::Insufficient);
+        assert_eq!(estimate.confidence_score, 0.0);
+        assert_eq!(estimate.adjusted_calories(&Targets::default()), None);
+    }
+
+    #[test]
+    fn adjustments_are_capped_per_week() {
+        let entries: Vec<MealEntry> = (1..=WINDOW_DAYS).map(|days_ago| meal(days_ago, 2500.0)).collect();
+        let estimate = TdeeEstimate::estimate(&entries, &trend((1..=WINDOW_DAYS).map(|days_ago| (days_ago, 80.0))), today());
+        // 2200 wants to go to 2500, one step of 200 at a time
+        assert_eq!(estimate.adjusted_calories(&Targets::default()), Some(2400.0));
+        // half a kilo a week down is 550 a day under
+        let cutting = Targets { calories: 2000.0, goal_rate_kg_per_week: -0.5, ..Targets::default() };
+        assert_eq!(estimate.adjusted_calories(&cutting), Some(1950.0));
+    }
+}
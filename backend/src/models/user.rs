@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db, Tree};
+use std::sync::Arc;
+use std::error::Error;
+use chrono::{DateTime, Local};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UserSettings {
+    #[serde(default)]
+    pub units: UnitSystem,
+}
+
+// Who owns the data. Sign-ins (reddit, OpenID Connect, passwords, passkeys)
+// point here through the identity store, so any of them can change or go
+// away without the data moving.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct User {
+    // a UUID. Reddit users from before there were internal users had their
+    // reddit id, they're moved at startup (api::users::migrate_legacy_users).
+    pub id: String,
+    pub display_name: String,
+    pub created_at: DateTime<Local>,
+    #[serde(default)]
+    pub settings: UserSettings,
+    // set when a reddit account named in ADMINS signs in, and kept when
+    // that reddit session ends
+    #[serde(default)]
+    pub admin: bool,
+}
+
+impl User {
+    pub fn new(display_name: &str) -> Self {
+        User::with_id(&Uuid::new_v4().to_string(), display_name)
+    }
+
+    pub fn with_id(id: &str, display_name: &str) -> Self {
+        User {
+            id: id.to_owned(),
+            display_name: display_name.to_owned(),
+            created_at: Local::now(),
+            settings: UserSettings::default(),
+            admin: false,
+        }
+    }
+}
+
+// Every key under one prefix put under another, in one batch. For moving a
+// user's data to a new id.
+pub fn move_prefix(tree: &Tree, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+    let mut batch = Batch::default();
+    let mut moved = 0;
+    for item in tree.scan_prefix(from.as_bytes()) {
+        let (key, value) = item?;
+        let mut moved_key = to.as_bytes().to_vec();
+        moved_key.extend_from_slice(&key[from.len()..]);
+        batch.insert(moved_key, value);
+        batch.remove(key);
+        moved += 1;
+    }
+    tree.apply_batch(batch)?;
+    tree.flush()?;
+    Ok(moved)
+}
+
+// The same for a single key, false if there was nothing under it
+pub fn move_key(tree: &Tree, from: &str, to: &str) -> Result<bool, Box<dyn Error>> {
+    let Some(value) = tree.get(from.as_bytes())? else {
+        return Ok(false);
+    };
+    let mut batch = Batch::default();
+    batch.insert(to.as_bytes(), value);
+    batch.remove(from.as_bytes());
+    tree.apply_batch(batch)?;
+    tree.flush()?;
+    Ok(true)
+}
+
+// Reddit users from before internal users, whose id is their reddit id
+// rather than a UUID
+pub fn is_legacy_id(user_id: &str) -> bool {
+    Uuid::parse_str(user_id).is_err()
+}
+
+// fixed, so every run of the migration picks the same ids
+const LEGACY_NAMESPACE: Uuid = Uuid::from_u128(0x6d2b_94c1_0f3e_4a57_b8d6_31e0_c5a9_7f42);
+
+// What a legacy user's UUID is, see api::users::migrate_legacy_users
+pub fn legacy_user_id(reddit_id: &str) -> String {
+    Uuid::new_v5(&LEGACY_NAMESPACE, reddit_id.as_bytes()).to_string()
+}
+
+#[derive(Clone, Debug)]
+pub struct UserStore {
+    pub db: Arc<Db>,
+}
+
+impl UserStore {
+    pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(UserStore {
+            db: Arc::new(db),
+        })
+    }
+
+    pub async fn get_user(&self, user_id: &str) -> Result<Option<User>, Box<dyn Error>> {
+        if let Some(data) = self.db.get(user_id.as_bytes())? {
+            Ok(Some(serde_json::from_slice(&data)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn save_user(&self, user: &User) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_vec(user)?;
+        self.db.insert(user.id.as_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // The existing user, or a new one under this id named after the sign-in
+    pub async fn ensure_user(&self, user_id: &str, display_name: &str) -> Result<User, Box<dyn Error>> {
+        if let Some(user) = self.get_user(user_id).await? {
+            return Ok(user);
+        }
+        let user = User::with_id(user_id, display_name);
+        self.save_user(&user).await?;
+        Ok(user)
+    }
+
+    // Every user id, for the legacy migration
+    pub async fn get_user_ids(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut ids = Vec::new();
+        for key in self.db.iter().keys() {
+            ids.push(String::from_utf8(key?.to_vec())?);
+        }
+        Ok(ids)
+    }
+
+    // legacy users getting their UUID, see api::users::migrate_legacy_users
+    pub async fn move_user(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let Some(mut user) = self.get_user(from).await? else {
+            return Ok(0);
+        };
+        user.id = to.to_owned();
+        self.save_user(&user).await?;
+        self.delete_user(from).await?;
+        Ok(1)
+    }
+
+    // false if there's no such user
+    pub async fn set_admin(&self, user_id: &str, admin: bool) -> Result<bool, Box<dyn Error>> {
+        let Some(mut user) = self.get_user(user_id).await? else {
+            return Ok(false);
+        };
+        if user.admin != admin {
+            user.admin = admin;
+            self.save_user(&user).await?;
+        }
+        Ok(true)
+    }
+
+    // account deletion
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        self.db.remove(user_id.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_db() -> Db {
+        sled::Config::new().temporary(true).open().unwrap()
+    }
+
+    #[test]
+    fn moves_a_users_keys() {
+        let db = temporary_db();
+        db.insert("t2_abc:2024-01-01", "a").unwrap();
+        db.insert("t2_abc:2024-01-02", "b").unwrap();
+        db.insert("t2_abcd:2024-01-01", "c").unwrap();
+        assert_eq!(move_prefix(&db, "t2_abc:", "new:").unwrap(), 2);
+        assert_eq!(db.get("new:2024-01-02").unwrap().unwrap(), "b".as_bytes());
+        assert!(db.get("t2_abc:2024-01-01").unwrap().is_none());
+        // someone else's id that only starts the same
+        assert!(db.get("t2_abcd:2024-01-01").unwrap().is_some());
+
+        assert!(move_key(&db, "t2_abcd:2024-01-01", "other").unwrap());
+        assert!(!move_key(&db, "missing", "other").unwrap());
+        assert_eq!(db.get("other").unwrap().unwrap(), "c".as_bytes());
+    }
+
+    #[test]
+    fn only_reddit_ids_are_legacy() {
+        assert!(is_legacy_id("t2_abc"));
+        assert!(!is_legacy_id(&Uuid::new_v4().to_string()));
+        assert!(!is_legacy_id(&legacy_user_id("t2_abc")));
+    }
+
+    #[test]
+    fn legacy_ids_always_map_the_same() {
+        assert_eq!(legacy_user_id("t2_abc"), legacy_user_id("t2_abc"));
+        assert_ne!(legacy_user_id("t2_abc"), legacy_user_id("t2_abd"));
+    }
+}
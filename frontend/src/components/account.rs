@@ -0,0 +1,440 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, Event, InputEvent, UseStateHandle};
+use gloo_net::http::Request;
+use serde::{Deserialize, Serialize};
+use web_sys::{console, HtmlInputElement, HtmlSelectElement};
+use crate::components::AddPasskey;
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct ExternalIdentity {
+    provider: String,
+    subject: String,
+    display_name: String,
+    email: Option<String>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct LinkedIdentity {
+    identity: ExternalIdentity,
+    linked_at: String,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct ProviderInfo {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+struct UserSettings {
+    units: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+struct UserDetails {
+    display_name: String,
+    settings: UserSettings,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct PasswordChange {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+fn on_text_input(state: &UseStateHandle<String>) -> Callback<InputEvent> {
+    let state = state.clone();
+    Callback::from(move |e: InputEvent| {
+        state.set(e.target_unchecked_into::<HtmlInputElement>().value());
+    })
+}
+
+fn fetch_identities(identities: UseStateHandle<Vec<LinkedIdentity>>, providers: UseStateHandle<Vec<ProviderInfo>>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        match Request::get("/api/identities")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<Vec<LinkedIdentity>>().await {
+                        Ok(data) => identities.set(data),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse sign-ins: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching sign-ins: {}", e).into());
+            }
+        }
+
+        match Request::get("/api/identity-providers")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<Vec<ProviderInfo>>().await {
+                        Ok(data) => providers.set(data),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse login providers: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching login providers: {}", e).into());
+            }
+        }
+    });
+}
+
+// The ways to sign in to this account, and adding or removing them
+#[function_component]
+pub fn Account() -> Html {
+    let identities = use_state(Vec::<LinkedIdentity>::new);
+    let providers = use_state(Vec::<ProviderInfo>::new);
+    let message = use_state(|| None::<String>);
+    let local_enabled = use_state(|| false);
+    let username = use_state(String::new);
+    let current_password = use_state(String::new);
+    let new_password = use_state(String::new);
+    let details = use_state(UserDetails::default);
+
+    {
+        let details = details.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/me")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                match response.json::<UserDetails>().await {
+                                    Ok(data) => details.set(data),
+                                    Err(e) => {
+                                        console::log_1(&format!("Failed to parse user: {}", e).into());
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching user: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    {
+        let local_enabled = local_enabled.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/local-accounts/enabled")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                local_enabled.set(response.json::<bool>().await.unwrap_or(false));
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error checking password logins: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    {
+        let identities = identities.clone();
+        let providers = providers.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                fetch_identities(identities, providers);
+                || ()
+            },
+        );
+    }
+
+    let on_name_input = {
+        let details = details.clone();
+        Callback::from(move |e: InputEvent| {
+            let mut updated = (*details).clone();
+            updated.display_name = e.target_unchecked_into::<HtmlInputElement>().value();
+            details.set(updated);
+        })
+    };
+
+    let on_units_change = {
+        let details = details.clone();
+        Callback::from(move |e: Event| {
+            let mut updated = (*details).clone();
+            updated.settings.units = e.target_unchecked_into::<HtmlSelectElement>().value();
+            details.set(updated);
+        })
+    };
+
+    let on_save_details = {
+        let details = details.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let update = (*details).clone();
+            let details = details.clone();
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::put("/api/me").json(&update) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build account update: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            if let Ok(saved) = response.json::<UserDetails>().await {
+                                details.set(saved);
+                            }
+                            message.set(Some("Saved".to_owned()));
+                        } else {
+                            message.set(Some(response.text().await.unwrap_or_else(|_| "Unable to save".to_owned())));
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error saving account: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_unlink = {
+        let identities = identities.clone();
+        let providers = providers.clone();
+        let message = message.clone();
+        Callback::from(move |identity: ExternalIdentity| {
+            let identities = identities.clone();
+            let providers = providers.clone();
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let url = format!("/api/identities/{}/{}", identity.provider, identity.subject);
+                match Request::delete(&url).send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            message.set(None);
+                            fetch_identities(identities, providers);
+                        } else {
+                            message.set(Some(response.text().await.unwrap_or_else(|_| "Unable to remove the sign-in".to_owned())));
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error removing sign-in: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_change_password = {
+        let current_password = current_password.clone();
+        let new_password = new_password.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let change = PasswordChange {
+                current_password: (*current_password).clone(),
+                new_password: (*new_password).clone(),
+            };
+            let current_password = current_password.clone();
+            let new_password = new_password.clone();
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::put("/api/local-accounts/password").json(&change) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build password change: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            current_password.set(String::new());
+                            new_password.set(String::new());
+                            message.set(Some("Password changed".to_owned()));
+                        } else {
+                            message.set(Some(response.text().await.unwrap_or_else(|_| "Unable to change the password".to_owned())));
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error changing password: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    // registering while signed in adds the password login to this account
+    let on_add_password = {
+        let username = username.clone();
+        let new_password = new_password.clone();
+        let identities = identities.clone();
+        let providers = providers.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let credentials = Credentials {
+                username: (*username).clone(),
+                password: (*new_password).clone(),
+            };
+            let new_password = new_password.clone();
+            let identities = identities.clone();
+            let providers = providers.clone();
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/local-accounts/register").json(&credentials) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build password login: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            new_password.set(String::new());
+                            message.set(None);
+                            fetch_identities(identities, providers);
+                        } else {
+                            message.set(Some(response.text().await.unwrap_or_else(|_| "Unable to add a password login".to_owned())));
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error adding password login: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_passkey_added = {
+        let identities = identities.clone();
+        let providers = providers.clone();
+        Callback::from(move |_| fetch_identities(identities.clone(), providers.clone()))
+    };
+
+    let has_password = identities.iter().any(|linked| linked.identity.provider == "local");
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"Account"}</h2>
+
+        <div class={classes!("meal-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Name"}</label>
+            <input type="text" class="input-field" value={details.display_name.clone()} oninput={on_name_input}/>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Units"}</label>
+            <select class={classes!("select-field")} onchange={on_units_change}>
+              <option value="metric" selected={details.settings.units != "imperial"}>{"METRIC"}</option>
+              <option value="imperial" selected={details.settings.units == "imperial"}>{"IMPERIAL"}</option>
+            </select>
+          </div>
+          <button class={classes!("submit-button")} onclick={on_save_details}>{"SAVE"}</button>
+        </div>
+
+        <h3 class={classes!("panel-subheader")}>{"Sign-ins"}</h3>
+
+        <table class={classes!("report-table")}>
+          <tr>
+            <th>{"Sign-in"}</th>
+            <th>{"As"}</th>
+            <th></th>
+          </tr>
+          { for identities.iter().map(|linked| {
+              let identity = linked.identity.clone();
+              let on_unlink = on_unlink.clone();
+              let provider_name = match identity.provider.as_str() {
+                  "local" => "Password".to_owned(),
+                  "passkey" => "Passkey".to_owned(),
+                  _ => providers.iter()
+                      .find(|provider| provider.id == identity.provider)
+                      .map(|provider| provider.name.clone())
+                      .unwrap_or_else(|| identity.provider.clone()),
+              };
+              html! {
+                <tr>
+                  <td>{provider_name}</td>
+                  <td>{identity.email.clone().unwrap_or_else(|| identity.display_name.clone())}</td>
+                  <td>
+                    if identities.len() > 1 {
+                      <button class={classes!("submit-button")} onclick={Callback::from(move |_| on_unlink.emit(identity.clone()))}>{"UNLINK"}</button>
+                    }
+                  </td>
+                </tr>
+              }
+          }) }
+        </table>
+
+        if let Some(message) = (*message).clone() {
+          <p>{message}</p>
+        }
+
+        // linking goes through the provider's login, then back to the dashboard
+        { for providers.iter()
+            .filter(|provider| !identities.iter().any(|linked| linked.identity.provider == provider.id))
+            .map(|provider| html! {
+              <a class={classes!("submit-button")} href={format!("/login/{}?link=true", provider.id)}>
+                {format!("LINK {}", provider.name.to_uppercase())}
+              </a>
+          }) }
+        <AddPasskey on_added={on_passkey_added}/>
+
+        if has_password {
+          <h3 class={classes!("panel-subheader")}>{"Change Password"}</h3>
+          <div class={classes!("meal-form")}>
+            <div class={classes!("input-group")}>
+              <label class={classes!("input-label")}>{"Current password"}</label>
+              <input type="password" class="input-field" value={(*current_password).clone()} oninput={on_text_input(&current_password)}/>
+            </div>
+            <div class={classes!("input-group")}>
+              <label class={classes!("input-label")}>{"New password"}</label>
+              <input type="password" class="input-field" value={(*new_password).clone()} oninput={on_text_input(&new_password)}/>
+            </div>
+            <button class={classes!("submit-button")} onclick={on_change_password}>{"CHANGE PASSWORD"}</button>
+          </div>
+        } else if *local_enabled {
+          <h3 class={classes!("panel-subheader")}>{"Add a Password Login"}</h3>
+          <div class={classes!("meal-form")}>
+            <div class={classes!("input-group")}>
+              <label class={classes!("input-label")}>{"Username"}</label>
+              <input type="text" class="input-field" value={(*username).clone()} oninput={on_text_input(&username)}/>
+            </div>
+            <div class={classes!("input-group")}>
+              <label class={classes!("input-label")}>{"Password"}</label>
+              <input type="password" class="input-field" value={(*new_password).clone()} oninput={on_text_input(&new_password)}/>
+            </div>
+            <button class={classes!("submit-button")} onclick={on_add_password}>{"ADD PASSWORD"}</button>
+          </div>
+        }
+      </section>
+    }
+}
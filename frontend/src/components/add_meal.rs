@@ -1,89 +1,723 @@
-use yew::{function_component, classes, html, Html};
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, Event, InputEvent};
+use gloo_net::http::Request;
+use serde::{Deserialize, Serialize};
+use web_sys::{console, HtmlInputElement, HtmlSelectElement};
+
+use crate::components::CustomFoodForm;
+
+// value of the food select that opens the custom food form
+const CUSTOM_OPTION: &str = "custom";
+
+// (category, icon, label) in the order the buttons are shown
+const CATEGORIES: [(&str, &str, &str); 8] = [
+    ("beef", "🥩", "Beef"),
+    ("poultry", "🐓", "Poultry"),
+    ("fish", "🐟", "Fish"),
+    ("eggs", "🥚", "Eggs"),
+    ("pork", "🥓", "Pork"),
+    ("dairy", "🧀", "Dairy"),
+    ("fruits", "🍎", "Fruits"),
+    ("honey", "🍯", "Honey"),
+];
+
+// (profile, label)
+const PROFILES: [(&str, &str); 5] = [
+    ("strict_carnivore", "Strict Carnivore"),
+    ("animal_based", "Animal-Based"),
+    ("ketovore", "Ketovore"),
+    ("lion", "Lion Diet"),
+    ("custom", "Custom"),
+];
+
+// (sourcing, label, categories it applies to)
+const SOURCING: [(&str, &str, &[&str]); 8] = [
+    ("grass_fed", "Grass-fed", &["beef", "dairy"]),
+    ("grain_fed", "Grain-fed", &["beef", "dairy"]),
+    ("pasture_raised", "Pasture-raised", &["poultry", "eggs", "pork"]),
+    ("conventional", "Conventional", &["poultry", "eggs", "pork"]),
+    ("wild_caught", "Wild-caught", &["fish"]),
+    ("farmed", "Farmed", &["fish"]),
+    ("raw", "Raw", &["dairy"]),
+    ("pasteurized", "Pasteurized", &["dairy"]),
+];
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct Food {
+    pub id: String,
+    pub name: String,
+    pub category: String,
+    pub protein_g: f64,
+    pub fat_g: f64,
+    pub carbs_g: f64,
+    pub calories: f64,
+    #[serde(default)]
+    pub default_sourcing: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+struct UserProfile {
+    diet: String,
+    #[serde(default)]
+    custom_allowed: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct ProfileResponse {
+    profile: UserProfile,
+    allowed_categories: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct NewMealEntry {
+    food_id: String,
+    grams: f64,
+    sourcing: Vec<String>,
+}
+
+// no sourcing, so the server uses the food's default
+#[derive(Serialize)]
+struct ParsedEntry {
+    food_id: String,
+    grams: f64,
+}
+
+#[derive(Serialize)]
+struct MealText {
+    text: String,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct FoodMatch {
+    food_id: String,
+    food_name: String,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct ParsedItem {
+    text: String,
+    grams: Option<f64>,
+    food: Option<FoodMatch>,
+    alternatives: Vec<FoodMatch>,
+    confidence: f64,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct SearchHit {
+    food: Food,
+    matched_alias: Option<String>,
+}
+
+// Percent-encodes a search term for the query string
+fn encode_query(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// below this the parsed line is flagged for a second look
+const SURE_CONFIDENCE: f64 = 0.7;
 
 #[function_component]
 pub fn AddMeal() -> Html {
+    let foods = use_state(Vec::<Food>::new);
+    let category = use_state(|| "beef".to_owned());
+    let food_id = use_state(|| None::<String>);
+    let grams = use_state(String::new);
+    let profile = use_state(|| None::<ProfileResponse>);
+    // None until the user touches it, then the food's default no longer applies
+    let sourcing = use_state(|| None::<Vec<String>>);
+    let barcode = use_state(String::new);
+    let barcode_missing = use_state(|| false);
+    let meal_text = use_state(String::new);
+    let search_text = use_state(String::new);
+    let search_hits = use_state(Vec::<SearchHit>::new);
+    let parsed = use_state(Vec::<ParsedItem>::new);
+
+    // Load the food catalogue and diet profile once
+    {
+        let foods = foods.clone();
+        let profile = profile.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/profile")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                match response.json::<ProfileResponse>().await {
+                                    Ok(data) => profile.set(Some(data)),
+                                    Err(e) => {
+                                        console::log_1(&format!("Failed to parse profile: {}", e).into());
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching profile: {}", e).into());
+                        }
+                    }
+
+                    match Request::get("/api/foods")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                match response.json::<Vec<Food>>().await {
+                                    Ok(data) => foods.set(data),
+                                    Err(e) => {
+                                        console::log_1(&format!("Failed to parse foods: {}", e).into());
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching foods: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    let visible: Vec<Food> = foods.iter()
+        .filter(|food| food.category == *category)
+        .cloned()
+        .collect();
+    // fall back to the first food of the category until one is picked
+    let selected: Option<Food> = (*food_id).as_ref()
+        .and_then(|id| visible.iter().find(|food| &food.id == id))
+        .or_else(|| visible.first())
+        .cloned();
+    let adding_custom = (*food_id).as_deref() == Some(CUSTOM_OPTION);
+    let current_sourcing: Vec<String> = (*sourcing).clone()
+        .or_else(|| selected.as_ref().map(|food| food.default_sourcing.clone()))
+        .unwrap_or_default();
+    let amount = grams.parse::<f64>().unwrap_or(0.0);
+    let scale = amount / 100.0;
+    // everything is on plan until we know the profile
+    let is_allowed = |category: &str| {
+        (*profile).as_ref()
+            .map(|p| p.allowed_categories.iter().any(|allowed| allowed == category))
+            .unwrap_or(true)
+    };
+    let current_diet = (*profile).as_ref().map(|p| p.profile.diet.clone()).unwrap_or_default();
+
+    let on_profile_change = {
+        let profile = profile.clone();
+        Callback::from(move |e: Event| {
+            let update = UserProfile {
+                diet: e.target_unchecked_into::<HtmlSelectElement>().value(),
+                custom_allowed: (*profile).as_ref().map(|p| p.profile.custom_allowed.clone()).unwrap_or_default(),
+            };
+            let profile = profile.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::put("/api/profile?apply_defaults=true").json(&update) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build profile request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<ProfileResponse>().await {
+                                Ok(data) => profile.set(Some(data)),
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse profile: {}", e).into());
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error updating profile: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_food_change = {
+        let food_id = food_id.clone();
+        let sourcing = sourcing.clone();
+        Callback::from(move |e: Event| {
+            food_id.set(Some(e.target_unchecked_into::<HtmlSelectElement>().value()));
+            sourcing.set(None);
+        })
+    };
+
+    let on_custom_saved = {
+        let foods = foods.clone();
+        let food_id = food_id.clone();
+        Callback::from(move |food: Food| {
+            let mut updated = (*foods).clone();
+            food_id.set(Some(food.id.clone()));
+            updated.push(food);
+            foods.set(updated);
+        })
+    };
+
+    let on_barcode_input = {
+        let barcode = barcode.clone();
+        Callback::from(move |e: InputEvent| {
+            barcode.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    // Packaged foods come from the barcode index and get selected like any other
+    let on_barcode_click = {
+        let foods = foods.clone();
+        let category = category.clone();
+        let food_id = food_id.clone();
+        let sourcing = sourcing.clone();
+        let barcode = barcode.clone();
+        let barcode_missing = barcode_missing.clone();
+        Callback::from(move |_| {
+            let code = barcode.trim().to_owned();
+            if code.is_empty() {
+                return;
+            }
+            let foods = foods.clone();
+            let category = category.clone();
+            let food_id = food_id.clone();
+            let sourcing = sourcing.clone();
+            let barcode_missing = barcode_missing.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Request::get(&format!("/api/foods/barcode/{}", code))
+                    .send()
+                    .await
+                {
+                    Ok(response) => {
+                        if response.status() != 200 {
+                            barcode_missing.set(true);
+                            return;
+                        }
+                        match response.json::<Food>().await {
+                            Ok(food) => {
+                                barcode_missing.set(false);
+                                let mut updated = (*foods).clone();
+                                if !updated.iter().any(|known| known.id == food.id) {
+                                    updated.push(food.clone());
+                                }
+                                foods.set(updated);
+                                category.set(food.category.clone());
+                                food_id.set(Some(food.id));
+                                sourcing.set(None);
+                            }
+                            Err(e) => {
+                                console::log_1(&format!("Failed to parse barcode food: {}", e).into());
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error looking up barcode: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_grams_input = {
+        let grams = grams.clone();
+        Callback::from(move |e: InputEvent| {
+            grams.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_add_click = {
+        let grams = grams.clone();
+        let selected = selected.clone();
+        let current_sourcing = current_sourcing.clone();
+        Callback::from(move |_| {
+            let Some(food) = selected.clone() else {
+                return;
+            };
+            if amount <= 0.0 || adding_custom {
+                return;
+            }
+            let entry = NewMealEntry {
+                food_id: food.id,
+                grams: amount,
+                sourcing: current_sourcing.clone(),
+            };
+            let grams = grams.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/meals").json(&entry) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build meal request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            grams.set(String::new());
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error adding meal: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_search_input = {
+        let search_text = search_text.clone();
+        let search_hits = search_hits.clone();
+        Callback::from(move |e: InputEvent| {
+            let text = e.target_unchecked_into::<HtmlInputElement>().value();
+            search_text.set(text.clone());
+            if text.trim().is_empty() {
+                search_hits.set(Vec::new());
+                return;
+            }
+            let search_hits = search_hits.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Request::get(&format!("/api/foods/search?q={}", encode_query(&text)))
+                    .send()
+                    .await
+                {
+                    Ok(response) => {
+                        if response.status() == 200 {
+                            match response.json::<Vec<SearchHit>>().await {
+                                Ok(data) => search_hits.set(data),
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse food search: {}", e).into());
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error searching foods: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_meal_text_input = {
+        let meal_text = meal_text.clone();
+        Callback::from(move |e: InputEvent| {
+            meal_text.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_parse_click = {
+        let meal_text = meal_text.clone();
+        let parsed = parsed.clone();
+        Callback::from(move |_| {
+            if meal_text.trim().is_empty() {
+                return;
+            }
+            let body = MealText { text: (*meal_text).clone() };
+            let parsed = parsed.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/meals/parse").json(&body) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build parse request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<Vec<ParsedItem>>().await {
+                                Ok(data) => parsed.set(data),
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse meal text response: {}", e).into());
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error parsing meal text: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    // Logs every reviewed line that has a food and an amount
+    let on_log_parsed_click = {
+        let meal_text = meal_text.clone();
+        let parsed = parsed.clone();
+        Callback::from(move |_| {
+            let entries: Vec<ParsedEntry> = parsed.iter()
+                .filter_map(|item| Some(ParsedEntry {
+                    food_id: item.food.as_ref()?.food_id.clone(),
+                    grams: item.grams.filter(|grams| *grams > 0.0)?,
+                }))
+                .collect();
+            if entries.is_empty() {
+                return;
+            }
+            let meal_text = meal_text.clone();
+            let parsed = parsed.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let mut logged = 0;
+                for entry in &entries {
+                    let request = match Request::post("/api/meals").json(entry) {
+                        Ok(request) => request,
+                        Err(e) => {
+                            console::log_1(&format!("Failed to build meal request: {}", e).into());
+                            continue;
+                        }
+                    };
+                    match request.send().await {
+                        Ok(response) => {
+                            if response.ok() {
+                                logged += 1;
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error adding meal: {}", e).into());
+                        }
+                    }
+                }
+                if logged == entries.len() {
+                    meal_text.set(String::new());
+                    parsed.set(Vec::new());
+                }
+            });
+        })
+    };
+
     html! {
       <section class={classes!("panel")}>
         <h2 class={classes!("panel-header")}>{"Add Meal"}</h2>
+        <div class={classes!("input-group")}>
+          <label class={classes!("input-label")}>{"Quick Add"}</label>
+          <input type="text" class="input-field" placeholder="300g ribeye, 3 eggs and a tbsp of honey" value={(*meal_text).clone()} oninput={on_meal_text_input}/>
+          <button class={classes!("sourcing-tag")} onclick={on_parse_click}>{"PARSE"}</button>
+        </div>
+        if !parsed.is_empty() {
+          <table class={classes!("report-table")}>
+            <tr>
+              <th>{"You wrote"}</th>
+              <th>{"Food"}</th>
+              <th>{"Grams"}</th>
+            </tr>
+            { for parsed.iter().enumerate().map(|(index, item)| {
+                let on_choice = {
+                    let parsed = parsed.clone();
+                    Callback::from(move |e: Event| {
+                        let chosen = e.target_unchecked_into::<HtmlSelectElement>().value();
+                        let mut updated = (*parsed).clone();
+                        let item = &mut updated[index];
+                        // the old pick becomes an alternative so it can be chosen again
+                        let (picked, others): (Vec<FoodMatch>, Vec<FoodMatch>) = item.food.iter()
+                            .chain(item.alternatives.iter())
+                            .cloned()
+                            .partition(|option| option.food_id == chosen);
+                        item.food = picked.into_iter().next();
+                        item.alternatives = others;
+                        parsed.set(updated);
+                    })
+                };
+                let on_item_grams = {
+                    let parsed = parsed.clone();
+                    Callback::from(move |e: InputEvent| {
+                        let mut updated = (*parsed).clone();
+                        updated[index].grams = e.target_unchecked_into::<HtmlInputElement>().value().parse::<f64>().ok();
+                        parsed.set(updated);
+                    })
+                };
+                let chosen_id = item.food.as_ref().map(|food| food.food_id.clone()).unwrap_or_default();
+                html! {
+                  <tr class={classes!((item.confidence < SURE_CONFIDENCE).then_some("running-low"))}>
+                    <td>{item.text.clone()}</td>
+                    <td>
+                      <select class={classes!("select-field")} onchange={on_choice}>
+                        if item.food.is_none() {
+                          <option value="" selected=true>{"(no match)"}</option>
+                        }
+                        { for item.food.iter().chain(item.alternatives.iter()).map(|option| html! {
+                            <option value={option.food_id.clone()} selected={option.food_id == chosen_id}>{option.food_name.clone()}</option>
+                        }) }
+                      </select>
+                    </td>
+                    <td>
+                      <input type="number" class="input-field" value={item.grams.map(|grams| format!("{:.0}", grams)).unwrap_or_default()} oninput={on_item_grams}/>
+                    </td>
+                  </tr>
+                }
+            }) }
+          </table>
+          <button class={classes!("submit-button")} onclick={on_log_parsed_click}>{"LOG ALL"}</button>
+        }
+        <div class={classes!("input-group")}>
+          <label class={classes!("input-label")}>{"Diet Profile"}</label>
+          <select class={classes!("select-field")} onchange={on_profile_change}>
+            { for PROFILES.iter().map(|(key, label)| html! {
+                <option value={key.to_string()} selected={current_diet == *key}>{*label}</option>
+            }) }
+          </select>
+        </div>
         <div class={classes!("food-selector")}>
             <label class={classes!("selector-label")}>{"Select Food Type"}</label>
             <div class={classes!("food-buttons")}>
-              <button class={classes!("food-button", "active")}>
-                <span class={classes!("food-icon")}>{"🥩"}</span>
-                <span>{"Beef"}</span>
-              </button>
-              <button class={classes!("food-button")}>
-                <span class={classes!("food-icon")}>{"🐓"}</span>
-                <span>{"Poultry"}</span>
-              </button>
-              <button class={classes!("food-button")}>
-                <span class={classes!("food-icon")}>{"🐟"}</span>
-                <span>{"Fish"}</span>
-              </button>
-              <button class={classes!("food-button")}>
-                <span class={classes!("food-icon")}>{"🥚"}</span>
-                <span>{"Eggs"}</span>
-              </button>
-              <button class={classes!("food-button")}>
-                <span class={classes!("food-icon")}>{"🥓"}</span>
-                <span>{"Pork"}</span>
-              </button>
-              <button class={classes!("food-button")}>
-                <span class={classes!("food-icon")}>{"🧀"}</span>
-                <span>{"Dairy"}</span>
-              </button>
-              <button class={classes!("food-button")}>
-                <span class={classes!("food-icon")}>{"🍎"}</span>
-                <span>{"Fruits"}</span>
-              </button>
-              <button class={classes!("food-button")}>
-                <span class={classes!("food-icon")}>{"🍯"}</span>
-                <span>{"Honey"}</span>
-              </button>
+              { for CATEGORIES.iter().map(|(key, icon, label)| {
+                  let onclick = {
+                      let category = category.clone();
+                      let food_id = food_id.clone();
+                      let key = key.to_string();
+                      let sourcing = sourcing.clone();
+                      Callback::from(move |_| {
+                          category.set(key.clone());
+                          food_id.set(None);
+                          sourcing.set(None);
+                      })
+                  };
+                  let off_plan = !is_allowed(key);
+                  html! {
+                    <button class={classes!("food-button", (*category == *key).then_some("active"), off_plan.then_some("off-plan"))} {onclick}>
+                      <span class={classes!("food-icon")}>{*icon}</span>
+                      <span>{*label}</span>
+                      if off_plan {
+                        <span class={classes!("off-plan-tag")}>{"OFF PLAN"}</span>
+                      }
+                    </button>
+                  }
+              }) }
             </div>
           </div>
-          
+
           <div class={classes!("meal-form")}>
+            <div class={classes!("input-group")}>
+              <label class={classes!("input-label")}>{"Barcode"}</label>
+              <input type="text" inputmode="numeric" class="input-field" placeholder="EAN / UPC" value={(*barcode).clone()} oninput={on_barcode_input}/>
+              <button class={classes!("sourcing-tag")} onclick={on_barcode_click}>{"LOOKUP"}</button>
+            </div>
+            if *barcode_missing {
+              <div class={classes!("off-plan-warning")}>{"No product found for that barcode"}</div>
+            }
+
+            <div class={classes!("input-group", "food-search")}>
+              <label class={classes!("input-label")}>{"Search"}</label>
+              <input type="text" role="combobox" aria-expanded={(!search_hits.is_empty()).to_string()} class="input-field" placeholder="mince, ribeye, eggs..." value={(*search_text).clone()} oninput={on_search_input}/>
+              if !search_hits.is_empty() {
+                <ul class={classes!("search-results")} role="listbox">
+                  { for search_hits.iter().map(|hit| {
+                      // picks the food like the category buttons and select would
+                      let onclick = {
+                          let foods = foods.clone();
+                          let category = category.clone();
+                          let food_id = food_id.clone();
+                          let sourcing = sourcing.clone();
+                          let search_text = search_text.clone();
+                          let search_hits = search_hits.clone();
+                          let food = hit.food.clone();
+                          Callback::from(move |_| {
+                              let mut updated = (*foods).clone();
+                              if !updated.iter().any(|known| known.id == food.id) {
+                                  updated.push(food.clone());
+                              }
+                              foods.set(updated);
+                              category.set(food.category.clone());
+                              food_id.set(Some(food.id.clone()));
+                              sourcing.set(None);
+                              search_text.set(String::new());
+                              search_hits.set(Vec::new());
+                          })
+                      };
+                      html! {
+                        <li role="option" {onclick}>
+                          {hit.food.name.clone()}
+                          if let Some(alias) = hit.matched_alias.clone() {
+                            <span class={classes!("search-alias")}>{format!(" ({})", alias)}</span>
+                          }
+                        </li>
+                      }
+                  }) }
+                </ul>
+              }
+            </div>
+
             <div class={classes!("input-group")}>
               <label class={classes!("input-label")}>
                 <span class={classes!("animal-icon")}>{"🍖"}</span>
                 {"Food"}
               </label>
-              <select class={classes!("select-field")}>
-                <option>{"Ribeye Steak"} </option>
-                <option>{"Ground Beef"}</option>
-                <option>{"Beef Liver"}</option>
-                <option>{"Brisket"}</option>
-                <option>{"Custom..."}</option>
+              <select class={classes!("select-field")} onchange={on_food_change}>
+                { for visible.iter().map(|food| html! {
+                    <option value={food.id.clone()} selected={!adding_custom && selected.as_ref().map(|s| s.id == food.id).unwrap_or(false)}>
+                      {food.name.clone()}
+                    </option>
+                }) }
+                <option value={CUSTOM_OPTION} selected={adding_custom}>{"Custom..."}</option>
               </select>
             </div>
-          
+
+            if adding_custom {
+              <CustomFoodForm category={(*category).clone()} on_saved={on_custom_saved}/>
+            }
+
+            <div class={classes!("sourcing-tags")}>
+              { for SOURCING.iter().filter(|(_, _, categories)| categories.contains(&category.as_str())).map(|(key, label, _)| {
+                  let active = current_sourcing.iter().any(|tag| tag == key);
+                  let onclick = {
+                      let sourcing = sourcing.clone();
+                      let current_sourcing = current_sourcing.clone();
+                      let key = key.to_string();
+                      Callback::from(move |_| {
+                          let mut tags: Vec<String> = current_sourcing.iter().filter(|tag| **tag != key).cloned().collect();
+                          if !active {
+                              tags.push(key.clone());
+                          }
+                          sourcing.set(Some(tags));
+                      })
+                  };
+                  html! {
+                    <button class={classes!("sourcing-tag", active.then_some("active"))} {onclick}>{*label}</button>
+                  }
+              }) }
+            </div>
+
             <div class={classes!("input-group")}>
               <label class={classes!("input-label")}>
                 <span class={classes!("animal-icon")}>{"⚖️"}</span>
                   {"Amount (g)"}
                 </label>
-                <input type="number" class="input-field" placeholder="0"/> 
+                <input type="number" class="input-field" placeholder="0" value={(*grams).clone()} oninput={on_grams_input}/>
             </div>
-          
+
+            if !is_allowed(&category) {
+              <div class={classes!("off-plan-warning")}>{"⚠️ Not on your diet profile - this will lower your Animal Score"}</div>
+            }
+
             <div class={classes!("macro-preview")}>
               <div class={classes!("macro-preview-item")}>
                 <span class={classes!("preview-label")}>{"Protein"}</span>
-                <span class={classes!("preview-value")}>{"45g"}</span>
+                <span class={classes!("preview-value")}>{format!("{:.0}g", selected.as_ref().map(|f| f.protein_g * scale).unwrap_or(0.0))}</span>
               </div>
               <div class={classes!("macro-preview-item")}>
                 <span class={classes!("preview-label")}>{"Fat"}</span>
-                <span class={classes!("preview-value")}>{"38g"}</span>
+                <span class={classes!("preview-value")}>{format!("{:.0}g", selected.as_ref().map(|f| f.fat_g * scale).unwrap_or(0.0))}</span>
+              </div>
+              <div class={classes!("macro-preview-item")}>
+                <span class={classes!("preview-label")}>{"Carbs"}</span>
+                <span class={classes!("preview-value")}>{format!("{:.0}g", selected.as_ref().map(|f| f.carbs_g * scale).unwrap_or(0.0))}</span>
               </div>
               <div class={classes!("macro-preview-item")}>
                 <span class={classes!("preview-label")}>{"Calories"}</span>
-                <span class={classes!("preview-value")}>{"530"}</span>
+                <span class={classes!("preview-value")}>{format!("{:.0}", selected.as_ref().map(|f| f.calories * scale).unwrap_or(0.0))}</span>
               </div>
             </div>
           </div>
-          
-          <button class={classes!("submit-button")}>{"ADD FOOD"}</button>
-        
+
+          <button class={classes!("submit-button")} onclick={on_add_click}>{"ADD FOOD"}</button>
+
         <div class={classes!("animal-container")}>
           <div class={classes!("animal-graphic")} id="animal1">{"🐄"}</div>
           <div class={classes!("animal-graphic")} id="animal2">{"🐓"}</div>
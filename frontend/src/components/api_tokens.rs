@@ -0,0 +1,244 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, Event, InputEvent, UseStateHandle};
+use gloo_net::http::Request;
+use serde::{Deserialize, Serialize};
+use web_sys::{console, HtmlInputElement, HtmlSelectElement};
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct ApiToken {
+    id: String,
+    name: String,
+    scopes: Vec<String>,
+    created_at: String,
+    expires_at: String,
+    last_used_at: Option<String>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct NewToken {
+    name: String,
+    scopes: Vec<String>,
+    expires_in_days: i64,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct IssuedToken {
+    token: String,
+}
+
+const SCOPES: [(&str, &str); 3] = [("read", "Read"), ("write:meals", "Log meals"), ("admin", "Admin")];
+
+fn fetch_tokens(tokens: UseStateHandle<Vec<ApiToken>>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        match Request::get("/api/tokens")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<Vec<ApiToken>>().await {
+                        Ok(data) => tokens.set(data),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse API tokens: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching API tokens: {}", e).into());
+            }
+        }
+    });
+}
+
+// dates only, the times are in the tooltip
+fn day(timestamp: &str) -> String {
+    timestamp.get(..10).unwrap_or(timestamp).to_owned()
+}
+
+// Tokens for scripts and home automation, sent as Authorization: Bearer
+#[function_component]
+pub fn ApiTokens() -> Html {
+    let tokens = use_state(Vec::<ApiToken>::new);
+    let name = use_state(String::new);
+    let scopes = use_state(|| vec!["read".to_owned()]);
+    let days = use_state(|| 90i64);
+    // shown once, right after it's made
+    let issued = use_state(|| None::<String>);
+    let message = use_state(|| None::<String>);
+
+    {
+        let tokens = tokens.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                fetch_tokens(tokens);
+                || ()
+            },
+        );
+    }
+
+    let on_name_input = {
+        let name = name.clone();
+        Callback::from(move |e: InputEvent| {
+            name.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_days_change = {
+        let days = days.clone();
+        Callback::from(move |e: Event| {
+            if let Ok(value) = e.target_unchecked_into::<HtmlSelectElement>().value().parse() {
+                days.set(value);
+            }
+        })
+    };
+
+    let on_create_click = {
+        let tokens = tokens.clone();
+        let name = name.clone();
+        let scopes = scopes.clone();
+        let days = days.clone();
+        let issued = issued.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let tokens = tokens.clone();
+            let name = name.clone();
+            let issued = issued.clone();
+            let message = message.clone();
+            let new_token = NewToken {
+                name: (*name).clone(),
+                scopes: (*scopes).clone(),
+                expires_in_days: *days,
+            };
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/tokens").json(&new_token) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build API token request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<IssuedToken>().await {
+                                Ok(data) => {
+                                    issued.set(Some(data.token));
+                                    message.set(Some("Copy the token now, it won't be shown again".to_owned()));
+                                    name.set(String::new());
+                                    fetch_tokens(tokens);
+                                },
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse API token: {}", e).into());
+                                }
+                            }
+                        } else {
+                            issued.set(None);
+                            message.set(response.text().await.ok());
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error creating API token: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_delete = {
+        let tokens = tokens.clone();
+        Callback::from(move |id: String| {
+            let tokens = tokens.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Request::delete(&format!("/api/tokens/{}", id)).send().await {
+                    Ok(response) => {
+                        if !response.ok() {
+                            console::log_1(&format!("Unable to delete API token: {}", response.status()).into());
+                        }
+                        fetch_tokens(tokens);
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error deleting API token: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"API Tokens"}</h2>
+
+        if !tokens.is_empty() {
+          <table class={classes!("report-table")}>
+            <tr>
+              <th>{"Name"}</th>
+              <th>{"Scopes"}</th>
+              <th>{"Expires"}</th>
+              <th>{"Last used"}</th>
+              <th></th>
+            </tr>
+            { for tokens.iter().map(|token| {
+                let on_delete = on_delete.clone();
+                let id = token.id.clone();
+                html! {
+                  <tr>
+                    <td title={token.created_at.clone()}>{token.name.clone()}</td>
+                    <td>{token.scopes.join(", ")}</td>
+                    <td title={token.expires_at.clone()}>{day(&token.expires_at)}</td>
+                    <td title={token.last_used_at.clone().unwrap_or_default()}>
+                      {token.last_used_at.as_deref().map(day).unwrap_or_else(|| "never".to_owned())}
+                    </td>
+                    <td>
+                      <button class={classes!("submit-button")} onclick={Callback::from(move |_| on_delete.emit(id.clone()))}>{"DELETE"}</button>
+                    </td>
+                  </tr>
+                }
+            }) }
+          </table>
+        }
+
+        <h3 class={classes!("panel-subheader")}>{"New token"}</h3>
+        <div class={classes!("meal-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Name"}</label>
+            <input type="text" class="input-field" placeholder="kitchen scale" value={(*name).clone()} oninput={on_name_input}/>
+          </div>
+          { for SCOPES.iter().map(|(scope, label)| {
+              let scopes = scopes.clone();
+              let scope = scope.to_string();
+              let checked = scopes.contains(&scope);
+              html! {
+                <div class={classes!("input-group")}>
+                  <label class={classes!("input-label")}>{*label}</label>
+                  <input type="checkbox" checked={checked} onchange={Callback::from(move |e: Event| {
+                      let mut updated = (*scopes).clone();
+                      updated.retain(|existing| *existing != scope);
+                      if e.target_unchecked_into::<HtmlInputElement>().checked() {
+                          updated.push(scope.clone());
+                      }
+                      scopes.set(updated);
+                  })}/>
+                </div>
+              }
+          }) }
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Expires in"}</label>
+            <select class={classes!("select-field")} onchange={on_days_change}>
+              <option value="30" selected={*days == 30}>{"30 DAYS"}</option>
+              <option value="90" selected={*days == 90}>{"90 DAYS"}</option>
+              <option value="365" selected={*days == 365}>{"A YEAR"}</option>
+            </select>
+          </div>
+        </div>
+        <button class={classes!("submit-button")} onclick={on_create_click}>{"CREATE TOKEN"}</button>
+
+        if let Some(text) = (*message).clone() {
+          <p class={classes!("plan-summary")}>{text}</p>
+        }
+        if let Some(token) = (*issued).clone() {
+          <pre class={classes!("share-preview")}>{token}</pre>
+        }
+      </section>
+    }
+}
@@ -0,0 +1,235 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, InputEvent, UseStateHandle};
+use gloo_net::http::Request;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use web_sys::{console, HtmlInputElement};
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct Trend {
+    latest_weight_kg: Option<f64>,
+    trend_weight_kg: Option<f64>,
+    weekly_rate_kg: Option<f64>,
+    latest_body_fat_pct: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct NewBodyMetric {
+    weight_kg: Option<f64>,
+    body_fat_pct: Option<f64>,
+    waist_cm: Option<f64>,
+    // one named measurement at a time, chest, hips, arm...
+    measurements: HashMap<String, f64>,
+}
+
+fn fetch_trend(trend: UseStateHandle<Option<Trend>>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        match Request::get("/api/body/trend")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<Trend>().await {
+                        Ok(data) => trend.set(Some(data)),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse body trend: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching body trend: {}", e).into());
+            }
+        }
+    });
+}
+
+fn format_kg(value: Option<f64>) -> String {
+    match value {
+        Some(kg) => format!("{:.1}kg", kg),
+        None => "--".to_owned(),
+    }
+}
+
+#[function_component]
+pub fn BodyTrend() -> Html {
+    let trend = use_state(|| None::<Trend>);
+    let weight = use_state(String::new);
+    let body_fat = use_state(String::new);
+    let waist = use_state(String::new);
+    let measurement_name = use_state(String::new);
+    let measurement_value = use_state(String::new);
+
+    {
+        let trend = trend.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                fetch_trend(trend);
+                || ()
+            },
+        );
+    }
+
+    let on_weight_input = {
+        let weight = weight.clone();
+        Callback::from(move |e: InputEvent| {
+            weight.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_body_fat_input = {
+        let body_fat = body_fat.clone();
+        Callback::from(move |e: InputEvent| {
+            body_fat.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_waist_input = {
+        let waist = waist.clone();
+        Callback::from(move |e: InputEvent| {
+            waist.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_measurement_name_input = {
+        let measurement_name = measurement_name.clone();
+        Callback::from(move |e: InputEvent| {
+            measurement_name.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_measurement_value_input = {
+        let measurement_value = measurement_value.clone();
+        Callback::from(move |e: InputEvent| {
+            measurement_value.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_log_click = {
+        let trend = trend.clone();
+        let weight = weight.clone();
+        let body_fat = body_fat.clone();
+        let waist = waist.clone();
+        let measurement_name = measurement_name.clone();
+        let measurement_value = measurement_value.clone();
+        Callback::from(move |_| {
+            let mut measurements = HashMap::new();
+            if let (false, Ok(value)) = (measurement_name.trim().is_empty(), measurement_value.parse::<f64>()) {
+                measurements.insert(measurement_name.trim().to_lowercase(), value);
+            }
+            let metric = NewBodyMetric {
+                weight_kg: weight.parse::<f64>().ok(),
+                body_fat_pct: body_fat.parse::<f64>().ok(),
+                waist_cm: waist.parse::<f64>().ok(),
+                measurements,
+            };
+            if metric.weight_kg.is_none() && metric.body_fat_pct.is_none() && metric.waist_cm.is_none() && metric.measurements.is_empty() {
+                return;
+            }
+            let trend = trend.clone();
+            let weight = weight.clone();
+            let body_fat = body_fat.clone();
+            let waist = waist.clone();
+            let measurement_name = measurement_name.clone();
+            let measurement_value = measurement_value.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/body").json(&metric) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build body metric request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            weight.set(String::new());
+                            body_fat.set(String::new());
+                            waist.set(String::new());
+                            measurement_name.set(String::new());
+                            measurement_value.set(String::new());
+                            fetch_trend(trend);
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error logging body metric: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let current = (*trend).clone();
+    let rate = match current.as_ref().and_then(|t| t.weekly_rate_kg) {
+        Some(rate) => format!("{:+.2}kg/wk", rate),
+        None => "--".to_owned(),
+    };
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"Weight Trend"}</h2>
+
+        <div class={classes!("macro-display")}>
+          <div class={classes!("macro-card")}>
+            <div class={classes!("macro-title")}>{"Trend"}</div>
+            <div class={classes!("macro-value")}>{format_kg(current.as_ref().and_then(|t| t.trend_weight_kg))}</div>
+          </div>
+
+          <div class={classes!("macro-card")}>
+            <div class={classes!("macro-title")}>{"Weekly Rate"}</div>
+            <div class={classes!("macro-value")}>{rate}</div>
+          </div>
+
+          <div class={classes!("macro-card")}>
+            <div class={classes!("macro-title")}>{"Last Weigh-In"}</div>
+            <div class={classes!("macro-value")}>{format_kg(current.as_ref().and_then(|t| t.latest_weight_kg))}</div>
+          </div>
+
+          <div class={classes!("macro-card")}>
+            <div class={classes!("macro-title")}>{"Body Fat"}</div>
+            <div class={classes!("macro-value")}>
+              {match current.as_ref().and_then(|t| t.latest_body_fat_pct) {
+                  Some(pct) => format!("{:.1}%", pct),
+                  None => "--".to_owned(),
+              }}
+            </div>
+          </div>
+        </div>
+
+        <div class={classes!("meal-form", "body-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>
+              <span class={classes!("animal-icon")}>{"⚖️"}</span>
+              {"Weight (kg)"}
+            </label>
+            <input type="number" step="0.1" class="input-field" placeholder="0" value={(*weight).clone()} oninput={on_weight_input}/>
+          </div>
+
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>
+              <span class={classes!("animal-icon")}>{"📏"}</span>
+              {"Body Fat (%)"}
+            </label>
+            <input type="number" step="0.1" class="input-field" placeholder="0" value={(*body_fat).clone()} oninput={on_body_fat_input}/>
+          </div>
+
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>
+              <span class={classes!("animal-icon")}>{"📐"}</span>
+              {"Waist (cm)"}
+            </label>
+            <input type="number" step="0.1" class="input-field" placeholder="0" value={(*waist).clone()} oninput={on_waist_input}/>
+          </div>
+
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Other measurement"}</label>
+            <input type="text" class="input-field" placeholder="chest" value={(*measurement_name).clone()} oninput={on_measurement_name_input}/>
+            <input type="number" step="0.1" class="input-field" placeholder="cm" value={(*measurement_value).clone()} oninput={on_measurement_value_input}/>
+          </div>
+        </div>
+
+        <button class={classes!("submit-button")} onclick={on_log_click}>{"LOG"}</button>
+      </section>
+    }
+}
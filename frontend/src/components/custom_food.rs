@@ -0,0 +1,109 @@
+use yew::{function_component, classes, html, Html, use_state, Callback, Properties, TargetCast, InputEvent, UseStateHandle};
+use gloo_net::http::Request;
+use serde::Serialize;
+use web_sys::{console, HtmlInputElement};
+
+use crate::components::add_meal::Food;
+
+#[derive(Properties, PartialEq)]
+pub struct CustomFoodProps {
+    pub category: String,
+    pub on_saved: Callback<Food>,
+}
+
+#[derive(Serialize)]
+struct NewCustomFood {
+    name: String,
+    category: String,
+    protein_g: f64,
+    fat_g: f64,
+    carbs_g: f64,
+    calories: f64,
+    default_sourcing: Vec<String>,
+}
+
+fn field(label: &str, value: &UseStateHandle<String>, number: bool) -> Html {
+    let oninput = {
+        let value = value.clone();
+        Callback::from(move |e: InputEvent| {
+            value.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+    html! {
+      <div class={classes!("input-group")}>
+        <label class={classes!("input-label")}>{label.to_owned()}</label>
+        <input type={if number { "number" } else { "text" }} class="input-field" placeholder={if number { "0" } else { "" }} value={(**value).clone()} {oninput}/>
+      </div>
+    }
+}
+
+// Nutrition is entered per 100g, same as the catalogue
+#[function_component]
+pub fn CustomFoodForm(props: &CustomFoodProps) -> Html {
+    let name = use_state(String::new);
+    let protein = use_state(String::new);
+    let fat = use_state(String::new);
+    let carbs = use_state(String::new);
+    let calories = use_state(String::new);
+
+    let on_save_click = {
+        let category = props.category.clone();
+        let on_saved = props.on_saved.clone();
+        let name = name.clone();
+        let protein = protein.clone();
+        let fat = fat.clone();
+        let carbs = carbs.clone();
+        let calories = calories.clone();
+        Callback::from(move |_| {
+            if name.trim().is_empty() {
+                return;
+            }
+            let number = |value: &UseStateHandle<String>| value.parse::<f64>().unwrap_or(0.0);
+            let food = NewCustomFood {
+                name: (*name).clone(),
+                category: category.clone(),
+                protein_g: number(&protein),
+                fat_g: number(&fat),
+                carbs_g: number(&carbs),
+                calories: number(&calories),
+                default_sourcing: Vec::new(),
+            };
+            let on_saved = on_saved.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/foods/custom").json(&food) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build custom food request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<Food>().await {
+                                Ok(saved) => on_saved.emit(saved),
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse custom food: {}", e).into());
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error saving custom food: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    html! {
+      <div class={classes!("custom-food-form")}>
+        {field("Name", &name, false)}
+        {field("Protein /100g", &protein, true)}
+        {field("Fat /100g", &fat, true)}
+        {field("Carbs /100g", &carbs, true)}
+        {field("Calories /100g", &calories, true)}
+        <button class={classes!("nav-button")} onclick={on_save_click}>{"SAVE FOOD"}</button>
+      </div>
+    }
+}
@@ -1,5 +1,5 @@
 use yew::{function_component, classes, html, Html};
-use crate::components::{AddMeal, Progress, History};
+use crate::components::{AddMeal, Progress, History, BodyTrend, Tdee, Spending, Pantry, Planner, WeekPlan, Import, Share, Account, Sessions, ApiTokens};
 
 #[function_component]
 pub fn Dashboard() -> Html {
@@ -7,7 +7,18 @@ pub fn Dashboard() -> Html {
         <main class={classes!("dashboard")}>
             <AddMeal/>
             <Progress/>
+            <Planner/>
+            <WeekPlan/>
+            <BodyTrend/>
+            <Tdee/>
+            <Share/>
+            <Spending/>
+            <Pantry/>
             <History />
+            <Import/>
+            <Account/>
+            <Sessions/>
+            <ApiTokens/>
         </main>
     }
 }
@@ -3,11 +3,18 @@ use yew::{function_component, classes, html, Html, use_effect_with, use_state, C
 use gloo_net::http::Request;
 use serde::Deserialize;
 use web_sys::console;
+use crate::components::{LocalLogin, PasskeyLogin};
 
 #[derive(Deserialize, Clone, PartialEq, Debug)]
 struct UserInfo {
-    username: String,
     id: String,
+    display_name: String,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct ProviderInfo {
+    id: String,
+    name: String,
 }
 
 #[function_component]
@@ -51,40 +58,34 @@ pub fn Header() -> Html {
         );
     }
     
-    // Handle login click
-    let on_login_click = {
-        Callback::from(move |_| {
-            wasm_bindgen_futures::spawn_local(async {
-                match Request::get("/api/auth-url")
-                    .send()
-                    .await
-                {
-                    Ok(response) => {
-                        if response.status() == 200 {
-                            #[derive(Deserialize)]
-                            struct AuthUrl {
-                                url: String,
-                            }
-                            
-                            match response.json::<AuthUrl>().await {
-                                Ok(data) => {
-                                    // Redirect to Reddit authorization page
-                                    let window = web_sys::window().unwrap();
-                                    let _ = window.location().set_href(&data.url);
-                                }
-                                Err(e) => {
-                                    console::log_1(&format!("Failed to parse auth URL: {}", e).into());
-                                }
+    // The login buttons, one per provider the backend has configured
+    let providers = use_state(Vec::<ProviderInfo>::new);
+    {
+        let providers = providers.clone();
+        
+        use_effect_with(
+            (),
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/identity-providers")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => match response.json::<Vec<ProviderInfo>>().await {
+                            Ok(data) => providers.set(data),
+                            Err(e) => {
+                                console::log_1(&format!("Failed to parse login providers: {}", e).into());
                             }
+                        },
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching login providers: {}", e).into());
                         }
                     }
-                    Err(e) => {
-                        console::log_1(&format!("Error fetching auth URL: {}", e).into());
-                    }
-                }
-            });
-        })
-    };
+                });
+                || ()
+            },
+        );
+    }
     
     // Handle logout click
     let on_logout_click = {
@@ -118,7 +119,7 @@ pub fn Header() -> Html {
             <div class={classes!("nav-controls")}>
                 if let Some(user_info) = (*user).clone() {
                     <span class={classes!("username")}>
-                        {format!("Hi, {}", user_info.username)}
+                        {format!("Hi, {}", user_info.display_name)}
                     </span>
                     <button 
                         class={classes!("nav-button")}
@@ -127,12 +128,14 @@ pub fn Header() -> Html {
                         {"LOGOUT"}
                     </button>
                 } else {
-                    <button 
-                        class={classes!("nav-button")}
-                        onclick={on_login_click}
-                    >
-                        {"LOGIN WITH REDDIT"}
-                    </button>
+                    // the backend redirects to the provider from here
+                    { for providers.iter().map(|provider| html! {
+                        <a class={classes!("nav-button")} href={format!("/login/{}", provider.id)}>
+                            {format!("LOGIN WITH {}", provider.name.to_uppercase())}
+                        </a>
+                    }) }
+                    <PasskeyLogin/>
+                    <LocalLogin/>
                 }
             </div>
         </header>
@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, Event, UseStateHandle};
+use gloo_net::http::Request;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{console, HtmlInputElement, HtmlSelectElement};
+
+use crate::components::add_meal::Food;
+
+#[derive(Serialize, Clone)]
+struct ImportRequest {
+    csv: String,
+    mappings: HashMap<String, String>,
+    create_custom: bool,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct PreviewRow {
+    row: usize,
+    food_name: String,
+    status: String,
+    problem: Option<String>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct ImportPreview {
+    source: String,
+    matched: usize,
+    unmatched: usize,
+    duplicates: usize,
+    invalid: usize,
+    rows: Vec<PreviewRow>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug, Default)]
+struct RestoreCounts {
+    added: usize,
+    replaced: usize,
+    skipped: usize,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct RestoreReport {
+    tables: HashMap<String, RestoreCounts>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct ImportResult {
+    imported: usize,
+    created_foods: Vec<Food>,
+    skipped: usize,
+}
+
+fn fetch_preview(request: ImportRequest, preview: UseStateHandle<Option<ImportPreview>>, message: UseStateHandle<Option<String>>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let request = match Request::post("/api/import/preview").json(&request) {
+            Ok(request) => request,
+            Err(e) => {
+                console::log_1(&format!("Failed to build import preview request: {}", e).into());
+                return;
+            }
+        };
+        match request.send().await {
+            Ok(response) => {
+                if response.ok() {
+                    match response.json::<ImportPreview>().await {
+                        Ok(data) => {
+                            preview.set(Some(data));
+                            message.set(None);
+                        },
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse import preview: {}", e).into());
+                        }
+                    }
+                } else {
+                    preview.set(None);
+                    message.set(response.text().await.ok());
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error previewing import: {}", e).into());
+            }
+        }
+    });
+}
+
+// Brings in history from a Cronometer or MyFitnessPal export: preview,
+// map the foods we couldn't match, then import
+#[function_component]
+pub fn Import() -> Html {
+    let foods = use_state(Vec::<Food>::new);
+    let csv = use_state(String::new);
+    let preview = use_state(|| None::<ImportPreview>);
+    let mappings = use_state(HashMap::<String, String>::new);
+    let create_custom = use_state(|| false);
+    let message = use_state(|| None::<String>);
+    let on_conflict = use_state(|| "skip".to_owned());
+
+    {
+        let foods = foods.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/foods")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                match response.json::<Vec<Food>>().await {
+                                    Ok(data) => foods.set(data),
+                                    Err(e) => {
+                                        console::log_1(&format!("Failed to parse foods: {}", e).into());
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching foods: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    let request = ImportRequest {
+        csv: (*csv).clone(),
+        mappings: (*mappings).clone(),
+        create_custom: *create_custom,
+    };
+
+    let on_file_change = {
+        let csv = csv.clone();
+        let preview = preview.clone();
+        let mappings = mappings.clone();
+        let message = message.clone();
+        Callback::from(move |e: Event| {
+            let Some(file) = e.target_unchecked_into::<HtmlInputElement>().files().and_then(|files| files.get(0)) else {
+                return;
+            };
+            let csv = csv.clone();
+            let preview = preview.clone();
+            let message = message.clone();
+            mappings.set(HashMap::new());
+            wasm_bindgen_futures::spawn_local(async move {
+                match JsFuture::from(file.text()).await {
+                    Ok(text) => {
+                        let text = text.as_string().unwrap_or_default();
+                        csv.set(text.clone());
+                        let request = ImportRequest { csv: text, mappings: HashMap::new(), create_custom: false };
+                        fetch_preview(request, preview, message);
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error reading export: {:?}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_preview_click = {
+        let request = request.clone();
+        let preview = preview.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            if request.csv.is_empty() {
+                return;
+            }
+            fetch_preview(request.clone(), preview.clone(), message.clone());
+        })
+    };
+
+    let on_custom_change = {
+        let create_custom = create_custom.clone();
+        Callback::from(move |e: Event| {
+            create_custom.set(e.target_unchecked_into::<HtmlInputElement>().checked());
+        })
+    };
+
+    let on_import_click = {
+        let request = request.clone();
+        let preview = preview.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            if request.csv.is_empty() {
+                return;
+            }
+            let request = request.clone();
+            let preview = preview.clone();
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/import/commit").json(&request) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build import request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<ImportResult>().await {
+                                Ok(result) => {
+                                    preview.set(None);
+                                    message.set(Some(format!(
+                                        "Imported {} meals, created {} foods, skipped {} rows",
+                                        result.imported, result.created_foods.len(), result.skipped,
+                                    )));
+                                },
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse import result: {}", e).into());
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error importing: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_conflict_change = {
+        let on_conflict = on_conflict.clone();
+        Callback::from(move |e: Event| {
+            on_conflict.set(e.target_unchecked_into::<HtmlSelectElement>().value());
+        })
+    };
+
+    // Sends an exported archive (JSON or zip) back as is
+    let on_restore_change = {
+        let on_conflict = on_conflict.clone();
+        let message = message.clone();
+        Callback::from(move |e: Event| {
+            let Some(file) = e.target_unchecked_into::<HtmlInputElement>().files().and_then(|files| files.get(0)) else {
+                return;
+            };
+            let url = format!("/api/export/restore?on_conflict={}", *on_conflict);
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let contents = match JsFuture::from(file.array_buffer()).await {
+                    Ok(contents) => contents,
+                    Err(e) => {
+                        console::log_1(&format!("Error reading archive: {:?}", e).into());
+                        return;
+                    }
+                };
+                match Request::post(&url).body(contents).send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<RestoreReport>().await {
+                                Ok(report) => {
+                                    let mut total = RestoreCounts::default();
+                                    for counts in report.tables.values() {
+                                        total.added += counts.added;
+                                        total.replaced += counts.replaced;
+                                        total.skipped += counts.skipped;
+                                    }
+                                    message.set(Some(format!(
+                                        "Restored: {} added, {} replaced, {} already there",
+                                        total.added, total.replaced, total.skipped,
+                                    )));
+                                },
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse restore report: {}", e).into());
+                                }
+                            }
+                        } else {
+                            message.set(response.text().await.ok());
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error restoring archive: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    // Revokes the Reddit login and wipes everything, after asking
+    let on_delete_account_click = {
+        let message = message.clone();
+        Callback::from(move |_| {
+            let Some(window) = web_sys::window() else {
+                return;
+            };
+            if !window.confirm_with_message("Delete your account and everything you've logged? Download an export first if you want to keep it.").unwrap_or(false) {
+                return;
+            }
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Request::delete("/api/account").send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            if let Some(window) = web_sys::window() {
+                                let _ = window.location().set_href("/");
+                            }
+                        } else {
+                            message.set(Some("Unable to delete the account, try again".to_owned()));
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error deleting account: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    // one mapping per food name, however many rows it appears on
+    let mut unmatched: Vec<String> = preview.iter()
+        .flat_map(|preview| preview.rows.iter())
+        .filter(|row| row.status == "unmatched")
+        .map(|row| row.food_name.clone())
+        .collect();
+    unmatched.sort();
+    unmatched.dedup();
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"Import History"}</h2>
+
+        <div class={classes!("meal-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Cronometer or MyFitnessPal CSV"}</label>
+            <input type="file" accept=".csv,text/csv" class="input-field" onchange={on_file_change}/>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Create unmatched foods"}</label>
+            <input type="checkbox" checked={*create_custom} onchange={on_custom_change}/>
+          </div>
+        </div>
+
+        if let Some(text) = (*message).clone() {
+          <p class={classes!("plan-summary")}>{text}</p>
+        }
+
+        if let Some(current) = (*preview).clone() {
+          <p class={classes!("plan-summary")}>
+            {format!(
+                "{}: {} matched, {} unmatched, {} already imported, {} unreadable",
+                current.source, current.matched, current.unmatched, current.duplicates, current.invalid,
+            )}
+          </p>
+          <table class={classes!("report-table")}>
+            <tr>
+              <th>{"Their food"}</th>
+              <th>{"Our food"}</th>
+            </tr>
+            { for unmatched.iter().map(|name| {
+                let on_change = {
+                    let mappings = mappings.clone();
+                    let name = name.clone();
+                    Callback::from(move |e: Event| {
+                        let mut updated = (*mappings).clone();
+                        let food_id = e.target_unchecked_into::<HtmlSelectElement>().value();
+                        if food_id.is_empty() {
+                            updated.remove(&name);
+                        } else {
+                            updated.insert(name.clone(), food_id);
+                        }
+                        mappings.set(updated);
+                    })
+                };
+                html! {
+                  <tr>
+                    <td>{name.clone()}</td>
+                    <td>
+                      <select class={classes!("select-field")} onchange={on_change}>
+                        <option value="" selected={!mappings.contains_key(name)}>{"(unmatched)"}</option>
+                        { for foods.iter().map(|food| html! {
+                            <option value={food.id.clone()} selected={mappings.get(name) == Some(&food.id)}>{food.name.clone()}</option>
+                        }) }
+                      </select>
+                    </td>
+                  </tr>
+                }
+            }) }
+            { for current.rows.iter().filter(|row| row.status == "invalid").map(|row| html! {
+                <tr class={classes!("running-low")}>
+                  <td>{format!("Row {}: {}", row.row, row.food_name)}</td>
+                  <td>{row.problem.clone().unwrap_or_default()}</td>
+                </tr>
+            }) }
+          </table>
+          <button class={classes!("submit-button")} onclick={on_preview_click}>{"PREVIEW AGAIN"}</button>
+          <button class={classes!("submit-button")} onclick={on_import_click}>{"IMPORT"}</button>
+        }
+
+        <h3 class={classes!("panel-subheader")}>{"Your Data"}</h3>
+        <p class={classes!("feed-link")}>
+          <a href="/api/export">{"Download JSON archive"}</a>
+          {" / "}
+          <a href="/api/export?format=csv">{"Download CSVs (zip)"}</a>
+        </p>
+        <div class={classes!("meal-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Restore archive"}</label>
+            <input type="file" accept=".json,.zip" class="input-field" onchange={on_restore_change}/>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Already there"}</label>
+            <select class={classes!("select-field")} onchange={on_conflict_change}>
+              <option value="skip" selected={*on_conflict == "skip"}>{"KEEP MINE"}</option>
+              <option value="replace" selected={*on_conflict == "replace"}>{"REPLACE"}</option>
+            </select>
+          </div>
+        </div>
+        <button class={classes!("submit-button")} onclick={on_delete_account_click}>{"DELETE ACCOUNT"}</button>
+      </section>
+    }
+}
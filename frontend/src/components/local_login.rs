@@ -0,0 +1,172 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, InputEvent, UseStateHandle};
+use gloo_net::http::Request;
+use serde::Serialize;
+use web_sys::{console, HtmlInputElement};
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct PasswordReset {
+    token: String,
+    password: String,
+}
+
+fn on_text_input(state: &UseStateHandle<String>) -> Callback<InputEvent> {
+    let state = state.clone();
+    Callback::from(move |e: InputEvent| {
+        state.set(e.target_unchecked_into::<HtmlInputElement>().value());
+    })
+}
+
+// Username and password sign-in for when the instance has it turned on
+#[function_component]
+pub fn LocalLogin() -> Html {
+    let enabled = use_state(|| false);
+    let username = use_state(String::new);
+    let password = use_state(String::new);
+    let reset_token = use_state(String::new);
+    // a code from an admin instead of the username
+    let resetting = use_state(|| false);
+    let message = use_state(|| None::<String>);
+
+    {
+        let enabled = enabled.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/local-accounts/enabled")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                enabled.set(response.json::<bool>().await.unwrap_or(false));
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error checking password logins: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    // login and register only differ in the endpoint
+    let submit = {
+        let username = username.clone();
+        let password = password.clone();
+        let message = message.clone();
+        Callback::from(move |url: &'static str| {
+            let credentials = Credentials {
+                username: (*username).clone(),
+                password: (*password).clone(),
+            };
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post(url).json(&credentials) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build login request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            if let Some(window) = web_sys::window() {
+                                let _ = window.location().set_href("/");
+                            }
+                        } else {
+                            message.set(Some(response.text().await.unwrap_or_else(|_| "Unable to sign in".to_owned())));
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error signing in: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_reset_click = {
+        let reset_token = reset_token.clone();
+        let password = password.clone();
+        let resetting = resetting.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let reset = PasswordReset {
+                token: (*reset_token).clone(),
+                password: (*password).clone(),
+            };
+            let resetting = resetting.clone();
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/local-accounts/reset").json(&reset) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build reset request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            resetting.set(false);
+                            message.set(Some("Password changed, log in with it".to_owned()));
+                        } else {
+                            message.set(Some(response.text().await.unwrap_or_else(|_| "Unable to reset the password".to_owned())));
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error resetting password: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_login_click = {
+        let submit = submit.clone();
+        Callback::from(move |_| submit.emit("/api/local-accounts/login"))
+    };
+    let on_register_click = Callback::from(move |_| submit.emit("/api/local-accounts/register"));
+    let on_toggle_reset = {
+        let resetting = resetting.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            message.set(None);
+            resetting.set(!*resetting);
+        })
+    };
+
+    if !*enabled {
+        return html! {};
+    }
+
+    html! {
+      <div class={classes!("local-login")}>
+        if *resetting {
+          <input type="text" class="input-field" placeholder="Reset code" value={(*reset_token).clone()} oninput={on_text_input(&reset_token)}/>
+          <input type="password" class="input-field" placeholder="New password" value={(*password).clone()} oninput={on_text_input(&password)}/>
+          <button class={classes!("nav-button")} onclick={on_reset_click}>{"SET PASSWORD"}</button>
+          <button class={classes!("nav-button")} onclick={on_toggle_reset}>{"BACK"}</button>
+        } else {
+          <input type="text" class="input-field" placeholder="Username" value={(*username).clone()} oninput={on_text_input(&username)}/>
+          <input type="password" class="input-field" placeholder="Password" value={(*password).clone()} oninput={on_text_input(&password)}/>
+          <button class={classes!("nav-button")} onclick={on_login_click}>{"LOGIN"}</button>
+          <button class={classes!("nav-button")} onclick={on_register_click}>{"REGISTER"}</button>
+          <button class={classes!("nav-button")} onclick={on_toggle_reset}>{"RESET CODE"}</button>
+        }
+        if let Some(message) = (*message).clone() {
+          <p>{message}</p>
+        }
+      </div>
+    }
+}
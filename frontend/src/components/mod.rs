@@ -3,9 +3,37 @@ pub mod header;
 pub mod history;
 pub mod add_meal;
 pub mod progress;
+pub mod body_trend;
+pub mod tdee;
+pub mod custom_food;
+pub mod spending;
+pub mod pantry;
+pub mod planner;
+pub mod week_plan;
+pub mod import;
+pub mod share;
+pub mod account;
+pub mod local_login;
+pub mod passkey;
+pub mod sessions;
+pub mod api_tokens;
 
 pub use dashboard::Dashboard;
 pub use header::Header;
 pub use history::History;
 pub use add_meal::AddMeal;
 pub use progress::Progress;
+pub use body_trend::BodyTrend;
+pub use tdee::Tdee;
+pub use custom_food::CustomFoodForm;
+pub use spending::Spending;
+pub use pantry::Pantry;
+pub use planner::Planner;
+pub use week_plan::WeekPlan;
+pub use import::Import;
+pub use share::Share;
+pub use account::Account;
+pub use local_login::LocalLogin;
+pub use passkey::{PasskeyLogin, AddPasskey};
+pub use sessions::Sessions;
+pub use api_tokens::ApiTokens;
@@ -0,0 +1,129 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, UseStateHandle};
+use gloo_net::http::Request;
+use serde::Deserialize;
+use web_sys::console;
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct StockLevel {
+    food_name: String,
+    grams_on_hand: f64,
+    days_of_supply: Option<f64>,
+    running_low: bool,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct ShoppingItem {
+    food_name: String,
+    grams_needed: f64,
+    estimated_cost: Option<f64>,
+    store: Option<String>,
+}
+
+fn fetch_pantry(levels: UseStateHandle<Vec<StockLevel>>, shopping: UseStateHandle<Vec<ShoppingItem>>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        match Request::get("/api/pantry/status")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<Vec<StockLevel>>().await {
+                        Ok(data) => levels.set(data),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse pantry status: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching pantry status: {}", e).into());
+            }
+        }
+
+        match Request::get("/api/pantry/shopping-list")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<Vec<ShoppingItem>>().await {
+                        Ok(data) => shopping.set(data),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse shopping list: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching shopping list: {}", e).into());
+            }
+        }
+    });
+}
+
+fn format_grams(grams: f64) -> String {
+    if grams >= 1000.0 {
+        format!("{:.1}kg", grams / 1000.0)
+    } else {
+        format!("{:.0}g", grams)
+    }
+}
+
+#[function_component]
+pub fn Pantry() -> Html {
+    let levels = use_state(Vec::<StockLevel>::new);
+    let shopping = use_state(Vec::<ShoppingItem>::new);
+
+    {
+        let levels = levels.clone();
+        let shopping = shopping.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                fetch_pantry(levels, shopping);
+                || ()
+            },
+        );
+    }
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"Pantry"}</h2>
+
+        <table class={classes!("report-table")}>
+          <tr>
+            <th>{"Food"}</th>
+            <th>{"On hand"}</th>
+            <th>{"Days left"}</th>
+          </tr>
+          { for levels.iter().map(|level| html! {
+              <tr class={classes!(level.running_low.then_some("running-low"))}>
+                <td>{level.food_name.clone()}</td>
+                <td>{format_grams(level.grams_on_hand)}</td>
+                <td>{level.days_of_supply.map(|days| format!("{:.1}", days)).unwrap_or_else(|| "--".to_owned())}</td>
+              </tr>
+          }) }
+        </table>
+
+        if !shopping.is_empty() {
+          <h3 class={classes!("panel-subheader")}>{"Shopping List"}</h3>
+          <table class={classes!("report-table")}>
+            <tr>
+              <th>{"Food"}</th>
+              <th>{"Buy"}</th>
+              <th>{"Est. $"}</th>
+              <th>{"Store"}</th>
+            </tr>
+            { for shopping.iter().map(|item| html! {
+                <tr>
+                  <td>{item.food_name.clone()}</td>
+                  <td>{format_grams(item.grams_needed)}</td>
+                  <td>{item.estimated_cost.map(|cost| format!("{:.2}", cost)).unwrap_or_else(|| "--".to_owned())}</td>
+                  <td>{item.store.clone().unwrap_or_default()}</td>
+                </tr>
+            }) }
+          </table>
+        }
+      </section>
+    }
+}
@@ -0,0 +1,207 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, Properties, UseStateHandle};
+use gloo_net::http::Request;
+use serde::Serialize;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{console, CredentialCreationOptions, CredentialRequestOptions, PublicKeyCredential};
+use webauthn_rs_proto::{CreationChallengeResponse, RegisterPublicKeyCredential, RequestChallengeResponse, PublicKeyCredential as AuthenticationCredential};
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct RegistrationStart {
+    name: String,
+}
+
+fn use_passkeys_enabled() -> UseStateHandle<bool> {
+    let enabled = use_state(|| false);
+    {
+        let enabled = enabled.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/passkeys/enabled")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                enabled.set(response.json::<bool>().await.unwrap_or(false));
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error checking passkeys: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+    enabled
+}
+
+async fn response_error(response: gloo_net::http::Response, fallback: &str) -> String {
+    response.text().await.ok().filter(|text| !text.is_empty()).unwrap_or_else(|| fallback.to_owned())
+}
+
+// Both ceremonies: start on the backend, the browser talks to the
+// authenticator, finish on the backend
+async fn register_passkey(name: String) -> Result<(), String> {
+    let response = Request::post("/api/passkeys/register/start")
+        .json(&RegistrationStart { name })
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.ok() {
+        return Err(response_error(response, "Unable to start the passkey registration").await);
+    }
+    let challenge = response.json::<CreationChallengeResponse>().await.map_err(|e| e.to_string())?;
+    let options: CredentialCreationOptions = challenge.into();
+    let window = web_sys::window().ok_or("No window")?;
+    let promise = window.navigator().credentials().create_with_options(&options).map_err(|e| format!("{:?}", e))?;
+    let credential = JsFuture::from(promise).await.map_err(|_| "The passkey wasn't created".to_owned())?;
+    let credential = RegisterPublicKeyCredential::from(PublicKeyCredential::from(credential));
+    let response = Request::post("/api/passkeys/register/finish")
+        .json(&credential)
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.ok() {
+        return Err(response_error(response, "The passkey couldn't be registered").await);
+    }
+    Ok(())
+}
+
+async fn login_with_passkey() -> Result<(), String> {
+    let response = Request::post("/api/passkeys/login/start")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.ok() {
+        return Err(response_error(response, "Unable to start the passkey login").await);
+    }
+    let challenge = response.json::<RequestChallengeResponse>().await.map_err(|e| e.to_string())?;
+    let options: CredentialRequestOptions = challenge.into();
+    let window = web_sys::window().ok_or("No window")?;
+    let promise = window.navigator().credentials().get_with_options(&options).map_err(|e| format!("{:?}", e))?;
+    let credential = JsFuture::from(promise).await.map_err(|_| "No passkey was chosen".to_owned())?;
+    let credential = AuthenticationCredential::from(PublicKeyCredential::from(credential));
+    let response = Request::post("/api/passkeys/login/finish")
+        .json(&credential)
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.ok() {
+        return Err(response_error(response, "That passkey didn't work").await);
+    }
+    Ok(())
+}
+
+fn go_home() {
+    if let Some(window) = web_sys::window() {
+        let _ = window.location().set_href("/");
+    }
+}
+
+// Header buttons for signing in, or up, with only a passkey
+#[function_component]
+pub fn PasskeyLogin() -> Html {
+    let enabled = use_passkeys_enabled();
+    let message = use_state(|| None::<String>);
+
+    let on_login_click = {
+        let message = message.clone();
+        Callback::from(move |_| {
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match login_with_passkey().await {
+                    Ok(_) => go_home(),
+                    Err(e) => message.set(Some(e)),
+                }
+            });
+        })
+    };
+
+    let on_sign_up_click = {
+        let message = message.clone();
+        Callback::from(move |_| {
+            let Some(name) = web_sys::window()
+                .and_then(|window| window.prompt_with_message("What should we call you?").ok().flatten())
+                .filter(|name| !name.trim().is_empty()) else {
+                return;
+            };
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match register_passkey(name).await {
+                    Ok(_) => go_home(),
+                    Err(e) => message.set(Some(e)),
+                }
+            });
+        })
+    };
+
+    if !*enabled {
+        return html! {};
+    }
+
+    html! {
+      <>
+        <button class={classes!("nav-button")} onclick={on_login_click}>{"LOGIN WITH PASSKEY"}</button>
+        <button class={classes!("nav-button")} onclick={on_sign_up_click}>{"SIGN UP WITH PASSKEY"}</button>
+        if let Some(message) = (*message).clone() {
+          <p>{message}</p>
+        }
+      </>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct AddPasskeyProps {
+    // reload the sign-in list
+    pub on_added: Callback<()>,
+}
+
+// For the account panel, adds a passkey to the signed in user
+#[function_component]
+pub fn AddPasskey(props: &AddPasskeyProps) -> Html {
+    let enabled = use_passkeys_enabled();
+    let message = use_state(|| None::<String>);
+
+    let on_add_click = {
+        let message = message.clone();
+        let on_added = props.on_added.clone();
+        Callback::from(move |_| {
+            let Some(name) = web_sys::window()
+                .and_then(|window| window.prompt_with_message("Name this passkey, like \"phone\" or \"laptop\"").ok().flatten())
+                .filter(|name| !name.trim().is_empty()) else {
+                return;
+            };
+            let message = message.clone();
+            let on_added = on_added.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match register_passkey(name).await {
+                    Ok(_) => {
+                        message.set(None);
+                        on_added.emit(());
+                    },
+                    Err(e) => message.set(Some(e)),
+                }
+            });
+        })
+    };
+
+    if !*enabled {
+        return html! {};
+    }
+
+    html! {
+      <>
+        <button class={classes!("submit-button")} onclick={on_add_click}>{"ADD PASSKEY"}</button>
+        if let Some(message) = (*message).clone() {
+          <p>{message}</p>
+        }
+      </>
+    }
+}
@@ -0,0 +1,360 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, Event, InputEvent};
+use gloo_net::http::Request;
+use serde::{Deserialize, Serialize};
+use web_sys::{console, HtmlInputElement, HtmlSelectElement};
+
+use crate::components::add_meal::Food;
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct FoodBounds {
+    food_id: String,
+    min_g: f64,
+    max_g: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct PlanRequest {
+    foods: Vec<FoodBounds>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct Macros {
+    protein_g: f64,
+    fat_g: f64,
+    carbs_g: f64,
+    calories: f64,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct PlannedFood {
+    food_id: String,
+    food_name: String,
+    grams: f64,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct MealPlan {
+    foods: Vec<PlannedFood>,
+    totals: Macros,
+    targets: Macros,
+    feasible: bool,
+}
+
+#[derive(Serialize)]
+struct PlanPortion {
+    food_id: String,
+    grams: f64,
+}
+
+#[derive(Serialize)]
+struct CommitPlan {
+    foods: Vec<PlanPortion>,
+}
+
+#[derive(Serialize)]
+struct NewPlannedMeal {
+    date: String,
+    slot: String,
+    foods: Vec<PlanPortion>,
+}
+
+fn portions(plan: &MealPlan) -> Vec<PlanPortion> {
+    plan.foods.iter()
+        .filter(|food| food.grams > 0.0)
+        .map(|food| PlanPortion { food_id: food.food_id.clone(), grams: food.grams })
+        .collect()
+}
+
+#[function_component]
+pub fn Planner() -> Html {
+    let foods = use_state(Vec::<Food>::new);
+    let chosen = use_state(Vec::<FoodBounds>::new);
+    let food_id = use_state(String::new);
+    let max_g = use_state(String::new);
+    let plan = use_state(|| None::<MealPlan>);
+    let plan_date = use_state(String::new);
+    let slot = use_state(|| "dinner".to_owned());
+
+    {
+        let foods = foods.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/foods")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                match response.json::<Vec<Food>>().await {
+                                    Ok(data) => foods.set(data),
+                                    Err(e) => {
+                                        console::log_1(&format!("Failed to parse foods: {}", e).into());
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching foods: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    let on_food_change = {
+        let food_id = food_id.clone();
+        Callback::from(move |e: Event| {
+            food_id.set(e.target_unchecked_into::<HtmlSelectElement>().value());
+        })
+    };
+
+    let on_max_input = {
+        let max_g = max_g.clone();
+        Callback::from(move |e: InputEvent| {
+            max_g.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_add_click = {
+        let foods = foods.clone();
+        let chosen = chosen.clone();
+        let food_id = food_id.clone();
+        let max_g = max_g.clone();
+        Callback::from(move |_| {
+            // the select shows the first food until it is changed
+            let picked = if food_id.is_empty() {
+                foods.first().map(|food| food.id.clone())
+            } else {
+                Some((*food_id).clone())
+            };
+            let Some(picked) = picked else {
+                return;
+            };
+            let mut updated: Vec<FoodBounds> = chosen.iter()
+                .filter(|bounds| bounds.food_id != picked)
+                .cloned()
+                .collect();
+            updated.push(FoodBounds {
+                food_id: picked,
+                min_g: 0.0,
+                max_g: max_g.parse::<f64>().ok(),
+            });
+            chosen.set(updated);
+            max_g.set(String::new());
+        })
+    };
+
+    let on_plan_click = {
+        let chosen = chosen.clone();
+        let plan = plan.clone();
+        Callback::from(move |_| {
+            let request = PlanRequest { foods: (*chosen).clone() };
+            let plan = plan.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/plan").json(&request) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build plan request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<MealPlan>().await {
+                                Ok(data) => plan.set(Some(data)),
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse plan: {}", e).into());
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error making plan: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_commit_click = {
+        let plan = plan.clone();
+        Callback::from(move |_| {
+            let Some(current) = (*plan).clone() else {
+                return;
+            };
+            let commit = CommitPlan { foods: portions(&current) };
+            let plan = plan.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/plan/commit").json(&commit) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build commit request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            plan.set(None);
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error logging plan: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_date_input = {
+        let plan_date = plan_date.clone();
+        Callback::from(move |e: InputEvent| {
+            plan_date.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_slot_change = {
+        let slot = slot.clone();
+        Callback::from(move |e: Event| {
+            slot.set(e.target_unchecked_into::<HtmlSelectElement>().value());
+        })
+    };
+
+    // Saves the solved amounts into the weekly plan instead of logging them
+    let on_schedule_click = {
+        let plan = plan.clone();
+        let plan_date = plan_date.clone();
+        let slot = slot.clone();
+        Callback::from(move |_| {
+            let Some(current) = (*plan).clone() else {
+                return;
+            };
+            if plan_date.is_empty() {
+                return;
+            }
+            let planned = NewPlannedMeal {
+                date: (*plan_date).clone(),
+                slot: (*slot).clone(),
+                foods: portions(&current),
+            };
+            let plan = plan.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/plans/meals").json(&planned) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build planned meal request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            plan.set(None);
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error saving planned meal: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let food_name = |id: &str| {
+        foods.iter()
+            .find(|food| food.id == id)
+            .map(|food| food.name.clone())
+            .unwrap_or_else(|| id.to_owned())
+    };
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"Meal Planner"}</h2>
+
+        <div class={classes!("meal-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Food"}</label>
+            <select class={classes!("select-field")} onchange={on_food_change}>
+              { for foods.iter().map(|food| html! {
+                  <option value={food.id.clone()} selected={*food_id == food.id}>{food.name.clone()}</option>
+              }) }
+            </select>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Max (g)"}</label>
+            <input type="number" class="input-field" placeholder="no limit" value={(*max_g).clone()} oninput={on_max_input}/>
+          </div>
+        </div>
+        <button class={classes!("submit-button")} onclick={on_add_click}>{"ADD FOOD"}</button>
+
+        <ul class={classes!("plan-foods")}>
+          { for chosen.iter().map(|bounds| {
+              let remove = {
+                  let chosen = chosen.clone();
+                  let id = bounds.food_id.clone();
+                  Callback::from(move |_| {
+                      chosen.set(chosen.iter().filter(|bounds| bounds.food_id != id).cloned().collect());
+                  })
+              };
+              html! {
+                <li>
+                  {food_name(&bounds.food_id)}
+                  {bounds.max_g.map(|max| format!(" (max {:.0}g)", max)).unwrap_or_default()}
+                  <button class={classes!("remove-button")} onclick={remove}>{"x"}</button>
+                </li>
+              }
+          }) }
+        </ul>
+
+        <button class={classes!("submit-button")} onclick={on_plan_click}>
+          { if chosen.is_empty() { "PLAN FROM PANTRY" } else { "PLAN" } }
+        </button>
+
+        if let Some(current) = (*plan).clone() {
+          <table class={classes!("report-table")}>
+            <tr>
+              <th>{"Food"}</th>
+              <th>{"Grams"}</th>
+            </tr>
+            { for current.foods.iter().filter(|food| food.grams > 0.0).map(|food| html! {
+                <tr>
+                  <td>{food.food_name.clone()}</td>
+                  <td>{format!("{:.0}", food.grams)}</td>
+                </tr>
+            }) }
+          </table>
+          <p class={classes!("plan-summary", (!current.feasible).then_some("off-target"))}>
+            {format!(
+                "P {:.0}/{:.0}g  F {:.0}/{:.0}g  C {:.0}/{:.0}g  {:.0}/{:.0} kcal",
+                current.totals.protein_g, current.targets.protein_g,
+                current.totals.fat_g, current.targets.fat_g,
+                current.totals.carbs_g, current.targets.carbs_g,
+                current.totals.calories, current.targets.calories,
+            )}
+          </p>
+          <button class={classes!("submit-button")} onclick={on_commit_click}>{"LOG PLAN"}</button>
+
+          <div class={classes!("meal-form")}>
+            <div class={classes!("input-group")}>
+              <label class={classes!("input-label")}>{"Day"}</label>
+              <input type="date" class="input-field" value={(*plan_date).clone()} oninput={on_date_input}/>
+            </div>
+            <div class={classes!("input-group")}>
+              <label class={classes!("input-label")}>{"Meal"}</label>
+              <select class={classes!("select-field")} onchange={on_slot_change}>
+                { for ["breakfast", "lunch", "snack", "dinner"].iter().map(|name| html! {
+                    <option value={*name} selected={*slot == *name}>{name.to_uppercase()}</option>
+                }) }
+              </select>
+            </div>
+          </div>
+          <button class={classes!("submit-button")} onclick={on_schedule_click}>{"ADD TO WEEK"}</button>
+        }
+      </section>
+    }
+}
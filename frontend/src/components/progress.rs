@@ -1,44 +1,164 @@
-use yew::{function_component, classes, html, Html};
+use yew::{function_component, classes, html, Html, use_effect_with, use_state};
+use gloo_net::http::Request;
+use serde::Deserialize;
+use web_sys::console;
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct MacroTotals {
+    protein_g: f64,
+    fat_g: f64,
+    carbs_g: f64,
+    calories: f64,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct RelativeTarget {
+    grams: f64,
+    per: String,
+    basis: String,
+}
+
+impl RelativeTarget {
+    // "2.2 g/lb lean mass"
+    fn label(&self) -> String {
+        let basis = if self.basis == "lean_mass" { "lean mass" } else { "body weight" };
+        format!("{} g/{} {}", self.grams, self.per, basis)
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct Targets {
+    protein_g: f64,
+    fat_g: f64,
+    carbs_g: f64,
+    calories: f64,
+    protein_relative: Option<RelativeTarget>,
+    fat_relative: Option<RelativeTarget>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct Summary {
+    totals: MacroTotals,
+    targets: Targets,
+    animal_score: Option<f64>,
+    planned: Option<MacroTotals>,
+}
+
+fn percent(value: f64, target: f64) -> String {
+    let pct = if target > 0.0 { (value / target * 100.0).min(100.0) } else { 0.0 };
+    format!("width: {:.0}%;", pct)
+}
+
+fn macro_card(title: &str, value: String, current: f64, target: f64, note: Option<String>) -> Html {
+    html! {
+      <div class={classes!("macro-card")}>
+        <div class={classes!("macro-title")}>{title.to_owned()}</div>
+        <div class={classes!("macro-value")}>{value}</div>
+        if let Some(note) = note {
+          <div class={classes!("macro-note")}>{note}</div>
+        }
+        <div class={classes!("progress-container")}>
+          <div class={classes!("progress-bar")} style={percent(current, target)}></div>
+        </div>
+      </div>
+    }
+}
 
 #[function_component]
 pub fn Progress() -> Html {
+    let summary = use_state(|| None::<Summary>);
+
+    {
+        let summary = summary.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/summary")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                match response.json::<Summary>().await {
+                                    Ok(data) => summary.set(Some(data)),
+                                    Err(e) => {
+                                        console::log_1(&format!("Failed to parse summary: {}", e).into());
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching summary: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    let Some(summary) = (*summary).clone() else {
+        return html! {
+          <section class={classes!("panel")}>
+            <h2 class={classes!("panel-header")}>{"Today's Progress"}</h2>
+          </section>
+        };
+    };
+    let totals = &summary.totals;
+    let targets = &summary.targets;
+
     html! {
       <section class={classes!("panel")}>
         <h2 class={classes!("panel-header")}>{"Today's Progress"}</h2>
-        
+
         <div class={classes!("macro-display")}>
-          <div class={classes!("macro-card")}>
-            <div class={classes!("macro-title")}>{"Protein"}</div>
-            <div class={classes!("macro-value")}>{"120g / 180g"}</div>
-            <div class={classes!("progress-container")}>
-              <div class={classes!("progress-bar")} style="width: 65%;"></div>
-            </div>
-          </div>
-          
-          <div class={classes!("macro-card")}>
-            <div class={classes!("macro-title")}>{"Fat"}</div>
-            <div class={classes!("macro-value")}>{"75g / 100g"}</div>
-            <div class={classes!("progress-container")}>
-              <div class={classes!("progress-bar")} style="width: 75%;"></div>
-            </div>
-          </div>
-          
-          <div class={classes!("macro-card")}>
-            <div class={classes!("macro-title")}>{"Calories"}</div>
-            <div class={classes!("macro-value")}>{"1500 / 2200"}</div>
-            <div class={classes!("progress-container")}>
-              <div class={classes!("progress-bar")} style="width: 68%;"></div>
-            </div>
-          </div>
-          
-          <div class={classes!("macro-card")}>
-            <div class={classes!("macro-title")}>{"Animal Score"}</div>
-            <div class={classes!("macro-value")}>{"72"}</div>
-            <div class={classes!("progress-container")}>
-              <div class={classes!("progress-bar")} style="width: 72%;"></div>
-            </div>
-          </div>
+          {macro_card(
+              "Protein",
+              format!("{:.0}g / {:.0}g", totals.protein_g, targets.protein_g),
+              totals.protein_g,
+              targets.protein_g,
+              targets.protein_relative.as_ref().map(RelativeTarget::label),
+          )}
+          {macro_card(
+              "Fat",
+              format!("{:.0}g / {:.0}g", totals.fat_g, targets.fat_g),
+              totals.fat_g,
+              targets.fat_g,
+              targets.fat_relative.as_ref().map(RelativeTarget::label),
+          )}
+          {macro_card(
+              "Carbs",
+              format!("{:.0}g / {:.0}g", totals.carbs_g, targets.carbs_g),
+              totals.carbs_g,
+              targets.carbs_g,
+              None,
+          )}
+          {macro_card(
+              "Calories",
+              format!("{:.0} / {:.0}", totals.calories, targets.calories),
+              totals.calories,
+              targets.calories,
+              None,
+          )}
+
+          {macro_card(
+              "Animal Score",
+              summary.animal_score.map(|score| format!("{:.0}", score)).unwrap_or_else(|| "--".to_owned()),
+              summary.animal_score.unwrap_or(0.0),
+              100.0,
+              None,
+          )}
         </div>
+
+        if let Some(planned) = summary.planned.as_ref() {
+          <p class={classes!("plan-summary")}>
+            {format!(
+                "Planned today: {:.0}g protein, {:.0}g fat, {:.0}g carbs, {:.0} kcal",
+                planned.protein_g, planned.fat_g, planned.carbs_g, planned.calories,
+            )}
+          </p>
+        }
       </section>
     }
 }
@@ -0,0 +1,159 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, UseStateHandle};
+use gloo_net::http::Request;
+use serde::Deserialize;
+use web_sys::console;
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct SessionInfo {
+    id: String,
+    user_agent: Option<String>,
+    ip_prefix: Option<String>,
+    created_at: String,
+    last_seen_at: String,
+    current: bool,
+}
+
+fn fetch_sessions(sessions: UseStateHandle<Vec<SessionInfo>>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        match Request::get("/api/sessions")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<Vec<SessionInfo>>().await {
+                        Ok(data) => sessions.set(data),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse sessions: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching sessions: {}", e).into());
+            }
+        }
+    });
+}
+
+// "Firefox on Linux" from a full user agent, or the raw one when unsure
+fn describe_device(user_agent: &Option<String>) -> String {
+    let Some(user_agent) = user_agent else {
+        return "Unknown device".to_owned();
+    };
+    let browser = [("Edg/", "Edge"), ("Firefox/", "Firefox"), ("Chrome/", "Chrome"), ("Safari/", "Safari"), ("curl/", "curl")]
+        .iter()
+        .find(|(marker, _)| user_agent.contains(marker))
+        .map(|(_, name)| *name);
+    let system = [("Android", "Android"), ("iPhone", "iPhone"), ("iPad", "iPad"), ("Windows", "Windows"), ("Mac OS", "macOS"), ("Linux", "Linux")]
+        .iter()
+        .find(|(marker, _)| user_agent.contains(marker))
+        .map(|(_, name)| *name);
+    match (browser, system) {
+        (Some(browser), Some(system)) => format!("{} on {}", browser, system),
+        (Some(name), None) | (None, Some(name)) => name.to_owned(),
+        (None, None) => user_agent.clone(),
+    }
+}
+
+// dates only, the times are in the tooltip
+fn day(timestamp: &str) -> String {
+    timestamp.get(..10).unwrap_or(timestamp).to_owned()
+}
+
+// Where the account is signed in, and signing those out
+#[function_component]
+pub fn Sessions() -> Html {
+    let sessions = use_state(Vec::<SessionInfo>::new);
+
+    {
+        let sessions = sessions.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                fetch_sessions(sessions);
+                || ()
+            },
+        );
+    }
+
+    let on_revoke = {
+        let sessions = sessions.clone();
+        Callback::from(move |session: SessionInfo| {
+            let sessions = sessions.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Request::delete(&format!("/api/sessions/{}", session.id)).send().await {
+                    Ok(response) => {
+                        if !response.ok() {
+                            console::log_1(&format!("Unable to sign out session: {}", response.status()).into());
+                        } else if session.current {
+                            if let Some(window) = web_sys::window() {
+                                let _ = window.location().set_href("/");
+                            }
+                        } else {
+                            fetch_sessions(sessions);
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error signing out session: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_revoke_others = {
+        let sessions = sessions.clone();
+        Callback::from(move |_| {
+            let sessions = sessions.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Request::post("/api/sessions/revoke-others").send().await {
+                    Ok(_) => fetch_sessions(sessions),
+                    Err(e) => {
+                        console::log_1(&format!("Error signing out other sessions: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"Signed In"}</h2>
+
+        <table class={classes!("report-table")}>
+          <tr>
+            <th>{"Device"}</th>
+            <th>{"Network"}</th>
+            <th>{"Since"}</th>
+            <th>{"Last seen"}</th>
+            <th></th>
+          </tr>
+          { for sessions.iter().map(|session| {
+              let on_revoke = on_revoke.clone();
+              let revoked = session.clone();
+              html! {
+                <tr>
+                  <td title={session.user_agent.clone().unwrap_or_default()}>
+                    {describe_device(&session.user_agent)}
+                    if session.current {
+                      {" (this device)"}
+                    }
+                  </td>
+                  <td>{session.ip_prefix.clone().unwrap_or_else(|| "--".to_owned())}</td>
+                  <td title={session.created_at.clone()}>{day(&session.created_at)}</td>
+                  <td title={session.last_seen_at.clone()}>{day(&session.last_seen_at)}</td>
+                  <td>
+                    <button class={classes!("submit-button")} onclick={Callback::from(move |_| on_revoke.emit(revoked.clone()))}>{"SIGN OUT"}</button>
+                  </td>
+                </tr>
+              }
+          }) }
+        </table>
+
+        if sessions.len() > 1 {
+          <button class={classes!("submit-button")} onclick={on_revoke_others}>{"SIGN OUT EVERYWHERE ELSE"}</button>
+        }
+      </section>
+    }
+}
@@ -0,0 +1,270 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, Event, InputEvent};
+use gloo_net::http::Request;
+use serde::{Deserialize, Serialize};
+use web_sys::{console, HtmlInputElement, HtmlSelectElement};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+struct ShareSettings {
+    enabled: bool,
+    subreddit: Option<String>,
+    thread: Option<String>,
+    hide_weight: bool,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct SharePreview {
+    title: String,
+    markdown: String,
+    destination: Option<String>,
+    enabled: bool,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct SharePosted {
+    url: String,
+}
+
+fn optional(text: String) -> Option<String> {
+    if text.trim().is_empty() { None } else { Some(text) }
+}
+
+// Opt in to posting the day or week to Reddit, see the post first
+#[function_component]
+pub fn Share() -> Html {
+    let settings = use_state(ShareSettings::default);
+    let period = use_state(|| "day".to_owned());
+    let preview = use_state(|| None::<SharePreview>);
+    let message = use_state(|| None::<String>);
+
+    {
+        let settings = settings.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/sharing/settings")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                match response.json::<ShareSettings>().await {
+                                    Ok(data) => settings.set(data),
+                                    Err(e) => {
+                                        console::log_1(&format!("Failed to parse share settings: {}", e).into());
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching share settings: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    let on_enabled_change = {
+        let settings = settings.clone();
+        Callback::from(move |e: Event| {
+            let mut updated = (*settings).clone();
+            updated.enabled = e.target_unchecked_into::<HtmlInputElement>().checked();
+            settings.set(updated);
+        })
+    };
+
+    let on_hide_weight_change = {
+        let settings = settings.clone();
+        Callback::from(move |e: Event| {
+            let mut updated = (*settings).clone();
+            updated.hide_weight = e.target_unchecked_into::<HtmlInputElement>().checked();
+            settings.set(updated);
+        })
+    };
+
+    let on_subreddit_input = {
+        let settings = settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let mut updated = (*settings).clone();
+            updated.subreddit = optional(e.target_unchecked_into::<HtmlInputElement>().value());
+            settings.set(updated);
+        })
+    };
+
+    let on_thread_input = {
+        let settings = settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let mut updated = (*settings).clone();
+            updated.thread = optional(e.target_unchecked_into::<HtmlInputElement>().value());
+            settings.set(updated);
+        })
+    };
+
+    let on_period_change = {
+        let period = period.clone();
+        let preview = preview.clone();
+        Callback::from(move |e: Event| {
+            period.set(e.target_unchecked_into::<HtmlSelectElement>().value());
+            preview.set(None);
+        })
+    };
+
+    let on_save_click = {
+        let settings = settings.clone();
+        let preview = preview.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let settings = settings.clone();
+            let preview = preview.clone();
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::put("/api/sharing/settings").json(&*settings) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build share settings request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<ShareSettings>().await {
+                                Ok(data) => {
+                                    settings.set(data);
+                                    preview.set(None);
+                                    message.set(Some("Sharing settings saved".to_owned()));
+                                },
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse share settings: {}", e).into());
+                                }
+                            }
+                        } else if let Some(link) = response.headers().get("location").filter(|_| response.status() == 403) {
+                            // off to reddit to allow posting, it comes back with sharing on
+                            if let Some(window) = web_sys::window() {
+                                let _ = window.location().set_href(&link);
+                            }
+                        } else {
+                            message.set(response.text().await.ok());
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error saving share settings: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_preview_click = {
+        let period = period.clone();
+        let preview = preview.clone();
+        Callback::from(move |_| {
+            let url = format!("/api/sharing/preview?period={}", *period);
+            let preview = preview.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Request::get(&url)
+                    .send()
+                    .await
+                {
+                    Ok(response) => {
+                        if response.status() == 200 {
+                            match response.json::<SharePreview>().await {
+                                Ok(data) => preview.set(Some(data)),
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse share preview: {}", e).into());
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error fetching share preview: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let on_post_click = {
+        let period = period.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let url = format!("/api/sharing/post?period={}", *period);
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Request::post(&url).send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<SharePosted>().await {
+                                Ok(posted) => message.set(Some(format!("Posted: {}", posted.url))),
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse share result: {}", e).into());
+                                }
+                            }
+                        } else {
+                            message.set(response.text().await.ok());
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error posting to reddit: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"Share to Reddit"}</h2>
+
+        <div class={classes!("meal-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Allow posting"}</label>
+            <input type="checkbox" checked={settings.enabled} onchange={on_enabled_change}/>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Subreddit"}</label>
+            <input type="text" class="input-field" placeholder="r/animalbased" value={settings.subreddit.clone().unwrap_or_default()} oninput={on_subreddit_input}/>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Or reply in thread"}</label>
+            <input type="text" class="input-field" placeholder="https://www.reddit.com/r/.../comments/..." value={settings.thread.clone().unwrap_or_default()} oninput={on_thread_input}/>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Leave out weight"}</label>
+            <input type="checkbox" checked={settings.hide_weight} onchange={on_hide_weight_change}/>
+          </div>
+        </div>
+        <button class={classes!("submit-button")} onclick={on_save_click}>{"SAVE"}</button>
+
+        <div class={classes!("meal-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Summary"}</label>
+            <select class={classes!("select-field")} onchange={on_period_change}>
+              <option value="day" selected={*period == "day"}>{"TODAY"}</option>
+              <option value="week" selected={*period == "week"}>{"THIS WEEK"}</option>
+            </select>
+          </div>
+        </div>
+        <button class={classes!("submit-button")} onclick={on_preview_click}>{"PREVIEW"}</button>
+
+        if let Some(text) = (*message).clone() {
+          <p class={classes!("plan-summary")}>{text}</p>
+        }
+
+        if let Some(current) = (*preview).clone() {
+          <p class={classes!("plan-summary")}>
+            {match &current.destination {
+                Some(destination) => format!("{} → {}", current.title, destination),
+                None => format!("{} (choose a subreddit or thread to post)", current.title),
+            }}
+          </p>
+          <pre class={classes!("share-preview")}>{current.markdown.clone()}</pre>
+          if current.enabled && current.destination.is_some() {
+            <button class={classes!("submit-button")} onclick={on_post_click}>{"POST"}</button>
+          }
+        }
+      </section>
+    }
+}
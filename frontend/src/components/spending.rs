@@ -0,0 +1,284 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, Event, InputEvent, UseStateHandle};
+use gloo_net::http::Request;
+use serde::{Deserialize, Serialize};
+use web_sys::{console, HtmlInputElement, HtmlSelectElement};
+
+use crate::components::add_meal::Food;
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct SpendReport {
+    total: f64,
+    average_per_day: Option<f64>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct ProteinCost {
+    food_name: String,
+    price_per_kg: f64,
+    cost_per_100g_protein: f64,
+    cost_for_target: f64,
+}
+
+#[derive(Serialize)]
+struct NewPurchase {
+    food_id: String,
+    price: f64,
+    quantity: f64,
+    unit: String,
+    grams_per_unit: Option<f64>,
+    store: Option<String>,
+    add_to_pantry: bool,
+}
+
+fn fetch_reports(spend: UseStateHandle<Option<SpendReport>>, costs: UseStateHandle<Vec<ProteinCost>>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        match Request::get("/api/reports/spend")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<SpendReport>().await {
+                        Ok(data) => spend.set(Some(data)),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse spend report: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching spend report: {}", e).into());
+            }
+        }
+
+        match Request::get("/api/reports/protein-cost")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<Vec<ProteinCost>>().await {
+                        Ok(data) => costs.set(data),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse protein costs: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching protein costs: {}", e).into());
+            }
+        }
+    });
+}
+
+fn text_input(value: &UseStateHandle<String>) -> Callback<InputEvent> {
+    let value = value.clone();
+    Callback::from(move |e: InputEvent| {
+        value.set(e.target_unchecked_into::<HtmlInputElement>().value());
+    })
+}
+
+#[function_component]
+pub fn Spending() -> Html {
+    let spend = use_state(|| None::<SpendReport>);
+    let costs = use_state(Vec::<ProteinCost>::new);
+    let foods = use_state(Vec::<Food>::new);
+    let food_id = use_state(String::new);
+    let price = use_state(String::new);
+    let quantity = use_state(String::new);
+    let unit = use_state(|| "kg".to_owned());
+    let grams_per_unit = use_state(String::new);
+    let store = use_state(String::new);
+    let add_to_pantry = use_state(|| true);
+
+    {
+        let spend = spend.clone();
+        let costs = costs.clone();
+        let foods = foods.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                fetch_reports(spend, costs);
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/foods")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                match response.json::<Vec<Food>>().await {
+                                    Ok(data) => foods.set(data),
+                                    Err(e) => {
+                                        console::log_1(&format!("Failed to parse foods: {}", e).into());
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching foods: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    let on_food_change = {
+        let food_id = food_id.clone();
+        Callback::from(move |e: Event| {
+            food_id.set(e.target_unchecked_into::<HtmlSelectElement>().value());
+        })
+    };
+
+    let on_unit_change = {
+        let unit = unit.clone();
+        Callback::from(move |e: Event| {
+            unit.set(e.target_unchecked_into::<HtmlSelectElement>().value());
+        })
+    };
+
+    let on_pantry_change = {
+        let add_to_pantry = add_to_pantry.clone();
+        Callback::from(move |e: Event| {
+            add_to_pantry.set(e.target_unchecked_into::<HtmlInputElement>().checked());
+        })
+    };
+
+    let on_save_click = {
+        let spend = spend.clone();
+        let costs = costs.clone();
+        let foods = foods.clone();
+        let food_id = food_id.clone();
+        let price = price.clone();
+        let quantity = quantity.clone();
+        let unit = unit.clone();
+        let grams_per_unit = grams_per_unit.clone();
+        let store = store.clone();
+        let add_to_pantry = add_to_pantry.clone();
+        Callback::from(move |_| {
+            // the select shows the first food until it is changed
+            let chosen = if food_id.is_empty() {
+                foods.first().map(|food| food.id.clone())
+            } else {
+                Some((*food_id).clone())
+            };
+            let (Some(chosen), Ok(amount), Ok(paid)) = (chosen, quantity.parse::<f64>(), price.parse::<f64>()) else {
+                return;
+            };
+            let purchase = NewPurchase {
+                food_id: chosen,
+                price: paid,
+                quantity: amount,
+                unit: (*unit).clone(),
+                grams_per_unit: grams_per_unit.parse::<f64>().ok(),
+                store: Some((*store).clone()).filter(|store| !store.is_empty()),
+                add_to_pantry: *add_to_pantry,
+            };
+            let spend = spend.clone();
+            let costs = costs.clone();
+            let price = price.clone();
+            let quantity = quantity.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/purchases").json(&purchase) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build purchase request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            price.set(String::new());
+                            quantity.set(String::new());
+                            fetch_reports(spend, costs);
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error saving purchase: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let spend_now = (*spend).clone();
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"Spending"}</h2>
+
+        <div class={classes!("macro-display")}>
+          <div class={classes!("macro-card")}>
+            <div class={classes!("macro-title")}>{"This Week"}</div>
+            <div class={classes!("macro-value")}>{format!("${:.2}", spend_now.as_ref().map(|s| s.total).unwrap_or(0.0))}</div>
+          </div>
+          <div class={classes!("macro-card")}>
+            <div class={classes!("macro-title")}>{"Per Day"}</div>
+            <div class={classes!("macro-value")}>
+              {spend_now.as_ref().and_then(|s| s.average_per_day).map(|avg| format!("${:.2}", avg)).unwrap_or_else(|| "--".to_owned())}
+            </div>
+          </div>
+        </div>
+
+        <table class={classes!("report-table")}>
+          <tr>
+            <th>{"Food"}</th>
+            <th>{"$/kg"}</th>
+            <th>{"$/100g protein"}</th>
+            <th>{"$ for target"}</th>
+          </tr>
+          { for costs.iter().take(5).map(|cost| html! {
+              <tr>
+                <td>{cost.food_name.clone()}</td>
+                <td>{format!("{:.2}", cost.price_per_kg)}</td>
+                <td>{format!("{:.2}", cost.cost_per_100g_protein)}</td>
+                <td>{format!("{:.2}", cost.cost_for_target)}</td>
+              </tr>
+          }) }
+        </table>
+
+        <div class={classes!("meal-form", "body-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Food"}</label>
+            <select class={classes!("select-field")} onchange={on_food_change}>
+              { for foods.iter().map(|food| html! {
+                  <option value={food.id.clone()} selected={*food_id == food.id}>{food.name.clone()}</option>
+              }) }
+            </select>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Price"}</label>
+            <input type="number" step="0.01" class="input-field" placeholder="0" value={(*price).clone()} oninput={text_input(&price)}/>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Amount"}</label>
+            <input type="number" step="0.1" class="input-field" placeholder="0" value={(*quantity).clone()} oninput={text_input(&quantity)}/>
+            <select class={classes!("select-field", "unit-select")} onchange={on_unit_change}>
+              <option value="kg" selected={*unit == "kg"}>{"kg"}</option>
+              <option value="lb" selected={*unit == "lb"}>{"lb"}</option>
+              <option value="unit" selected={*unit == "unit"}>{"units"}</option>
+            </select>
+          </div>
+          if *unit == "unit" {
+            <div class={classes!("input-group")}>
+              <label class={classes!("input-label")}>{"Grams each"}</label>
+              <input type="number" class="input-field" placeholder="0" value={(*grams_per_unit).clone()} oninput={text_input(&grams_per_unit)}/>
+            </div>
+          }
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Store"}</label>
+            <input type="text" class="input-field" value={(*store).clone()} oninput={text_input(&store)}/>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Into pantry"}</label>
+            <input type="checkbox" checked={*add_to_pantry} onchange={on_pantry_change}/>
+          </div>
+        </div>
+
+        <button class={classes!("submit-button")} onclick={on_save_click}>{"LOG PURCHASE"}</button>
+      </section>
+    }
+}
@@ -0,0 +1,215 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, Event, InputEvent, UseStateHandle};
+use gloo_net::http::Request;
+use serde::Deserialize;
+use serde_json::Value;
+use web_sys::{console, HtmlInputElement};
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct TdeeEstimate {
+    intake_days: i64,
+    weigh_in_days: i64,
+    average_intake_kcal: Option<f64>,
+    trend_change_kg: Option<f64>,
+    tdee_kcal: Option<f64>,
+    confidence: String,
+}
+
+fn fetch_estimate(estimate: UseStateHandle<Option<TdeeEstimate>>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        match Request::get("/api/tdee")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<TdeeEstimate>().await {
+                        Ok(data) => estimate.set(Some(data)),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse TDEE estimate: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching TDEE estimate: {}", e).into());
+            }
+        }
+    });
+}
+
+fn format_kcal(value: Option<f64>) -> String {
+    match value {
+        Some(kcal) => format!("{:.0}kcal", kcal),
+        None => "--".to_owned(),
+    }
+}
+
+// What the logs say the user actually burns, and the weekly calorie nudge
+// toward their goal rate
+#[function_component]
+pub fn Tdee() -> Html {
+    let estimate = use_state(|| None::<TdeeEstimate>);
+    // the whole targets object, only the goal fields are edited here
+    let targets = use_state(|| None::<Value>);
+    let goal_rate = use_state(String::new);
+    let message = use_state(|| None::<String>);
+
+    {
+        let estimate = estimate.clone();
+        let targets = targets.clone();
+        let goal_rate = goal_rate.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                fetch_estimate(estimate);
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/targets")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                match response.json::<Value>().await {
+                                    Ok(data) => {
+                                        let rate = data["goal_rate_kg_per_week"].as_f64().unwrap_or(0.0);
+                                        goal_rate.set(format!("{}", rate));
+                                        targets.set(Some(data));
+                                    },
+                                    Err(e) => {
+                                        console::log_1(&format!("Failed to parse targets: {}", e).into());
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching targets: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    let on_goal_rate_input = {
+        let goal_rate = goal_rate.clone();
+        Callback::from(move |e: InputEvent| {
+            goal_rate.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_auto_adjust_change = {
+        let targets = targets.clone();
+        Callback::from(move |e: Event| {
+            if let Some(mut updated) = (*targets).clone() {
+                updated["auto_adjust"] = Value::Bool(e.target_unchecked_into::<HtmlInputElement>().checked());
+                targets.set(Some(updated));
+            }
+        })
+    };
+
+    let on_save_click = {
+        let targets = targets.clone();
+        let goal_rate = goal_rate.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let Some(mut updated) = (*targets).clone() else {
+                return;
+            };
+            let Ok(rate) = goal_rate.trim().parse::<f64>() else {
+                message.set(Some("Goal rate should be a number, negative to lose".to_owned()));
+                return;
+            };
+            updated["goal_rate_kg_per_week"] = Value::from(rate);
+            let targets = targets.clone();
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::put("/api/targets").json(&updated) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build targets request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<Value>().await {
+                                Ok(data) => {
+                                    targets.set(Some(data));
+                                    message.set(Some("Goal saved".to_owned()));
+                                },
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse targets: {}", e).into());
+                                }
+                            }
+                        } else {
+                            message.set(response.text().await.ok());
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error saving targets: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let current = (*estimate).clone();
+    let auto_adjust = (*targets).as_ref().and_then(|targets| targets["auto_adjust"].as_bool()).unwrap_or(false);
+    let change = match current.as_ref().and_then(|e| e.trend_change_kg) {
+        Some(kg) => format!("{:+.1}kg", kg),
+        None => "--".to_owned(),
+    };
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"Energy Expenditure"}</h2>
+
+        <div class={classes!("macro-display")}>
+          <div class={classes!("macro-card")}>
+            <div class={classes!("macro-title")}>{"TDEE"}</div>
+            <div class={classes!("macro-value")}>{format_kcal(current.as_ref().and_then(|e| e.tdee_kcal))}</div>
+          </div>
+
+          <div class={classes!("macro-card")}>
+            <div class={classes!("macro-title")}>{"Confidence"}</div>
+            <div class={classes!("macro-value")}>{current.as_ref().map(|e| e.confidence.clone()).unwrap_or_else(|| "--".to_owned())}</div>
+          </div>
+
+          <div class={classes!("macro-card")}>
+            <div class={classes!("macro-title")}>{"Average Intake"}</div>
+            <div class={classes!("macro-value")}>{format_kcal(current.as_ref().and_then(|e| e.average_intake_kcal))}</div>
+          </div>
+
+          <div class={classes!("macro-card")}>
+            <div class={classes!("macro-title")}>{"Trend Change"}</div>
+            <div class={classes!("macro-value")}>{change}</div>
+          </div>
+        </div>
+
+        if let Some(current) = current.as_ref() {
+          <p class={classes!("plan-summary")}>
+            {format!("From {} days of food logs and {} weigh-ins over the last 4 weeks", current.intake_days, current.weigh_in_days)}
+          </p>
+        }
+
+        <h3 class={classes!("panel-subheader")}>{"Goal"}</h3>
+        <div class={classes!("meal-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Rate (kg/week)"}</label>
+            <input type="number" step="0.05" class="input-field" placeholder="-0.5" value={(*goal_rate).clone()} oninput={on_goal_rate_input}/>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Adjust calories weekly"}</label>
+            <input type="checkbox" checked={auto_adjust} onchange={on_auto_adjust_change}/>
+          </div>
+        </div>
+        <button class={classes!("submit-button")} onclick={on_save_click}>{"SAVE"}</button>
+
+        if let Some(text) = (*message).clone() {
+          <p class={classes!("plan-summary")}>{text}</p>
+        }
+      </section>
+    }
+}
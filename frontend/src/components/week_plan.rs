@@ -0,0 +1,251 @@
+use yew::{function_component, classes, html, Html, use_effect_with, use_state, Callback, TargetCast, InputEvent, UseStateHandle};
+use gloo_net::http::Request;
+use serde::{Deserialize, Serialize};
+use web_sys::{console, HtmlInputElement};
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct PlannedMeal {
+    id: String,
+    date: String,
+    slot: String,
+    food_name: String,
+    grams: f64,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct PrepTask {
+    id: String,
+    date: String,
+    time: Option<String>,
+    title: String,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug, Default)]
+struct WeekPlanData {
+    meals: Vec<PlannedMeal>,
+    tasks: Vec<PrepTask>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct FeedToken {
+    path: String,
+}
+
+#[derive(Serialize)]
+struct NewPrepTask {
+    date: String,
+    title: String,
+}
+
+fn fetch_week(week: UseStateHandle<WeekPlanData>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        match Request::get("/api/plans")
+            .send()
+            .await
+        {
+            Ok(response) => {
+                if response.status() == 200 {
+                    match response.json::<WeekPlanData>().await {
+                        Ok(data) => week.set(data),
+                        Err(e) => {
+                            console::log_1(&format!("Failed to parse meal plan: {}", e).into());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                console::log_1(&format!("Error fetching meal plan: {}", e).into());
+            }
+        }
+    });
+}
+
+fn delete_and_refresh(url: String, week: UseStateHandle<WeekPlanData>) -> Callback<yew::MouseEvent> {
+    Callback::from(move |_| {
+        let url = url.clone();
+        let week = week.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            match Request::delete(&url).send().await {
+                Ok(response) => {
+                    if response.ok() {
+                        fetch_week(week);
+                    }
+                }
+                Err(e) => {
+                    console::log_1(&format!("Error deleting from meal plan: {}", e).into());
+                }
+            }
+        });
+    })
+}
+
+#[function_component]
+pub fn WeekPlan() -> Html {
+    let week = use_state(WeekPlanData::default);
+    let feed = use_state(|| None::<FeedToken>);
+    let task_date = use_state(String::new);
+    let task_title = use_state(String::new);
+
+    {
+        let week = week.clone();
+        let feed = feed.clone();
+        use_effect_with(
+            (),
+            move |_| {
+                fetch_week(week);
+                wasm_bindgen_futures::spawn_local(async move {
+                    match Request::get("/api/plans/feed")
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            if response.status() == 200 {
+                                match response.json::<FeedToken>().await {
+                                    Ok(data) => feed.set(Some(data)),
+                                    Err(e) => {
+                                        console::log_1(&format!("Failed to parse feed token: {}", e).into());
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            console::log_1(&format!("Error fetching feed token: {}", e).into());
+                        }
+                    }
+                });
+                || ()
+            },
+        );
+    }
+
+    let on_date_input = {
+        let task_date = task_date.clone();
+        Callback::from(move |e: InputEvent| {
+            task_date.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_title_input = {
+        let task_title = task_title.clone();
+        Callback::from(move |e: InputEvent| {
+            task_title.set(e.target_unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let on_task_click = {
+        let week = week.clone();
+        let task_date = task_date.clone();
+        let task_title = task_title.clone();
+        Callback::from(move |_| {
+            if task_date.is_empty() || task_title.trim().is_empty() {
+                return;
+            }
+            let task = NewPrepTask {
+                date: (*task_date).clone(),
+                title: (*task_title).clone(),
+            };
+            let week = week.clone();
+            let task_title = task_title.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = match Request::post("/api/plans/prep").json(&task) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        console::log_1(&format!("Failed to build prep task request: {}", e).into());
+                        return;
+                    }
+                };
+                match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            task_title.set(String::new());
+                            fetch_week(week);
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error saving prep task: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    // the link is only shown once, a new one replaces it
+    let on_rotate_click = {
+        let feed = feed.clone();
+        Callback::from(move |_| {
+            let feed = feed.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Request::post("/api/plans/feed/rotate").send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            match response.json::<FeedToken>().await {
+                                Ok(data) => feed.set(Some(data)),
+                                Err(e) => {
+                                    console::log_1(&format!("Failed to parse feed token: {}", e).into());
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        console::log_1(&format!("Error rotating feed token: {}", e).into());
+                    }
+                }
+            });
+        })
+    };
+
+    let mut dates: Vec<String> = week.meals.iter()
+        .map(|meal| meal.date.clone())
+        .chain(week.tasks.iter().map(|task| task.date.clone()))
+        .collect();
+    dates.sort();
+    dates.dedup();
+
+    html! {
+      <section class={classes!("panel")}>
+        <h2 class={classes!("panel-header")}>{"This Week's Plan"}</h2>
+
+        { for dates.iter().map(|date| html! {
+            <div class={classes!("plan-day")}>
+              <h3 class={classes!("panel-subheader")}>{date.clone()}</h3>
+              <ul class={classes!("plan-foods")}>
+                { for week.tasks.iter().filter(|task| &task.date == date).map(|task| html! {
+                    <li>
+                      {format!("{} {}", task.time.clone().unwrap_or_default(), task.title)}
+                      <button class={classes!("remove-button")} onclick={delete_and_refresh(format!("/api/plans/prep/{}", task.id), week.clone())}>{"x"}</button>
+                    </li>
+                }) }
+                { for week.meals.iter().filter(|meal| &meal.date == date).map(|meal| html! {
+                    <li>
+                      {format!("{}: {:.0}g {}", meal.slot, meal.grams, meal.food_name)}
+                      <button class={classes!("remove-button")} onclick={delete_and_refresh(format!("/api/plans/meals/{}", meal.id), week.clone())}>{"x"}</button>
+                    </li>
+                }) }
+              </ul>
+            </div>
+        }) }
+
+        <div class={classes!("meal-form")}>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Day"}</label>
+            <input type="date" class="input-field" value={(*task_date).clone()} oninput={on_date_input}/>
+          </div>
+          <div class={classes!("input-group")}>
+            <label class={classes!("input-label")}>{"Prep"}</label>
+            <input type="text" class="input-field" placeholder="Start broth" value={(*task_title).clone()} oninput={on_title_input}/>
+          </div>
+        </div>
+        <button class={classes!("submit-button")} onclick={on_task_click}>{"ADD PREP TASK"}</button>
+
+        if let Some(feed) = (*feed).clone() {
+          <p class={classes!("feed-link")}>
+            {"Subscribe in your calendar (this link is only shown once): "}
+            <a href={feed.path.clone()}>{feed.path.clone()}</a>
+          </p>
+        } else {
+          <p class={classes!("feed-link")}>{"Lost your calendar link? A new one stops the old one working."}</p>
+          <button class={classes!("submit-button")} onclick={on_rotate_click}>{"NEW CALENDAR LINK"}</button>
+        }
+      </section>
+    }
+}